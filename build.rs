@@ -0,0 +1,41 @@
+use std::env;
+use std::process::Command;
+
+/// Embeds the commit of the build, from `GIT_COMMIT` (set by the Docker build) or the checkout,
+/// and generates the gRPC service of the proto
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_proto();
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let commit = env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+                .map(|commit| commit.trim().to_string())
+        })
+        .unwrap_or("unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+}
+
+/// Compiles `proto/account_monitor.proto` without `protoc`, for the `grpc` module
+#[cfg(feature = "grpc")]
+fn compile_proto() {
+    println!("cargo:rerun-if-changed=proto");
+
+    let file_descriptors = protox::compile(["account_monitor.proto"], ["proto"])
+        .expect("Could not parse proto/account_monitor.proto");
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_fds(file_descriptors)
+        .expect("Could not generate the gRPC service");
+}
@@ -0,0 +1,437 @@
+use ethers::core::{
+    abi::{Abi, Event, RawLog, Token},
+    types::{Address, Log, H256},
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use rusqlite::{named_params, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::{
+    chain::Chain,
+    explorer_api, http_client,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    state_db::CONNECTION,
+};
+use crate::{FullString, ToLabel};
+
+const ABI_DIR_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Contracts whose ABI was fetched, or found unverified, aren't fetched again for this long
+const ABI_REFETCH_INTERVAL: Duration = Duration::from_secs(86400);
+/// Blocks wait this long for the ABIs of their unknown contracts, slower fetches go on in the
+/// background for the next blocks
+const ABI_FETCH_WAIT: Duration = Duration::from_secs(5);
+/// Sourcify answers up to a few requests per second per client
+const SOURCIFY_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Event of a loaded ABI decoded from a log
+#[derive(Debug)]
+pub struct DecodedEvent {
+    pub contract: Address,
+    pub name: String,
+    pub params: Vec<(String, Token)>,
+}
+
+impl DecodedEvent {
+    /// Event with its parameters, using the labels of known addresses
+    pub fn describe(&self, addressbook: &HashMap<String, String>) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Token::Address(address) => address.to_label(addressbook),
+                    Token::FixedBytes(bytes) | Token::Bytes(bytes) => {
+                        format!("0x{}", ethers::core::utils::hex::encode(bytes))
+                    }
+                    value => value.to_string(),
+                };
+                format!("{}: {}", name, value)
+            })
+            .collect();
+
+        format!(
+            "{}({}) from {}",
+            self.name,
+            params.join(", "),
+            self.contract.to_label(addressbook)
+        )
+    }
+}
+
+lazy_static! {
+    /// Events of the loaded ABIs by signature. Events sharing a signature can differ in which
+    /// parameters are indexed
+    static ref EVENTS: RwLock<HashMap<H256, Vec<Event>>> = RwLock::new(HashMap::new());
+    /// Events of the ABIs fetched from the explorers, kept apart from the reloaded `ABI_DIR`
+    static ref FETCHED_EVENTS: RwLock<HashMap<H256, Vec<Event>>> = RwLock::new(HashMap::new());
+    /// Sourcify server the verified ABIs are fetched from, after the chain's explorer API
+    static ref SOURCIFY_URL: Option<String> = env::var("SOURCIFY_URL")
+        .ok()
+        .filter(|sourcify_url| !sourcify_url.is_empty())
+        .map(|sourcify_url| sourcify_url.trim_end_matches('/').to_string());
+    static ref SOURCIFY_NEXT_REQUEST: tokio::sync::Mutex<Instant> =
+        tokio::sync::Mutex::new(Instant::now());
+    /// When the ABI of each contract by chain was last fetched, or found unverified, so that
+    /// they aren't fetched again without a state database
+    static ref FETCHED_AT: Mutex<HashMap<(String, String), u64>> = Mutex::new(HashMap::new());
+    /// Contracts by chain whose ABI is being fetched
+    static ref FETCHING: Mutex<HashSet<(String, String)>> = Mutex::new(HashSet::new());
+}
+
+/// Decodes the log with the events of the loaded or fetched ABIs
+pub fn decode(log: &Log) -> Option<DecodedEvent> {
+    decode_with(&EVENTS.read().unwrap(), log)
+        .or_else(|| decode_with(&FETCHED_EVENTS.read().unwrap(), log))
+}
+
+fn decode_with(events: &HashMap<H256, Vec<Event>>, log: &Log) -> Option<DecodedEvent> {
+    events
+        .get(log.topics.first()?)?
+        .iter()
+        .find_map(|event| {
+            event
+                .parse_log(RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.to_vec(),
+                })
+                .ok()
+                .map(|decoded_log| (event.name.clone(), decoded_log))
+        })
+        .map(|(name, decoded_log)| DecodedEvent {
+            contract: log.address,
+            name,
+            params: decoded_log
+                .params
+                .into_iter()
+                .map(|param| (param.name, param.value))
+                .collect(),
+        })
+}
+
+/// Reads a plain ABI, or the `abi` of a Hardhat/Foundry artifact
+fn read_abi(path: &Path) -> eyre::Result<Abi> {
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    let abi = match json {
+        serde_json::Value::Object(mut artifact) => artifact
+            .remove("abi")
+            .ok_or(eyre::eyre!("No abi in artifact"))?,
+        abi => abi,
+    };
+    Ok(serde_json::from_value(abi)?)
+}
+
+/// Adds the non anonymous events of the ABI, returning whether any was new
+fn add_events(events: &mut HashMap<H256, Vec<Event>>, abi: &Abi) -> bool {
+    let mut added = false;
+    for event in abi.events().filter(|event| !event.anonymous) {
+        let signature_events = events.entry(event.signature()).or_default();
+        if !signature_events.contains(event) {
+            signature_events.push(event.clone());
+            added = true;
+        }
+    }
+    added
+}
+
+fn load_abi_dir(abi_dir: &Path) -> HashMap<H256, Vec<Event>> {
+    let mut events: HashMap<H256, Vec<Event>> = HashMap::new();
+    let Ok(entries) = std::fs::read_dir(abi_dir) else {
+        error!("Could not read ABI_DIR {}", abi_dir.display());
+        return events;
+    };
+
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match read_abi(&path) {
+            Ok(abi) => {
+                add_events(&mut events, &abi);
+            }
+            Err(err) => error!("Invalid ABI {}: {}", path.display(), err),
+        }
+    }
+
+    events
+}
+
+fn last_modified(abi_dir: &Path) -> Option<SystemTime> {
+    let directory_modified = std::fs::metadata(abi_dir).and_then(|metadata| metadata.modified());
+    std::fs::read_dir(abi_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .chain(directory_modified.ok())
+        .max()
+}
+
+/// Loads the events of the ABIs in the directory, reloading them whenever a file changes
+pub async fn watch_abi_dir(abi_dir: PathBuf) {
+    let mut loaded_modified = None;
+
+    loop {
+        let modified = last_modified(&abi_dir);
+        if modified != loaded_modified {
+            let events = load_abi_dir(&abi_dir);
+            info!(
+                "Loaded {} events from the ABIs in {}",
+                events.values().map(Vec::len).sum::<usize>(),
+                abi_dir.display()
+            );
+            *EVENTS.write().unwrap() = events;
+            loaded_modified = modified;
+        }
+
+        sleep(ABI_DIR_POLL_INTERVAL).await;
+    }
+}
+
+/// Loads the ABIs fetched by previous runs from the state database
+pub fn load_fetched() {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    let abis: Vec<String> = match connection
+        .lock()
+        .unwrap()
+        .prepare("SELECT abi FROM contract_abis WHERE abi IS NOT NULL")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect()
+        }) {
+        Ok(abis) => abis,
+        Err(err) => {
+            error!("Error while reading the fetched ABIs: {}", err);
+            return;
+        }
+    };
+
+    let mut fetched_events = FETCHED_EVENTS.write().unwrap();
+    for abi in abis.iter() {
+        match serde_json::from_str::<Abi>(abi) {
+            Ok(abi) => {
+                add_events(&mut fetched_events, &abi);
+            }
+            Err(err) => error!("Invalid stored ABI: {}", err),
+        }
+    }
+    info!("Loaded {} fetched ABIs", abis.len());
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Whether the contract's ABI was fetched, or found unverified, within `ABI_REFETCH_INTERVAL`
+fn recently_fetched(chain: &Chain, address: &str) -> bool {
+    let fetched_at = FETCHED_AT
+        .lock()
+        .unwrap()
+        .get(&(chain.name.clone(), address.to_string()))
+        .copied();
+    if fetched_at.is_some_and(|fetched_at| now() < fetched_at + ABI_REFETCH_INTERVAL.as_secs()) {
+        return true;
+    }
+    let Some(connection) = CONNECTION.as_ref() else {
+        return false;
+    };
+
+    connection
+        .lock()
+        .unwrap()
+        .query_row(
+            "SELECT fetched_at FROM contract_abis WHERE chain = :chain AND address = :address",
+            named_params! {":chain": chain.name, ":address": address},
+            |row| row.get::<_, u64>(0),
+        )
+        .optional()
+        .unwrap_or_else(|err| {
+            error!("Error while reading the ABI of {}: {}", address, err);
+            None
+        })
+        .is_some_and(|fetched_at| now() < fetched_at + ABI_REFETCH_INTERVAL.as_secs())
+}
+
+/// Stores the fetched ABI, `None` for unverified contracts
+fn store_abi(chain: &Chain, address: &str, abi: Option<&Abi>) {
+    FETCHED_AT
+        .lock()
+        .unwrap()
+        .insert((chain.name.clone(), address.to_string()), now());
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    let abi = abi.map(|abi| serde_json::to_string(abi).unwrap());
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT INTO contract_abis (chain, address, abi, fetched_at)
+        VALUES (:chain, :address, :abi, :fetched_at)
+        ON CONFLICT (chain, address) DO UPDATE SET abi = :abi, fetched_at = :fetched_at",
+        named_params! {":chain": chain.name, ":address": address, ":abi": abi, ":fetched_at": now()},
+    ) {
+        error!("Error while storing the ABI of {}: {}", address, err);
+    }
+}
+
+/// Verified ABI of the contract from the chain's explorer API
+async fn fetch_explorer_abi(chain: &Chain, address: &str) -> eyre::Result<Option<Abi>> {
+    let Some(abi) = explorer_api::query(
+        chain,
+        &[
+            ("module", "contract"),
+            ("action", "getabi"),
+            ("address", address),
+        ],
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let abi = abi.as_str().ok_or(eyre::eyre!("ABI is not a string"))?;
+    Ok(Some(serde_json::from_str(abi)?))
+}
+
+/// Verified ABI of the contract from Sourcify, which also has the contracts verified on it by
+/// the block explorers
+async fn fetch_sourcify_abi(
+    sourcify_url: &str,
+    chain: &Chain,
+    address: &str,
+) -> eyre::Result<Option<Abi>> {
+    let chain_id = chain
+        .id
+        .ok_or(eyre::eyre!("Unknown {} chain id", chain.name))?;
+
+    // Slots are handed out in order, as for the explorer APIs
+    let mut next_request = SOURCIFY_NEXT_REQUEST.lock().await;
+    let slot = (*next_request).max(Instant::now());
+    *next_request = slot + SOURCIFY_REQUEST_INTERVAL;
+    drop(next_request);
+    sleep_until(slot).await;
+
+    let response = http_client::client()
+        .get(format!(
+            "{}/v2/contract/{}/{}",
+            sourcify_url, chain_id, address
+        ))
+        .query(&[("fields", "abi")])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let mut contract: serde_json::Value = response.error_for_status()?.json().await?;
+    match contract.get_mut("abi").map(serde_json::Value::take) {
+        Some(abi) if !abi.is_null() => Ok(Some(serde_json::from_value(abi)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Verified ABI of the contract, from the chain's explorer API or else from Sourcify
+async fn fetch_abi(chain: &Chain, address: &str) -> eyre::Result<Option<Abi>> {
+    if explorer_api::is_enabled(chain) {
+        if let Some(abi) = fetch_explorer_abi(chain, address).await? {
+            return Ok(Some(abi));
+        }
+    }
+    match SOURCIFY_URL.as_ref() {
+        Some(sourcify_url) => fetch_sourcify_abi(sourcify_url, chain, address).await,
+        None => Ok(None),
+    }
+}
+
+/// Fetches the verified ABIs of the contracts emitting the logs of unknown transactions which
+/// could not be decoded, from the chain's explorer or Sourcify, returning whether new events
+/// were added. The ABIs are stored in the state database, if any. Fetches outlasting
+/// `ABI_FETCH_WAIT` go on in the background, only decoding the logs of the next blocks
+pub async fn fetch_abis<'a>(
+    chain: &Chain,
+    logs: impl IntoIterator<Item = &'a Log>,
+    interesting_transactions: &[InterestingTransaction],
+) -> bool {
+    if !explorer_api::is_enabled(chain) && SOURCIFY_URL.is_none() {
+        return false;
+    }
+
+    let unknown_transactions: HashSet<H256> = interesting_transactions
+        .iter()
+        .filter(|tx| tx.kind == InterestingTransactionKind::Other && tx.event.is_none())
+        .map(|tx| tx.hash)
+        .collect();
+    if unknown_transactions.is_empty() {
+        return false;
+    }
+    let contracts: HashSet<Address> = logs
+        .into_iter()
+        .filter(|log| {
+            log.transaction_hash
+                .is_some_and(|hash| unknown_transactions.contains(&hash))
+                && decode(log).is_none()
+        })
+        .map(|log| log.address)
+        .collect();
+
+    let contracts: Vec<String> = contracts
+        .into_iter()
+        .map(|contract| contract.full_string())
+        .filter(|address| {
+            !recently_fetched(chain, address)
+                && FETCHING
+                    .lock()
+                    .unwrap()
+                    .insert((chain.name.clone(), address.clone()))
+        })
+        .collect();
+    if contracts.is_empty() {
+        return false;
+    }
+
+    let chain = chain.clone();
+    let fetching = tokio::spawn(async move {
+        let mut added = false;
+        for address in contracts {
+            match fetch_abi(&chain, &address).await {
+                Ok(Some(abi)) => {
+                    store_abi(&chain, &address, Some(&abi));
+                    added |= add_events(&mut FETCHED_EVENTS.write().unwrap(), &abi);
+                }
+                Ok(None) => {
+                    debug!("No verified ABI for {} on {}", address, chain.name);
+                    store_abi(&chain, &address, None);
+                }
+                Err(err) => error!(
+                    "Error while fetching the ABI of {} on {}: {}",
+                    address, chain.name, err
+                ),
+            }
+            FETCHING
+                .lock()
+                .unwrap()
+                .remove(&(chain.name.clone(), address));
+        }
+        added
+    });
+
+    match tokio::time::timeout(ABI_FETCH_WAIT, fetching).await {
+        Ok(added) => added.unwrap_or(false),
+        Err(_) => {
+            debug!("Still fetching ABIs, the block is processed without them");
+            false
+        }
+    }
+}
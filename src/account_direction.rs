@@ -0,0 +1,55 @@
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use strum_macros::EnumString;
+use utoipa::ToSchema;
+
+use crate::interesting_transaction::InterestingTransaction;
+use crate::FullString;
+
+/// Transactions of a watched account which are notified
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, EnumString, DeserializeMacro, SerializeMacro, ToSchema,
+)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+    #[default]
+    Both,
+}
+
+lazy_static! {
+    /// Direction of each watched account which is not watched in both
+    static ref ACCOUNT_DIRECTIONS: Mutex<HashMap<String, Direction>> = Mutex::new(HashMap::new());
+}
+
+pub fn register(address: &str, direction: Direction) {
+    let mut account_directions = ACCOUNT_DIRECTIONS.lock().unwrap();
+    if direction == Direction::Both {
+        account_directions.remove(&address.to_lowercase());
+    } else {
+        account_directions.insert(address.to_lowercase(), direction);
+    }
+}
+
+/// Whether the transaction goes in a direction watched for its account. Transactions neither
+/// from nor to the account, such as unknown operations, are always watched
+pub fn is_watched(tx: &InterestingTransaction) -> bool {
+    let Some(direction) = ACCOUNT_DIRECTIONS
+        .lock()
+        .unwrap()
+        .get(&tx.involved_account.full_string())
+        .copied()
+    else {
+        return true;
+    };
+
+    let outgoing = tx.from == Some(tx.involved_account);
+    let incoming = tx.to == Some(tx.involved_account);
+    match direction {
+        Direction::Incoming => incoming || !outgoing,
+        Direction::Outgoing => outgoing || !incoming,
+        Direction::Both => true,
+    }
+}
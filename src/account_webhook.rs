@@ -0,0 +1,39 @@
+use lazy_static::lazy_static;
+use log::error;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::{http_client, interesting_transaction::TransactionEvent};
+
+lazy_static! {
+    /// Webhook of each watched account which has one
+    static ref ACCOUNT_WEBHOOKS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+pub fn register(address: &str, webhook_url: &str) {
+    ACCOUNT_WEBHOOKS
+        .lock()
+        .unwrap()
+        .insert(address.to_lowercase(), webhook_url.to_string());
+}
+
+/// Posts each notified transaction to the webhook of its account, if any
+pub async fn post_events(transaction_events: &[TransactionEvent]) {
+    for transaction_event in transaction_events {
+        let Some(webhook_url) = ACCOUNT_WEBHOOKS
+            .lock()
+            .unwrap()
+            .get(&transaction_event.account)
+            .cloned()
+        else {
+            continue;
+        };
+
+        if let Err(err) = http_client::post_webhook(&webhook_url, transaction_event).await {
+            error!(
+                "Error while posting {} to the webhook of {}: {}",
+                transaction_event.hash, transaction_event.account, err
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use ethers::core::types::Address;
+use lazy_static::lazy_static;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use strum_macros::EnumString;
+
+use crate::{
+    chain::Chain,
+    history,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+};
+use crate::{FullString, ToLabel};
+
+/// Bytes at the start or at the end of an address shown by wallets, which lookalike addresses copy
+const MATCHED_BYTES: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, EnumString)]
+pub enum PoisoningAction {
+    /// Notifies poisoning attempts with a warning instead of the transfer message
+    Warn,
+    /// Drops poisoning attempts without notifying them
+    Drop,
+    None,
+}
+
+pub enum Poisoning {
+    ZeroValue,
+    /// Lookalike of a known counterparty, which is the imitated address
+    Lookalike(Address),
+}
+
+lazy_static! {
+    pub static ref ADDRESS_POISONING_ACTION: PoisoningAction = PoisoningAction::from_str(
+        &env::var("ADDRESS_POISONING_ACTION").unwrap_or("Warn".to_string())
+    )
+    .expect("Invalid ADDRESS_POISONING_ACTION");
+    /// Recipients of the transfers sent by each watched account
+    static ref COUNTERPARTIES: Mutex<HashMap<Address, HashSet<Address>>> = {
+        let mut counterparties: HashMap<Address, HashSet<Address>> = HashMap::new();
+        for (account, counterparty) in history::sent_to() {
+            counterparties.entry(account).or_default().insert(counterparty);
+        }
+        Mutex::new(counterparties)
+    };
+}
+
+fn is_lookalike(address: &Address, known: &Address) -> bool {
+    address != known
+        && (address[..MATCHED_BYTES] == known[..MATCHED_BYTES]
+            || address[20 - MATCHED_BYTES..] == known[20 - MATCHED_BYTES..])
+}
+
+/// Transfers sent by the watched account to a new address, which becomes a known counterparty
+fn sent_to(tx: &InterestingTransaction) -> Option<Address> {
+    if !matches!(
+        tx.kind,
+        InterestingTransactionKind::Send | InterestingTransactionKind::Transfer
+    ) || tx.from != Some(tx.involved_account)
+        || (tx.token_id.is_none() && tx.amount.is_none_or(|amount| amount.is_zero()))
+    {
+        return None;
+    }
+    tx.to
+}
+
+/// Zero-value transfers, and transfers with a lookalike of an address the watched account sent to
+/// before or of any watched account
+pub fn detect(
+    tx: &InterestingTransaction,
+    addressbook: &HashMap<String, String>,
+) -> Option<Poisoning> {
+    if !matches!(
+        tx.kind,
+        InterestingTransactionKind::Transfer | InterestingTransactionKind::Transfer1155
+    ) {
+        return None;
+    }
+    // Only fungible transfers have an amount, ERC-721 and ERC-1155 ones have a token id instead
+    let is_fungible = tx.kind == InterestingTransactionKind::Transfer && tx.token_id.is_none();
+    if is_fungible && tx.amount.is_some_and(|amount| amount.is_zero()) {
+        return Some(Poisoning::ZeroValue);
+    }
+
+    let counterparty = if tx.from == Some(tx.involved_account) {
+        tx.to
+    } else {
+        tx.from
+    }?;
+    let counterparties = COUNTERPARTIES.lock().unwrap();
+    let known_counterparties = counterparties.get(&tx.involved_account);
+    if known_counterparties.is_some_and(|known| known.contains(&counterparty))
+        || addressbook.contains_key(&counterparty.full_string())
+    {
+        return None;
+    }
+
+    known_counterparties
+        .into_iter()
+        .flatten()
+        .copied()
+        .chain(
+            addressbook
+                .keys()
+                .filter_map(|account| Address::from_str(account).ok()),
+        )
+        .find(|known| is_lookalike(&counterparty, known))
+        .map(Poisoning::Lookalike)
+}
+
+/// Drops the poisoning attempts when configured to, remembering the counterparties of the rest
+pub fn filter(
+    chain: &Chain,
+    interesting_transactions: Vec<InterestingTransaction>,
+    addressbook: &HashMap<String, String>,
+) -> Vec<InterestingTransaction> {
+    if *ADDRESS_POISONING_ACTION == PoisoningAction::None {
+        return interesting_transactions;
+    }
+
+    interesting_transactions
+        .into_iter()
+        .filter(|tx| {
+            if detect(tx, addressbook).is_some() {
+                info!(
+                    "Address poisoning attempt {} on {}",
+                    tx.hash.full_string(),
+                    chain.name
+                );
+                return *ADDRESS_POISONING_ACTION != PoisoningAction::Drop;
+            }
+
+            if let Some(counterparty) = sent_to(tx) {
+                COUNTERPARTIES
+                    .lock()
+                    .unwrap()
+                    .entry(tx.involved_account)
+                    .or_default()
+                    .insert(counterparty);
+            }
+            true
+        })
+        .collect()
+}
+
+/// Warning replacing the message of a poisoning attempt
+pub fn warning(
+    tx: &InterestingTransaction,
+    chain: &Chain,
+    addressbook: &HashMap<String, String>,
+) -> Option<String> {
+    if *ADDRESS_POISONING_ACTION != PoisoningAction::Warn {
+        return None;
+    }
+
+    let account = tx.involved_account.to_label(addressbook);
+    match detect(tx, addressbook)? {
+        Poisoning::ZeroValue => Some(format!(
+            "⚠ Address poisoning attempt on {}: zero-value transfer from {} to {}, do not copy addresses from the history of {}",
+            chain.name,
+            tx.from?.to_label(addressbook),
+            tx.to?.to_label(addressbook),
+            account
+        )),
+        Poisoning::Lookalike(imitated) => Some(format!(
+            "⚠ Address poisoning attempt on {}: {} imitates {}, do not copy addresses from the history of {}",
+            chain.name,
+            if tx.from == Some(tx.involved_account) { tx.to } else { tx.from }?.full_string(),
+            imitated.to_label(addressbook),
+            account
+        )),
+    }
+}
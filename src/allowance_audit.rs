@@ -0,0 +1,306 @@
+use ethers::{
+    core::{
+        abi::{AbiDecode, AbiEncode},
+        types::{Address, Bytes, U256},
+        utils::{format_units, hex},
+    },
+    providers::{Provider, ProviderError},
+};
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::{
+    batch,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    history,
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+    rotki, tenants,
+    throttled_http::ThrottledHttp,
+    token::{self, FromChainAddress, Token},
+};
+use crate::{scale_amount, FullString, ToLabel};
+
+const ALLOWANCE_SELECTOR: &str = "0xdd62ed3e";
+const IS_APPROVED_FOR_ALL_SELECTOR: &str = "0xe985e9c5";
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+/// Time between checks of the exposure to each spender
+const EXPOSURE_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    /// Seconds between the reports of the active allowances of the watched accounts
+    pub static ref ALLOWANCE_REPORT_INTERVAL: Option<Duration> =
+        env::var("ALLOWANCE_REPORT_INTERVAL")
+            .ok()
+            .map(|interval| Duration::from_secs(
+                interval
+                    .parse::<u64>()
+                    .expect("Invalid ALLOWANCE_REPORT_INTERVAL")
+            ))
+            .filter(|interval| !interval.is_zero());
+    /// USD value of the tokens of the watched accounts a single spender can move above which it
+    /// is alerted
+    pub static ref ALLOWANCE_EXPOSURE_THRESHOLD: Option<f64> =
+        env::var("ALLOWANCE_EXPOSURE_THRESHOLD")
+            .ok()
+            .map(|threshold| threshold
+                .parse::<f64>()
+                .expect("Invalid ALLOWANCE_EXPOSURE_THRESHOLD"));
+}
+
+/// Whether the allowances are audited, which needs the history of the approvals
+pub fn is_enabled() -> bool {
+    history::is_enabled()
+        && (ALLOWANCE_REPORT_INTERVAL.is_some() || ALLOWANCE_EXPOSURE_THRESHOLD.is_some())
+}
+
+/// Spender of the allowances of the accounts of a tenant, `None` for the accounts without one
+type TenantSpender = (Option<&'static str>, Address);
+
+/// Approval of a watched account which was not revoked
+struct Allowance {
+    owner: Address,
+    token: Address,
+    spender: Address,
+    /// Tokens the spender can move, `None` for operators of all the NFTs of a collection
+    amount: Option<U256>,
+    /// Balance of the owner, the NFTs held for collections
+    balance: U256,
+}
+
+impl Allowance {
+    /// USD value the spender can move, for priced ERC20 tokens
+    fn exposure(&self, token: &Token, prices: &HashMap<Address, f64>) -> Option<f64> {
+        let amount = self.amount?.min(self.balance);
+        let price = prices.get(&self.token)?;
+        let amount: f64 = format_units(amount, token.decimals).ok()?.parse().ok()?;
+        Some(amount * price)
+    }
+
+    fn describe(
+        &self,
+        chain: &Chain,
+        prices: &HashMap<Address, f64>,
+        addressbook: &HashMap<String, String>,
+    ) -> String {
+        let token = Token::from_chain_address(chain, self.token);
+        let allowance = match self.amount {
+            None => format!("all {} ({} held)", token.symbol, self.balance),
+            Some(amount) => format!(
+                "{} {} ({} held{})",
+                match amount == U256::MAX {
+                    true => "Infinite".to_string(),
+                    false => scale_amount(amount, token.decimals),
+                },
+                token.symbol,
+                scale_amount(self.balance, token.decimals),
+                self.exposure(&token, prices)
+                    .map_or(String::new(), |exposure| format!(
+                        ", {:.2} USD exposed",
+                        exposure
+                    ))
+            ),
+        };
+        format!(
+            "{} → {}: {}",
+            self.owner.to_label(addressbook),
+            self.spender.to_label(addressbook),
+            allowance
+        )
+    }
+}
+
+fn call(to: Address, selector: &str, params: &[Address]) -> serde_json::Value {
+    let data = params.iter().fold(selector.to_string(), |data, param| {
+        data + &hex::encode(param.encode())
+    });
+    json!([{ "to": to, "data": data }, "latest"])
+}
+
+/// Allowances of the approvals in the history which are still active, with all the calls in a
+/// single JSON-RPC batch. Contracts answering `allowance` are ERC20 tokens, the others NFT
+/// collections answering `isApprovedForAll`
+async fn active_allowances(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+) -> Result<Vec<Allowance>, ProviderError> {
+    let approvals = history::approvals(chain);
+    let params: Vec<serde_json::Value> = approvals
+        .iter()
+        .flat_map(|(owner, token, spender)| {
+            [
+                call(*token, ALLOWANCE_SELECTOR, &[*owner, *spender]),
+                call(*token, IS_APPROVED_FOR_ALL_SELECTOR, &[*owner, *spender]),
+                call(*token, BALANCE_OF_SELECTOR, &[*owner]),
+            ]
+        })
+        .collect();
+    let results = batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await?;
+
+    let decode = |result: &Result<Bytes, _>| {
+        result
+            .as_ref()
+            .ok()
+            .and_then(|result| U256::decode(result).ok())
+    };
+    Ok(approvals
+        .into_iter()
+        .zip(results.chunks(3))
+        .filter_map(|((owner, token, spender), calls)| {
+            let amount = match (decode(&calls[0]), decode(&calls[1])) {
+                (Some(allowance), _) if !allowance.is_zero() => Some(allowance),
+                (None, Some(approved)) if !approved.is_zero() => None,
+                _ => return None,
+            };
+            Some(Allowance {
+                owner,
+                token,
+                spender,
+                amount,
+                balance: decode(&calls[2]).unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// Periodically reads the current allowances of the approvals the watched accounts gave, as
+/// stored in the history, reporting them every `ALLOWANCE_REPORT_INTERVAL` and alerting as soon
+/// as the USD value a single spender can move exceeds `ALLOWANCE_EXPOSURE_THRESHOLD`
+pub async fn audit_allowances(chain: Chain, addressbook: Arc<Mutex<HashMap<String, String>>>) {
+    let interval = match (*ALLOWANCE_REPORT_INTERVAL, *ALLOWANCE_EXPOSURE_THRESHOLD) {
+        (Some(report_interval), None) => report_interval,
+        (Some(report_interval), Some(_)) => report_interval.min(EXPOSURE_CHECK_INTERVAL),
+        (None, _) => EXPOSURE_CHECK_INTERVAL,
+    };
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut last_report = Instant::now();
+    // Spenders above the threshold for each tenant, alerted again only once they fall below it
+    let mut alerted_spenders: HashSet<TenantSpender> = HashSet::new();
+
+    loop {
+        sleep(interval).await;
+        if chain_control::is_paused(&chain.name) {
+            continue;
+        }
+
+        let allowances = match active_allowances(&provider, &chain).await {
+            Ok(allowances) => allowances,
+            Err(err) => {
+                log_rpc_error(&chain, "allowances", &err);
+                continue;
+            }
+        };
+        token::prefetch_tokens(
+            &provider,
+            &chain,
+            allowances.iter().map(|allowance| allowance.token).collect(),
+        )
+        .await;
+        let erc20_tokens: Vec<Address> = allowances
+            .iter()
+            .filter(|allowance| allowance.amount.is_some())
+            .map(|allowance| allowance.token)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let prices = rotki::usd_prices(&chain, &erc20_tokens).await;
+        let addressbook = addressbook.lock().unwrap().clone();
+        debug!(
+            "Found {} active allowances on {}",
+            allowances.len(),
+            chain.name
+        );
+
+        let mut notifications = vec![];
+        if let Some(threshold) = *ALLOWANCE_EXPOSURE_THRESHOLD {
+            // Each tenant is only alerted of the exposure of its own accounts
+            let mut exposures: HashMap<TenantSpender, (f64, Vec<&Allowance>)> = HashMap::new();
+            for allowance in &allowances {
+                let token = Token::from_chain_address(&chain, allowance.token);
+                let Some(exposure) = allowance.exposure(&token, &prices) else {
+                    continue;
+                };
+                let spender_exposure = exposures
+                    .entry((tenants::name_of(&allowance.owner), allowance.spender))
+                    .or_default();
+                spender_exposure.0 += exposure;
+                spender_exposure.1.push(allowance);
+            }
+            exposures.retain(|_, (exposure, _)| *exposure > threshold);
+            alerted_spenders.retain(|key| exposures.contains_key(key));
+
+            for ((tenant, spender), (exposure, spender_allowances)) in exposures {
+                if !alerted_spenders.insert((tenant, spender)) {
+                    continue;
+                }
+                warn!(
+                    "{} can move {:.2} USD of the watched accounts on {}",
+                    spender.full_string(),
+                    exposure,
+                    chain.name
+                );
+                let lines: Vec<String> = spender_allowances
+                    .iter()
+                    .map(|allowance| allowance.describe(&chain, &prices, &addressbook))
+                    .collect();
+                notifications.push(Notification {
+                    message: format!(
+                        "⚠ {} can move {:.2} USD of the watched accounts on {}\n{}",
+                        spender.to_label(&addressbook),
+                        exposure,
+                        chain.name,
+                        lines.join("\n")
+                    ),
+                    url: chain.address_url(spender),
+                    severity: Some(Severity::High),
+                    route: tenant.and_then(tenants::route_of_tenant),
+                    image: None,
+                    links: vec![],
+                });
+            }
+        }
+
+        if ALLOWANCE_REPORT_INTERVAL
+            .is_some_and(|report_interval| last_report.elapsed() >= report_interval)
+        {
+            last_report = Instant::now();
+            let mut reports: HashMap<Option<&str>, Vec<String>> = HashMap::new();
+            for allowance in &allowances {
+                reports
+                    .entry(tenants::name_of(&allowance.owner))
+                    .or_default()
+                    .push(allowance.describe(&chain, &prices, &addressbook));
+            }
+            for (tenant, lines) in reports {
+                notifications.push(Notification {
+                    message: format!(
+                        "Active allowances of the watched accounts on {}:\n{}",
+                        chain.name,
+                        lines.join("\n")
+                    ),
+                    url: None,
+                    severity: Some(Severity::Low),
+                    route: tenant.and_then(tenants::route_of_tenant),
+                    image: None,
+                    links: vec![],
+                });
+            }
+        }
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending allowance notification");
+            }
+        }
+    }
+}
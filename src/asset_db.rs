@@ -0,0 +1,245 @@
+use ethers::core::types::{Address, U256};
+use lazy_static::lazy_static;
+use log::info;
+use rusqlite::{named_params, Connection, OpenFlags};
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+use crate::{
+    chain::Chain,
+    token::{self, Token},
+    FullString,
+};
+
+/// Source of the symbol and decimals of the tokens, and of the tokens known by the spam filter
+pub trait AssetDb: Send + Sync {
+    /// Whether the token is known on any chain
+    fn is_known_token(&self, address: Address) -> bool;
+    /// Symbol and decimals of the token on the chain, if known
+    fn token(&self, chain: &Chain, address: Address) -> Option<Token>;
+    /// Whether a known token of any chain has the symbol, which spam tokens impersonate
+    fn is_known_symbol(&self, symbol: &str) -> bool;
+}
+
+#[derive(Debug, EnumString)]
+enum AssetDbKind {
+    Rotki,
+    TokenList,
+    OnChain,
+}
+
+lazy_static! {
+    static ref ASSET_DB_KIND: AssetDbKind =
+        AssetDbKind::from_str(&env::var("ASSET_DB").unwrap_or("Rotki".to_string()))
+            .expect("Invalid ASSET_DB");
+    static ref ASSET_DB: Box<dyn AssetDb> = match *ASSET_DB_KIND {
+        AssetDbKind::Rotki => Box::new(RotkiDb::open(
+            env::var("ROTKI_DB_PATH").unwrap_or("rotki_db.db".to_string())
+        )),
+        AssetDbKind::TokenList => Box::new(TokenList::load(
+            &env::var("TOKEN_LIST_PATH")
+                .expect("TOKEN_LIST_PATH is required with a TokenList ASSET_DB")
+        )),
+        AssetDbKind::OnChain => Box::new(OnChain),
+    };
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Whether the test running on the thread uses the `OnChain` asset DB instead of the configured one
+    static TEST_ON_CHAIN: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Uses the `OnChain` asset DB in the test running on the current thread, so that no asset DB file
+/// is needed
+#[cfg(test)]
+pub(crate) fn set_on_chain_for_test() {
+    TEST_ON_CHAIN.set(true);
+}
+
+fn kind() -> &'static AssetDbKind {
+    #[cfg(test)]
+    if TEST_ON_CHAIN.get() {
+        return &AssetDbKind::OnChain;
+    }
+    &ASSET_DB_KIND
+}
+
+/// The configured asset DB
+pub fn asset_db() -> &'static dyn AssetDb {
+    #[cfg(test)]
+    if TEST_ON_CHAIN.get() {
+        return &OnChain;
+    }
+    ASSET_DB.as_ref()
+}
+
+/// Loads the asset DB, failing on startup when it can't be read
+pub fn load() {
+    let _ = asset_db();
+    info!("Using the {:?} asset DB", kind());
+}
+
+/// Whether the known tokens are curated, rather than any contract reporting a symbol
+pub fn is_curated() -> bool {
+    !matches!(kind(), AssetDbKind::OnChain)
+}
+
+/// Tokens of the [rotki assets](https://github.com/rotki/assets) DB
+struct RotkiDb {
+    path: String,
+}
+
+impl RotkiDb {
+    fn open(path: String) -> Self {
+        Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .and_then(|connection| {
+                connection.query_row("SELECT COUNT(1) FROM evm_tokens", [], |_| Ok(()))
+            })
+            .expect("Could not read the rotki DB");
+        RotkiDb { path }
+    }
+
+    fn connection(&self) -> Connection {
+        Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap()
+    }
+}
+
+impl AssetDb for RotkiDb {
+    fn is_known_token(&self, address: Address) -> bool {
+        let connection = self.connection();
+        let query = "SELECT COUNT(1)
+                FROM evm_tokens
+                WHERE
+                  lower(address) = lower(:address)";
+        let mut statement = connection.prepare(query).unwrap();
+
+        let res: Result<bool, rusqlite::Error> = statement
+            .query_row(named_params! {":address": address.full_string()}, |row| {
+                Ok(1 == row.get::<usize, i32>(0).unwrap())
+            });
+
+        res.unwrap()
+    }
+
+    fn token(&self, chain: &Chain, address: Address) -> Option<Token> {
+        let connection = self.connection();
+        let query = "SELECT
+               decimals,
+               symbol
+            FROM evm_tokens
+            JOIN common_asset_details ON evm_tokens.identifier = common_asset_details.identifier
+            WHERE
+              lower(address) = lower(:address) AND
+              chain = :chain";
+        let mut statement = connection.prepare(query).unwrap();
+
+        statement
+            .query_row(
+                named_params! {":address": address.full_string(),":chain": chain.id.unwrap().as_u64()},
+                |row| {
+                    Ok(Token {
+                        decimals: row.get(0).unwrap(),
+                        symbol: row.get(1).unwrap(),
+                    })
+                },
+            )
+            .ok()
+    }
+
+    fn is_known_symbol(&self, symbol: &str) -> bool {
+        let connection = self.connection();
+        let query = "SELECT COUNT(1)
+                FROM evm_tokens
+                JOIN common_asset_details ON evm_tokens.identifier = common_asset_details.identifier
+                WHERE
+                  lower(symbol) = lower(:symbol)";
+        let mut statement = connection.prepare(query).unwrap();
+
+        statement
+            .query_row(named_params! {":symbol": symbol}, |row| {
+                row.get::<usize, i32>(0)
+            })
+            .is_ok_and(|count| count > 0)
+    }
+}
+
+#[derive(DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct TokenListEntry {
+    chain_id: u64,
+    address: Address,
+    symbol: String,
+    decimals: u32,
+}
+
+#[derive(DeserializeMacro)]
+struct TokenListFile {
+    tokens: Vec<TokenListEntry>,
+}
+
+/// Tokens of a [token list](https://tokenlists.org/) file
+struct TokenList {
+    tokens: HashMap<(U256, Address), Token>,
+}
+
+impl TokenList {
+    fn load(path: &str) -> Self {
+        let file = std::fs::File::open(path).expect("Could not open token list file.");
+        let token_list: TokenListFile =
+            serde_json::from_reader(file).expect("Could not read token list.");
+        TokenList {
+            tokens: token_list
+                .tokens
+                .into_iter()
+                .map(|entry| {
+                    (
+                        (U256::from(entry.chain_id), entry.address),
+                        Token {
+                            symbol: entry.symbol,
+                            decimals: entry.decimals,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl AssetDb for TokenList {
+    fn is_known_token(&self, address: Address) -> bool {
+        self.tokens
+            .keys()
+            .any(|(_, token_address)| *token_address == address)
+    }
+
+    fn token(&self, chain: &Chain, address: Address) -> Option<Token> {
+        self.tokens.get(&(chain.id.unwrap(), address)).cloned()
+    }
+
+    fn is_known_symbol(&self, symbol: &str) -> bool {
+        self.tokens
+            .values()
+            .any(|token| token.symbol.eq_ignore_ascii_case(symbol))
+    }
+}
+
+/// Tokens read from their contracts only, any contract reporting a symbol and decimals is known
+struct OnChain;
+
+impl AssetDb for OnChain {
+    fn is_known_token(&self, address: Address) -> bool {
+        token::has_onchain_metadata(address)
+    }
+
+    fn token(&self, chain: &Chain, address: Address) -> Option<Token> {
+        token::onchain_token(chain, address)
+    }
+
+    fn is_known_symbol(&self, _symbol: &str) -> bool {
+        false
+    }
+}
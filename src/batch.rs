@@ -0,0 +1,106 @@
+use ethers::providers::{HttpClientError, JsonRpcError, Middleware, Provider, ProviderError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::Value;
+use std::time::Instant;
+
+use crate::{metrics_middleware::MetricsMiddleware, throttled_http::ThrottledHttp};
+
+const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(SerializeMacro)]
+struct BatchedRequest<'a, P: Serialize> {
+    jsonrpc: &'static str,
+    id: usize,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(DeserializeMacro)]
+struct BatchedResponse {
+    id: usize,
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+/// Calls the same JSON-RPC method once per params, sending the calls as JSON-RPC batches instead
+/// of one request per call. Results are returned in the same order as the params, failing if any
+/// of the calls failed
+pub async fn batch_request<P: Serialize, R: DeserializeOwned>(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    method: &str,
+    params: &[P],
+) -> Result<Vec<R>, ProviderError> {
+    batch_request_each(provider, method, params)
+        .await?
+        .into_iter()
+        .collect()
+}
+
+/// Same as [`batch_request`], but keeping the result of each call, for calls which are expected
+/// to fail sometimes (e.g. `eth_call` to contracts not implementing the called function)
+pub async fn batch_request_each<P: Serialize, R: DeserializeOwned>(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    method: &str,
+    params: &[P],
+) -> Result<Vec<Result<R, ProviderError>>, ProviderError> {
+    let mut results = Vec::with_capacity(params.len());
+
+    for params_chunk in params.chunks(MAX_BATCH_SIZE) {
+        let requests: Vec<BatchedRequest<&P>> = params_chunk
+            .iter()
+            .enumerate()
+            .map(|(id, params)| BatchedRequest {
+                jsonrpc: "2.0",
+                id,
+                method,
+                params,
+            })
+            .collect();
+
+        let now = Instant::now();
+        let responses = post_batch(provider.provider().as_ref(), &requests).await;
+        provider.record(method, now.elapsed(), responses.is_ok());
+        let mut responses = responses?;
+
+        if responses.len() != requests.len() {
+            return Err(ProviderError::CustomError(format!(
+                "Sent {} batched {} calls but got {} responses",
+                requests.len(),
+                method,
+                responses.len()
+            )));
+        }
+
+        // Batched responses can be returned in any order
+        responses.sort_by_key(|response| response.id);
+        // Errors keep their JSON-RPC code when they have one, to be classified like the others
+        results.extend(responses.into_iter().map(|response| match response.error {
+            Some(error) => Err(
+                match serde_json::from_value::<JsonRpcError>(error.clone()) {
+                    Ok(error) => HttpClientError::JsonRpcError(error).into(),
+                    Err(_) => ProviderError::CustomError(format!(
+                        "Batched {} call failed: {}",
+                        method, error
+                    )),
+                },
+            ),
+            None => Ok(serde_json::from_value(
+                response.result.unwrap_or(Value::Null),
+            )?),
+        }));
+    }
+
+    Ok(results)
+}
+
+async fn post_batch<P: Serialize>(
+    transport: &ThrottledHttp,
+    requests: &[BatchedRequest<'_, P>],
+) -> Result<Vec<BatchedResponse>, HttpClientError> {
+    let body = transport.post(&requests, requests.len() as u32).await?;
+    serde_json::from_slice(&body).map_err(|err| HttpClientError::SerdeJson {
+        err,
+        text: String::from_utf8_lossy(&body).to_string(),
+    })
+}
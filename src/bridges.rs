@@ -0,0 +1,563 @@
+use ethers::{
+    core::{
+        types::{Address, H256, U256},
+        utils::format_units,
+    },
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use rusqlite::named_params;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    chain::Chain,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    register_protocol_label,
+    state_db::CONNECTION,
+    tenants,
+    throttled_http::ThrottledHttp,
+    token::{FromChainAddress, Token},
+    transaction_details,
+};
+use crate::{format_duration, scale_amount, FullString, ToLabel};
+
+/// Canonical bridges, by chain id. Tokens sent to them leave the chain
+const BRIDGES: [(u64, &str, &str); 20] = [
+    (
+        1,
+        "0x99C9fc46f92E8a1c0deC1b1747d010903E884bE1",
+        "Optimism bridge",
+    ),
+    (
+        1,
+        "0x3154Cf16ccdb4C6d922629664174b904d80F2C35",
+        "Base bridge",
+    ),
+    (
+        1,
+        "0x72Ce9c846789fdB6fC1f34aC4AD25Dd9ef7031ef",
+        "Arbitrum bridge",
+    ),
+    (
+        1,
+        "0xa3A7B6F88361F48403514059F1F16C8E78d60EeC",
+        "Arbitrum bridge",
+    ),
+    (
+        1,
+        "0x4Dbd4fc535Ac27206064B68FfCf827b0A60BAB3f",
+        "Arbitrum bridge",
+    ),
+    (
+        1,
+        "0x88ad09518695c6c3712AC10a214bE5109a655671",
+        "Gnosis Omnibridge",
+    ),
+    (
+        1,
+        "0x4aa42145Aa6Ebf72e164C9bBC74fbD3788045016",
+        "Gnosis xDai bridge",
+    ),
+    (1, "0xBd3fa81B58Ba92a82136038B25aDec7066af3155", "CCTP"),
+    (
+        10,
+        "0x4200000000000000000000000000000000000010",
+        "OP Stack bridge",
+    ),
+    (
+        10,
+        "0x4200000000000000000000000000000000000016",
+        "OP Stack bridge",
+    ),
+    (10, "0x2B4069517957735bE00ceE0fadAE88a26365528f", "CCTP"),
+    (
+        100,
+        "0xf6A78083ca3e2a662D6dd1703c939c8aCE2e268d",
+        "Gnosis Omnibridge",
+    ),
+    (
+        100,
+        "0x7301CFA0e1756B71869E93d4e4Dca5c7d0eb0AA6",
+        "Gnosis xDai bridge",
+    ),
+    (
+        8453,
+        "0x4200000000000000000000000000000000000010",
+        "OP Stack bridge",
+    ),
+    (
+        8453,
+        "0x4200000000000000000000000000000000000016",
+        "OP Stack bridge",
+    ),
+    (8453, "0x1682Ae6375C4E4A97e4B583BC394c861A46D8962", "CCTP"),
+    (
+        42161,
+        "0x5288c571Fd7aD117beA99bF60FE0846C4E84F933",
+        "Arbitrum bridge",
+    ),
+    (
+        42161,
+        "0x09e9222E96E7B4AE2a407B98d48e330053351EEe",
+        "Arbitrum bridge",
+    ),
+    (
+        42161,
+        "0x0000000000000000000000000000000000000064",
+        "Arbitrum bridge",
+    ),
+    (42161, "0x19330d10D9Cc8751218eaf51E8885D058642E08A", "CCTP"),
+];
+/// Arrivals received less than this share below the departure, after the bridge fees, match it
+const FEE_TOLERANCE: f64 = 0.01;
+/// Longest time a departure waits for its arrival, optimistic rollup withdrawals take 7 days
+const MAX_BRIDGE_TIME: Duration = Duration::from_secs(8 * 86400);
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// Seconds departures are held waiting for their arrival before being notified on their own.
+    /// Bridges are only correlated when set
+    pub static ref BRIDGE_NOTIFICATION_DELAY: Option<Duration> =
+        env::var("BRIDGE_NOTIFICATION_DELAY").ok().map(|delay| {
+            Duration::from_secs(
+                delay
+                    .parse::<u64>()
+                    .expect("Invalid BRIDGE_NOTIFICATION_DELAY"),
+            )
+        });
+    static ref BRIDGE_NAMES: HashMap<(u64, Address), &'static str> = BRIDGES
+        .iter()
+        .map(|(chain_id, address, name)| ((*chain_id, Address::from_str(address).unwrap()), *name))
+        .collect();
+    /// Tokens sent to a bridge by the watched accounts, waiting for their arrival
+    static ref DEPARTURES: Mutex<Vec<Departure>> = Mutex::new(vec![]);
+}
+
+pub fn is_enabled() -> bool {
+    BRIDGE_NOTIFICATION_DELAY.is_some()
+}
+
+/// Labels the bridges, so they are named in every notification, and reads the departures kept
+/// in the state DB
+pub fn load() {
+    for ((_, address), name) in BRIDGE_NAMES.iter() {
+        register_protocol_label(address, name.to_string());
+    }
+    if !is_enabled() {
+        return;
+    }
+
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare(
+            "SELECT chain, hash, account, bridge, symbol, amount, transferred, sent_at,
+            held_notification FROM bridge_departures",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((
+                        (
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, String>(3)?,
+                        ),
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, f64>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, u64>(7)?,
+                        row.get::<_, Option<String>>(8)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Error while reading the bridge departures: {}", err);
+            return;
+        }
+    };
+
+    let mut departures = DEPARTURES.lock().unwrap();
+    for ((chain, hash, account, bridge), symbol, amount, transferred, sent_at, held_notification) in
+        rows
+    {
+        let (Ok(hash), Ok(account), Some(bridge)) = (
+            H256::from_str(&hash),
+            Address::from_str(&account),
+            BRIDGE_NAMES.values().find(|name| **name == bridge),
+        ) else {
+            error!("Invalid bridge departure {} on {}", hash, chain);
+            continue;
+        };
+        // The route isn't stored, held notifications are sent to the account's tenant
+        let held_notification = held_notification
+            .and_then(|notification| serde_json::from_str::<Notification>(&notification).ok())
+            .map(|notification| Notification {
+                route: tenants::route_of(&account),
+                ..notification
+            });
+        departures.push(Departure {
+            chain,
+            hash,
+            account,
+            bridge,
+            symbol,
+            amount,
+            transferred,
+            sent_at,
+            held_notification,
+        });
+    }
+    info!("Loaded {} bridge departures", departures.len());
+}
+
+fn store(departure: &Departure) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR REPLACE INTO bridge_departures (chain, hash, account, bridge, symbol, amount,
+        transferred, sent_at, held_notification)
+        VALUES (:chain, :hash, :account, :bridge, :symbol, :amount, :transferred, :sent_at,
+        :held_notification)",
+        named_params! {
+            ":chain": departure.chain,
+            ":hash": departure.hash.full_string(),
+            ":account": departure.account.full_string(),
+            ":bridge": departure.bridge,
+            ":symbol": departure.symbol,
+            ":amount": departure.amount,
+            ":transferred": departure.transferred,
+            ":sent_at": departure.sent_at,
+            ":held_notification": departure
+                .held_notification
+                .as_ref()
+                .and_then(|notification| serde_json::to_string(notification).ok()),
+        },
+    ) {
+        error!(
+            "Error while storing bridge departure {}: {}",
+            departure.hash.full_string(),
+            err
+        );
+    }
+}
+
+fn remove(departure: &Departure) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "DELETE FROM bridge_departures WHERE chain = :chain AND hash = :hash AND account = :account",
+        named_params! {
+            ":chain": departure.chain,
+            ":hash": departure.hash.full_string(),
+            ":account": departure.account.full_string(),
+        },
+    ) {
+        error!(
+            "Error while removing bridge departure {}: {}",
+            departure.hash.full_string(),
+            err
+        );
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+struct Departure {
+    chain: String,
+    hash: H256,
+    account: Address,
+    bridge: &'static str,
+    /// Symbol of the token, `None` for native transfers
+    symbol: Option<String>,
+    amount: f64,
+    /// Amount and symbol, as notified
+    transferred: String,
+    /// Unix timestamp the departure was seen at
+    sent_at: u64,
+    /// Notification of the departure, until it is sent on its own
+    held_notification: Option<Notification>,
+}
+
+/// Bridged tokens can have a different symbol on each chain, e.g. `USDC.e`
+fn same_token(departure: Option<&str>, arrival: Option<&str>) -> bool {
+    let base_symbol = |symbol: &str| symbol.split('.').next().unwrap_or_default().to_uppercase();
+    match (departure, arrival) {
+        (Some(departure), Some(arrival)) => base_symbol(departure) == base_symbol(arrival),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Symbol and amount, in units, of a transfer of native or ERC20 tokens
+fn transferred(chain: &Chain, tx: &InterestingTransaction) -> Option<(Option<String>, U256, u32)> {
+    match tx.kind {
+        InterestingTransactionKind::Send => Some((None, tx.amount?, chain.native_decimals)),
+        InterestingTransactionKind::Transfer if tx.token_id.is_none() => {
+            let token = Token::from_chain_address(chain, tx.token?);
+            Some((Some(token.symbol), tx.amount?, token.decimals))
+        }
+        _ => None,
+    }
+}
+
+/// Bridge called by each transaction sent by a watched account, with the native value sent to it
+pub type BridgeCalls = HashMap<H256, (&'static str, U256)>;
+
+fn is_burn(tx: &InterestingTransaction) -> bool {
+    tx.kind == InterestingTransactionKind::Transfer
+        && tx.from == Some(tx.involved_account)
+        && tx.to.is_some_and(|to| to.is_zero())
+}
+
+/// Fills the amount of the native sends found without it, and finds the bridges called by the
+/// transactions the watched accounts sent to one, such as the OP Stack withdrawals burning the
+/// tokens or the deposits of native tokens through a bridge's function
+pub async fn fetch_details(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: &mut [InterestingTransaction],
+) -> BridgeCalls {
+    let Some(chain_id) = chain.id.filter(|_| is_enabled()) else {
+        return HashMap::new();
+    };
+    let hashes: HashSet<H256> = interesting_transactions
+        .iter()
+        .filter(|tx| match tx.kind {
+            InterestingTransactionKind::Send => tx.amount.is_none(),
+            InterestingTransactionKind::Other => true,
+            _ => is_burn(tx),
+        })
+        .map(|tx| tx.hash)
+        .collect();
+    let transactions = transaction_details::fetch_transactions(provider, chain, hashes).await;
+
+    let mut bridge_calls = HashMap::new();
+    for tx in interesting_transactions.iter_mut() {
+        let Some(transaction) = transactions.get(&tx.hash) else {
+            continue;
+        };
+        if tx.kind == InterestingTransactionKind::Send {
+            tx.amount = tx.amount.or(Some(transaction.value));
+        }
+        if transaction.from != tx.involved_account {
+            continue;
+        }
+        if let Some(bridge) = transaction
+            .to
+            .and_then(|to| BRIDGE_NAMES.get(&(chain_id.as_u64(), to)))
+        {
+            bridge_calls.insert(tx.hash, (*bridge, transaction.value));
+        }
+    }
+    bridge_calls
+}
+
+/// Holds the notification of tokens sent to a bridge until they arrive on another chain, and
+/// replaces the notification of their arrival with a single one of the whole bridging. Returns
+/// the notification to send now, if any. The tokens burnt and the native tokens sent by the
+/// transactions calling a bridge, found by `fetch_details`, also leave the chain
+pub fn correlate(
+    chain: &Chain,
+    tx: &InterestingTransaction,
+    notification: Notification,
+    bridge_calls: &BridgeCalls,
+    addressbook: &HashMap<String, String>,
+) -> Option<Notification> {
+    if !is_enabled() {
+        return Some(notification);
+    }
+    let Some(chain_id) = chain.id else {
+        return Some(notification);
+    };
+    let bridge_call = bridge_calls.get(&tx.hash);
+    let (transferred, bridge) = match tx.kind {
+        InterestingTransactionKind::Other => (
+            bridge_call
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(_, value)| (None, *value, chain.native_decimals)),
+            bridge_call.map(|(bridge, _)| bridge),
+        ),
+        _ if tx.from != Some(tx.involved_account) => (transferred(chain, tx), None),
+        _ if is_burn(tx) => (
+            transferred(chain, tx),
+            bridge_call.map(|(bridge, _)| bridge),
+        ),
+        _ => (
+            transferred(chain, tx),
+            tx.to
+                .and_then(|to| BRIDGE_NAMES.get(&(chain_id.as_u64(), to))),
+        ),
+    };
+    let Some((symbol, amount, decimals)) = transferred else {
+        return Some(notification);
+    };
+    let transferred = format!(
+        "{} {}",
+        scale_amount(amount, decimals),
+        symbol.as_ref().unwrap_or(&chain.native_symbol)
+    );
+    let amount: f64 = format_units(amount, decimals)
+        .ok()
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or_default();
+
+    if let Some(bridge) = bridge {
+        let from = tx.involved_account;
+        info!(
+            "{} sent {} to {} on {}",
+            from.full_string(),
+            transferred,
+            bridge,
+            chain.name
+        );
+        let (held_notification, notification) = match BRIDGE_NOTIFICATION_DELAY.unwrap().is_zero() {
+            true => (None, Some(notification)),
+            false => (Some(notification), None),
+        };
+        let departure = Departure {
+            chain: chain.name.clone(),
+            hash: tx.hash,
+            account: from,
+            bridge,
+            symbol,
+            amount,
+            transferred,
+            sent_at: now(),
+            held_notification,
+        };
+        let mut departures = DEPARTURES.lock().unwrap();
+        // Transactions reprocessed after a reorg or a restart are already held
+        if !departures.iter().any(|held| {
+            held.chain == departure.chain
+                && held.hash == departure.hash
+                && held.account == departure.account
+        }) {
+            store(&departure);
+            departures.push(departure);
+        }
+        return notification;
+    }
+    let (Some(from), Some(to)) = (tx.from, tx.to) else {
+        return Some(notification);
+    };
+
+    // Bridges mint the tokens they deliver, or send them from their contracts
+    if to != tx.involved_account
+        || !(from.is_zero() || BRIDGE_NAMES.contains_key(&(chain_id.as_u64(), from)))
+    {
+        return Some(notification);
+    }
+    let departure = {
+        let mut departures = DEPARTURES.lock().unwrap();
+        let position = departures.iter().position(|departure| {
+            departure.chain != chain.name
+                && departure.account == to
+                && same_token(departure.symbol.as_deref(), symbol.as_deref())
+                && amount <= departure.amount
+                && amount >= departure.amount * (1.0 - FEE_TOLERANCE)
+        });
+        match position {
+            Some(position) => departures.remove(position),
+            None => return Some(notification),
+        }
+    };
+    remove(&departure);
+
+    info!(
+        "{} bridged {} from {} to {}",
+        to.full_string(),
+        departure.transferred,
+        departure.chain,
+        chain.name
+    );
+    let elapsed = format_duration(now().saturating_sub(departure.sent_at) as f64);
+    let message = match departure.held_notification.is_some() {
+        true => format!(
+            "{} bridged {} from {} → {} through the {}, arrived in {}",
+            to.to_label(addressbook),
+            departure.transferred,
+            departure.chain,
+            chain.name,
+            departure.bridge,
+            elapsed
+        ),
+        false => format!(
+            "{} bridged by {} from {} through the {} arrived on {} after {}",
+            departure.transferred,
+            to.to_label(addressbook),
+            departure.chain,
+            departure.bridge,
+            chain.name,
+            elapsed
+        ),
+    };
+    // The bridging is as relevant as its departure
+    let url = notification.url.clone();
+    let links = notification.links.clone();
+    Some(Notification {
+        message,
+        url,
+        links,
+        ..departure.held_notification.unwrap_or(notification)
+    })
+}
+
+/// Sends the notifications of the departures which didn't arrive within
+/// `BRIDGE_NOTIFICATION_DELAY`, and forgets the departures which never arrived
+pub async fn send_held_departures() {
+    let delay = BRIDGE_NOTIFICATION_DELAY.unwrap();
+    loop {
+        sleep(FLUSH_INTERVAL).await;
+
+        let notifications: Vec<Notification> = {
+            let now = now();
+            let mut departures = DEPARTURES.lock().unwrap();
+            departures.retain(|departure| {
+                let expired = now.saturating_sub(departure.sent_at) >= MAX_BRIDGE_TIME.as_secs();
+                if expired {
+                    remove(departure);
+                }
+                !expired
+            });
+            departures
+                .iter_mut()
+                .filter(|departure| now.saturating_sub(departure.sent_at) >= delay.as_secs())
+                .filter_map(|departure| {
+                    let notification = departure.held_notification.take()?;
+                    store(departure);
+                    Some(notification)
+                })
+                .collect()
+        };
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending bridge notification");
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use lazy_static::lazy_static;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::env;
+
+use crate::{
+    chain::Chain, interesting_transaction::InterestingTransaction, rules::TransactionConditions,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, DeserializeMacro, strum_macros::Display)]
+pub enum Category {
+    Income,
+    Expense,
+    Transfer,
+    Fee,
+    Trade,
+}
+
+/// Accounting category to attach to the transactions matching every configured condition
+#[derive(Debug, DeserializeMacro)]
+pub struct CategoryRule {
+    category: Category,
+    #[serde(flatten)]
+    conditions: TransactionConditions,
+}
+
+lazy_static! {
+    static ref CATEGORY_RULES: Vec<CategoryRule> = match env::var("CATEGORY_RULES_PATH") {
+        Ok(category_rules_path) => {
+            let file = std::fs::File::open(category_rules_path)
+                .expect("Could not open category rules file.");
+            serde_yaml::from_reader(file).expect("Could not read category rules.")
+        }
+        Err(_) => vec![],
+    };
+}
+
+pub trait Categorize {
+    fn category(&self, chain: &Chain) -> Option<Category>;
+}
+
+impl Categorize for InterestingTransaction {
+    /// Category of the first matching rule
+    fn category(&self, chain: &Chain) -> Option<Category> {
+        CATEGORY_RULES
+            .iter()
+            .find(|rule| rule.conditions.matches(self, chain))
+            .map(|rule| rule.category)
+    }
+}
@@ -1,20 +1,35 @@
-use ethers::core::types::U256;
+use ethers::core::types::{Address, H256, U256};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
 use std::env;
 use std::str::FromStr;
 use std::time::Duration;
 use strum_macros::EnumString;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug)]
+use crate::chain_presets::{self, ChainPreset};
+use crate::FullString;
+
+#[derive(Clone, Debug, EnumString, DeserializeMacro, SerializeMacro, ToSchema)]
 pub enum ChainMode {
     Blocks,
     Events,
+    Push,
 }
 
-#[derive(Clone, Debug, EnumString)]
+#[derive(Clone, Debug, EnumString, DeserializeMacro, ToSchema)]
 pub enum SpamFilterLevel {
     None,
     KnownAssets,
     SelfSubmittedTxs,
+    /// Incoming token transfers are spam when their token scores as airdropped spam
+    Heuristic,
+}
+
+#[derive(Clone, Debug)]
+pub enum Confirmations {
+    Blocks(u64),
+    Safe,
+    Finalized,
 }
 
 #[derive(Clone, Debug)]
@@ -23,9 +38,208 @@ pub struct Chain {
     pub name: String,
     pub blocktime: Duration,
     pub explorer: Option<String>,
+    /// Template of the explorer links of transactions, with a `{hash}` placeholder
+    pub explorer_tx_url: Option<String>,
+    /// Template of the explorer links of addresses, with an `{address}` placeholder
+    pub explorer_address_url: Option<String>,
+    pub rpc: String,
+    pub mode: ChainMode,
+    pub spam_filter_level: SpamFilterLevel,
+    pub confirmations: Confirmations,
+    pub server_side_filtering: bool,
+    pub concurrency: u64,
+    pub max_rps: Option<u32>,
+    pub webhook_secret: Option<String>,
+    pub max_block_age: Duration,
+    pub rpc_headers: Vec<(String, String)>,
+    /// Username and password for RPCs behind basic auth
+    pub rpc_basic_auth: Option<(String, String)>,
+    /// Etherscan compatible API, for the explorer integrations
+    pub explorer_api: Option<String>,
+    pub explorer_api_key: Option<String>,
+    /// Safe Transaction Service, for the pending proposals of the watched Safes
+    pub safe_transaction_service: Option<String>,
+    /// Symbol of the native currency, e.g. `xDAI`
+    pub native_symbol: String,
+    pub native_decimals: u32,
+    /// Blocks per `eth_getLogs` request in `Events` mode
+    pub max_block_range: u64,
+    /// Blocks `Events` mode stays behind the head, as some RPCs don't serve the logs of the
+    /// latest block right away
+    pub head_delay: u64,
+    /// Challenge period of the withdrawals to L1, instead of `WITHDRAWAL_CHALLENGE_PERIOD`
+    pub withdrawal_challenge_period: Option<Duration>,
+}
+
+impl Chain {
+    /// Explorer link of a transaction
+    pub fn tx_url(&self, hash: H256) -> Option<String> {
+        self.explorer_tx_url
+            .as_ref()
+            .map(|template| template.replace("{hash}", &hash.full_string()))
+    }
+
+    /// Explorer link of an account or contract
+    pub fn address_url(&self, address: Address) -> Option<String> {
+        self.explorer_address_url
+            .as_ref()
+            .map(|template| template.replace("{address}", &address.full_string()))
+    }
+}
+
+/// Chain added through the API, the settings it lacks take their env var defaults
+#[derive(Clone, Debug, DeserializeMacro, ToSchema)]
+pub struct ChainDefinition {
+    pub name: String,
+    pub id: Option<u64>,
     pub rpc: String,
+    /// Milliseconds, as `CHAIN_BLOCKTME`. Optional for the chains with a preset
+    pub blocktime: Option<u64>,
+    #[serde(default = "default_mode")]
     pub mode: ChainMode,
+    #[serde(default = "default_spam_filter_level")]
     pub spam_filter_level: SpamFilterLevel,
+    pub explorer: Option<String>,
+    /// Etherscan compatible API, as `CHAIN_EXPLORER_API`, queried with `EXPLORER_API_KEY`
+    pub explorer_api: Option<String>,
+    pub native_symbol: Option<String>,
+    #[serde(default = "default_native_decimals")]
+    pub native_decimals: u32,
+    pub max_block_range: Option<u64>,
+    #[serde(default = "default_head_delay")]
+    pub head_delay: u64,
+}
+
+pub(crate) fn default_mode() -> ChainMode {
+    ChainMode::Blocks
+}
+
+pub(crate) fn default_spam_filter_level() -> SpamFilterLevel {
+    SpamFilterLevel::KnownAssets
+}
+
+fn default_native_symbol() -> String {
+    "native".to_string()
+}
+
+pub(crate) fn default_native_decimals() -> u32 {
+    18
+}
+
+fn default_max_block_range() -> u64 {
+    100
+}
+
+pub(crate) fn default_head_delay() -> u64 {
+    1
+}
+
+/// Head delays beyond this many blocks are most likely a duration mistaken for a block count
+const MAX_HEAD_DELAY: u64 = 1000;
+
+fn is_valid_head_delay(head_delay: u64) -> bool {
+    head_delay <= MAX_HEAD_DELAY
+}
+
+/// Etherscan style links of the explorer, unless a template is configured
+fn explorer_url_template(
+    template: Option<String>,
+    explorer: &Option<String>,
+    path: &str,
+) -> Option<String> {
+    template.or(explorer
+        .as_ref()
+        .map(|explorer| format!("{}/{}", explorer.trim_end_matches('/'), path)))
+}
+
+impl ChainDefinition {
+    pub fn into_chain(self) -> Result<Chain, String> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err("Invalid chain name\n".to_string());
+        }
+        if reqwest::Url::parse(&self.rpc).is_err() {
+            return Err("Invalid RPC URL\n".to_string());
+        }
+        // Looked up by id first, the name is free to be anything
+        let preset = self
+            .id
+            .and_then(|id| chain_presets::find(&id.to_string()))
+            .or_else(|| chain_presets::find(&self.name));
+        let blocktime = match self.blocktime.or(preset.map(|preset| preset.blocktime)) {
+            Some(blocktime) if blocktime > 0 => blocktime,
+            Some(_) => return Err("Invalid blocktime\n".to_string()),
+            None => return Err("Missing blocktime\n".to_string()),
+        };
+        if self.max_block_range == Some(0) {
+            return Err("Invalid max_block_range\n".to_string());
+        }
+        if !is_valid_head_delay(self.head_delay) {
+            return Err("Invalid head_delay\n".to_string());
+        }
+        if matches!(self.mode, ChainMode::Push) {
+            return Err("Push mode chains can only be configured with env vars\n".to_string());
+        }
+
+        let explorer = self
+            .explorer
+            .or(preset.map(|preset| preset.explorer.to_string()));
+
+        Ok(Chain {
+            id: self.id.or(preset.map(|preset| preset.id)).map(U256::from),
+            name: self.name,
+            blocktime: Duration::from_millis(blocktime),
+            explorer_tx_url: explorer_url_template(None, &explorer, "tx/{hash}"),
+            explorer_address_url: explorer_url_template(None, &explorer, "address/{address}"),
+            explorer,
+            rpc: self.rpc,
+            mode: self.mode,
+            spam_filter_level: self.spam_filter_level,
+            confirmations: Confirmations::Blocks(0),
+            server_side_filtering: false,
+            concurrency: 1,
+            max_rps: None,
+            webhook_secret: None,
+            max_block_age: Duration::from_secs(600),
+            rpc_headers: vec![],
+            rpc_basic_auth: None,
+            explorer_api: self.explorer_api,
+            explorer_api_key: env::var("EXPLORER_API_KEY").ok(),
+            safe_transaction_service: None,
+            native_symbol: self
+                .native_symbol
+                .or(preset.map(|preset| preset.native_symbol.to_string()))
+                .unwrap_or_else(default_native_symbol),
+            native_decimals: self.native_decimals,
+            max_block_range: self
+                .max_block_range
+                .or(preset.map(|preset| preset.max_block_range))
+                .unwrap_or_else(default_max_block_range),
+            head_delay: self.head_delay,
+            withdrawal_challenge_period: None,
+        })
+    }
+}
+
+/// Gnosis on a local RPC with the fields of `definition` on top, for the tests
+#[cfg(test)]
+pub(crate) fn test_chain(definition: serde_json::Value) -> Chain {
+    let mut fields = serde_json::json!({
+        "name": "Gnosis",
+        "rpc": "http://127.0.0.1:8545",
+    });
+    fields
+        .as_object_mut()
+        .unwrap()
+        .extend(definition.as_object().unwrap().clone());
+    serde_json::from_value::<ChainDefinition>(fields)
+        .unwrap()
+        .into_chain()
+        .unwrap()
 }
 
 pub trait EnvInitializable {
@@ -43,38 +257,192 @@ impl EnvInitializable for Chain {
         let chain_name_var = format!("CHAIN_NAME{}", clean_sufix);
         let chain_blocktime_var = format!("CHAIN_BLOCKTME{}", clean_sufix);
         let chain_explorer_var = format!("CHAIN_EXPLORER{}", clean_sufix);
+        let chain_explorer_tx_url_var = format!("CHAIN_EXPLORER_TX_URL{}", clean_sufix);
+        let chain_explorer_address_url_var = format!("CHAIN_EXPLORER_ADDRESS_URL{}", clean_sufix);
         let chain_rpc_var = format!("CHAIN_RPC{}", clean_sufix);
         let chain_mode_var = format!("CHAIN_MODE{}", clean_sufix);
         let chain_spam_filter_level_var = format!("CHAIN_SPAM_FILTER_LEVEL{}", clean_sufix);
+        let chain_confirmations_var = format!("CHAIN_CONFIRMATIONS{}", clean_sufix);
+        let chain_server_side_filtering_var = format!("CHAIN_SERVER_SIDE_FILTERING{}", clean_sufix);
+        let chain_concurrency_var = format!("CHAIN_CONCURRENCY{}", clean_sufix);
+        let chain_max_rps_var = format!("CHAIN_MAX_RPS{}", clean_sufix);
+        let chain_webhook_secret_var = format!("CHAIN_WEBHOOK_SECRET{}", clean_sufix);
+        let chain_max_block_age_var = format!("CHAIN_MAX_BLOCK_AGE{}", clean_sufix);
+        let chain_rpc_headers_var = format!("CHAIN_RPC_HEADERS{}", clean_sufix);
+        let chain_rpc_basic_auth_var = format!("CHAIN_RPC_BASIC_AUTH{}", clean_sufix);
+        let chain_explorer_api_var = format!("CHAIN_EXPLORER_API{}", clean_sufix);
+        let chain_explorer_api_key_var = format!("CHAIN_EXPLORER_API_KEY{}", clean_sufix);
+        let chain_safe_transaction_service_var =
+            format!("CHAIN_SAFE_TRANSACTION_SERVICE{}", clean_sufix);
+        let chain_native_symbol_var = format!("CHAIN_NATIVE_SYMBOL{}", clean_sufix);
+        let chain_native_decimals_var = format!("CHAIN_NATIVE_DECIMALS{}", clean_sufix);
+        let chain_max_block_range_var = format!("CHAIN_MAX_BLOCK_RANGE{}", clean_sufix);
+        let chain_head_delay_var = format!("CHAIN_HEAD_DELAY{}", clean_sufix);
+        let chain_withdrawal_challenge_period_var =
+            format!("CHAIN_WITHDRAWAL_CHALLENGE_PERIOD{}", clean_sufix);
+
+        // Well known chains are recognized by their id, name or the key they are listed with in
+        // `CHAINS`, and only need an RPC
+        let preset: Option<&ChainPreset> = [
+            env::var(&chain_id_var).ok(),
+            env::var(&chain_name_var).ok(),
+            Some(clean_sufix.trim_start_matches('_').to_string()),
+        ]
+        .iter()
+        .flatten()
+        .find_map(|key| chain_presets::find(key));
+
+        let mode = match env::var(&chain_mode_var)
+            .unwrap_or("Blocks".to_string())
+            .as_str()
+        {
+            "Blocks" => ChainMode::Blocks,
+            "Events" => ChainMode::Events,
+            "Push" => ChainMode::Push,
+            &_ => panic!("Invalid {}", &chain_mode_var),
+        };
+        let webhook_secret = env::var(&chain_webhook_secret_var).ok();
+        let explorer = env::var(&chain_explorer_var)
+            .ok()
+            .or(preset.map(|preset| preset.explorer.to_string()));
+        if matches!(mode, ChainMode::Push) && webhook_secret.is_none() {
+            panic!("Missing {}", &chain_webhook_secret_var);
+        }
 
         Chain {
             id: match &env::var(&chain_id_var) {
                 Ok(chain_id) => Some(U256::from_dec_str(chain_id).expect("Invalid CHAIN_ID")),
-                Err(_) => None,
+                Err(_) => preset.map(|preset| U256::from(preset.id)),
             },
 
             name: env::var(&chain_name_var)
-                .unwrap_or_else(|_| panic!("Missing {}", &chain_name_var)),
-            blocktime: Duration::from_millis(
-                env::var(&chain_blocktime_var)
-                    .unwrap_or_else(|_| panic!("Missing {}", &chain_blocktime_var))
-                    .parse::<u64>()
-                    .expect("Invalid CHAIN_BLOCKTME"),
+                .ok()
+                .or(preset.map(|preset| preset.name.to_string()))
+                .unwrap_or_else(|| panic!("Missing {}", &chain_name_var)),
+            blocktime: Duration::from_millis(match env::var(&chain_blocktime_var) {
+                Ok(blocktime) => blocktime.parse::<u64>().expect("Invalid CHAIN_BLOCKTME"),
+                Err(_) => preset
+                    .map(|preset| preset.blocktime)
+                    .unwrap_or_else(|| panic!("Missing {}", &chain_blocktime_var)),
+            }),
+            explorer_tx_url: explorer_url_template(
+                env::var(&chain_explorer_tx_url_var).ok(),
+                &explorer,
+                "tx/{hash}",
+            ),
+            explorer_address_url: explorer_url_template(
+                env::var(&chain_explorer_address_url_var).ok(),
+                &explorer,
+                "address/{address}",
             ),
-            explorer: env::var(&chain_explorer_var).ok(),
+            explorer,
             rpc: env::var(&chain_rpc_var).unwrap_or_else(|_| panic!("Missing {}", &chain_rpc_var)),
-            mode: match env::var(&chain_mode_var)
-                .unwrap_or("Blocks".to_string())
-                .as_str()
-            {
-                "Blocks" => ChainMode::Blocks,
-                "Events" => ChainMode::Events,
-                &_ => panic!("Invalid {}", &chain_mode_var),
-            },
+            mode,
             spam_filter_level: SpamFilterLevel::from_str(
                 &env::var(&chain_spam_filter_level_var).unwrap_or("KnownAssets".to_string()),
             )
             .unwrap_or_else(|_| panic!("Invalid {}", &chain_spam_filter_level_var)),
+            confirmations: match env::var(&chain_confirmations_var)
+                .unwrap_or("0".to_string())
+                .as_str()
+            {
+                "Safe" => Confirmations::Safe,
+                "Finalized" => Confirmations::Finalized,
+                blocks => Confirmations::Blocks(
+                    blocks
+                        .parse::<u64>()
+                        .unwrap_or_else(|_| panic!("Invalid {}", &chain_confirmations_var)),
+                ),
+            },
+            server_side_filtering: env::var(&chain_server_side_filtering_var)
+                .unwrap_or("false".to_string())
+                .to_lowercase()
+                .parse::<bool>()
+                .unwrap_or_else(|_| panic!("Invalid {}", &chain_server_side_filtering_var)),
+            concurrency: env::var(&chain_concurrency_var)
+                .unwrap_or("1".to_string())
+                .parse::<u64>()
+                .ok()
+                .filter(|concurrency| *concurrency > 0)
+                .unwrap_or_else(|| panic!("Invalid {}", &chain_concurrency_var)),
+            max_rps: env::var(&chain_max_rps_var).ok().map(|max_rps| {
+                max_rps
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|max_rps| *max_rps > 0)
+                    .unwrap_or_else(|| panic!("Invalid {}", &chain_max_rps_var))
+            }),
+            webhook_secret,
+            max_block_age: Duration::from_secs(
+                env::var(&chain_max_block_age_var)
+                    .unwrap_or("600".to_string())
+                    .parse::<u64>()
+                    .unwrap_or_else(|_| panic!("Invalid {}", &chain_max_block_age_var)),
+            ),
+            rpc_headers: env::var(&chain_rpc_headers_var)
+                .map(|rpc_headers| {
+                    rpc_headers
+                        .split(';')
+                        .filter(|header| !header.trim().is_empty())
+                        .map(|header| {
+                            let (name, value) = header
+                                .split_once(':')
+                                .unwrap_or_else(|| panic!("Invalid {}", &chain_rpc_headers_var));
+                            (name.trim().to_string(), value.trim().to_string())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            rpc_basic_auth: env::var(&chain_rpc_basic_auth_var).ok().map(|basic_auth| {
+                let (username, password) = basic_auth
+                    .split_once(':')
+                    .unwrap_or_else(|| panic!("Invalid {}", &chain_rpc_basic_auth_var));
+                (username.to_string(), password.to_string())
+            }),
+            explorer_api: env::var(&chain_explorer_api_var).ok(),
+            // A single Etherscan key can be used for every chain
+            explorer_api_key: env::var(&chain_explorer_api_key_var)
+                .or(env::var("EXPLORER_API_KEY"))
+                .ok(),
+            safe_transaction_service: env::var(&chain_safe_transaction_service_var).ok(),
+            native_symbol: env::var(&chain_native_symbol_var)
+                .ok()
+                .or(preset.map(|preset| preset.native_symbol.to_string()))
+                .unwrap_or_else(default_native_symbol),
+            native_decimals: env::var(&chain_native_decimals_var)
+                .map(|decimals| {
+                    decimals
+                        .parse::<u32>()
+                        .unwrap_or_else(|_| panic!("Invalid {}", &chain_native_decimals_var))
+                })
+                .unwrap_or_else(|_| default_native_decimals()),
+            max_block_range: env::var(&chain_max_block_range_var)
+                .ok()
+                .map(|max_block_range| {
+                    max_block_range
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|max_block_range| *max_block_range > 0)
+                        .unwrap_or_else(|| panic!("Invalid {}", &chain_max_block_range_var))
+                })
+                .or(preset.map(|preset| preset.max_block_range))
+                .unwrap_or_else(default_max_block_range),
+            head_delay: env::var(&chain_head_delay_var)
+                .map(|head_delay| {
+                    head_delay
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|head_delay| is_valid_head_delay(*head_delay))
+                        .unwrap_or_else(|| panic!("Invalid {}", &chain_head_delay_var))
+                })
+                .unwrap_or_else(|_| default_head_delay()),
+            withdrawal_challenge_period: env::var(&chain_withdrawal_challenge_period_var).ok().map(
+                |period| {
+                    Duration::from_secs(period.parse::<u64>().unwrap_or_else(|_| {
+                        panic!("Invalid {}", &chain_withdrawal_challenge_period_var)
+                    }))
+                },
+            ),
         }
     }
 
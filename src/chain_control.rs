@@ -0,0 +1,223 @@
+use ethers::core::types::U64;
+use lazy_static::lazy_static;
+use log::info;
+use prometheus::core::Collector;
+use serde_derive::Serialize as SerializeMacro;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::{
+    chain::{Chain, ChainMode},
+    metrics_middleware::RPC_REQUESTS,
+};
+
+/// A monitored chain and how far it got
+struct ChainProgress {
+    chain: Chain,
+    head: Option<U64>,
+    last_processed_block: Option<U64>,
+    last_notification: Option<u64>,
+}
+
+/// State of a chain, as served by the API
+#[derive(SerializeMacro, ToSchema)]
+pub struct ChainState {
+    pub name: String,
+    /// Only the origin, the rest of the URL usually includes an API key
+    pub rpc: String,
+    pub chain_id: Option<String>,
+    pub mode: ChainMode,
+    pub paused: bool,
+    pub head: Option<u64>,
+    pub last_processed_block: Option<u64>,
+    pub lag: Option<u64>,
+    /// Failed RPC requests per method, across restarts when the counters are persisted
+    pub rpc_errors: BTreeMap<String, u64>,
+    /// Unix timestamp of the last notification sent
+    pub last_notification: Option<u64>,
+}
+
+lazy_static! {
+    /// Monitored chains, by lowercase chain name
+    static ref CHAINS: Mutex<HashMap<String, ChainProgress>> = Mutex::new(HashMap::new());
+    /// Whether each polling chain is paused, by lowercase chain name
+    static ref PAUSED: Mutex<HashMap<String, watch::Sender<bool>>> = Mutex::new(HashMap::new());
+}
+
+/// Adds a chain to the monitored ones, `false` when there is already one with its name.
+/// Polling chains can be paused, push chains are driven by their webhook instead
+pub fn register(chain: &Chain) -> bool {
+    let mut chains = CHAINS.lock().unwrap();
+    let name = chain.name.to_lowercase();
+    if chains.contains_key(&name) {
+        return false;
+    }
+    if !matches!(chain.mode, ChainMode::Push) {
+        PAUSED
+            .lock()
+            .unwrap()
+            .insert(name.clone(), watch::channel(false).0);
+    }
+    chains.insert(
+        name,
+        ChainProgress {
+            chain: chain.clone(),
+            head: None,
+            last_processed_block: None,
+            last_notification: None,
+        },
+    );
+    true
+}
+
+fn update(chain: &Chain, update: impl FnOnce(&mut ChainProgress)) {
+    if let Some(progress) = CHAINS.lock().unwrap().get_mut(&chain.name.to_lowercase()) {
+        update(progress);
+    }
+}
+
+/// Stores the chain id the RPC is connected to, for chains configured without one
+pub fn record_connected(chain: &Chain) {
+    update(chain, |progress| progress.chain.id = chain.id);
+}
+
+pub fn record_head(chain: &Chain, head: U64) {
+    update(chain, |progress| progress.head = Some(head));
+}
+
+pub fn record_processed(chain: &Chain, block_number: U64) {
+    update(chain, |progress| {
+        progress.last_processed_block = Some(block_number)
+    });
+}
+
+pub fn record_notification(chain: &Chain) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    update(chain, |progress| progress.last_notification = Some(now));
+}
+
+/// Origin of a URL, without the credentials and API keys of its path or query
+pub(crate) fn rpc_origin(rpc: &str) -> String {
+    reqwest::Url::parse(rpc)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_default()
+}
+
+fn rpc_errors(chain_name: &str) -> BTreeMap<String, u64> {
+    let mut rpc_errors = BTreeMap::new();
+    for metric in RPC_REQUESTS
+        .collect()
+        .iter()
+        .flat_map(|metric_family| metric_family.get_metric())
+    {
+        let labels: HashMap<&str, &str> = metric
+            .get_label()
+            .iter()
+            .map(|label| (label.get_name(), label.get_value()))
+            .collect();
+        if labels.get("chain") == Some(&chain_name) && labels.get("outcome") == Some(&"error") {
+            rpc_errors.insert(
+                labels["method"].to_string(),
+                metric.get_counter().get_value() as u64,
+            );
+        }
+    }
+    rpc_errors
+}
+
+/// Monitored chain, by case insensitive name
+pub fn get(chain_name: &str) -> Option<Chain> {
+    CHAINS
+        .lock()
+        .unwrap()
+        .get(&chain_name.to_lowercase())
+        .map(|progress| progress.chain.clone())
+}
+
+/// Every monitored chain, sorted by name
+pub fn chains() -> Vec<Chain> {
+    let mut chains: Vec<Chain> = CHAINS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|progress| progress.chain.clone())
+        .collect();
+    chains.sort_by(|a, b| a.name.cmp(&b.name));
+    chains
+}
+
+/// State of every monitored chain, sorted by name
+pub fn states() -> Vec<ChainState> {
+    let chains = CHAINS.lock().unwrap();
+    let mut states: Vec<ChainState> = chains
+        .values()
+        .map(|progress| ChainState {
+            name: progress.chain.name.clone(),
+            rpc: rpc_origin(&progress.chain.rpc),
+            chain_id: progress.chain.id.map(|id| id.to_string()),
+            mode: progress.chain.mode.clone(),
+            paused: is_paused(&progress.chain.name),
+            head: progress.head.map(|head| head.as_u64()),
+            last_processed_block: progress
+                .last_processed_block
+                .map(|last_processed_block| last_processed_block.as_u64()),
+            lag: progress.head.zip(progress.last_processed_block).map(
+                |(head, last_processed_block)| head.saturating_sub(last_processed_block).as_u64(),
+            ),
+            rpc_errors: rpc_errors(&progress.chain.name),
+            last_notification: progress.last_notification,
+        })
+        .collect();
+    states.sort_by(|a, b| a.name.cmp(&b.name));
+    states
+}
+
+/// Pauses or resumes a chain, `false` when there is no such polling chain
+pub fn set_paused(chain_name: &str, paused: bool) -> bool {
+    let chains = PAUSED.lock().unwrap();
+    let Some(sender) = chains.get(&chain_name.to_lowercase()) else {
+        return false;
+    };
+    sender.send_replace(paused);
+    true
+}
+
+pub fn is_paused(chain_name: &str) -> bool {
+    PAUSED
+        .lock()
+        .unwrap()
+        .get(&chain_name.to_lowercase())
+        .is_some_and(|sender| *sender.borrow())
+}
+
+/// Holds the chain's polling loop while it is paused, the next block is processed once resumed
+pub async fn wait_while_paused(chain: &Chain, next_block_number: U64) {
+    let Some(mut paused) = PAUSED
+        .lock()
+        .unwrap()
+        .get(&chain.name.to_lowercase())
+        .map(|sender| sender.subscribe())
+    else {
+        return;
+    };
+    if !*paused.borrow_and_update() {
+        return;
+    }
+
+    info!(
+        "Monitoring of {} paused before block {}",
+        chain.name, next_block_number
+    );
+    // The sender lives as long as the process, waiting can't fail
+    let _ = paused.wait_for(|paused| !paused).await;
+    info!(
+        "Monitoring of {} resumed from block {}",
+        chain.name, next_block_number
+    );
+}
@@ -0,0 +1,80 @@
+/// Defaults of a well known chain, so configuring it only takes its RPC
+pub struct ChainPreset {
+    pub id: u64,
+    pub name: &'static str,
+    /// Other names the chain is known by, lowercase
+    pub aliases: &'static [&'static str],
+    /// Milliseconds
+    pub blocktime: u64,
+    pub explorer: &'static str,
+    pub native_symbol: &'static str,
+    /// Blocks per `eth_getLogs` request in `Events` mode
+    pub max_block_range: u64,
+}
+
+const PRESETS: [ChainPreset; 6] = [
+    ChainPreset {
+        id: 1,
+        name: "Ethereum",
+        aliases: &["mainnet", "eth"],
+        blocktime: 12000,
+        explorer: "https://etherscan.io",
+        native_symbol: "ETH",
+        max_block_range: 100,
+    },
+    ChainPreset {
+        id: 100,
+        name: "Gnosis",
+        aliases: &["xdai"],
+        blocktime: 5000,
+        explorer: "https://gnosisscan.io",
+        native_symbol: "xDAI",
+        max_block_range: 500,
+    },
+    ChainPreset {
+        id: 42161,
+        name: "Arbitrum",
+        aliases: &["arbitrum_one", "arb"],
+        blocktime: 250,
+        explorer: "https://arbiscan.io",
+        native_symbol: "ETH",
+        max_block_range: 2000,
+    },
+    ChainPreset {
+        id: 10,
+        name: "Optimism",
+        aliases: &["op"],
+        blocktime: 2000,
+        explorer: "https://optimistic.etherscan.io",
+        native_symbol: "ETH",
+        max_block_range: 1000,
+    },
+    ChainPreset {
+        id: 8453,
+        name: "Base",
+        aliases: &[],
+        blocktime: 2000,
+        explorer: "https://basescan.org",
+        native_symbol: "ETH",
+        max_block_range: 1000,
+    },
+    ChainPreset {
+        id: 137,
+        name: "Polygon",
+        aliases: &["matic", "pol"],
+        blocktime: 2000,
+        explorer: "https://polygonscan.com",
+        native_symbol: "POL",
+        max_block_range: 1000,
+    },
+];
+
+/// Preset of a chain, by its id, name or one of its aliases (case insensitive)
+pub fn find(key: &str) -> Option<&'static ChainPreset> {
+    let key = key.trim().to_lowercase();
+    PRESETS.iter().find(|preset| {
+        preset.id.to_string() == key
+            || preset.name.to_lowercase() == key
+            || preset.aliases.contains(&key.as_str())
+    })
+}
@@ -0,0 +1,1067 @@
+use ethers::{
+    core::{
+        abi::ethereum_types::BloomInput,
+        types::{
+            Address, Block, BlockNumber, Filter as LogFilter, Log, Transaction, TransactionReceipt,
+            H256, U64,
+        },
+    },
+    middleware::Middleware,
+    providers::{Provider, ProviderError},
+};
+use futures::{
+    stream::{self, StreamExt},
+    FutureExt,
+};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::{
+    sync::{mpsc, watch},
+    time::sleep,
+};
+use tracing::{field, info_span, Instrument};
+use utoipa::ToSchema;
+
+use crate::{
+    abi_registry, batch,
+    chain::{Chain, ChainMode, Confirmations},
+    chain_control,
+    decoding::{
+        add_send_values, parse_logs, process_block, ADMIN_CHANGED_TOPIC,
+        OWNERSHIP_TRANSFERRED_TOPIC, UPGRADED_TOPIC,
+    },
+    fixtures,
+    interesting_transaction::InterestingTransaction,
+    metrics::{record_loop_iteration, CURRENT_BLOCK},
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable, DRY_RUN},
+    persistent_metrics::BLOCKS_PROCESSED,
+    pipeline::{
+        build_notifications, filter_interesting_transactions, notified_transactions,
+        notify_interesting_transactions,
+    },
+    provider::{build_provider, connect_and_verify},
+    recovery,
+    reorg::RecentBlocks,
+    retry::{log_rpc_error, RpcBackoff},
+    throttled_http::ThrottledHttp,
+    watched_topics::WatchedTopics,
+    webhook::WebhookPayload,
+};
+
+const REORG_TRACKED_BLOCKS: usize = 64;
+const MAX_FILTER_TOPICS: usize = 100;
+/// Confirmations used instead of the `safe` and `finalized` tags when the RPC doesn't support
+/// them, an epoch and two epochs on Ethereum
+const SAFE_FALLBACK_CONFIRMATIONS: u64 = 32;
+const FINALIZED_FALLBACK_CONFIRMATIONS: u64 = 64;
+
+/// Notifications a single block would generate, for the API
+#[derive(SerializeMacro, ToSchema)]
+pub struct Reprocessed {
+    /// Transactions involving the watched accounts, before the spam filter and account directions
+    transactions: usize,
+    notifications: Vec<Notification>,
+}
+
+/// Task monitoring a single chain, notifying the transactions of the watched accounts
+pub struct ChainWorker {
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+}
+
+impl ChainWorker {
+    /// Worker of the chain, notified through `addressbook_updates` whenever an account is added to
+    /// the addressbook
+    pub fn new(
+        chain: Chain,
+        addressbook: Arc<Mutex<HashMap<String, String>>>,
+        addressbook_updates: watch::Receiver<()>,
+    ) -> Self {
+        Self {
+            chain,
+            addressbook,
+            addressbook_updates,
+        }
+    }
+
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    /// Polls the chain in its `Blocks` or `Events` mode, forever
+    pub async fn run(self) {
+        let span = chain_span(&self.chain);
+        match self.chain.mode {
+            ChainMode::Blocks => {
+                monitor_chain_blocks(self.chain, self.addressbook, self.addressbook_updates)
+                    .instrument(span)
+                    .await
+            }
+            ChainMode::Events => {
+                monitor_chain_events(self.chain, self.addressbook, self.addressbook_updates)
+                    .instrument(span)
+                    .await
+            }
+            ChainMode::Push => panic!(
+                "{} is in Push mode, it has to be run with its webhook payloads",
+                self.chain.name
+            ),
+        }
+    }
+
+    /// Processes the payloads of the chain's webhook, for chains in `Push` mode
+    pub async fn run_push(self, webhook_payloads: mpsc::Receiver<WebhookPayload>) {
+        let span = chain_span(&self.chain);
+        monitor_chain_push(
+            self.chain,
+            self.addressbook,
+            self.addressbook_updates,
+            webhook_payloads,
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Processes the blocks once, sending and printing the notifications of their transactions.
+    /// Used to check the configuration, returns how many notifications were sent
+    pub async fn debug(self, debug_blocks: RangeInclusive<u64>) -> Result<usize, ProviderError> {
+        let span = chain_span(&self.chain);
+        match self.chain.mode {
+            ChainMode::Blocks => {
+                debug_chain_blocks(
+                    self.chain,
+                    self.addressbook,
+                    self.addressbook_updates,
+                    debug_blocks,
+                )
+                .instrument(span)
+                .await
+            }
+            ChainMode::Events => {
+                debug_chain_events(
+                    self.chain,
+                    self.addressbook,
+                    self.addressbook_updates,
+                    debug_blocks,
+                )
+                .instrument(span)
+                .await
+            }
+            ChainMode::Push => {
+                warn!("{} is in Push mode, no block to debug", self.chain.name);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Processes a single block as the chain's mode would, returning the notifications its
+    /// transactions generate without sending them
+    pub async fn reprocess(self, block_number: u64) -> Result<Reprocessed, ProviderError> {
+        let provider = build_provider(&self.chain);
+        let mut watched_topics =
+            WatchedTopics::new(self.addressbook.clone(), self.addressbook_updates);
+        // Push chains also have an RPC, and all the receipts of the block are the closest to
+        // what their webhooks deliver
+        let interesting_transactions = match self.chain.mode {
+            ChainMode::Events => {
+                let events = get_chain_logs(
+                    &provider,
+                    &self.chain,
+                    block_number.into(),
+                    block_number.into(),
+                    watched_topics.current(),
+                )
+                .await?;
+                parse_logs(&events, watched_topics.current())
+            }
+            ChainMode::Blocks | ChainMode::Push => {
+                let receipts_method = probe_receipts_method(&provider).await;
+                let block =
+                    flexible_get_block_receipts(&provider, receipts_method, block_number).await?;
+                process_block(&block, watched_topics.current())
+            }
+        };
+
+        let transactions = interesting_transactions.len();
+        // Only a preview, the spam and withdrawals of the block were recorded when first processed
+        let interesting_transactions = notified_transactions(interesting_transactions, &self.chain);
+        Ok(Reprocessed {
+            transactions,
+            notifications: build_notifications(
+                &interesting_transactions,
+                &self.chain,
+                self.addressbook,
+            ),
+        })
+    }
+}
+
+/// Span of a chain task, for the structured logs to include the chain and block being processed
+pub fn chain_span(chain: &Chain) -> tracing::Span {
+    info_span!("chain", chain = %chain.name, block = field::Empty)
+}
+
+/// Root span of the processing of a block, or of a range of blocks in Events mode, so each one
+/// is exported as its own trace
+fn block_span(chain: &Chain, from_block: U64, to_block: U64) -> tracing::Span {
+    info_span!(
+        parent: None,
+        "block",
+        chain = %chain.name,
+        block = to_block.as_u64(),
+        from_block = from_block.as_u64()
+    )
+}
+
+#[derive(SerializeMacro, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AlchemyBlockReceiptsParam {
+    block_number: BlockNumber,
+}
+
+#[derive(SerializeMacro, DeserializeMacro, Debug)]
+struct AlchemyBlockReceipts {
+    receipts: Vec<TransactionReceipt>,
+}
+
+async fn alchemy_get_block_receipts<T: Into<BlockNumber> + Send + Sync + Serialize>(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    block: T,
+) -> Result<AlchemyBlockReceipts, ProviderError> {
+    let param = AlchemyBlockReceiptsParam {
+        block_number: block.into(),
+    };
+
+    provider
+        .request("alchemy_getTransactionReceipts", [param])
+        .await
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ReceiptsMethod {
+    Alchemy,
+    BlockReceipts,
+    TransactionReceipts,
+}
+
+/// Checks which method the RPC supports to get all the receipts of a block
+async fn probe_receipts_method(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+) -> ReceiptsMethod {
+    let is_provider_alchemy = provider
+        .provider()
+        .as_ref()
+        .url()
+        .host_str()
+        .unwrap_or("not")
+        .contains("alchemy.com");
+
+    if is_provider_alchemy {
+        return ReceiptsMethod::Alchemy;
+    }
+
+    match provider.get_block_receipts(BlockNumber::Latest).await {
+        Err(ProviderError::JsonRpcClientError(err)) if err.as_error_response().is_some() => {
+            warn!(
+                "{} does not support eth_getBlockReceipts, falling back to per transaction receipts",
+                provider.provider().as_ref().url().host_str().unwrap_or("RPC")
+            );
+            ReceiptsMethod::TransactionReceipts
+        }
+        _ => ReceiptsMethod::BlockReceipts,
+    }
+}
+
+async fn flexible_get_block_receipts<T: Into<BlockNumber> + Send + Sync + Serialize>(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    receipts_method: ReceiptsMethod,
+    block: T,
+) -> Result<Vec<TransactionReceipt>, ProviderError> {
+    match receipts_method {
+        ReceiptsMethod::Alchemy => {
+            let wrapped_result = alchemy_get_block_receipts(provider, block).await;
+            match wrapped_result {
+                Ok(res) => Ok(res.receipts),
+                Err(err) => Err(err),
+            }
+        }
+        ReceiptsMethod::BlockReceipts => provider.get_block_receipts(block).await,
+        ReceiptsMethod::TransactionReceipts => {
+            let block_number: BlockNumber = block.into();
+            let block = provider
+                .get_block(block_number)
+                .await?
+                .ok_or_else(|| ProviderError::CustomError("Block not found".to_string()))?;
+
+            let params: Vec<[H256; 1]> = block.transactions.iter().map(|hash| [*hash]).collect();
+            let receipts: Vec<Option<TransactionReceipt>> =
+                batch::batch_request(provider, "eth_getTransactionReceipt", &params).await?;
+
+            receipts
+                .into_iter()
+                .map(|receipt| {
+                    receipt.ok_or_else(|| ProviderError::CustomError("Missing receipt".to_string()))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Checks whether the RPC supports the `safe` or `finalized` tag the chain waits for, falling back
+/// to a number of confirmations when it rejects it
+async fn probe_confirmations(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+) -> Confirmations {
+    let (tagged_block, fallback) = match chain.confirmations {
+        Confirmations::Blocks(_) => return chain.confirmations.clone(),
+        Confirmations::Safe => (BlockNumber::Safe, SAFE_FALLBACK_CONFIRMATIONS),
+        Confirmations::Finalized => (BlockNumber::Finalized, FINALIZED_FALLBACK_CONFIRMATIONS),
+    };
+
+    match provider.get_block(tagged_block).await {
+        Err(ProviderError::JsonRpcClientError(err)) if err.as_error_response().is_some() => {}
+        Ok(None) => {}
+        _ => return chain.confirmations.clone(),
+    }
+    warn!(
+        "{} does not support the {} block tag, falling back to {} confirmations on {}",
+        provider
+            .provider()
+            .as_ref()
+            .url()
+            .host_str()
+            .unwrap_or("RPC"),
+        tagged_block,
+        fallback,
+        chain.name
+    );
+    Confirmations::Blocks(fallback)
+}
+
+/// Latest block deep enough to be processed according to the chain's confirmations setting
+async fn get_confirmed_block_number(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+) -> Result<U64, ProviderError> {
+    let tagged_block = match chain.confirmations {
+        Confirmations::Blocks(confirmations) => {
+            let block_number = provider.get_block_number().await?;
+            return Ok(block_number.saturating_sub(confirmations.into()));
+        }
+        Confirmations::Safe => BlockNumber::Safe,
+        Confirmations::Finalized => BlockNumber::Finalized,
+    };
+
+    provider
+        .get_block(tagged_block)
+        .await?
+        .and_then(|block| block.number)
+        .ok_or_else(|| ProviderError::CustomError(format!("{:?} block not found", tagged_block)))
+}
+
+/// Whether any of the block's transactions was sent to or from a watched account, or its
+/// logsBloom might include logs with a watched account as a topic. Blocks without a logsBloom
+/// are always considered relevant
+fn may_involve_watched_accounts(
+    block: &Block<Transaction>,
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> bool {
+    let Some(logs_bloom) = block.logs_bloom else {
+        return true;
+    };
+
+    let is_watched =
+        |address: &Address| watched_addresses_as_topics.contains(&H256::from(*address));
+
+    block
+        .transactions
+        .iter()
+        .any(|tx| is_watched(&tx.from) || tx.to.as_ref().is_some_and(is_watched))
+        || watched_addresses_as_topics.iter().any(|topic| {
+            // As an indexed parameter, or as the contract emitting the log
+            logs_bloom.contains_input(BloomInput::Raw(topic.as_bytes()))
+                || logs_bloom.contains_input(BloomInput::Raw(&topic.as_bytes()[12..]))
+        })
+}
+
+/// Block with its transactions, and its receipts
+type FetchedBlock = (Block<Transaction>, Vec<TransactionReceipt>);
+
+/// RPC error while fetching a block, with what was requested
+struct FetchBlockError {
+    requested: &'static str,
+    err: ProviderError,
+}
+
+/// Gets a block with its transactions, and its receipts if it might involve a watched account.
+/// `None` when the block isn't available yet or changed meanwhile
+#[tracing::instrument(skip_all)]
+async fn fetch_block(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    receipts_method: ReceiptsMethod,
+    block_number: U64,
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Result<Option<FetchedBlock>, FetchBlockError> {
+    let header = match provider.get_block_with_txs(block_number).await {
+        Ok(Some(header)) if header.hash.is_some() => header,
+        Ok(_) => {
+            warn!(
+                "{} block {} not available from RPC yet, retrying",
+                chain.name, block_number
+            );
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(FetchBlockError {
+                requested: "block header",
+                err,
+            })
+        }
+    };
+
+    if !may_involve_watched_accounts(&header, watched_addresses_as_topics) {
+        debug!(
+            "Skipping {} block {} receipts, no watched accounts involved",
+            chain.name, block_number
+        );
+        return Ok(Some((header, vec![])));
+    }
+
+    let block = flexible_get_block_receipts(provider, receipts_method, block_number)
+        .await
+        .map_err(|err| FetchBlockError {
+            requested: "block receipts",
+            err,
+        })?;
+
+    if block
+        .iter()
+        .any(|receipt| receipt.block_hash != header.hash)
+    {
+        warn!(
+            "{} block {} changed while fetching its receipts, retrying",
+            chain.name, block_number
+        );
+        return Ok(None);
+    }
+
+    Ok(Some((header, block)))
+}
+
+async fn find_fork_point(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    recent_blocks: &RecentBlocks,
+) -> Result<U64, ProviderError> {
+    let stored_blocks = recent_blocks.stored();
+
+    for (number, hash) in stored_blocks.iter() {
+        let canonical_block = provider.get_block(*number).await?;
+        if canonical_block.and_then(|block| block.hash) == Some(*hash) {
+            return Ok(*number);
+        }
+    }
+
+    let oldest_block_number = stored_blocks.last().map(|(number, _)| *number).unwrap();
+    warn!(
+        "Reorg is deeper than the {} tracked blocks, re-processing from block {}",
+        REORG_TRACKED_BLOCKS, oldest_block_number
+    );
+    Ok(oldest_block_number.saturating_sub(1.into()))
+}
+
+async fn debug_chain_blocks(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+    debug_blocks: RangeInclusive<u64>,
+) -> Result<usize, ProviderError> {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), addressbook_updates);
+
+    let receipts_method = probe_receipts_method(&provider).await;
+    let mut notified = 0;
+    for debug_block_number in debug_blocks {
+        let block =
+            flexible_get_block_receipts(&provider, receipts_method, debug_block_number).await?;
+        let interesting_transactions = process_block(&block, watched_topics.current());
+        notified += send_debug_notifications(
+            &chain,
+            addressbook.clone(),
+            interesting_transactions,
+            debug_block_number,
+        )
+        .await;
+    }
+    Ok(notified)
+}
+
+/// Sends and prints the notifications of the debugged blocks, returning how many there were
+async fn send_debug_notifications(
+    chain: &Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    interesting_transactions: Vec<InterestingTransaction>,
+    debug_block_number: u64,
+) -> usize {
+    let interesting_transactions = filter_interesting_transactions(interesting_transactions, chain);
+    let notifications = build_notifications(&interesting_transactions, chain, addressbook);
+    let notified = notifications.len();
+    for notification in notifications {
+        // The stdout sink already prints them
+        if !*DRY_RUN {
+            println!(
+                "{} block {}: {}",
+                chain.name, debug_block_number, notification.message
+            );
+        }
+        if let Err(err) = notification.send().await {
+            error!("Error while sending debug notification: {}", err);
+        }
+    }
+    notified
+}
+
+async fn monitor_chain_blocks(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+) {
+    let (mut chain, provider) = connect_and_verify(chain).await;
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), addressbook_updates);
+
+    info!("Starting Account Watcher for {} in Blocks Mode", chain.name);
+    chain.confirmations = probe_confirmations(&provider, &chain).await;
+
+    let receipts_method = probe_receipts_method(&provider).await;
+    debug!("Getting {} receipts with {:?}", chain.name, receipts_method);
+
+    let (mut next_block_number, mut recovery) = recovery::resume(
+        &chain,
+        get_confirmed_block_number(&provider, &chain).await.unwrap(),
+    );
+    let mut recent_blocks = RecentBlocks::new(REORG_TRACKED_BLOCKS);
+
+    let mut rpc_backoff = RpcBackoff::new();
+    let mut consecutive_over_budget = 0;
+
+    'poll: loop {
+        chain_control::wait_while_paused(&chain, next_block_number).await;
+        let now = Instant::now();
+        let block_number = match get_confirmed_block_number(&provider, &chain).await {
+            Ok(res) => res,
+            Err(err) => {
+                rpc_backoff.failed(&chain, "block number", &err).await;
+                continue;
+            }
+        };
+
+        debug!("Current block number on {}: {}", chain.name, block_number);
+        chain_control::record_head(&chain, block_number);
+
+        'blocks: while next_block_number <= block_number {
+            let last_fetched_block_number =
+                block_number.min(next_block_number + chain.concurrency - 1);
+            let watched_addresses_as_topics = watched_topics.current();
+
+            // Blocks are fetched concurrently, but processed in order
+            let fetched_blocks: Vec<_> =
+                stream::iter(next_block_number.as_u64()..=last_fetched_block_number.as_u64())
+                    .map(|fetched_block_number| {
+                        let span = block_span(
+                            &chain,
+                            fetched_block_number.into(),
+                            fetched_block_number.into(),
+                        );
+                        fetch_block(
+                            &provider,
+                            &chain,
+                            receipts_method,
+                            fetched_block_number.into(),
+                            watched_addresses_as_topics,
+                        )
+                        .instrument(span.clone())
+                        .map(|fetched_block| (span, fetched_block))
+                    })
+                    .buffered(chain.concurrency as usize)
+                    .collect()
+                    .await;
+
+            for (span, fetched_block) in fetched_blocks {
+                let (header, block) = match fetched_block {
+                    Ok(Some(fetched_block)) => fetched_block,
+                    Ok(None) => break 'blocks,
+                    // Waits according to the error before the block is fetched again
+                    Err(FetchBlockError { requested, err }) => {
+                        rpc_backoff.failed(&chain, requested, &err).await;
+                        continue 'poll;
+                    }
+                };
+                debug!("Processing {} block {}", chain.name, next_block_number);
+                tracing::Span::current().record("block", next_block_number.as_u64());
+
+                if !recent_blocks.is_parent(next_block_number, header.parent_hash) {
+                    let fork_point = match find_fork_point(&provider, &recent_blocks).await {
+                        Ok(fork_point) => fork_point,
+                        Err(err) => {
+                            log_rpc_error(&chain, "reorg fork point", &err);
+                            break 'blocks;
+                        }
+                    };
+                    warn!(
+                        "Reorg detected on {} at block {}, re-processing from block {}",
+                        chain.name,
+                        next_block_number,
+                        fork_point + 1
+                    );
+                    recent_blocks.rollback(fork_point);
+                    next_block_number = fork_point + 1;
+                    continue 'blocks;
+                }
+
+                let mut interesting_transactions =
+                    span.in_scope(|| process_block(&block, watched_addresses_as_topics));
+                if abi_registry::fetch_abis(
+                    &chain,
+                    block.iter().flat_map(|receipt| receipt.logs.iter()),
+                    &interesting_transactions,
+                )
+                .instrument(span.clone())
+                .await
+                {
+                    interesting_transactions =
+                        span.in_scope(|| process_block(&block, watched_addresses_as_topics));
+                }
+                add_send_values(&mut interesting_transactions, &header.transactions);
+                for tx in interesting_transactions.iter_mut() {
+                    tx.timestamp = Some(header.timestamp.as_u64());
+                }
+                fixtures::record_block(
+                    &chain,
+                    next_block_number,
+                    header.timestamp.as_u64(),
+                    &block,
+                    &header.transactions,
+                    &interesting_transactions,
+                );
+                let block_interesting_transactions =
+                    interesting_transactions.iter().map(|tx| tx.hash).collect();
+
+                // Transactions which survived a reorg were already notified
+                interesting_transactions.retain(|tx| !recent_blocks.was_notified(&tx.hash));
+
+                notify_interesting_transactions(
+                    &provider,
+                    &chain,
+                    addressbook.clone(),
+                    interesting_transactions,
+                    recovery.as_mut(),
+                )
+                .instrument(span)
+                .await;
+
+                recent_blocks.push(
+                    next_block_number,
+                    header.hash.unwrap(),
+                    block_interesting_transactions,
+                );
+                recovery::save_progress(&chain, next_block_number);
+                chain_control::record_processed(&chain, next_block_number);
+                BLOCKS_PROCESSED
+                    .with_label_values(&[chain.name.as_str()])
+                    .inc();
+                next_block_number = next_block_number + 1
+            }
+        }
+
+        if recovery
+            .as_ref()
+            .is_some_and(|recovery| recovery.is_done(next_block_number))
+        {
+            recovery
+                .take()
+                .unwrap()
+                .send_summary(&chain, addressbook.clone())
+                .await;
+        }
+
+        CURRENT_BLOCK
+            .with_label_values(&[chain.name.as_str()])
+            .set(block_number.try_into().unwrap());
+
+        rpc_backoff.succeeded(&chain).await;
+
+        let elapsed_time = now.elapsed();
+        record_loop_iteration(
+            &chain,
+            elapsed_time,
+            (block_number + 1)
+                .saturating_sub(next_block_number)
+                .as_u64(),
+            &mut consecutive_over_budget,
+        );
+
+        if elapsed_time < chain.blocktime {
+            let sleep_time = chain.blocktime - elapsed_time;
+            debug!("Sleeping {} for: {} ms", chain.name, sleep_time.as_millis());
+            sleep(sleep_time).await;
+        }
+    }
+}
+
+async fn debug_chain_events(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+    debug_blocks: RangeInclusive<u64>,
+) -> Result<usize, ProviderError> {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), addressbook_updates);
+
+    let mut notified = 0;
+    let mut from_block = *debug_blocks.start();
+    while from_block <= *debug_blocks.end() {
+        let to_block = debug_blocks
+            .end()
+            .min(&(from_block + chain.max_block_range - 1))
+            .to_owned();
+        let events = get_chain_logs(
+            &provider,
+            &chain,
+            from_block.into(),
+            to_block.into(),
+            watched_topics.current(),
+        )
+        .await?;
+        // Logs of a range are notified by block, as the monitoring does
+        let mut blocks: Vec<u64> = events
+            .iter()
+            .filter_map(|log| log.block_number.map(|block_number| block_number.as_u64()))
+            .collect();
+        blocks.dedup();
+        for block_number in blocks {
+            let block_events: Vec<Log> = events
+                .iter()
+                .filter(|log| log.block_number == Some(block_number.into()))
+                .cloned()
+                .collect();
+            let interesting_transactions = parse_logs(&block_events, watched_topics.current());
+            notified += send_debug_notifications(
+                &chain,
+                addressbook.clone(),
+                interesting_transactions,
+                block_number,
+            )
+            .await;
+        }
+        from_block = to_block + 1;
+    }
+    Ok(notified)
+}
+
+async fn monitor_chain_events(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+) {
+    let (mut chain, provider) = connect_and_verify(chain).await;
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), addressbook_updates);
+
+    info!("Starting Account Watcher for {} Event Mode", chain.name);
+    chain.confirmations = probe_confirmations(&provider, &chain).await;
+
+    let (mut next_block_number, mut recovery) = recovery::resume(
+        &chain,
+        get_confirmed_block_number(&provider, &chain).await.unwrap(),
+    );
+
+    let mut rpc_backoff = RpcBackoff::new();
+    let mut consecutive_over_budget = 0;
+
+    loop {
+        chain_control::wait_while_paused(&chain, next_block_number).await;
+        let now = Instant::now();
+        let block_number = match get_confirmed_block_number(&provider, &chain).await {
+            Ok(res) => res,
+            Err(err) => {
+                rpc_backoff.failed(&chain, "block number", &err).await;
+                continue;
+            }
+        };
+
+        debug!("Current block number on {}: {}", chain.name, block_number);
+        chain_control::record_head(&chain, block_number);
+
+        // Fresh devnets start at block 0, there is nothing to process until they reach the delay
+        let Some(block_number_with_delay) = block_number.checked_sub(chain.head_delay.into())
+        else {
+            sleep(chain.blocktime).await;
+            continue;
+        };
+
+        if next_block_number <= block_number_with_delay {
+            let to_block =
+                if block_number_with_delay - next_block_number < chain.max_block_range.into() {
+                    block_number_with_delay
+                } else {
+                    next_block_number + chain.max_block_range - 1
+                };
+
+            debug!(
+                "Processing {} from block {} to block {}",
+                chain.name, next_block_number, to_block
+            );
+            tracing::Span::current().record("block", to_block.as_u64());
+            let span = block_span(&chain, next_block_number, to_block);
+            let events = match get_chain_logs(
+                &provider,
+                &chain,
+                next_block_number,
+                to_block,
+                watched_topics.current(),
+            )
+            .instrument(span.clone())
+            .await
+            {
+                Ok(events) => events,
+                Err(err) => {
+                    rpc_backoff.failed(&chain, "events", &err).await;
+                    continue;
+                }
+            };
+
+            let mut interesting_transactions =
+                span.in_scope(|| parse_logs(&events, watched_topics.current()));
+            if abi_registry::fetch_abis(&chain, &events, &interesting_transactions)
+                .instrument(span.clone())
+                .await
+            {
+                interesting_transactions =
+                    span.in_scope(|| parse_logs(&events, watched_topics.current()));
+            }
+            fixtures::record_logs(
+                &chain,
+                next_block_number,
+                to_block,
+                &events,
+                &interesting_transactions,
+            );
+
+            notify_interesting_transactions(
+                &provider,
+                &chain,
+                addressbook.clone(),
+                interesting_transactions,
+                recovery.as_mut(),
+            )
+            .instrument(span)
+            .await;
+            recovery::save_progress(&chain, to_block);
+            chain_control::record_processed(&chain, to_block);
+            BLOCKS_PROCESSED
+                .with_label_values(&[chain.name.as_str()])
+                .inc_by((to_block - next_block_number).as_u64() + 1);
+            next_block_number = to_block + 1;
+        }
+
+        if recovery
+            .as_ref()
+            .is_some_and(|recovery| recovery.is_done(next_block_number))
+        {
+            recovery
+                .take()
+                .unwrap()
+                .send_summary(&chain, addressbook.clone())
+                .await;
+        }
+
+        CURRENT_BLOCK
+            .with_label_values(&[chain.name.as_str()])
+            .set(next_block_number.as_u64().saturating_sub(1) as i64);
+
+        rpc_backoff.succeeded(&chain).await;
+
+        let elapsed_time = now.elapsed();
+        record_loop_iteration(
+            &chain,
+            elapsed_time,
+            (block_number_with_delay + 1)
+                .saturating_sub(next_block_number)
+                .as_u64(),
+            &mut consecutive_over_budget,
+        );
+
+        if elapsed_time < chain.blocktime {
+            let sleep_time = chain.blocktime - elapsed_time;
+            debug!("Sleeping {} for: {} ms", chain.name, sleep_time.as_millis());
+            sleep(sleep_time).await;
+        }
+    }
+}
+
+async fn monitor_chain_push(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Receiver<()>,
+    mut webhook_payloads: mpsc::Receiver<WebhookPayload>,
+) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), addressbook_updates);
+
+    info!("Starting Account Watcher for {} in Push Mode", chain.name);
+
+    while let Some(webhook_payload) = webhook_payloads.recv().await {
+        let block_count = webhook_payload.block_count();
+        let interesting_transactions = match webhook_payload {
+            WebhookPayload::Alchemy(address_activity) => {
+                address_activity.interesting_transactions(watched_topics.current())
+            }
+            WebhookPayload::QuickNode(receipts) => {
+                process_block(&receipts, watched_topics.current())
+            }
+        };
+
+        notify_interesting_transactions(
+            &provider,
+            &chain,
+            addressbook.clone(),
+            interesting_transactions,
+            None,
+        )
+        .await;
+        BLOCKS_PROCESSED
+            .with_label_values(&[chain.name.as_str()])
+            .inc_by(block_count as u64);
+    }
+}
+
+/// Gets the logs of a block range, bisecting it whenever the RPC rejects a range for returning
+/// too many results
+#[tracing::instrument(skip_all)]
+async fn get_chain_logs(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    from_block: U64,
+    to_block: U64,
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Result<Vec<Log>, ProviderError> {
+    let mut logs = vec![];
+    let mut pending_ranges = vec![(from_block, to_block)];
+
+    while let Some((from_block, to_block)) = pending_ranges.pop() {
+        match get_range_logs(
+            provider,
+            chain,
+            from_block,
+            to_block,
+            watched_addresses_as_topics,
+        )
+        .await
+        {
+            Ok(range_logs) => logs.extend(range_logs),
+            Err(err) if from_block < to_block && is_log_limit_error(&err) => {
+                let middle_block = from_block + (to_block - from_block) / 2;
+                debug!(
+                    "{} logs from block {} to block {} exceed the RPC limits, splitting range",
+                    chain.name, from_block, to_block
+                );
+                // Lower half is popped first to keep the logs in order
+                pending_ranges.push((middle_block + 1, to_block));
+                pending_ranges.push((from_block, middle_block));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(logs)
+}
+
+fn is_log_limit_error(err: &ProviderError) -> bool {
+    let ProviderError::JsonRpcClientError(rpc_error) = err else {
+        return false;
+    };
+
+    rpc_error.as_error_response().is_some_and(|response| {
+        let message = response.message.to_lowercase();
+        // -32005 is also used for rate limiting, which splitting would only make worse
+        let is_rate_limit = message.contains("rate") || message.contains("requests");
+
+        !is_rate_limit
+            && (response.code == -32005
+                || [
+                    "too many results",
+                    "more than",
+                    "range",
+                    "limited to",
+                    "response size",
+                ]
+                .iter()
+                .any(|hint| message.contains(hint)))
+    })
+}
+
+async fn get_range_logs(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    from_block: U64,
+    to_block: U64,
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Result<Vec<Log>, ProviderError> {
+    if chain.server_side_filtering {
+        get_watched_logs(provider, from_block, to_block, watched_addresses_as_topics).await
+    } else {
+        provider
+            .get_logs(&LogFilter::new().from_block(from_block).to_block(to_block))
+            .await
+    }
+}
+
+/// Gets only the logs which have a watched account as one of the indexed topics, querying each
+/// topic position separately since filters can only OR values within the same position, and the
+/// contract control events emitted by watched accounts
+async fn get_watched_logs(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    from_block: U64,
+    to_block: U64,
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Result<Vec<Log>, ProviderError> {
+    let watched_addresses_as_topics: Vec<H256> =
+        watched_addresses_as_topics.iter().cloned().collect();
+
+    let mut logs = vec![];
+    for topics in watched_addresses_as_topics.chunks(MAX_FILTER_TOPICS) {
+        let range_filter = LogFilter::new().from_block(from_block).to_block(to_block);
+        for filter in [
+            range_filter.clone().topic1(topics.to_vec()),
+            range_filter.clone().topic2(topics.to_vec()),
+            range_filter.clone().topic3(topics.to_vec()),
+            range_filter
+                .address(
+                    topics
+                        .iter()
+                        .map(|topic| Address::from(*topic))
+                        .collect::<Vec<Address>>(),
+                )
+                .topic0(vec![
+                    *UPGRADED_TOPIC,
+                    *ADMIN_CHANGED_TOPIC,
+                    *OWNERSHIP_TRANSFERRED_TOPIC,
+                ]),
+        ] {
+            logs.extend(provider.get_logs(&filter).await?);
+        }
+    }
+
+    // The same log is returned once per position holding a watched account
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+    logs.dedup_by_key(|log| (log.block_number, log.log_index));
+
+    Ok(logs)
+}
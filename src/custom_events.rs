@@ -0,0 +1,120 @@
+use ethers::core::types::{Address, Log, H256};
+use handlebars::Handlebars;
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::Serialize;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::env;
+
+use crate::{
+    abi_registry::{self, DecodedEvent},
+    decoding::LogDecoder,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+};
+
+/// Event watched by the user, notified with its own message
+#[derive(Debug, DeserializeMacro)]
+pub struct CustomEvent {
+    pub name: String,
+    pub topic: H256,
+    /// Indexed parameter holding the watched account, from 1 to 3
+    pub position: usize,
+    pub message: String,
+}
+
+lazy_static! {
+    /// Events of `CUSTOM_EVENTS_PATH`
+    static ref CUSTOM_EVENTS: Vec<CustomEvent> = match env::var("CUSTOM_EVENTS_PATH") {
+        Ok(custom_events_path) => {
+            let file = std::fs::File::open(custom_events_path)
+                .expect("Could not open custom events file.");
+            serde_yaml::from_reader(file).expect("Could not read custom events.")
+        }
+        Err(_) => vec![],
+    };
+    /// Messages of the custom events, named after the event
+    static ref MESSAGES: Handlebars<'static> = {
+        let mut messages = Handlebars::new();
+        messages.register_escape_fn(handlebars::no_escape);
+        messages.set_strict_mode(true);
+
+        for event in CUSTOM_EVENTS.iter() {
+            if !(1..=3).contains(&event.position) {
+                panic!(
+                    "Invalid position {} of the {} custom event, indexed parameters go from 1 to 3",
+                    event.position, event.name
+                );
+            }
+            if messages.has_template(&event.name) {
+                panic!("Duplicate {} custom event", event.name);
+            }
+            messages
+                .register_template_string(&event.name, &event.message)
+                .unwrap_or_else(|err| panic!("Invalid {} custom event message: {}", event.name, err));
+        }
+
+        messages
+    };
+}
+
+/// Decoders of the custom events, validating them on startup
+pub fn load() -> Vec<&'static CustomEvent> {
+    lazy_static::initialize(&MESSAGES);
+    if !CUSTOM_EVENTS.is_empty() {
+        info!("Loaded {} custom events", CUSTOM_EVENTS.len());
+    }
+    CUSTOM_EVENTS.iter().collect()
+}
+
+/// Message of the custom event. Variables are only built when needed
+pub fn render<T: Serialize>(name: &str, variables: impl FnOnce() -> T) -> Option<String> {
+    MESSAGES
+        .render(name, &variables())
+        .inspect_err(|err| {
+            error!(
+                "Error while rendering {} custom event message: {}",
+                name, err
+            )
+        })
+        .ok()
+}
+
+impl LogDecoder for &'static CustomEvent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn topic(&self) -> H256 {
+        self.topic
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        // The account is only watched in its position
+        if *log.topics.get(self.position)? != H256::from(involved_account) {
+            return None;
+        }
+
+        Some(InterestingTransaction {
+            hash: log.transaction_hash.unwrap(),
+            from: None,
+            to: None,
+            kind: InterestingTransactionKind::Other,
+            amount: None,
+            token: None,
+            token_id: None,
+            involved_account,
+            event: abi_registry::decode(log).or_else(|| {
+                Some(DecodedEvent {
+                    contract: log.address,
+                    name: self.name.clone(),
+                    params: vec![],
+                })
+            }),
+            custom_event: Some(self.name.clone()),
+            function: None,
+            block_number: log.block_number,
+            timestamp: None,
+            fee: None,
+        })
+    }
+}
@@ -0,0 +1,401 @@
+use ethers::core::{
+    abi::AbiDecode,
+    types::{Address, Log, Transaction, TransactionReceipt, H256, U256},
+};
+use lazy_static::lazy_static;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use crate::{
+    abi_registry, custom_events,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    protocol_decoders, transaction_details, FullString,
+};
+
+lazy_static! {
+    /// EIP-1967 `Upgraded(address)`, emitted by proxies
+    pub static ref UPGRADED_TOPIC: H256 =
+        H256::from_str("0xbc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b").unwrap();
+    /// EIP-1967 `AdminChanged(address,address)`, emitted by proxies
+    pub static ref ADMIN_CHANGED_TOPIC: H256 =
+        H256::from_str("0x7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f").unwrap();
+    /// OpenZeppelin `OwnershipTransferred(address,address)`, emitted by ownable contracts
+    pub static ref OWNERSHIP_TRANSFERRED_TOPIC: H256 =
+        H256::from_str("0x8be0079c531659141344cd1fd0a4f28419497f9722a3daafe3b4186f6b6457e0").unwrap();
+    /// ERC20 and ERC721 `Transfer(address,address,uint256)`
+    pub static ref TRANSFER_TOPIC: H256 =
+        H256::from_str("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap();
+    /// ERC1155 `TransferSingle(address,address,address,uint256,uint256)`
+    pub static ref TRANSFER_SINGLE_TOPIC: H256 =
+        H256::from_str("0xc3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62").unwrap();
+    /// ERC20 and ERC721 `Approval(address,address,uint256)`
+    pub static ref APPROVAL_TOPIC: H256 =
+        H256::from_str("0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925").unwrap();
+    /// ERC721 and ERC1155 `ApprovalForAll(address,address,bool)`
+    pub static ref APPROVAL_FOR_ALL_TOPIC: H256 =
+        H256::from_str("0x17307eab39ab6107e8899845ad3d59bd9653f200f220920489ca2b5937696c31").unwrap();
+
+    /// Decoders of the events with a watched account as an indexed parameter, by topic. Holds the
+    /// token events, the protocols, the custom events and the decoders registered on startup
+    static ref LOG_DECODERS: RwLock<HashMap<H256, Box<dyn LogDecoder>>> = {
+        let mut decoders: Vec<Box<dyn LogDecoder>> = vec![
+            Box::new(Erc20Transfer),
+            Box::new(Erc1155TransferSingle),
+            Box::new(Erc20Approval),
+            Box::new(NftApprovalForAll),
+        ];
+        decoders.extend(
+            protocol_decoders::load()
+                .into_iter()
+                .map(|decoder| Box::new(decoder) as Box<dyn LogDecoder>),
+        );
+        decoders.extend(
+            custom_events::load()
+                .into_iter()
+                .map(|decoder| Box::new(decoder) as Box<dyn LogDecoder>),
+        );
+
+        let mut log_decoders: HashMap<H256, Box<dyn LogDecoder>> = HashMap::new();
+        for decoder in decoders {
+            if let Some(registered) = log_decoders.get(&decoder.topic()) {
+                panic!(
+                    "{} event has the same topic as {}",
+                    decoder.name(),
+                    registered.name()
+                );
+            }
+            log_decoders.insert(decoder.topic(), decoder);
+        }
+        RwLock::new(log_decoders)
+    };
+}
+
+/// Decoder of an event, identified by its topic, into a transaction of a watched account
+pub trait LogDecoder: Send + Sync {
+    fn name(&self) -> &str;
+    fn topic(&self) -> H256;
+    /// Transaction of the log involving the account, `None` when the log doesn't match the event
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction>;
+}
+
+/// Token event with the sender and recipient as indexed parameters
+fn token_transaction(
+    log: &Log,
+    kind: InterestingTransactionKind,
+    from: &H256,
+    to: &H256,
+    amount: U256,
+    involved_account: Address,
+) -> InterestingTransaction {
+    InterestingTransaction {
+        hash: log.transaction_hash.unwrap(),
+        from: Some(Address::from(*from)),
+        to: Some(Address::from(*to)),
+        kind,
+        amount: Some(amount),
+        token: Some(log.address),
+        token_id: None,
+        involved_account,
+        event: None,
+        custom_event: None,
+        function: None,
+        block_number: log.block_number,
+        timestamp: None,
+        fee: None,
+    }
+}
+
+struct Erc20Transfer;
+
+impl LogDecoder for Erc20Transfer {
+    fn name(&self) -> &str {
+        "Transfer"
+    }
+
+    fn topic(&self) -> H256 {
+        *TRANSFER_TOPIC
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let mut tx = token_transaction(
+            log,
+            InterestingTransactionKind::Transfer,
+            log.topics.get(1)?,
+            log.topics.get(2)?,
+            U256::decode(&log.data).unwrap_or_default(),
+            involved_account,
+        );
+        // ERC721 transfers have the token id as a third indexed parameter
+        tx.token_id = log
+            .topics
+            .get(3)
+            .map(|token_id| U256::from_big_endian(token_id.as_bytes()));
+        Some(tx)
+    }
+}
+
+struct Erc1155TransferSingle;
+
+impl LogDecoder for Erc1155TransferSingle {
+    fn name(&self) -> &str {
+        "TransferSingle"
+    }
+
+    fn topic(&self) -> H256 {
+        *TRANSFER_SINGLE_TOPIC
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let mut tx = token_transaction(
+            log,
+            InterestingTransactionKind::Transfer1155,
+            log.topics.get(2)?,
+            log.topics.get(3)?,
+            U256::zero(),
+            involved_account,
+        );
+        tx.token_id = <(U256, U256)>::decode(&log.data)
+            .ok()
+            .map(|(token_id, _)| token_id);
+        Some(tx)
+    }
+}
+
+struct Erc20Approval;
+
+impl LogDecoder for Erc20Approval {
+    fn name(&self) -> &str {
+        "Approval"
+    }
+
+    fn topic(&self) -> H256 {
+        *APPROVAL_TOPIC
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        Some(token_transaction(
+            log,
+            InterestingTransactionKind::Approval,
+            log.topics.get(1)?,
+            log.topics.get(2)?,
+            U256::decode(&log.data).unwrap_or_default(),
+            involved_account,
+        ))
+    }
+}
+
+struct NftApprovalForAll;
+
+impl LogDecoder for NftApprovalForAll {
+    fn name(&self) -> &str {
+        "ApprovalForAll"
+    }
+
+    fn topic(&self) -> H256 {
+        *APPROVAL_FOR_ALL_TOPIC
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        // Operators approved for all the tokens of the collection have an infinite allowance
+        let amount = match U256::decode(&log.data).unwrap_or_default().is_zero() {
+            true => U256::zero(),
+            false => U256::MAX,
+        };
+        Some(token_transaction(
+            log,
+            InterestingTransactionKind::Approval,
+            log.topics.get(1)?,
+            log.topics.get(2)?,
+            amount,
+            involved_account,
+        ))
+    }
+}
+
+/// Loads the built-in decoders and the protocols, failing on startup when they are invalid
+pub fn load() {
+    lazy_static::initialize(&LOG_DECODERS);
+}
+
+/// Adds a decoder for an event not decoded yet, fails when its topic already has a decoder
+pub fn register_decoder(decoder: Box<dyn LogDecoder>) -> Result<(), String> {
+    let mut log_decoders = LOG_DECODERS.write().unwrap();
+    if let Some(registered) = log_decoders.get(&decoder.topic()) {
+        return Err(format!(
+            "{} event has the same topic as {}",
+            decoder.name(),
+            registered.name()
+        ));
+    }
+    info!("Registered {} event decoder", decoder.name());
+    log_decoders.insert(decoder.topic(), decoder);
+    Ok(())
+}
+
+/// Name of the decoder of the event, if any
+pub fn decoder_name(topic: &H256) -> Option<String> {
+    LOG_DECODERS
+        .read()
+        .unwrap()
+        .get(topic)
+        .map(|decoder| decoder.name().to_string())
+}
+
+/// Transactions of the logs emitted by or involving the watched accounts, given as topics
+#[tracing::instrument(skip_all, fields(logs = logs.len()))]
+pub fn parse_logs(
+    logs: &[Log],
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Vec<InterestingTransaction> {
+    let log_decoders = LOG_DECODERS.read().unwrap();
+    let mut interesting_transactions: Vec<InterestingTransaction> = vec![];
+    for log in logs.iter() {
+        if watched_addresses_as_topics.contains(&H256::from(log.address)) {
+            interesting_transactions.extend(parse_contract_control_log(log));
+        }
+
+        for topic in log.topics.iter() {
+            if watched_addresses_as_topics.contains(topic) {
+                let involved_account = Address::from_str(&topic.full_string()[26..]).unwrap();
+
+                let decoded = log_decoders
+                    .get(&log.topics[0])
+                    .and_then(|decoder| decoder.decode(log, involved_account));
+
+                // Add as unknown transaction if no known logs were emmited
+                interesting_transactions.push(decoded.unwrap_or_else(|| InterestingTransaction {
+                    hash: log.transaction_hash.unwrap(),
+                    involved_account,
+                    from: None,
+                    to: None,
+                    kind: InterestingTransactionKind::Other,
+                    amount: None,
+                    token: None,
+                    token_id: None,
+                    event: abi_registry::decode(log),
+                    custom_event: None,
+                    function: None,
+                    block_number: log.block_number,
+                    timestamp: None,
+                    fee: None,
+                }));
+            }
+        }
+    }
+    interesting_transactions
+}
+
+/// Proxy upgrade, or admin or owner change, emitted by a watched contract
+fn parse_contract_control_log(log: &Log) -> Option<InterestingTransaction> {
+    let topic = log.topics.first()?;
+    let (kind, from, to) = if *topic == *UPGRADED_TOPIC {
+        (
+            InterestingTransactionKind::Upgrade,
+            None,
+            Address::from(*log.topics.get(1)?),
+        )
+    } else if *topic == *ADMIN_CHANGED_TOPIC {
+        let (previous_admin, new_admin) = <(Address, Address)>::decode(&log.data).ok()?;
+        (
+            InterestingTransactionKind::AdminChange,
+            Some(previous_admin),
+            new_admin,
+        )
+    } else if *topic == *OWNERSHIP_TRANSFERRED_TOPIC {
+        (
+            InterestingTransactionKind::OwnershipTransfer,
+            Some(Address::from(*log.topics.get(1)?)),
+            Address::from(*log.topics.get(2)?),
+        )
+    } else {
+        return None;
+    };
+
+    Some(InterestingTransaction {
+        hash: log.transaction_hash?,
+        from,
+        to: Some(to),
+        kind,
+        amount: None,
+        token: None,
+        token_id: None,
+        involved_account: log.address,
+        event: None,
+        custom_event: None,
+        function: None,
+        block_number: log.block_number,
+        timestamp: None,
+        fee: None,
+    })
+}
+
+/// Transactions of a block's receipts involving the watched accounts, with their fees
+#[tracing::instrument(skip_all, fields(receipts = block.len()))]
+pub fn process_block(
+    block: &[TransactionReceipt],
+    watched_addresses_as_topics: &HashSet<H256>,
+) -> Vec<InterestingTransaction> {
+    block
+        .iter()
+        .flat_map(|receipt| {
+            let mut interesting_transactions =
+                parse_logs(&receipt.logs, watched_addresses_as_topics);
+            for tx in interesting_transactions.iter_mut() {
+                tx.fee = transaction_details::fee(receipt);
+            }
+            if interesting_transactions.is_empty() {
+                let involved_account =
+                    if watched_addresses_as_topics.contains(&H256::from(receipt.from)) {
+                        Some(receipt.from)
+                    } else {
+                        receipt
+                            .to
+                            .filter(|to| watched_addresses_as_topics.contains(&H256::from(*to)))
+                    };
+
+                if let Some(involved_account) = involved_account {
+                    interesting_transactions.push(InterestingTransaction {
+                        hash: receipt.transaction_hash,
+                        from: Some(receipt.from),
+                        to: receipt.to,
+                        kind: if receipt.gas_used.unwrap() == U256::from_dec_str("21000").unwrap() {
+                            InterestingTransactionKind::Send
+                        } else {
+                            InterestingTransactionKind::Other
+                        },
+                        amount: None,
+                        token: None,
+                        token_id: None,
+                        involved_account,
+                        event: None,
+                        custom_event: None,
+                        function: None,
+                        block_number: receipt.block_number,
+                        timestamp: None,
+                        fee: transaction_details::fee(receipt),
+                    });
+                }
+            }
+            interesting_transactions
+        })
+        .collect()
+}
+
+/// Sets the amount of the native sends found through their receipts, which don't have it, from
+/// the transactions of their block
+pub fn add_send_values(
+    interesting_transactions: &mut [InterestingTransaction],
+    transactions: &[Transaction],
+) {
+    for tx in interesting_transactions
+        .iter_mut()
+        .filter(|tx| tx.kind == InterestingTransactionKind::Send && tx.amount.is_none())
+    {
+        tx.amount = transactions
+            .iter()
+            .find(|transaction| transaction.hash == tx.hash)
+            .map(|transaction| transaction.value);
+    }
+}
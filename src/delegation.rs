@@ -0,0 +1,479 @@
+use ethers::{
+    core::{
+        types::{Address, Bytes, RecoveryMessage, Signature, H256, U256, U64},
+        utils::{keccak256, rlp::RlpStream},
+    },
+    middleware::Middleware,
+    providers::{Provider, ProviderError},
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use rusqlite::{named_params, OptionalExtension};
+use serde_derive::Deserialize as DeserializeMacro;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{
+    batch,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+    state_db::CONNECTION,
+    tenants,
+    throttled_http::ThrottledHttp,
+};
+use crate::{FullString, ToLabel};
+
+/// Code of an EOA delegating to a contract, followed by the contract address (EIP-7702)
+const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+/// Prefix of the signed message of an authorization (EIP-7702)
+const AUTHORIZATION_MAGIC: u8 = 0x05;
+const SET_CODE_TX_TYPE: u64 = 4;
+/// Blocks read per check at most, the changes of older blocks are only found by their code
+const MAX_BLOCK_RANGE: u64 = 1000;
+
+lazy_static! {
+    /// Seconds between checks of the delegations of the watched accounts, `0` disables them
+    pub static ref DELEGATION_CHECK_INTERVAL: Duration = Duration::from_secs(
+        env::var("DELEGATION_CHECK_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid DELEGATION_CHECK_INTERVAL"))
+            .unwrap_or(60)
+    );
+}
+
+pub fn is_enabled() -> bool {
+    !DELEGATION_CHECK_INTERVAL.is_zero()
+}
+
+/// Delegation signed by an account, part of the authorization list of a set-code transaction
+#[derive(DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct Authorization {
+    chain_id: U256,
+    address: Address,
+    nonce: U64,
+    #[serde(alias = "v")]
+    y_parity: U64,
+    r: U256,
+    s: U256,
+}
+
+impl Authorization {
+    /// Account which signed the authorization
+    fn authority(&self) -> Option<Address> {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.chain_id);
+        stream.append(&self.address);
+        stream.append(&self.nonce);
+        let mut message = vec![AUTHORIZATION_MAGIC];
+        message.extend_from_slice(&stream.out());
+
+        Signature {
+            r: self.r,
+            s: self.s,
+            v: self.y_parity.as_u64(),
+        }
+        .recover(RecoveryMessage::Hash(H256::from(keccak256(message))))
+        .ok()
+    }
+
+    /// Contract delegated to, `None` when the authorization removes the delegation
+    fn delegate(&self) -> Option<Address> {
+        Some(self.address).filter(|address| !address.is_zero())
+    }
+}
+
+#[derive(DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct SetCodeTransaction {
+    hash: H256,
+    #[serde(rename = "type")]
+    transaction_type: Option<U64>,
+    #[serde(default)]
+    authorization_list: Vec<Authorization>,
+}
+
+#[derive(DeserializeMacro)]
+struct BlockTransactions {
+    transactions: Vec<SetCodeTransaction>,
+}
+
+/// Delegations of the watched accounts known by a previous run, by account
+fn load_delegates(chain: &Chain) -> HashMap<Address, Option<Address>> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return HashMap::new();
+    };
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare("SELECT account, delegate FROM delegations WHERE chain = :chain")
+        .and_then(|mut statement| {
+            statement
+                .query_map(named_params! {":chain": chain.name}, |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    match rows {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|(account, delegate)| {
+                Some((
+                    Address::from_str(&account).ok()?,
+                    delegate.and_then(|delegate| Address::from_str(&delegate).ok()),
+                ))
+            })
+            .collect(),
+        Err(err) => {
+            error!(
+                "Error while reading the {} delegations: {}",
+                chain.name, err
+            );
+            HashMap::new()
+        }
+    }
+}
+
+fn store_delegate(chain: &Chain, account: Address, delegate: Option<Address>) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR REPLACE INTO delegations (chain, account, delegate)
+        VALUES (:chain, :account, :delegate)",
+        named_params! {
+            ":chain": chain.name,
+            ":account": account.full_string(),
+            ":delegate": delegate.map(|delegate| delegate.full_string()),
+        },
+    ) {
+        error!(
+            "Error while storing the delegation of {}: {}",
+            account.full_string(),
+            err
+        );
+    }
+}
+
+/// Last block checked by a previous run
+fn last_checked_block(chain: &Chain) -> Option<U64> {
+    let connection = CONNECTION.as_ref()?.lock().unwrap();
+    connection
+        .query_row(
+            "SELECT block FROM delegation_progress WHERE chain = :chain",
+            named_params! {":chain": chain.name},
+            |row| row.get::<_, u64>(0),
+        )
+        .optional()
+        .unwrap_or_else(|err| {
+            error!(
+                "Error while reading {} delegation progress: {}",
+                chain.name, err
+            );
+            None
+        })
+        .map(U64::from)
+}
+
+fn save_progress(chain: &Chain, block_number: U64) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT INTO delegation_progress (chain, block) VALUES (:chain, :block)
+        ON CONFLICT (chain) DO UPDATE SET block = :block",
+        named_params! {":chain": chain.name, ":block": block_number.as_u64()},
+    ) {
+        error!(
+            "Error while saving {} delegation progress: {}",
+            chain.name, err
+        );
+    }
+}
+
+/// Delegations signed by the watched accounts in the set-code transactions of the blocks, in
+/// order, with the transaction setting them
+async fn read_authorizations(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    from_block: U64,
+    to_block: U64,
+    accounts: &[Address],
+) -> Result<Vec<(Address, Option<Address>, H256)>, ProviderError> {
+    let params: Vec<serde_json::Value> = (from_block.as_u64()..=to_block.as_u64())
+        .map(|block_number| json!([U64::from(block_number), true]))
+        .collect();
+    let blocks = batch::batch_request::<_, Option<BlockTransactions>>(
+        provider,
+        "eth_getBlockByNumber",
+        &params,
+    )
+    .await?;
+
+    Ok(blocks
+        .into_iter()
+        .flatten()
+        .flat_map(|block| block.transactions)
+        .filter(|tx| tx.transaction_type == Some(U64::from(SET_CODE_TX_TYPE)))
+        .flat_map(|tx| {
+            let hash = tx.hash;
+            tx.authorization_list
+                .into_iter()
+                .map(move |authorization| (hash, authorization))
+        })
+        // Authorizations for another chain are invalid, `0` is valid on every chain
+        .filter(|(_, authorization)| {
+            authorization.chain_id.is_zero() || Some(authorization.chain_id) == chain.id
+        })
+        .filter_map(|(hash, authorization)| {
+            let authority = authorization.authority()?;
+            accounts
+                .contains(&authority)
+                .then(|| (authority, authorization.delegate(), hash))
+        })
+        .collect())
+}
+
+/// Contract an account delegates to, `Ok(None)` for plain EOAs and `Err` for contracts
+fn delegate(code: &Bytes) -> Result<Option<Address>, ()> {
+    if code.is_empty() {
+        Ok(None)
+    } else if code.len() == DELEGATION_PREFIX.len() + Address::len_bytes()
+        && code.starts_with(&DELEGATION_PREFIX)
+    {
+        Ok(Some(Address::from_slice(&code[DELEGATION_PREFIX.len()..])))
+    } else {
+        Err(())
+    }
+}
+
+fn change_notification(
+    chain: &Chain,
+    account: Address,
+    previous: Option<Address>,
+    current: Option<Address>,
+    hash: Option<H256>,
+    addressbook: &HashMap<String, String>,
+) -> Notification {
+    let label = account.to_label(addressbook);
+    let (message, severity) = match (previous, current) {
+        (None, Some(current)) => (
+            format!(
+                "⚠ {} delegated its code to {} on {} (EIP-7702), the contract can now move all its assets",
+                label,
+                current.to_label(addressbook),
+                chain.name
+            ),
+            Severity::Critical,
+        ),
+        (Some(previous), Some(current)) => (
+            format!(
+                "⚠ {} changed its EIP-7702 delegation on {} from {} to {}",
+                label,
+                chain.name,
+                previous.to_label(addressbook),
+                current.to_label(addressbook)
+            ),
+            Severity::Critical,
+        ),
+        (Some(previous), None) => (
+            format!(
+                "{} removed its EIP-7702 delegation to {} on {}",
+                label,
+                previous.to_label(addressbook),
+                chain.name
+            ),
+            Severity::High,
+        ),
+        (None, None) => unreachable!("Unchanged delegation"),
+    };
+
+    Notification {
+        message,
+        url: hash.map_or_else(|| chain.address_url(account), |hash| chain.tx_url(hash)),
+        severity: Some(severity),
+        route: tenants::route_of(&account),
+        image: None,
+        links: vec![],
+    }
+}
+
+/// Periodically reads the authorizations of the set-code transactions since the last check, and
+/// the code of the watched accounts, alerting when one of them sets, changes or removes its
+/// EIP-7702 delegation. Every authorization is notified, even when changed again before the
+/// check, and the code catches the changes of the blocks skipped after a long pause. The
+/// delegation of an account when it is first seen is only logged. The delegations and the last
+/// block checked are kept across restarts in the state DB
+pub async fn watch_delegations(chain: Chain, addressbook: Arc<Mutex<HashMap<String, String>>>) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut delegates: HashMap<Address, Option<Address>> = load_delegates(&chain);
+    let mut last_block: Option<U64> = last_checked_block(&chain);
+
+    loop {
+        sleep(*DELEGATION_CHECK_INTERVAL).await;
+        if chain_control::is_paused(&chain.name) {
+            continue;
+        }
+
+        let latest_block = match provider.get_block_number().await {
+            Ok(latest_block) => latest_block,
+            Err(err) => {
+                log_rpc_error(&chain, "block number", &err);
+                continue;
+            }
+        };
+        let accounts: Vec<Address> = addressbook
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|address| Address::from_str(address).ok())
+            .collect();
+
+        let authorizations = match last_block {
+            Some(last_block) if last_block < latest_block => {
+                let from_block =
+                    (last_block + 1).max(latest_block.saturating_sub(MAX_BLOCK_RANGE.into()));
+                match read_authorizations(&provider, &chain, from_block, latest_block, &accounts)
+                    .await
+                {
+                    Ok(authorizations) => authorizations,
+                    Err(err) => {
+                        log_rpc_error(&chain, "set-code transactions", &err);
+                        continue;
+                    }
+                }
+            }
+            _ => vec![],
+        };
+
+        let params: Vec<serde_json::Value> = accounts
+            .iter()
+            .map(|account| json!([account, latest_block]))
+            .collect();
+        let codes = match batch::batch_request::<_, Bytes>(&provider, "eth_getCode", &params).await
+        {
+            Ok(codes) => codes,
+            Err(err) => {
+                log_rpc_error(&chain, "account codes", &err);
+                continue;
+            }
+        };
+
+        let mut notifications = vec![];
+        let addressbook = addressbook.lock().unwrap().clone();
+        for (account, code) in accounts.into_iter().zip(codes) {
+            // Contracts can't delegate
+            let Ok(current) = delegate(&code) else {
+                continue;
+            };
+            let Some(&previous) = delegates.get(&account) else {
+                if let Some(current) = current {
+                    info!(
+                        "{} delegates to {} on {}",
+                        account.full_string(),
+                        current.full_string(),
+                        chain.name
+                    );
+                }
+                delegates.insert(account, current);
+                store_delegate(&chain, account, current);
+                continue;
+            };
+
+            // Each authorization, then the code if it differs from the last one, such as after
+            // an invalid authorization or a skipped range
+            let changes = authorizations
+                .iter()
+                .filter(|(authority, _, _)| *authority == account)
+                .map(|(_, delegate, hash)| (*delegate, Some(*hash)))
+                .chain([(current, None)]);
+            let mut known = previous;
+            for (delegate, hash) in changes {
+                if delegate == known {
+                    continue;
+                }
+                warn!(
+                    "{} delegation changed from {:?} to {:?} on {}",
+                    account.full_string(),
+                    known,
+                    delegate,
+                    chain.name
+                );
+                notifications.push(change_notification(
+                    &chain,
+                    account,
+                    known,
+                    delegate,
+                    hash,
+                    &addressbook,
+                ));
+                known = delegate;
+            }
+            if current != previous {
+                delegates.insert(account, current);
+                store_delegate(&chain, account, current);
+            }
+        }
+        last_block = Some(latest_block);
+        save_progress(&chain, latest_block);
+        debug!(
+            "Checked the delegations of {} accounts on {}",
+            delegates.len(),
+            chain.name
+        );
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending delegation notification");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[test]
+    fn recovers_the_authority_of_an_authorization() {
+        let wallet = LocalWallet::from_str(
+            "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+        )
+        .unwrap();
+        let delegate = Address::repeat_byte(0x22);
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&U256::from(100));
+        stream.append(&delegate);
+        stream.append(&U64::from(7));
+        let mut message = vec![AUTHORIZATION_MAGIC];
+        message.extend_from_slice(&stream.out());
+        let signature = wallet.sign_hash(H256::from(keccak256(message))).unwrap();
+
+        // RPCs return the parity of the signature, not its legacy `v`
+        let authorization: Authorization = serde_json::from_value(json!({
+            "chainId": "0x64",
+            "address": delegate,
+            "nonce": "0x7",
+            "yParity": format!("{:#x}", signature.v - 27),
+            "r": signature.r,
+            "s": signature.s,
+        }))
+        .unwrap();
+        assert_eq!(authorization.authority(), Some(wallet.address()));
+        assert_eq!(authorization.delegate(), Some(delegate));
+    }
+}
@@ -0,0 +1,150 @@
+use serde_derive::Serialize as SerializeMacro;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::{
+    allowance_audit, bridges,
+    chain::{Chain, ChainMode},
+    chain_control::{self, rpc_origin},
+    delegation, ens_names, escalation, history, kafka, mqtt, nats, nft_metadata,
+    notification::{self, NTFY},
+    redis_backend, retryables, rotki, scam_list, selector_watch, state_db, stuck_transactions,
+    withdrawals,
+};
+
+/// Shown instead of the configured secrets
+const MASK: &str = "********";
+
+/// Configuration of the running instance, as served by the API. Secrets are masked and URLs only
+/// keep their origin
+#[derive(SerializeMacro, ToSchema)]
+pub struct EffectiveConfig {
+    chains: Vec<ChainConfig>,
+    sinks: Vec<&'static str>,
+    ntfy: Option<NtfyConfig>,
+    /// Count of watched accounts
+    accounts: usize,
+    /// Optional features which are configured
+    features: Vec<&'static str>,
+}
+
+#[derive(SerializeMacro, ToSchema)]
+struct ChainConfig {
+    name: String,
+    id: Option<String>,
+    rpc: String,
+    /// Names of the headers sent to the RPC
+    rpc_headers: Vec<String>,
+    rpc_basic_auth: Option<&'static str>,
+    mode: ChainMode,
+    spam_filter_level: String,
+    confirmations: String,
+    /// Milliseconds
+    blocktime: u128,
+    /// Seconds
+    max_block_age: u64,
+    server_side_filtering: bool,
+    concurrency: u64,
+    max_rps: Option<u32>,
+    max_block_range: u64,
+    head_delay: u64,
+    /// Seconds, `None` for the default `WITHDRAWAL_CHALLENGE_PERIOD`
+    withdrawal_challenge_period: Option<u64>,
+    native_symbol: String,
+    native_decimals: u32,
+    explorer: Option<String>,
+    explorer_api: Option<String>,
+    explorer_api_key: Option<&'static str>,
+    safe_transaction_service: Option<String>,
+    webhook_secret: Option<&'static str>,
+}
+
+#[derive(SerializeMacro, ToSchema)]
+struct NtfyConfig {
+    url: String,
+    /// Anyone knowing the topic can read its notifications, so it is masked like a secret
+    topic: &'static str,
+    token: &'static str,
+}
+
+fn masked<T>(secret: &Option<T>) -> Option<&'static str> {
+    secret.as_ref().map(|_| MASK)
+}
+
+impl From<&Chain> for ChainConfig {
+    fn from(chain: &Chain) -> Self {
+        ChainConfig {
+            name: chain.name.clone(),
+            id: chain.id.map(|id| id.to_string()),
+            rpc: rpc_origin(&chain.rpc),
+            rpc_headers: chain
+                .rpc_headers
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            rpc_basic_auth: masked(&chain.rpc_basic_auth),
+            mode: chain.mode.clone(),
+            spam_filter_level: format!("{:?}", chain.spam_filter_level),
+            confirmations: format!("{:?}", chain.confirmations),
+            blocktime: chain.blocktime.as_millis(),
+            max_block_age: chain.max_block_age.as_secs(),
+            server_side_filtering: chain.server_side_filtering,
+            concurrency: chain.concurrency,
+            max_rps: chain.max_rps,
+            max_block_range: chain.max_block_range,
+            head_delay: chain.head_delay,
+            withdrawal_challenge_period: chain
+                .withdrawal_challenge_period
+                .map(|period| period.as_secs()),
+            native_symbol: chain.native_symbol.clone(),
+            native_decimals: chain.native_decimals,
+            explorer: chain.explorer.clone(),
+            explorer_api: chain.explorer_api.as_deref().map(rpc_origin),
+            explorer_api_key: masked(&chain.explorer_api_key),
+            safe_transaction_service: chain.safe_transaction_service.as_deref().map(rpc_origin),
+            webhook_secret: masked(&chain.webhook_secret),
+        }
+    }
+}
+
+/// Configuration of the monitored chains, including the ones added through the API, and of the
+/// notification sinks and features
+pub fn current(addressbook: &HashMap<String, String>) -> EffectiveConfig {
+    EffectiveConfig {
+        chains: chain_control::chains()
+            .iter()
+            .map(ChainConfig::from)
+            .collect(),
+        sinks: notification::configured_sinks(),
+        ntfy: NTFY.as_ref().map(|ntfy| NtfyConfig {
+            url: rpc_origin(&ntfy.url),
+            topic: MASK,
+            token: MASK,
+        }),
+        accounts: addressbook.len(),
+        features: [
+            ("allowance_audit", allowance_audit::is_enabled()),
+            ("bridges", bridges::is_enabled()),
+            ("delegation", delegation::is_enabled()),
+            ("ens_names", ens_names::is_enabled()),
+            ("escalation", escalation::is_enabled()),
+            ("history", history::is_enabled()),
+            ("kafka", kafka::is_enabled()),
+            ("mqtt", mqtt::is_enabled()),
+            ("nats", nats::is_enabled()),
+            ("nft_metadata", nft_metadata::is_enabled()),
+            ("redis", redis_backend::is_enabled()),
+            ("retryables", retryables::is_enabled()),
+            ("rotki", rotki::is_enabled()),
+            ("scam_list", scam_list::is_enabled()),
+            ("selector_watch", selector_watch::is_enabled()),
+            ("state_db", state_db::is_enabled()),
+            ("stuck_transactions", stuck_transactions::is_enabled()),
+            ("withdrawals", withdrawals::is_enabled()),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature)
+        .collect(),
+    }
+}
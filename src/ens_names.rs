@@ -0,0 +1,386 @@
+use ethers::{
+    core::{
+        abi::{AbiDecode, AbiEncode},
+        types::{Address, Bytes, H256, U256},
+        utils::{hex, keccak256},
+    },
+    providers::{ens::namehash, Provider, ProviderError},
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    batch,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+    schedule::{self, ScheduledEvent},
+    tenants,
+    throttled_http::ThrottledHttp,
+};
+use crate::{ToLabel, UtcDateTime};
+
+/// ENS names live on Ethereum mainnet
+const MAINNET_CHAIN_ID: u64 = 1;
+const OWNER_SELECTOR: &str = "0x02571be3";
+const RESOLVER_SELECTOR: &str = "0x0178b8bf";
+const OWNER_OF_SELECTOR: &str = "0x6352211e";
+const NAME_EXPIRES_SELECTOR: &str = "0xd6e4fa86";
+const ADDR_SELECTOR: &str = "0x3b3b57de";
+const CONTENTHASH_SELECTOR: &str = "0xbc1c58d1";
+/// Days before the expiration of a name its reminders are sent
+const EXPIRATION_REMINDERS: [u64; 3] = [30, 7, 1];
+
+lazy_static! {
+    static ref ENS_REGISTRY: Address =
+        Address::from_str("0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e").unwrap();
+    /// Registrar of the `.eth` second level names, holding their expirations
+    static ref BASE_REGISTRAR: Address =
+        Address::from_str("0x57f1887a8BF19b14fC0dF6Fd9B2acc9Af147eA85").unwrap();
+    static ref NAME_WRAPPER: Address =
+        Address::from_str("0xD4416b13d2b3a9aBae7AcD5D6C2BbDBE25686401").unwrap();
+    /// Names of `ENS_NAMES_PATH`
+    static ref ENS_NAMES: Vec<String> = match env::var("ENS_NAMES_PATH") {
+        Ok(ens_names_path) => {
+            let file = std::fs::File::open(ens_names_path).expect("Could not open ENS names file.");
+            let names: Vec<String> =
+                serde_yaml::from_reader(file).expect("Could not read ENS names.");
+            names.into_iter().map(|name| name.to_lowercase()).collect()
+        }
+        Err(_) => vec![],
+    };
+    /// Seconds between checks of the watched ENS names
+    pub static ref ENS_CHECK_INTERVAL: Duration = Duration::from_secs(
+        env::var("ENS_CHECK_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid ENS_CHECK_INTERVAL"))
+            .unwrap_or(300)
+    );
+}
+
+pub fn is_enabled() -> bool {
+    !ENS_NAMES.is_empty()
+}
+
+/// Second level `.eth` names are registered in the base registrar, and expire
+fn is_eth_2ld(name: &str) -> bool {
+    name.strip_suffix(".eth")
+        .is_some_and(|label| !label.is_empty() && !label.contains('.'))
+}
+
+fn labelhash(name: &str) -> H256 {
+    H256::from(keccak256(name.split('.').next().unwrap().as_bytes()))
+}
+
+fn call(to: Address, selector: &str, param: H256) -> serde_json::Value {
+    json!([{ "to": to, "data": format!("{}{}", selector, hex::encode(param.encode())) }, "latest"])
+}
+
+/// Ownership and records of a name
+#[derive(Clone, PartialEq)]
+struct EnsState {
+    owner: Address,
+    resolver: Address,
+    addr: Option<Address>,
+    contenthash: Option<Bytes>,
+    /// Unix timestamp, for `.eth` second level names
+    expires: Option<u64>,
+}
+
+/// Current state of the names, with all the calls of each step in a single JSON-RPC batch
+async fn read_names(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+) -> Result<Vec<EnsState>, ProviderError> {
+    let params: Vec<serde_json::Value> = ENS_NAMES
+        .iter()
+        .flat_map(|name| {
+            let node = namehash(name);
+            [
+                call(*ENS_REGISTRY, OWNER_SELECTOR, node),
+                call(*ENS_REGISTRY, RESOLVER_SELECTOR, node),
+                call(*BASE_REGISTRAR, OWNER_OF_SELECTOR, labelhash(name)),
+                call(*BASE_REGISTRAR, NAME_EXPIRES_SELECTOR, labelhash(name)),
+                call(*NAME_WRAPPER, OWNER_OF_SELECTOR, node),
+            ]
+        })
+        .collect();
+    let results = batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await?;
+    let decode_address = |result: &Result<Bytes, ProviderError>| {
+        result
+            .as_ref()
+            .ok()
+            .and_then(|result| Address::decode(result).ok())
+    };
+
+    let mut states: Vec<EnsState> = ENS_NAMES
+        .iter()
+        .zip(results.chunks(5))
+        .map(|(name, calls)| {
+            let eth_2ld = is_eth_2ld(name);
+            // The registrant of `.eth` names owns them, even if it is not the registry owner
+            let mut owner = match eth_2ld {
+                true => decode_address(&calls[2]),
+                false => None,
+            }
+            .or(decode_address(&calls[0]))
+            .unwrap_or_default();
+            if owner == *NAME_WRAPPER {
+                owner = decode_address(&calls[4]).unwrap_or_default();
+            }
+
+            EnsState {
+                owner,
+                resolver: decode_address(&calls[1]).unwrap_or_default(),
+                addr: None,
+                contenthash: None,
+                expires: match eth_2ld {
+                    true => calls[3]
+                        .as_ref()
+                        .ok()
+                        .and_then(|expires| U256::decode(expires).ok())
+                        .map(|expires| expires.low_u64()),
+                    false => None,
+                },
+            }
+        })
+        .collect();
+
+    let params: Vec<serde_json::Value> = ENS_NAMES
+        .iter()
+        .zip(&states)
+        .flat_map(|(name, state)| {
+            [
+                call(state.resolver, ADDR_SELECTOR, namehash(name)),
+                call(state.resolver, CONTENTHASH_SELECTOR, namehash(name)),
+            ]
+        })
+        .collect();
+    let results = batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await?;
+    for (state, calls) in states.iter_mut().zip(results.chunks(2)) {
+        if state.resolver.is_zero() {
+            continue;
+        }
+        state.addr = decode_address(&calls[0]).filter(|addr| !addr.is_zero());
+        state.contenthash = calls[1]
+            .as_ref()
+            .ok()
+            .and_then(|contenthash| Bytes::decode(contenthash).ok())
+            .filter(|contenthash| !contenthash.is_empty());
+    }
+
+    Ok(states)
+}
+
+/// Notification about the name, sent to the tenant of its owner
+fn notification(name: &str, owner: Address, message: String, severity: Severity) -> Notification {
+    Notification {
+        message,
+        url: Some(format!("https://app.ens.domains/{}", name)),
+        severity: Some(severity),
+        route: tenants::route_of(&owner),
+        image: None,
+        links: vec![],
+    }
+}
+
+/// Alerts of the changes of a name between two checks
+fn change_notifications(
+    name: &str,
+    previous: &EnsState,
+    current: &EnsState,
+    addressbook: &HashMap<String, String>,
+) -> Vec<Notification> {
+    let label = |address: Option<Address>| {
+        address.map_or("none".to_string(), |address| address.to_label(addressbook))
+    };
+    let mut notifications = vec![];
+
+    if previous.owner != current.owner {
+        notifications.push(notification(
+            name,
+            previous.owner,
+            format!(
+                "⚠ ENS name {} transferred from {} to {}",
+                name,
+                label(Some(previous.owner)),
+                label(Some(current.owner))
+            ),
+            Severity::Critical,
+        ));
+    }
+    if previous.resolver != current.resolver {
+        notifications.push(notification(
+            name,
+            previous.owner,
+            format!(
+                "⚠ Resolver of ENS name {} changed from {} to {}",
+                name,
+                label(Some(previous.resolver)),
+                label(Some(current.resolver))
+            ),
+            Severity::High,
+        ));
+    }
+    if previous.addr != current.addr {
+        notifications.push(notification(
+            name,
+            previous.owner,
+            format!(
+                "⚠ ENS name {} now resolves to {} instead of {}",
+                name,
+                label(current.addr),
+                label(previous.addr)
+            ),
+            Severity::High,
+        ));
+    }
+    if previous.contenthash != current.contenthash {
+        notifications.push(notification(
+            name,
+            previous.owner,
+            format!("⚠ Content hash of ENS name {} changed", name),
+            Severity::High,
+        ));
+    }
+    if let (Some(previous_expires), Some(expires)) = (previous.expires, current.expires) {
+        if expires > previous_expires {
+            notifications.push(notification(
+                name,
+                previous.owner,
+                format!(
+                    "ENS name {} renewed until {}",
+                    name,
+                    UtcDateTime::from_timestamp(expires)
+                ),
+                Severity::Normal,
+            ));
+        }
+    }
+
+    notifications
+}
+
+/// Periodically reads the owner, resolver, address and content hash records and expiration of
+/// the watched ENS names from mainnet, alerting on their changes and reminding of their
+/// expiration 30, 7 and 1 days before. The state of a name when it is first read is only logged
+pub async fn watch_ens_names(chain: Chain, addressbook: Arc<Mutex<HashMap<String, String>>>) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    if chain.id != Some(U256::from(MAINNET_CHAIN_ID)) {
+        return;
+    }
+    info!("Watching {} ENS names on {}", ENS_NAMES.len(), chain.name);
+
+    let mut states: HashMap<String, EnsState> = HashMap::new();
+    // Smallest reminder sent for the current expiration of each name
+    let mut reminders: HashMap<String, (u64, u64)> = HashMap::new();
+
+    loop {
+        if chain_control::is_paused(&chain.name) {
+            sleep(*ENS_CHECK_INTERVAL).await;
+            continue;
+        }
+
+        let current_states = match read_names(&provider).await {
+            Ok(current_states) => current_states,
+            Err(err) => {
+                log_rpc_error(&chain, "ENS names", &err);
+                sleep(*ENS_CHECK_INTERVAL).await;
+                continue;
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut notifications = vec![];
+        for (name, current) in ENS_NAMES.iter().zip(current_states) {
+            match states.insert(name.clone(), current.clone()) {
+                None => info!(
+                    "ENS name {} is owned by {} and expires {}",
+                    name,
+                    current.owner.to_label(&addressbook.lock().unwrap()),
+                    current.expires.map_or("never".to_string(), |expires| {
+                        UtcDateTime::from_timestamp(expires).to_string()
+                    })
+                ),
+                Some(previous) if previous != current => {
+                    warn!("ENS name {} changed", name);
+                    notifications.extend(change_notifications(
+                        name,
+                        &previous,
+                        &current,
+                        &addressbook.lock().unwrap(),
+                    ));
+                }
+                Some(_) => {}
+            }
+
+            let Some(expires) = current.expires.filter(|expires| *expires > now) else {
+                continue;
+            };
+            schedule::schedule_event(ScheduledEvent {
+                uid: format!("ens-{}", name),
+                title: format!("ENS name {} expires", name),
+                description: Some(format!(
+                    "ENS name {} of {} expires, unless renewed before",
+                    name,
+                    current.owner.to_label(&addressbook.lock().unwrap())
+                )),
+                url: Some(format!("https://app.ens.domains/{}", name)),
+                at: expires,
+            });
+            let days_left = (expires - now) / 86400;
+            let Some(reminder) = EXPIRATION_REMINDERS
+                .into_iter()
+                .filter(|reminder| days_left < *reminder)
+                .min()
+            else {
+                continue;
+            };
+            let sent = reminders
+                .get(name)
+                .is_some_and(|(sent_expires, sent_reminder)| {
+                    *sent_expires == expires && *sent_reminder <= reminder
+                });
+            if sent {
+                continue;
+            }
+            reminders.insert(name.clone(), (expires, reminder));
+            notifications.push(notification(
+                name,
+                current.owner,
+                format!(
+                    "ENS name {} expires in less than {} days, on {}",
+                    name,
+                    reminder,
+                    UtcDateTime::from_timestamp(expires)
+                ),
+                match reminder {
+                    1 => Severity::High,
+                    _ => Severity::Normal,
+                },
+            ));
+        }
+        debug!("Checked {} ENS names on {}", states.len(), chain.name);
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending ENS notification");
+            }
+        }
+        sleep(*ENS_CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,300 @@
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use utoipa::ToSchema;
+
+use crate::{
+    chain::Chain,
+    http_client,
+    interesting_transaction::InterestingTransaction,
+    message_budget,
+    notification::{self, Notification},
+    rules::TransactionConditions,
+};
+
+const ESCALATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    DeserializeMacro,
+    SerializeMacro,
+    ToSchema,
+    strum_macros::Display,
+)]
+pub enum Severity {
+    Low,
+    Normal,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// ntfy priority of the push notification
+    pub fn priority(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Normal => "default",
+            Severity::High => "high",
+            Severity::Critical => "urgent",
+        }
+    }
+}
+
+#[derive(Debug, DeserializeMacro)]
+enum Channel {
+    Email,
+    Sms,
+}
+
+#[derive(Debug, DeserializeMacro)]
+struct EscalationStep {
+    /// Seconds after the push notification was sent
+    after: u64,
+    via: Channel,
+}
+
+/// Severity to attach to the transactions matching every configured condition
+#[derive(Debug, DeserializeMacro)]
+struct SeverityRule {
+    severity: Severity,
+    #[serde(flatten)]
+    conditions: TransactionConditions,
+}
+
+#[derive(Debug, Default, DeserializeMacro)]
+struct EscalationConfig {
+    #[serde(default)]
+    rules: Vec<SeverityRule>,
+    #[serde(default)]
+    ladders: HashMap<Severity, Vec<EscalationStep>>,
+}
+
+struct PendingEscalation {
+    message: String,
+    url: Option<String>,
+    severity: Severity,
+    first_sent: Instant,
+    next_step: usize,
+}
+
+lazy_static! {
+    static ref ESCALATION_CONFIG: EscalationConfig = match env::var("ESCALATION_RULES_PATH") {
+        Ok(escalation_rules_path) => {
+            let file = std::fs::File::open(escalation_rules_path)
+                .expect("Could not open escalation rules file.");
+            serde_yaml::from_reader(file).expect("Could not read escalation rules.")
+        }
+        Err(_) => EscalationConfig::default(),
+    };
+    static ref PENDING_ESCALATIONS: Mutex<HashMap<String, PendingEscalation>> =
+        Mutex::new(HashMap::new());
+    pub static ref ACKNOWLEDGE_URL: Option<String> = env::var("ACKNOWLEDGE_URL").ok();
+    /// URL the `Sms` escalation steps are posted to
+    static ref SMS_GATEWAY_URL: Option<String> =
+        env::var("SMS_GATEWAY_URL").ok().filter(|url| !url.is_empty());
+    static ref SMS_GATEWAY_TOKEN: Option<String> = env::var("SMS_GATEWAY_TOKEN").ok();
+}
+
+/// Loads the escalation rules, failing on startup when a ladder has a step through a channel
+/// which is not configured
+pub fn load() {
+    let channels: Vec<&Channel> = ESCALATION_CONFIG
+        .ladders
+        .values()
+        .flatten()
+        .map(|step| &step.via)
+        .collect();
+    if channels
+        .iter()
+        .any(|channel| matches!(channel, Channel::Email))
+    {
+        notification::require_email("escalation ladders");
+    }
+    if channels
+        .iter()
+        .any(|channel| matches!(channel, Channel::Sms))
+    {
+        match SMS_GATEWAY_URL.as_ref() {
+            Some(sms_gateway_url) if reqwest::Url::parse(sms_gateway_url).is_err() => {
+                panic!("Invalid SMS_GATEWAY_URL")
+            }
+            Some(_) => {}
+            None => panic!("Missing SMS_GATEWAY_URL, escalation ladders send to Sms"),
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ESCALATION_CONFIG
+        .ladders
+        .values()
+        .any(|ladder| !ladder.is_empty())
+}
+
+pub trait Prioritize {
+    fn severity(&self, chain: &Chain) -> Option<Severity>;
+}
+
+impl Prioritize for InterestingTransaction {
+    /// Severity of the first matching rule, changes of who controls a watched contract are
+    /// critical otherwise
+    fn severity(&self, chain: &Chain) -> Option<Severity> {
+        ESCALATION_CONFIG
+            .rules
+            .iter()
+            .find(|rule| rule.conditions.matches(self, chain))
+            .map(|rule| rule.severity)
+            .or(self
+                .kind
+                .is_contract_control()
+                .then_some(Severity::Critical))
+    }
+}
+
+/// Keeps track of the notification until it is acknowledged when its severity has an escalation
+/// ladder, returning the id to acknowledge it with
+pub fn track(notification: &Notification) -> Option<String> {
+    let severity = notification.severity?;
+    if ESCALATION_CONFIG
+        .ladders
+        .get(&severity)
+        .is_none_or(|ladder| ladder.is_empty())
+    {
+        return None;
+    }
+
+    let id = format!("{:016x}", rand::random::<u64>());
+    PENDING_ESCALATIONS.lock().unwrap().insert(
+        id.clone(),
+        PendingEscalation {
+            message: notification.message.clone(),
+            url: notification.url.clone(),
+            severity,
+            first_sent: Instant::now(),
+            next_step: 0,
+        },
+    );
+
+    Some(id)
+}
+
+/// Stops escalating the notification, returning false if it wasn't pending
+pub fn acknowledge(id: &str) -> bool {
+    let acknowledged = PENDING_ESCALATIONS.lock().unwrap().remove(id);
+    if let Some(acknowledged) = &acknowledged {
+        info!("Acknowledged: {}", acknowledged.message);
+    }
+    acknowledged.is_some()
+}
+
+/// Sends the next step of the escalation ladder of every unacknowledged notification once due
+pub async fn escalate_unacknowledged() {
+    loop {
+        sleep(ESCALATION_CHECK_INTERVAL).await;
+
+        let mut due_steps: Vec<(&'static EscalationStep, Severity, String, Option<String>)> =
+            vec![];
+        PENDING_ESCALATIONS.lock().unwrap().retain(|_, pending| {
+            let ladder = &ESCALATION_CONFIG.ladders[&pending.severity];
+            while let Some(step) = ladder.get(pending.next_step) {
+                if pending.first_sent.elapsed() < Duration::from_secs(step.after) {
+                    break;
+                }
+                due_steps.push((
+                    step,
+                    pending.severity,
+                    format!(
+                        "Unacknowledged for {} minutes: {}",
+                        pending.first_sent.elapsed().as_secs() / 60,
+                        pending.message
+                    ),
+                    pending.url.clone(),
+                ));
+                pending.next_step += 1;
+            }
+            pending.next_step < ladder.len()
+        });
+
+        for (step, severity, message, url) in due_steps {
+            if *notification::DRY_RUN {
+                println!(
+                    "{}",
+                    json!({
+                        "escalation": format!("{:?}", step.via),
+                        "severity": severity,
+                        "message": message,
+                        "url": url,
+                    })
+                );
+                continue;
+            }
+            let escalated = match step.via {
+                Channel::Email => send_email(severity, &message, url).await,
+                Channel::Sms => send_sms(&message).await,
+            };
+            if let Err(err) = escalated {
+                error!(
+                    "Error while escalating notification via {:?}: {}",
+                    step.via, err
+                );
+            }
+        }
+    }
+}
+
+/// Sends the message through the ntfy email bridge, as a push notification also forwarded to
+/// `NTFY_EMAIL`
+pub async fn send_email(severity: Severity, message: &str, url: Option<String>) -> Result<()> {
+    let message = message_budget::fit(message, *message_budget::NTFY_MESSAGE_LIMIT);
+    let Some(ntfy) = notification::NTFY.as_ref() else {
+        info!("Email: {}", message);
+        return Ok(());
+    };
+    let email = notification::NTFY_EMAIL
+        .as_ref()
+        .ok_or_else(|| eyre!("Missing NTFY_EMAIL"))?;
+
+    let mut request = http_client::client()
+        .post(format!("{}/{}", ntfy.url, ntfy.topic))
+        .body(message)
+        .header("Authorization", format!("Bearer {}", ntfy.token))
+        .header("Email", email)
+        .header("Priority", severity.priority());
+    if let Some(url) = url {
+        request = request.header("Click", url);
+    }
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
+
+/// Posts the message as the body of a request to the SMS gateway, signed like the other webhooks
+async fn send_sms(message: &str) -> Result<()> {
+    let sms_gateway_url = SMS_GATEWAY_URL
+        .as_ref()
+        .ok_or_else(|| eyre!("Missing SMS_GATEWAY_URL"))?;
+
+    let mut request = http_client::signed(
+        http_client::client().post(sms_gateway_url),
+        message.as_bytes(),
+    )
+    .body(message.to_string());
+    if let Some(sms_gateway_token) = SMS_GATEWAY_TOKEN.as_ref() {
+        request = request.header("Authorization", format!("Bearer {}", sms_gateway_token));
+    }
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use ethers::core::{
+    types::{Address, H256, U256},
+    utils::{parse_units, ParseUnits},
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::Deserialize as DeserializeMacro;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use utoipa::ToSchema;
+
+use crate::FullString;
+use crate::{
+    chain::Chain,
+    escalation::Severity,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    notification::{Notification, Sendable},
+    routing::Route,
+    tenants,
+    token::{FromChainAddress, Token},
+};
+
+const EXPECTATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What the expected transaction looks like
+#[derive(Debug, DeserializeMacro, ToSchema)]
+#[serde(untagged)]
+pub enum ExpectedTransaction {
+    /// A transaction which was already broadcast
+    Hash {
+        #[schema(value_type = String)]
+        hash: H256,
+    },
+    /// A deposit of at least `min_amount` (in native or token units) to the account
+    Deposit {
+        #[schema(value_type = String)]
+        to: Address,
+        #[schema(value_type = Option<String>)]
+        token: Option<Address>,
+        min_amount: Option<String>,
+    },
+}
+
+/// Either the `hash` of a broadcast transaction, or a deposit `to` the account
+#[derive(Debug, DeserializeMacro, ToSchema)]
+pub struct Expectation {
+    #[serde(flatten)]
+    pub transaction: ExpectedTransaction,
+    /// Only matches transactions on this chain
+    pub chain: Option<String>,
+    /// Seconds for the transaction to land before alerting
+    pub within: u64,
+    pub label: Option<String>,
+}
+
+struct PendingExpectation {
+    id: String,
+    expectation: Expectation,
+    registered: Instant,
+}
+
+lazy_static! {
+    static ref PENDING_EXPECTATIONS: Mutex<Vec<PendingExpectation>> = Mutex::new(vec![]);
+}
+
+/// Minimum amount of a deposit in units of the token, or of the native currency, on the chain
+fn min_units(chain: &Chain, token: Option<Address>, min_amount: &str) -> Option<U256> {
+    let decimals = token.map_or(chain.native_decimals, |token| {
+        Token::from_chain_address(chain, token).decimals
+    });
+    // parse_units drops the digits beyond the decimals, which would lower the minimum
+    if min_amount
+        .split_once('.')
+        .is_some_and(|(_, fraction)| fraction.trim_end_matches('0').len() > decimals as usize)
+    {
+        return None;
+    }
+    match parse_units(min_amount, decimals).ok()? {
+        ParseUnits::U256(units) => Some(units),
+        ParseUnits::I256(_) => None,
+    }
+}
+
+impl Expectation {
+    fn applies_to(&self, chain: &Chain) -> bool {
+        self.chain
+            .as_ref()
+            .is_none_or(|expected_chain| expected_chain.eq_ignore_ascii_case(&chain.name))
+    }
+
+    /// Checks the `min_amount` fits the decimals of the token on every chain the expectation
+    /// applies to
+    pub fn validate(&self, chains: &[Chain]) -> Result<(), String> {
+        if let ExpectedTransaction::Deposit {
+            token,
+            min_amount: Some(min_amount),
+            ..
+        } = &self.transaction
+        {
+            if chains
+                .iter()
+                .filter(|chain| self.applies_to(chain))
+                .any(|chain| min_units(chain, *token, min_amount).is_none())
+            {
+                return Err("Invalid min_amount".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        if let Some(label) = &self.label {
+            return label.clone();
+        }
+
+        match &self.transaction {
+            ExpectedTransaction::Hash { hash } => format!("transaction {}", hash.full_string()),
+            ExpectedTransaction::Deposit { to, min_amount, .. } => format!(
+                "deposit of {} to {}",
+                min_amount.as_deref().unwrap_or("any amount"),
+                to.full_string()
+            ),
+        }
+    }
+
+    /// Route of the tenant of the deposit's account, hashes aren't tied to any
+    fn route(&self) -> Option<&'static Route> {
+        match &self.transaction {
+            ExpectedTransaction::Hash { .. } => None,
+            ExpectedTransaction::Deposit { to, .. } => tenants::route_of(to),
+        }
+    }
+
+    fn matches(&self, tx: &InterestingTransaction, chain: &Chain) -> bool {
+        if !self.applies_to(chain) {
+            return false;
+        }
+
+        match &self.transaction {
+            ExpectedTransaction::Hash { hash } => tx.hash == *hash,
+            ExpectedTransaction::Deposit {
+                to,
+                token,
+                min_amount,
+            } => {
+                let is_deposit = match token {
+                    Some(_) => tx.kind == InterestingTransactionKind::Transfer,
+                    None => tx.kind == InterestingTransactionKind::Send,
+                };
+                // Sends found through their receipts only have an amount when their block's
+                // transactions were fetched, it can't be known to be enough otherwise
+                let enough = match (min_amount, tx.amount) {
+                    (Some(min_amount), Some(amount)) => min_units(chain, *token, min_amount)
+                        .is_some_and(|min_amount| amount >= min_amount),
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+
+                is_deposit && tx.to == Some(*to) && tx.token == *token && enough
+            }
+        }
+    }
+}
+
+/// Starts waiting for the transaction, returning the expectation id
+pub fn register(expectation: Expectation) -> String {
+    let id = format!("{:016x}", rand::random::<u64>());
+    info!("Expecting {} ({})", expectation.description(), id);
+
+    PENDING_EXPECTATIONS
+        .lock()
+        .unwrap()
+        .push(PendingExpectation {
+            id: id.clone(),
+            expectation,
+            registered: Instant::now(),
+        });
+
+    id
+}
+
+/// Confirms the pending expectations met by the transactions
+pub async fn check(chain: &Chain, interesting_transactions: &[InterestingTransaction]) {
+    let mut met_expectations = vec![];
+    PENDING_EXPECTATIONS.lock().unwrap().retain(|pending| {
+        match interesting_transactions
+            .iter()
+            .find(|tx| pending.expectation.matches(tx, chain))
+        {
+            Some(tx) => {
+                met_expectations.push((
+                    pending.expectation.description(),
+                    tx.hash,
+                    tenants::route_of(&tx.involved_account),
+                ));
+                false
+            }
+            None => true,
+        }
+    });
+
+    for (description, hash, route) in met_expectations {
+        let notification = Notification {
+            message: format!("Expected {} landed on {}", description, chain.name),
+            url: chain.tx_url(hash),
+            severity: None,
+            route,
+            image: None,
+            links: vec![],
+        };
+        if notification.send().await.is_err() {
+            error!("Error while sending expectation confirmation");
+        }
+    }
+}
+
+/// Alerts about the expected transactions which didn't land in time
+pub async fn alert_overdue_expectations() {
+    loop {
+        sleep(EXPECTATION_CHECK_INTERVAL).await;
+
+        let mut overdue_expectations = vec![];
+        PENDING_EXPECTATIONS.lock().unwrap().retain(|pending| {
+            if pending.registered.elapsed() < Duration::from_secs(pending.expectation.within) {
+                return true;
+            }
+            overdue_expectations.push((
+                pending.id.clone(),
+                pending.expectation.description(),
+                pending.expectation.route(),
+            ));
+            false
+        });
+
+        for (id, description, route) in overdue_expectations {
+            info!("Expectation {} is overdue", id);
+            let notification = Notification {
+                message: format!(
+                    "Expected {} did not land in time, check if it is stuck or was sent elsewhere",
+                    description
+                ),
+                url: None,
+                severity: Some(Severity::High),
+                route,
+                image: None,
+                links: vec![],
+            };
+            if notification.send().await.is_err() {
+                error!("Error while sending overdue expectation alert");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chain::test_chain,
+        decoding::{add_send_values, process_block},
+        notification,
+    };
+    use ethers::core::types::{Transaction, TransactionReceipt};
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    const ACCOUNT: &str = "0x3333333333333333333333333333333333333333";
+
+    fn send(amount: Option<U256>) -> InterestingTransaction {
+        InterestingTransaction {
+            from: Some(Address::zero()),
+            to: Some(ACCOUNT.parse().unwrap()),
+            amount,
+            ..InterestingTransaction::new(
+                H256::zero(),
+                InterestingTransactionKind::Send,
+                ACCOUNT.parse().unwrap(),
+            )
+        }
+    }
+
+    fn deposit(min_amount: Option<&str>) -> Expectation {
+        serde_json::from_value(json!({
+            "to": ACCOUNT,
+            "min_amount": min_amount,
+            "within": 60,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn matches_deposits_of_at_least_the_min_amount() {
+        let chain = test_chain(json!({}));
+
+        assert!(deposit(Some("1")).matches(&send(Some(U256::exp10(18))), &chain));
+        assert!(!deposit(Some("1")).matches(&send(Some(U256::exp10(17))), &chain));
+        assert!(!deposit(Some("1")).matches(&send(None), &chain));
+        assert!(deposit(None).matches(&send(None), &chain));
+    }
+
+    #[test]
+    fn validates_the_min_amount_with_the_decimals_of_the_chain() {
+        let chain = test_chain(json!({"native_decimals": 2}));
+
+        assert!(deposit(Some("1.25"))
+            .validate(std::slice::from_ref(&chain))
+            .is_ok());
+        assert!(deposit(Some("1.255")).validate(&[chain]).is_err());
+    }
+
+    #[tokio::test]
+    async fn confirms_deposits_sent_in_blocks_mode() {
+        let chain = test_chain(json!({}));
+        let hash = H256::repeat_byte(0x27);
+        let receipt = TransactionReceipt {
+            transaction_hash: hash,
+            from: Address::zero(),
+            to: Some(ACCOUNT.parse().unwrap()),
+            gas_used: Some(U256::from(21000)),
+            ..Default::default()
+        };
+        let transaction = Transaction {
+            hash,
+            value: U256::exp10(18),
+            ..Default::default()
+        };
+        let watched_topics = HashSet::from([H256::from(ACCOUNT.parse::<Address>().unwrap())]);
+
+        let id = register(deposit(Some("1")));
+        let mut interesting_transactions = process_block(&[receipt], &watched_topics);
+        add_send_values(&mut interesting_transactions, &[transaction]);
+        check(&chain, &interesting_transactions).await;
+
+        assert!(PENDING_EXPECTATIONS
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|pending| pending.id != id));
+        assert!(notification::delivered_for_test().contains(&format!(
+            "Expected deposit of 1 to {} landed on Gnosis",
+            ACCOUNT
+        )));
+    }
+}
@@ -0,0 +1,158 @@
+use ethers::{
+    core::types::{H256, U256},
+    middleware::Middleware,
+};
+use serde_derive::Serialize as SerializeMacro;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::{
+    abi_registry, account_direction,
+    chain::Chain,
+    decoding::{decoder_name, parse_logs, process_block},
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind, SpamFilter},
+    pipeline::{build_notifications, most_relevant},
+    provider::build_provider,
+    token::{FromChainAddress, Token},
+    watched_topics::WatchedTopics,
+};
+use crate::{scale_amount, FullString, ToLabel};
+
+/// How the monitor sees a transaction, for the API
+#[derive(SerializeMacro, ToSchema)]
+pub struct Explanation {
+    hash: String,
+    from: String,
+    to: Option<String>,
+    logs: Vec<ExplainedLog>,
+    /// Transactions of the watched accounts found in the logs, or in the transaction itself when
+    /// none of its logs involve them
+    transactions: Vec<ExplainedTransaction>,
+    /// Messages of the notifications sent for the transactions which aren't filtered
+    notifications: Vec<String>,
+}
+
+#[derive(SerializeMacro, ToSchema)]
+struct ExplainedLog {
+    index: Option<u64>,
+    contract: String,
+    /// Name of its decoder, or the event of a loaded ABI
+    event: Option<String>,
+}
+
+#[derive(SerializeMacro, ToSchema)]
+struct ExplainedTransaction {
+    /// Log the transaction was decoded from
+    log_index: Option<u64>,
+    kind: String,
+    account: String,
+    from: Option<String>,
+    to: Option<String>,
+    amount: Option<String>,
+    /// Why it is not notified, by the account directions or the spam filter
+    filtered: Option<String>,
+}
+
+/// Decodes the logs of the transaction against the current addressbook, telling which of its
+/// transactions are notified and why the others are filtered
+pub async fn explain(
+    chain: &Chain,
+    hash: H256,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+) -> Result<Explanation, String> {
+    let receipt = build_provider(chain)
+        .get_transaction_receipt(hash)
+        .await
+        .map_err(|err| format!("Could not get the receipt: {}", err))?
+        .ok_or("Transaction not found".to_string())?;
+
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), watch::channel(()).1);
+    let watched_topics = watched_topics.current();
+    let mut interesting_transactions: Vec<(Option<u64>, InterestingTransaction)> = receipt
+        .logs
+        .iter()
+        .flat_map(|log| {
+            let log_index = log.log_index.map(|log_index| log_index.as_u64());
+            parse_logs(std::slice::from_ref(log), watched_topics)
+                .into_iter()
+                .map(move |tx| (log_index, tx))
+        })
+        .collect();
+    if interesting_transactions.is_empty() {
+        interesting_transactions = process_block(std::slice::from_ref(&receipt), watched_topics)
+            .into_iter()
+            .map(|tx| (None, tx))
+            .collect();
+    }
+
+    let addressbook_snapshot = addressbook.lock().unwrap().clone();
+    let logs = receipt
+        .logs
+        .iter()
+        .map(|log| ExplainedLog {
+            index: log.log_index.map(|log_index| log_index.as_u64()),
+            contract: log.address.to_label(&addressbook_snapshot),
+            event: log.topics.first().and_then(|topic| {
+                decoder_name(topic).or_else(|| abi_registry::decode(log).map(|event| event.name))
+            }),
+        })
+        .collect();
+    let transactions = interesting_transactions
+        .iter()
+        .map(|(log_index, tx)| ExplainedTransaction {
+            log_index: *log_index,
+            kind: format!("{:?}", tx.kind),
+            account: tx.involved_account.to_label(&addressbook_snapshot),
+            from: tx.from.map(|from| from.to_label(&addressbook_snapshot)),
+            to: tx.to.map(|to| to.to_label(&addressbook_snapshot)),
+            amount: describe_amount(chain, tx),
+            filtered: filter_reason(chain, tx),
+        })
+        .collect();
+    let notified: Vec<InterestingTransaction> = interesting_transactions
+        .into_iter()
+        .map(|(_, tx)| tx)
+        .filter(|tx| filter_reason(chain, tx).is_none())
+        .collect();
+
+    Ok(Explanation {
+        hash: hash.full_string(),
+        from: receipt.from.to_label(&addressbook_snapshot),
+        to: receipt.to.map(|to| to.to_label(&addressbook_snapshot)),
+        logs,
+        transactions,
+        notifications: build_notifications(&most_relevant(notified), chain, addressbook)
+            .into_iter()
+            .map(|notification| notification.message)
+            .collect(),
+    })
+}
+
+/// Why the pipeline drops the transaction, if it does
+fn filter_reason(chain: &Chain, tx: &InterestingTransaction) -> Option<String> {
+    if !account_direction::is_watched(tx) {
+        return Some("Direction not watched for the account".to_string());
+    }
+    tx.spam_reason(&chain.spam_filter_level)
+        .map(|reason| format!("Spam ({:?}): {}", chain.spam_filter_level, reason))
+}
+
+fn describe_amount(chain: &Chain, tx: &InterestingTransaction) -> Option<String> {
+    let amount: U256 = tx.amount?;
+    if tx.token_id.is_some() {
+        return Some(amount.to_string());
+    }
+    let (symbol, decimals) = match (&tx.kind, tx.token) {
+        (InterestingTransactionKind::Send, _) => {
+            (chain.native_symbol.clone(), chain.native_decimals)
+        }
+        (_, Some(token)) => {
+            let token = Token::from_chain_address(chain, token);
+            (token.symbol, token.decimals)
+        }
+        _ => return None,
+    };
+    Some(format!("{} {}", scale_amount(amount, decimals), symbol))
+}
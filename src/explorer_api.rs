@@ -0,0 +1,200 @@
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::debug;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, Instant},
+};
+
+use crate::{chain::Chain, http_client};
+
+const SECONDS_PER_DAY: u64 = 86400;
+/// Queries cached at most, the oldest being evicted once expired ones are dropped
+const CACHE_CAPACITY: usize = 10000;
+
+lazy_static! {
+    /// Requests per second of each API key, shared by every chain using it. Defaults to the
+    /// Etherscan free tier
+    static ref EXPLORER_API_MAX_RPS: u32 = env::var("EXPLORER_API_MAX_RPS")
+        .map(|max_rps| max_rps
+            .parse::<u32>()
+            .ok()
+            .filter(|max_rps| *max_rps > 0)
+            .expect("Invalid EXPLORER_API_MAX_RPS"))
+        .unwrap_or(5);
+    static ref EXPLORER_API_DAILY_LIMIT: u32 = env::var("EXPLORER_API_DAILY_LIMIT")
+        .map(|daily_limit| daily_limit
+            .parse::<u32>()
+            .expect("Invalid EXPLORER_API_DAILY_LIMIT"))
+        .unwrap_or(100000);
+    static ref EXPLORER_API_CACHE_TTL: Duration = Duration::from_secs(
+        env::var("EXPLORER_API_CACHE_TTL")
+            .map(|ttl| ttl.parse::<u64>().expect("Invalid EXPLORER_API_CACHE_TTL"))
+            .unwrap_or(SECONDS_PER_DAY)
+    );
+    static ref BUDGETS: Mutex<HashMap<String, Budget>> = Mutex::new(HashMap::new());
+    static ref CACHE: Mutex<HashMap<String, (Instant, Option<serde_json::Value>)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Requests made with an API key
+struct Budget {
+    next_request: Instant,
+    day: u64,
+    used: u32,
+}
+
+#[derive(DeserializeMacro)]
+struct ExplorerResponse {
+    status: String,
+    message: String,
+    result: serde_json::Value,
+}
+
+pub fn is_enabled(chain: &Chain) -> bool {
+    chain.explorer_api.is_some()
+}
+
+/// Waits for the next request slot of the API key, failing once its daily budget is spent
+async fn reserve(api_key: &str) -> Result<()> {
+    let mut budgets = BUDGETS.lock().await;
+    let budget = budgets.entry(api_key.to_string()).or_insert(Budget {
+        next_request: Instant::now(),
+        day: 0,
+        used: 0,
+    });
+
+    let today = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        / SECONDS_PER_DAY;
+    if budget.day != today {
+        budget.day = today;
+        budget.used = 0;
+    }
+    if budget.used >= *EXPLORER_API_DAILY_LIMIT {
+        return Err(eyre!("Daily explorer API budget spent"));
+    }
+    budget.used += 1;
+
+    // Slots are handed out in order, waiting for them without holding the other keys back
+    let slot = budget.next_request.max(Instant::now());
+    budget.next_request = slot + Duration::from_secs(1) / *EXPLORER_API_MAX_RPS;
+    drop(budgets);
+    sleep_until(slot).await;
+
+    Ok(())
+}
+
+/// Result of a query to the chain's explorer API, `None` when the explorer has no result for it
+/// (e.g. the ABI of an unverified contract). Both are cached for `EXPLORER_API_CACHE_TTL`, up to
+/// `CACHE_CAPACITY` queries
+pub async fn query(chain: &Chain, params: &[(&str, &str)]) -> Result<Option<serde_json::Value>> {
+    let explorer_api = chain
+        .explorer_api
+        .as_ref()
+        .ok_or(eyre!("No explorer API for {}", chain.name))?;
+    let cache_key = format!(
+        "{}?{}",
+        explorer_api,
+        params
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<String>>()
+            .join("&")
+    );
+    if let Some((cached_at, result)) = CACHE.lock().await.get(&cache_key) {
+        if cached_at.elapsed() < *EXPLORER_API_CACHE_TTL {
+            return Ok(result.clone());
+        }
+    }
+
+    let api_key = chain.explorer_api_key.clone().unwrap_or_default();
+    reserve(&api_key).await?;
+    debug!("Explorer API query {}", cache_key);
+    let response: ExplorerResponse = http_client::client()
+        .get(explorer_api)
+        .query(params)
+        .query(&[("apikey", api_key)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let result = if response.status == "1" {
+        Some(response.result)
+    } else {
+        // Rate limits and invalid keys are errors, the rest means there is no result
+        let reason = response.result.as_str().unwrap_or(&response.message);
+        if response.message.starts_with("NOTOK")
+            && ["rate limit", "api key", "apikey"]
+                .iter()
+                .any(|error| reason.to_lowercase().contains(error))
+        {
+            return Err(eyre!("{} explorer API error: {}", chain.name, reason));
+        }
+        None
+    };
+
+    let mut cache = CACHE.lock().await;
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, (cached_at, _)| cached_at.elapsed() < *EXPLORER_API_CACHE_TTL);
+    }
+    if cache.len() >= CACHE_CAPACITY {
+        if let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, (cached_at, _))| *cached_at)
+            .map(|(cache_key, _)| cache_key.clone())
+        {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(cache_key, (Instant::now(), result.clone()));
+    Ok(result)
+}
+
+/// Result of a JSON-RPC method proxied by the chain's explorer API, `null` when there is none
+/// (e.g. an unknown transaction). Not cached, as it is used for one-off lookups
+pub async fn proxy(
+    chain: &Chain,
+    action: &str,
+    params: &[(&str, &str)],
+) -> Result<serde_json::Value> {
+    let explorer_api = chain
+        .explorer_api
+        .as_ref()
+        .ok_or(eyre!("No explorer API for {}", chain.name))?;
+
+    let api_key = chain.explorer_api_key.clone().unwrap_or_default();
+    reserve(&api_key).await?;
+    debug!("Explorer API proxy {} on {}", action, chain.name);
+    let mut response: serde_json::Value = http_client::client()
+        .get(explorer_api)
+        .query(&[("module", "proxy"), ("action", action)])
+        .query(params)
+        .query(&[("apikey", api_key)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // Proxied responses are JSON-RPC ones, unless the explorer itself refuses the request
+    if let Some(error) = response.get("error") {
+        return Err(eyre!("{} explorer API error: {}", chain.name, error));
+    }
+    if response.get("status").and_then(|status| status.as_str()) == Some("0") {
+        return Err(eyre!(
+            "{} explorer API error: {}",
+            chain.name,
+            response["result"]
+        ));
+    }
+    Ok(response["result"].take())
+}
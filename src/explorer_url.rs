@@ -0,0 +1,83 @@
+use ethers::{
+    core::types::{Address, Transaction, H256},
+    middleware::Middleware,
+};
+use log::error;
+use std::str::FromStr;
+
+use crate::{
+    chain::Chain, explorer_api, provider::build_provider, retry::log_rpc_error, FullString,
+};
+
+fn host(url: &reqwest::Url) -> Option<String> {
+    url.host_str()
+        .map(|host| host.trim_start_matches("www.").to_lowercase())
+}
+
+/// Transaction looked up through the chain's explorer API, sharing its rate limit and budget with
+/// the other explorer queries, or through its RPC when it has none
+async fn get_transaction(chain: &Chain, hash: H256) -> Result<Option<Transaction>, String> {
+    if !explorer_api::is_enabled(chain) {
+        return build_provider(chain)
+            .get_transaction(hash)
+            .await
+            .map_err(|err| {
+                log_rpc_error(chain, "transaction", &err);
+                format!("Could not get the transaction from {}", chain.name)
+            });
+    }
+
+    explorer_api::proxy(
+        chain,
+        "eth_getTransactionByHash",
+        &[("txhash", &hash.full_string())],
+    )
+    .await
+    .and_then(|result| Ok(serde_json::from_value(result)?))
+    .map_err(|err| {
+        error!(
+            "Error while getting the transaction {}: {}",
+            hash.full_string(),
+            err
+        );
+        format!(
+            "Could not get the transaction from the {} explorer",
+            chain.name
+        )
+    })
+}
+
+/// Account of an explorer address URL, or the sender of an explorer transaction URL, of one of
+/// the configured chains
+pub async fn resolve_account(chains: &[Chain], explorer_url: &str) -> Result<Address, String> {
+    let explorer_url =
+        reqwest::Url::parse(explorer_url.trim()).map_err(|_| "Invalid URL".to_string())?;
+    let chain = chains
+        .iter()
+        .find(|chain| {
+            chain
+                .explorer
+                .as_ref()
+                .and_then(|explorer| reqwest::Url::parse(explorer).ok())
+                .is_some_and(|explorer| host(&explorer) == host(&explorer_url))
+        })
+        .ok_or("No configured chain uses this explorer".to_string())?;
+
+    let mut segments = explorer_url
+        .path_segments()
+        .ok_or("Invalid URL".to_string())?
+        .filter(|segment| !segment.is_empty());
+    match (segments.next(), segments.next()) {
+        (Some("address"), Some(address)) => {
+            Address::from_str(address).map_err(|_| "Invalid account address".to_string())
+        }
+        (Some("tx"), Some(hash)) => {
+            let hash = H256::from_str(hash).map_err(|_| "Invalid transaction hash".to_string())?;
+            get_transaction(chain, hash)
+                .await?
+                .map(|tx| tx.from)
+                .ok_or(format!("Transaction not found on {}", chain.name))
+        }
+        _ => Err("Not an address or transaction URL".to_string()),
+    }
+}
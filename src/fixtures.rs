@@ -0,0 +1,235 @@
+use ethers::core::types::{Log, Transaction, TransactionReceipt, H256, U64};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use crate::{
+    chain::Chain,
+    decoding::{add_send_values, parse_logs, process_block},
+    interesting_transaction::InterestingTransaction,
+    notification::Notification,
+    pipeline::{build_notifications, notified_transactions},
+    watched_topics::WatchedTopics,
+};
+
+lazy_static! {
+    static ref FIXTURES_RECORD_PATH: Option<PathBuf> =
+        env::var("FIXTURES_RECORD_PATH").ok().map(PathBuf::from);
+}
+
+pub fn is_enabled() -> bool {
+    FIXTURES_RECORD_PATH.is_some()
+}
+
+/// Raw data of processed blocks, the receipts of a block in `Blocks` mode or the logs of a block
+/// range in `Events` mode
+#[derive(DeserializeMacro, SerializeMacro)]
+struct Fixture {
+    chain: String,
+    from_block: u64,
+    to_block: u64,
+    /// Unix timestamp of the block, `Blocks` mode only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    receipts: Vec<TransactionReceipt>,
+    /// Transactions of the receipts, for the value of the native sends, `Blocks` mode only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transactions: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    logs: Vec<Log>,
+    /// Messages of the notifications the blocks must produce, added by hand to turn the fixture
+    /// into a regression test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected_notifications: Option<Vec<String>>,
+}
+
+fn save(fixture: Fixture) {
+    let path = FIXTURES_RECORD_PATH.as_ref().unwrap().join(format!(
+        "{}-{}-{}.json",
+        fixture.chain.to_lowercase(),
+        fixture.from_block,
+        fixture.to_block
+    ));
+    let saved = File::create(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|file| serde_json::to_writer(file, &fixture).map_err(|err| err.to_string()));
+    if let Err(err) = saved {
+        error!("Could not record fixture {}: {}", path.display(), err);
+    }
+}
+
+/// Hashes of the transactions involving the watched accounts, the only ones recorded
+fn involved_hashes(interesting_transactions: &[InterestingTransaction]) -> HashSet<H256> {
+    interesting_transactions.iter().map(|tx| tx.hash).collect()
+}
+
+/// Records the receipts of a block processed in `Blocks` mode which involve the watched accounts,
+/// unless none of them do
+pub fn record_block(
+    chain: &Chain,
+    block_number: U64,
+    timestamp: u64,
+    receipts: &[TransactionReceipt],
+    transactions: &[Transaction],
+    interesting_transactions: &[InterestingTransaction],
+) {
+    if !is_enabled() || interesting_transactions.is_empty() {
+        return;
+    }
+    let hashes = involved_hashes(interesting_transactions);
+    save(Fixture {
+        chain: chain.name.clone(),
+        from_block: block_number.as_u64(),
+        to_block: block_number.as_u64(),
+        timestamp: Some(timestamp),
+        receipts: receipts
+            .iter()
+            .filter(|receipt| hashes.contains(&receipt.transaction_hash))
+            .cloned()
+            .collect(),
+        transactions: transactions
+            .iter()
+            .filter(|transaction| hashes.contains(&transaction.hash))
+            .cloned()
+            .collect(),
+        logs: vec![],
+        expected_notifications: None,
+    });
+}
+
+/// Records the logs of a block range processed in `Events` mode which involve the watched
+/// accounts, unless none of them do
+pub fn record_logs(
+    chain: &Chain,
+    from_block: U64,
+    to_block: U64,
+    logs: &[Log],
+    interesting_transactions: &[InterestingTransaction],
+) {
+    if !is_enabled() || interesting_transactions.is_empty() {
+        return;
+    }
+    let hashes = involved_hashes(interesting_transactions);
+    save(Fixture {
+        chain: chain.name.clone(),
+        from_block: from_block.as_u64(),
+        to_block: to_block.as_u64(),
+        timestamp: None,
+        receipts: vec![],
+        transactions: vec![],
+        logs: logs
+            .iter()
+            .filter(|log| {
+                log.transaction_hash
+                    .is_some_and(|hash| hashes.contains(&hash))
+            })
+            .cloned()
+            .collect(),
+        expected_notifications: None,
+    });
+}
+
+fn load_fixture(path: &Path) -> Fixture {
+    let file = File::open(path)
+        .unwrap_or_else(|err| panic!("Could not open fixture {}: {}", path.display(), err));
+    serde_json::from_reader(file)
+        .unwrap_or_else(|err| panic!("Could not read fixture {}: {}", path.display(), err))
+}
+
+/// Fixtures of a file, or of every `.json` file of a directory, in block order
+fn load_fixtures(path: &Path) -> Vec<Fixture> {
+    if !path.is_dir() {
+        return vec![load_fixture(path)];
+    }
+    let mut fixtures: Vec<Fixture> = std::fs::read_dir(path)
+        .expect("Could not read fixtures directory.")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .map(|path| load_fixture(&path))
+        .collect();
+    fixtures.sort_by_key(|fixture| fixture.from_block);
+    fixtures
+}
+
+/// Messages of the notifications of the recorded blocks, decoded against the addressbook and
+/// filtered by the spam filter of their chain, without any RPC nor side effect
+fn notifications(
+    chain: &Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    fixture: &Fixture,
+) -> Vec<Notification> {
+    let mut watched_topics = WatchedTopics::new(addressbook.clone(), watch::channel(()).1);
+    let mut interesting_transactions = match fixture.receipts.is_empty() {
+        true => parse_logs(&fixture.logs, watched_topics.current()),
+        false => process_block(&fixture.receipts, watched_topics.current()),
+    };
+    add_send_values(&mut interesting_transactions, &fixture.transactions);
+    if let Some(timestamp) = fixture.timestamp {
+        for tx in interesting_transactions.iter_mut() {
+            tx.timestamp = Some(timestamp);
+        }
+    }
+    let mut notifications = build_notifications(
+        &notified_transactions(interesting_transactions, chain),
+        chain,
+        addressbook,
+    );
+    // Transactions are kept by hash, in no particular order
+    notifications.sort_by(|a, b| a.message.cmp(&b.message));
+    notifications
+}
+
+/// Replays the recorded blocks, printing their notifications as JSON lines instead of sending
+/// them, and returns the differences with the `expected_notifications` of the fixtures having
+/// them
+pub fn replay(
+    chains: &[Chain],
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    path: &Path,
+) -> Vec<String> {
+    let mut differences = vec![];
+    for fixture in load_fixtures(path) {
+        let chain = chains
+            .iter()
+            .find(|chain| chain.name.eq_ignore_ascii_case(&fixture.chain))
+            .unwrap_or_else(|| panic!("Fixture of unknown chain {}", fixture.chain));
+
+        let notifications = notifications(chain, addressbook.clone(), &fixture);
+        info!(
+            "Replaying {} blocks {} to {}, {} notifications",
+            chain.name,
+            fixture.from_block,
+            fixture.to_block,
+            notifications.len()
+        );
+        for notification in notifications.iter() {
+            println!("{}", serde_json::to_string(notification).unwrap());
+        }
+
+        let Some(mut expected_notifications) = fixture.expected_notifications else {
+            continue;
+        };
+        expected_notifications.sort();
+        let messages: Vec<&String> = notifications
+            .iter()
+            .map(|notification| &notification.message)
+            .collect();
+        if messages != expected_notifications.iter().collect::<Vec<&String>>() {
+            differences.push(format!(
+                "{} blocks {} to {}: expected {:?}, got {:?}",
+                chain.name, fixture.from_block, fixture.to_block, expected_notifications, messages
+            ));
+        }
+    }
+    differences
+}
@@ -0,0 +1,206 @@
+use ethers::{
+    core::{
+        types::{Address, U256},
+        utils::format_units,
+    },
+    middleware::Middleware,
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use prometheus::{GaugeVec, Opts as PrometheusOpts};
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::{
+    chain::Chain,
+    escalation::Severity,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+};
+use crate::{format_duration, FullString};
+
+const FLEET_POLL_INTERVAL: Duration = Duration::from_secs(300);
+/// Period over which the spend rate is averaged
+const SPEND_RATE_WINDOW: Duration = Duration::from_secs(6 * 3600);
+const DEFAULT_ALERT_BEFORE: u64 = 24 * 3600;
+
+lazy_static! {
+    pub static ref RELAYER_BALANCE: GaugeVec = GaugeVec::new(
+        PrometheusOpts::new(
+            "relayer_balance",
+            "Native balance of each relayer of the fleet"
+        ),
+        &["chain", "relayer"]
+    )
+    .expect("metric can be created");
+    pub static ref RELAYER_TIME_TO_EMPTY: GaugeVec = GaugeVec::new(
+        PrometheusOpts::new(
+            "relayer_time_to_empty_seconds",
+            "Predicted seconds until each relayer of the fleet runs out of gas at its current spend rate"
+        ),
+        &["chain", "relayer"]
+    )
+    .expect("metric can be created");
+}
+
+/// Relayer EOA whose native balance pays for the gas of the transactions it sends
+#[derive(Debug, Clone, DeserializeMacro)]
+pub struct Relayer {
+    pub chain: String,
+    pub address: Address,
+    pub label: String,
+    /// Alerts when the balance drops below, in native units
+    #[serde(default)]
+    pub min_balance: f64,
+    /// Alerts when the predicted time to empty drops below, in seconds
+    #[serde(default = "default_alert_before")]
+    pub alert_before: u64,
+}
+
+fn default_alert_before() -> u64 {
+    DEFAULT_ALERT_BEFORE
+}
+
+struct GasTank {
+    relayer: Relayer,
+    /// Balances since the last top up, oldest first
+    samples: VecDeque<(Instant, f64)>,
+    alerting: bool,
+}
+
+impl GasTank {
+    fn new(relayer: Relayer) -> Self {
+        Self {
+            relayer,
+            samples: VecDeque::new(),
+            alerting: false,
+        }
+    }
+
+    fn push(&mut self, balance: f64) {
+        // A top up restarts the spend rate
+        if self
+            .samples
+            .back()
+            .is_some_and(|(_, last_balance)| balance > *last_balance)
+        {
+            self.samples.clear();
+        }
+        self.samples.push_back((Instant::now(), balance));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(sampled_at, _)| sampled_at.elapsed() > SPEND_RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Native units spent per second
+    fn spend_rate(&self) -> Option<f64> {
+        let (first_sampled_at, first_balance) = self.samples.front()?;
+        let (last_sampled_at, last_balance) = self.samples.back()?;
+        let elapsed = last_sampled_at
+            .duration_since(*first_sampled_at)
+            .as_secs_f64();
+
+        (elapsed > 0.0 && first_balance > last_balance)
+            .then(|| (first_balance - last_balance) / elapsed)
+    }
+}
+
+fn to_native(chain: &Chain, amount: U256) -> f64 {
+    format_units(amount, chain.native_decimals)
+        .ok()
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Polls the balances of the chain's relayers, predicting when they will run out of gas and
+/// alerting ahead of it
+pub async fn watch_relayers(chain: Chain, relayers: Vec<Relayer>) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut gas_tanks: Vec<GasTank> = relayers.into_iter().map(GasTank::new).collect();
+
+    info!(
+        "Watching the gas of {} relayers on {}",
+        gas_tanks.len(),
+        chain.name
+    );
+
+    loop {
+        for gas_tank in gas_tanks.iter_mut() {
+            let address = gas_tank.relayer.address.full_string();
+            let balance = match provider.get_balance(gas_tank.relayer.address, None).await {
+                Ok(balance) => to_native(&chain, balance),
+                Err(err) => {
+                    log_rpc_error(&chain, "relayer balance", &err);
+                    continue;
+                }
+            };
+            gas_tank.push(balance);
+
+            let spend_rate = gas_tank.spend_rate();
+            let time_to_empty = spend_rate.map(|spend_rate| balance / spend_rate);
+            RELAYER_BALANCE
+                .with_label_values(&[&chain.name, &address])
+                .set(balance);
+            RELAYER_TIME_TO_EMPTY
+                .with_label_values(&[&chain.name, &address])
+                .set(time_to_empty.unwrap_or(f64::INFINITY));
+            debug!(
+                "{} relayer {} has {} {}, spending {:?} per second",
+                chain.name, gas_tank.relayer.label, balance, chain.native_symbol, spend_rate
+            );
+
+            let below_min_balance = balance < gas_tank.relayer.min_balance;
+            let running_out = time_to_empty
+                .is_some_and(|time_to_empty| time_to_empty < gas_tank.relayer.alert_before as f64);
+            let alerting = below_min_balance || running_out;
+            if alerting == gas_tank.alerting {
+                continue;
+            }
+            gas_tank.alerting = alerting;
+
+            let message = if !alerting {
+                format!(
+                    "{} relayer {} has enough gas again ({} {})",
+                    chain.name, gas_tank.relayer.label, balance, chain.native_symbol
+                )
+            } else if let (Some(spend_rate), Some(time_to_empty)) = (spend_rate, time_to_empty) {
+                format!(
+                    "{} relayer {} will run out of gas in about {} ({} {} left, spending {:.6} per hour)",
+                    chain.name,
+                    gas_tank.relayer.label,
+                    format_duration(time_to_empty),
+                    balance,
+                    chain.native_symbol,
+                    spend_rate * 3600.0
+                )
+            } else {
+                format!(
+                    "{} relayer {} is low on gas ({} {} left)",
+                    chain.name, gas_tank.relayer.label, balance, chain.native_symbol
+                )
+            };
+            info!("{}", message);
+
+            let notification = Notification {
+                message,
+                url: chain.address_url(gas_tank.relayer.address),
+                severity: alerting.then_some(Severity::High),
+                route: None,
+                image: None,
+                links: vec![],
+            };
+            if notification.send().await.is_err() {
+                error!("Error while sending relayer gas notification");
+            }
+        }
+
+        sleep(FLEET_POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,24 @@
+use ethers::utils::id;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+/// Signatures of common functions, looked up by selector
+const FUNCTION_SIGNATURES: &str = include_str!("function_signatures.txt");
+
+lazy_static! {
+    /// Names of the bundled functions by selector, the first signature wins on collisions
+    static ref FUNCTION_NAMES: HashMap<[u8; 4], &'static str> = {
+        let mut function_names = HashMap::new();
+        for signature in FUNCTION_SIGNATURES.lines().filter(|line| !line.is_empty()) {
+            let name = signature.split('(').next().unwrap();
+            function_names.entry(id(signature)).or_insert(name);
+        }
+        function_names
+    };
+}
+
+/// Name of the function called with the input, if it is a bundled one
+pub fn lookup(input: &[u8]) -> Option<&'static str> {
+    let selector: [u8; 4] = input.get(..4)?.try_into().ok()?;
+    FUNCTION_NAMES.get(&selector).copied()
+}
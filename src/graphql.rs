@@ -0,0 +1,166 @@
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject,
+};
+use ethers::core::types::Address;
+use log::error;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    chain::Chain,
+    history::{self, HistoryEntry, HistoryFilter, HistoryGroup, HistoryGrouping},
+    label_history, tenants,
+};
+
+pub type AccountMonitorSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct Account {
+    address: String,
+    label: String,
+    tenant: Option<String>,
+}
+
+#[ComplexObject]
+impl Account {
+    /// Transactions of the account in the history, newest first
+    async fn transactions(
+        &self,
+        #[graphql(default)] filter: HistoryFilter,
+    ) -> Result<Vec<HistoryEntry>> {
+        query_history(&HistoryFilter {
+            account: Some(self.address.clone()),
+            ..filter
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+struct ChainInfo {
+    name: String,
+    id: Option<String>,
+    mode: String,
+    spam_filter_level: String,
+    explorer: Option<String>,
+}
+
+#[derive(SimpleObject)]
+struct LabelChange {
+    label: String,
+    since: u64,
+}
+
+fn query_history(filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+    if !history::is_enabled() {
+        return Err("History is not enabled, set HISTORY_DB_PATH".into());
+    }
+    history::query(filter).map_err(|err| {
+        error!("Error while querying history: {}", err);
+        "Error while querying history".into()
+    })
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Watched accounts, optionally only those whose label contains `label` or of a tenant
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        label: Option<String>,
+        tenant: Option<String>,
+    ) -> Vec<Account> {
+        let addressbook = ctx
+            .data_unchecked::<Arc<Mutex<HashMap<String, String>>>>()
+            .lock()
+            .unwrap();
+
+        let mut accounts: Vec<Account> = addressbook
+            .iter()
+            .filter(|(_, account_label)| {
+                label
+                    .as_ref()
+                    .is_none_or(|label| account_label.contains(label))
+            })
+            .map(|(address, label)| Account {
+                address: address.clone(),
+                label: label.clone(),
+                tenant: Address::from_str(address)
+                    .ok()
+                    .and_then(|address| tenants::name_of(&address))
+                    .map(str::to_string),
+            })
+            .filter(|account| tenant.is_none() || account.tenant == tenant)
+            .collect();
+        accounts.sort_by(|a, b| a.address.cmp(&b.address));
+
+        accounts
+    }
+
+    /// Labels the account had, oldest first, with the unix timestamp from which each was used
+    async fn label_history(&self, address: String) -> Vec<LabelChange> {
+        label_history::history(&address)
+            .into_iter()
+            .map(|change| LabelChange {
+                label: change.label,
+                since: change.since,
+            })
+            .collect()
+    }
+
+    /// Label the account had at the unix timestamp, to label past transactions after renames
+    async fn label_at(&self, address: String, at: u64) -> Option<String> {
+        label_history::label_at(&address, at)
+    }
+
+    /// Stored transactions matching the filter, newest first
+    async fn history(
+        &self,
+        #[graphql(default)] filter: HistoryFilter,
+    ) -> Result<Vec<HistoryEntry>> {
+        query_history(&filter)
+    }
+
+    /// Count of the stored transactions matching the filter by chain, kind, category, account,
+    /// token or day, largest groups first
+    async fn history_summary(
+        &self,
+        group_by: HistoryGrouping,
+        #[graphql(default)] filter: HistoryFilter,
+    ) -> Result<Vec<HistoryGroup>> {
+        if !history::is_enabled() {
+            return Err("History is not enabled, set HISTORY_DB_PATH".into());
+        }
+        history::summarize(&filter, group_by).map_err(|err| {
+            error!("Error while summarizing history: {}", err);
+            "Error while summarizing history".into()
+        })
+    }
+
+    /// Configured chains, without their RPC since it usually includes an API key
+    async fn chains(&self, ctx: &Context<'_>) -> Vec<ChainInfo> {
+        ctx.data_unchecked::<Vec<Chain>>()
+            .iter()
+            .map(|chain| ChainInfo {
+                name: chain.name.clone(),
+                id: chain.id.map(|id| id.to_string()),
+                mode: format!("{:?}", chain.mode),
+                spam_filter_level: format!("{:?}", chain.spam_filter_level),
+                explorer: chain.explorer.clone(),
+            })
+            .collect()
+    }
+}
+
+pub fn build_schema(
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    chains: Vec<Chain>,
+) -> AccountMonitorSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(addressbook)
+        .data(chains)
+        .finish()
+}
@@ -0,0 +1,263 @@
+use futures::stream::{self, Stream};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::env;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    account_direction::Direction,
+    chain::{self, Chain, ChainDefinition, ChainMode, SpamFilterLevel},
+    chain_control, interesting_transaction, live_stream,
+    monitor::{AddChainError, Monitor, WatchedAccount},
+};
+use proto::{
+    account_monitor_server::{AccountMonitor, AccountMonitorServer},
+    AddChainRequest, AddChainResponse, ChainRequest, ChainResponse, ChainState, ListChainsRequest,
+    ListChainsResponse, StreamTransactionsRequest, TransactionEvent, WatchAccountRequest,
+    WatchAccountResponse,
+};
+
+lazy_static! {
+    static ref GRPC_PORT: Option<u16> = env::var("GRPC_PORT")
+        .ok()
+        .map(|port| port.parse().expect("Invalid GRPC_PORT"));
+}
+
+pub fn is_enabled() -> bool {
+    GRPC_PORT.is_some()
+}
+
+/// Messages and service of `proto/account_monitor.proto`, generated by the build script
+pub mod proto {
+    tonic::include_proto!("account_monitor.v1");
+}
+
+impl From<chain_control::ChainState> for ChainState {
+    fn from(state: chain_control::ChainState) -> Self {
+        ChainState {
+            name: state.name,
+            rpc: state.rpc,
+            chain_id: state.chain_id,
+            mode: format!("{:?}", state.mode),
+            paused: state.paused,
+            head: state.head,
+            last_processed_block: state.last_processed_block,
+            lag: state.lag,
+            rpc_errors: state.rpc_errors.into_iter().collect(),
+            last_notification: state.last_notification,
+        }
+    }
+}
+
+impl TryFrom<AddChainRequest> for ChainDefinition {
+    type Error = Status;
+
+    fn try_from(request: AddChainRequest) -> Result<Self, Self::Error> {
+        Ok(ChainDefinition {
+            name: request.name,
+            id: request.id,
+            rpc: request.rpc,
+            blocktime: request.blocktime,
+            mode: match request.mode {
+                Some(mode) => ChainMode::from_str(&mode)
+                    .map_err(|_| Status::invalid_argument("Invalid mode"))?,
+                None => chain::default_mode(),
+            },
+            spam_filter_level: match request.spam_filter_level {
+                Some(spam_filter_level) => SpamFilterLevel::from_str(&spam_filter_level)
+                    .map_err(|_| Status::invalid_argument("Invalid spam filter level"))?,
+                None => chain::default_spam_filter_level(),
+            },
+            explorer: request.explorer,
+            explorer_api: request.explorer_api,
+            native_symbol: request.native_symbol,
+            native_decimals: request
+                .native_decimals
+                .unwrap_or_else(chain::default_native_decimals),
+            max_block_range: request.max_block_range,
+            head_delay: request.head_delay.unwrap_or_else(chain::default_head_delay),
+        })
+    }
+}
+
+impl From<interesting_transaction::TransactionEvent> for TransactionEvent {
+    fn from(event: interesting_transaction::TransactionEvent) -> Self {
+        TransactionEvent {
+            chain: event.chain,
+            account: event.account,
+            hash: event.hash,
+            kind: event.kind,
+            from: event.from,
+            to: event.to,
+            token: event.token,
+            amount: event.amount,
+            block_number: event.block_number,
+            timestamp: event.timestamp,
+            fee: event.fee,
+            message: event.message,
+            url: event.url,
+            tenant: event.tenant,
+        }
+    }
+}
+
+type TransactionStream = Pin<Box<dyn Stream<Item = Result<TransactionEvent, Status>> + Send>>;
+
+/// The `AccountMonitor` service, mirroring the account and chain management of the REST API
+#[derive(Clone)]
+pub struct AccountMonitorService {
+    monitor: Monitor,
+    /// Chains whose explorer URLs can be resolved to accounts
+    chains: Arc<Vec<Chain>>,
+    read_only: bool,
+}
+
+impl AccountMonitorService {
+    pub fn new(monitor: Monitor, chains: Vec<Chain>, read_only: bool) -> Self {
+        AccountMonitorService {
+            monitor,
+            chains: Arc::new(chains),
+            read_only,
+        }
+    }
+
+    fn check_mutation(&self) -> Result<(), Status> {
+        match self.read_only {
+            true => Err(Status::permission_denied("Read-only mode")),
+            false => Ok(()),
+        }
+    }
+
+    fn set_paused(&self, request: ChainRequest, paused: bool) -> Result<ChainResponse, Status> {
+        self.check_mutation()?;
+        if !chain_control::set_paused(&request.name, paused) {
+            return Err(Status::not_found(format!(
+                "No polling chain named {}",
+                request.name
+            )));
+        }
+        info!(
+            "{} {} through gRPC",
+            if paused { "Pausing" } else { "Resuming" },
+            request.name
+        );
+        Ok(ChainResponse {})
+    }
+}
+
+#[tonic::async_trait]
+impl AccountMonitor for AccountMonitorService {
+    async fn watch_account(
+        &self,
+        request: Request<WatchAccountRequest>,
+    ) -> Result<Response<WatchAccountResponse>, Status> {
+        self.check_mutation()?;
+        let request = request.into_inner();
+        let direction = match request.direction {
+            Some(direction) => Direction::from_str(&direction)
+                .map_err(|_| Status::invalid_argument("Invalid direction"))?,
+            None => Direction::default(),
+        };
+        let account = WatchedAccount {
+            address: request.address,
+            label: request.label,
+            webhook: request.webhook,
+            direction,
+        };
+        let watched_accounts = self
+            .monitor
+            .add_account(&self.chains, account)
+            .await
+            .map_err(Status::invalid_argument)?;
+        Ok(Response::new(WatchAccountResponse { watched_accounts }))
+    }
+
+    async fn list_chains(
+        &self,
+        _request: Request<ListChainsRequest>,
+    ) -> Result<Response<ListChainsResponse>, Status> {
+        Ok(Response::new(ListChainsResponse {
+            chains: chain_control::states()
+                .into_iter()
+                .map(ChainState::from)
+                .collect(),
+        }))
+    }
+
+    async fn add_chain(
+        &self,
+        request: Request<AddChainRequest>,
+    ) -> Result<Response<AddChainResponse>, Status> {
+        self.check_mutation()?;
+        match self
+            .monitor
+            .add_chain(request.into_inner().try_into()?)
+            .await
+        {
+            Ok(name) => Ok(Response::new(AddChainResponse { name })),
+            Err(AddChainError::Invalid(err)) => Err(Status::invalid_argument(err)),
+            Err(AddChainError::AlreadyMonitored(err)) => Err(Status::already_exists(err)),
+        }
+    }
+
+    async fn pause_chain(
+        &self,
+        request: Request<ChainRequest>,
+    ) -> Result<Response<ChainResponse>, Status> {
+        self.set_paused(request.into_inner(), true)
+            .map(Response::new)
+    }
+
+    async fn resume_chain(
+        &self,
+        request: Request<ChainRequest>,
+    ) -> Result<Response<ChainResponse>, Status> {
+        self.set_paused(request.into_inner(), false)
+            .map(Response::new)
+    }
+
+    type StreamTransactionsStream = TransactionStream;
+
+    async fn stream_transactions(
+        &self,
+        request: Request<StreamTransactionsRequest>,
+    ) -> Result<Response<TransactionStream>, Status> {
+        let tenant = request.into_inner().tenant;
+        let transactions = stream::unfold(live_stream::subscribe_events(), move |mut receiver| {
+            let tenant = tenant.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(transaction_event)
+                            if !live_stream::is_visible(&transaction_event, tenant.as_deref()) => {}
+                        Ok(transaction_event) => {
+                            return Some((Ok(transaction_event.into()), receiver));
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("gRPC subscriber lagging, skipped {} events", skipped);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(transactions)))
+    }
+}
+
+/// Serves the `AccountMonitor` service on `GRPC_PORT`
+pub async fn serve(service: AccountMonitorService) {
+    let port = GRPC_PORT.unwrap();
+    info!("Serving gRPC on port {}", port);
+    if let Err(err) = tonic::transport::Server::builder()
+        .add_service(AccountMonitorServer::new(service))
+        .serve(([0, 0, 0, 0], port).into())
+        .await
+    {
+        panic!("gRPC server failed: {}", err);
+    }
+}
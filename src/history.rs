@@ -0,0 +1,417 @@
+use async_graphql::{Enum, InputObject, SimpleObject};
+use ethers::core::types::Address;
+use lazy_static::lazy_static;
+use log::error;
+use rusqlite::{named_params, Connection};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::FullString;
+use crate::{
+    category::Categorize,
+    chain::Chain,
+    interesting_transaction::{InterestingTransaction, SpamFilter},
+    tenants,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+
+lazy_static! {
+    static ref CONNECTION: Option<Mutex<Connection>> =
+        env::var("HISTORY_DB_PATH").ok().map(|history_db_path| {
+            let connection =
+                Connection::open(history_db_path).expect("Could not open HISTORY_DB_PATH");
+            connection
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS interesting_transactions (
+                        id INTEGER PRIMARY KEY,
+                        chain TEXT NOT NULL,
+                        hash TEXT NOT NULL,
+                        kind TEXT NOT NULL,
+                        involved_account TEXT NOT NULL,
+                        from_address TEXT,
+                        to_address TEXT,
+                        token TEXT,
+                        amount TEXT,
+                        timestamp INTEGER NOT NULL,
+                        spam INTEGER NOT NULL,
+                        category TEXT
+                    );
+                    CREATE INDEX IF NOT EXISTS interesting_transactions_account
+                        ON interesting_transactions (involved_account, timestamp);
+                    CREATE INDEX IF NOT EXISTS interesting_transactions_hash
+                        ON interesting_transactions (chain, hash);",
+                )
+                .expect("Could not create the history tables");
+            // Histories created before the categories lack their column
+            let has_category: bool = connection
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info('interesting_transactions')
+                    WHERE name = 'category'",
+                    [],
+                    |row| row.get(0),
+                )
+                .expect("Could not read the history tables");
+            if !has_category {
+                connection
+                    .execute(
+                        "ALTER TABLE interesting_transactions ADD COLUMN category TEXT",
+                        [],
+                    )
+                    .expect("Could not add the category to the history");
+            }
+            Mutex::new(connection)
+        });
+}
+
+pub fn is_enabled() -> bool {
+    CONNECTION.is_some()
+}
+
+/// Stores the transactions, including the ones flagged as spam, with the time of their block (or
+/// the time they were seen when it isn't known yet) and their category
+pub fn record(chain: &Chain, interesting_transactions: &[InterestingTransaction]) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+    let seen_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut connection = connection.lock().unwrap();
+    let result = connection.transaction().and_then(|transaction| {
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO interesting_transactions
+                    (chain, hash, kind, involved_account, from_address, to_address, token, amount,
+                     timestamp, spam, category)
+                VALUES
+                    (:chain, :hash, :kind, :involved_account, :from_address, :to_address, :token,
+                     :amount, :timestamp, :spam, :category)",
+            )?;
+            for tx in interesting_transactions {
+                statement.execute(named_params! {
+                    ":chain": chain.name,
+                    ":hash": tx.hash.full_string(),
+                    ":kind": tx.kind.to_string(),
+                    ":involved_account": tx.involved_account.full_string(),
+                    ":from_address": tx.from.map(|from| from.full_string()),
+                    ":to_address": tx.to.map(|to| to.full_string()),
+                    ":token": tx.token.map(|token| token.full_string()),
+                    ":amount": tx.amount.map(|amount| amount.to_string()),
+                    ":timestamp": tx.timestamp.unwrap_or(seen_at),
+                    ":spam": tx.is_spam(&chain.spam_filter_level),
+                    ":category": tx.category(chain).map(|category| category.to_string()),
+                })?;
+            }
+        }
+        transaction.commit()
+    });
+
+    if let Err(err) = result {
+        error!(
+            "Error while storing {} transactions in history: {}",
+            chain.name, err
+        );
+    }
+}
+
+/// Filters of the history query, all optional
+#[derive(Debug, Default, DeserializeMacro, InputObject, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryFilter {
+    pub account: Option<String>,
+    /// Only the transactions of this tenant's accounts
+    pub tenant: Option<String>,
+    pub chain: Option<String>,
+    pub kind: Option<String>,
+    pub category: Option<String>,
+    /// Unix timestamp, inclusive
+    pub since: Option<u64>,
+    /// Unix timestamp, exclusive
+    pub until: Option<u64>,
+    #[serde(default)]
+    #[graphql(default)]
+    pub include_spam: bool,
+    pub limit: Option<u32>,
+    #[serde(default)]
+    #[graphql(default)]
+    pub offset: u32,
+}
+
+/// Conditions of `HistoryFilter`
+const FILTER_CONDITIONS: &str = "
+    (:account IS NULL OR involved_account = :account) AND
+    (:tenant_accounts IS NULL OR involved_account IN (SELECT value FROM json_each(:tenant_accounts))) AND
+    (:chain IS NULL OR lower(chain) = :chain) AND
+    (:kind IS NULL OR lower(kind) = :kind) AND
+    (:category IS NULL OR lower(category) = :category) AND
+    (:since IS NULL OR timestamp >= :since) AND
+    (:until IS NULL OR timestamp < :until) AND
+    (:include_spam OR NOT spam)";
+
+/// Accounts of the filtered tenant as a JSON array, none of them for unknown tenants
+fn tenant_accounts(filter: &HistoryFilter) -> Option<String> {
+    filter.tenant.as_ref().map(|tenant| {
+        let accounts: Vec<String> = tenants::accounts_of(tenant)
+            .iter()
+            .map(|account| account.full_string())
+            .collect();
+        serde_json::to_string(&accounts).unwrap()
+    })
+}
+
+#[derive(Debug, SerializeMacro, SimpleObject, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub chain: String,
+    pub hash: String,
+    pub kind: String,
+    pub involved_account: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub token: Option<String>,
+    pub amount: Option<String>,
+    pub timestamp: u64,
+    pub spam: bool,
+    pub category: Option<String>,
+}
+
+/// Stored transactions matching the filter, newest first
+pub fn query(filter: &HistoryFilter) -> rusqlite::Result<Vec<HistoryEntry>> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return Ok(vec![]);
+    };
+
+    let connection = connection.lock().unwrap();
+    let mut statement = connection.prepare_cached(&format!(
+        "SELECT chain, hash, kind, involved_account, from_address, to_address, token, amount,
+            timestamp, spam, category
+        FROM interesting_transactions
+        WHERE {}
+        ORDER BY timestamp DESC, id DESC
+        LIMIT :limit OFFSET :offset",
+        FILTER_CONDITIONS
+    ))?;
+
+    let entries = statement
+        .query_map(
+            named_params! {
+                ":account": filter.account.as_ref().map(|account| account.to_lowercase()),
+                ":tenant_accounts": tenant_accounts(filter),
+                ":chain": filter.chain.as_ref().map(|chain| chain.to_lowercase()),
+                ":kind": filter.kind.as_ref().map(|kind| kind.to_lowercase()),
+                ":category": filter.category.as_ref().map(|category| category.to_lowercase()),
+                ":since": filter.since,
+                ":until": filter.until,
+                ":include_spam": filter.include_spam,
+                ":limit": filter.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE),
+                ":offset": filter.offset,
+            },
+            |row| {
+                Ok(HistoryEntry {
+                    chain: row.get(0)?,
+                    hash: row.get(1)?,
+                    kind: row.get(2)?,
+                    involved_account: row.get(3)?,
+                    from: row.get(4)?,
+                    to: row.get(5)?,
+                    token: row.get(6)?,
+                    amount: row.get(7)?,
+                    timestamp: row.get(8)?,
+                    spam: row.get(9)?,
+                    category: row.get(10)?,
+                })
+            },
+        )?
+        .collect();
+
+    entries
+}
+
+/// What the transactions of the history summary are grouped by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum HistoryGrouping {
+    Chain,
+    Kind,
+    /// `none` for the transactions without a category
+    Category,
+    Account,
+    /// Token address, `native` for the transactions without a token
+    Token,
+    /// UTC day, as `YYYY-MM-DD`
+    Day,
+}
+
+impl HistoryGrouping {
+    fn column(&self) -> &'static str {
+        match self {
+            HistoryGrouping::Chain => "chain",
+            HistoryGrouping::Kind => "kind",
+            HistoryGrouping::Category => "COALESCE(category, 'none')",
+            HistoryGrouping::Account => "involved_account",
+            HistoryGrouping::Token => "COALESCE(token, 'native')",
+            HistoryGrouping::Day => "strftime('%Y-%m-%d', timestamp, 'unixepoch')",
+        }
+    }
+}
+
+/// Transactions sharing the value they are grouped by
+#[derive(Debug, SimpleObject)]
+pub struct HistoryGroup {
+    pub key: String,
+    pub count: u64,
+    /// Unix timestamp of the oldest transaction
+    pub first_seen: u64,
+    /// Unix timestamp of the newest transaction
+    pub last_seen: u64,
+}
+
+/// Count of the transactions matching the filter by `grouping`, largest groups first. The
+/// pagination of the filter is ignored
+pub fn summarize(
+    filter: &HistoryFilter,
+    grouping: HistoryGrouping,
+) -> rusqlite::Result<Vec<HistoryGroup>> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return Ok(vec![]);
+    };
+
+    let connection = connection.lock().unwrap();
+    let mut statement = connection.prepare_cached(&format!(
+        "SELECT {} AS key, COUNT(*), MIN(timestamp), MAX(timestamp)
+        FROM interesting_transactions
+        WHERE {}
+        GROUP BY key
+        ORDER BY COUNT(*) DESC, key",
+        grouping.column(),
+        FILTER_CONDITIONS
+    ))?;
+
+    let groups = statement
+        .query_map(
+            named_params! {
+                ":account": filter.account.as_ref().map(|account| account.to_lowercase()),
+                ":tenant_accounts": tenant_accounts(filter),
+                ":chain": filter.chain.as_ref().map(|chain| chain.to_lowercase()),
+                ":kind": filter.kind.as_ref().map(|kind| kind.to_lowercase()),
+                ":category": filter.category.as_ref().map(|category| category.to_lowercase()),
+                ":since": filter.since,
+                ":until": filter.until,
+                ":include_spam": filter.include_spam,
+            },
+            |row| {
+                Ok(HistoryGroup {
+                    key: row.get(0)?,
+                    count: row.get(1)?,
+                    first_seen: row.get(2)?,
+                    last_seen: row.get(3)?,
+                })
+            },
+        )?
+        .collect();
+
+    groups
+}
+
+/// Watched accounts and the recipients of the transfers they sent, excluding zero-value ones
+pub fn sent_to() -> Vec<(Address, Address)> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return vec![];
+    };
+
+    let connection = connection.lock().unwrap();
+    let result = connection
+        .prepare(
+            "SELECT DISTINCT involved_account, to_address
+            FROM interesting_transactions
+            WHERE
+                kind IN ('Send', 'Transfer') AND
+                from_address = involved_account AND
+                to_address IS NOT NULL AND
+                amount != '0' AND
+                NOT spam",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<(String, String)>>>()
+        });
+
+    match result {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|(account, to)| {
+                Some((
+                    Address::from_str(&account).ok()?,
+                    Address::from_str(&to).ok()?,
+                ))
+            })
+            .collect(),
+        Err(err) => {
+            error!("Error while reading sent transfers from history: {}", err);
+            vec![]
+        }
+    }
+}
+
+/// Tokens the watched accounts approved a spender for on the chain, as (owner, token, spender),
+/// including the approvals revoked since
+pub fn approvals(chain: &Chain) -> Vec<(Address, Address, Address)> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return vec![];
+    };
+
+    let connection = connection.lock().unwrap();
+    let result = connection
+        .prepare(
+            "SELECT DISTINCT involved_account, token, to_address
+            FROM interesting_transactions
+            WHERE
+                chain = :chain AND
+                kind = 'Approval' AND
+                from_address = involved_account AND
+                token IS NOT NULL AND
+                to_address IS NOT NULL AND
+                NOT spam",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(named_params! {":chain": chain.name}, |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<(String, String, String)>>>()
+        });
+
+    match result {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|(owner, token, spender)| {
+                Some((
+                    Address::from_str(&owner).ok()?,
+                    Address::from_str(&token).ok()?,
+                    Address::from_str(&spender).ok()?,
+                ))
+            })
+            .collect(),
+        Err(err) => {
+            error!(
+                "Error while reading {} approvals from history: {}",
+                chain.name, err
+            );
+            vec![]
+        }
+    }
+}
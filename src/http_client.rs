@@ -0,0 +1,67 @@
+use ethers::core::utils::hex;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    pub static ref USER_AGENT: String =
+        env::var("USER_AGENT").unwrap_or(format!("account-monitor/{}", env!("CARGO_PKG_VERSION")));
+    static ref CLIENT: Client = Client::builder()
+        .user_agent(USER_AGENT.as_str())
+        .build()
+        .expect("Invalid USER_AGENT");
+    static ref WEBHOOK_SIGNING_SECRET: Option<String> = env::var("WEBHOOK_SIGNING_SECRET").ok();
+    pub static ref NOTIFICATION_WEBHOOK_URL: Option<String> =
+        env::var("NOTIFICATION_WEBHOOK_URL").ok();
+}
+
+/// Client shared by the outbound requests to notification sinks and rotki
+pub fn client() -> &'static Client {
+    &CLIENT
+}
+
+/// Signs the body when `WEBHOOK_SIGNING_SECRET` is set. The signature is the hex encoded
+/// HMAC-SHA256 of the timestamp, a dot and the body, so receivers can also reject replays
+pub fn signed(request: RequestBuilder, body: &[u8]) -> RequestBuilder {
+    let Some(secret) = WEBHOOK_SIGNING_SECRET.as_ref() else {
+        return request;
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        .to_string();
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).expect("Invalid signing secret");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    request
+        .header("X-Account-Monitor-Timestamp", timestamp)
+        .header(
+            "X-Account-Monitor-Signature",
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        )
+}
+
+/// Posts the value as JSON to the webhook, signed when a signing secret is configured
+#[tracing::instrument(skip_all)]
+pub async fn post_webhook<T: Serialize>(url: &str, value: &T) -> eyre::Result<()> {
+    let body = serde_json::to_vec(value)?;
+    let request = client()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+    signed(request, &body)
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
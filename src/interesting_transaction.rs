@@ -1,16 +1,39 @@
-use ethers::core::types::{Address, H256, U256};
+use ethers::core::types::{Address, H256, U256, U64};
 use log::debug;
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 use crate::{
+    abi_registry::DecodedEvent,
+    category::Categorize,
     chain::{Chain, SpamFilterLevel},
-    notification::Notification,
+    custom_events,
+    escalation::Prioritize,
+    nft_metadata,
+    notification::{Link, Notification},
+    scam_list, template, tenants,
     token::{FromChainAddress, Token},
+    transaction_details,
 };
-use account_monitor::{scale_amount, FullString, IsKnownToken, ToLabel};
+use crate::{scale_amount, FullString, IsKnownToken, ToLabel, UtcDateTime};
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    DeserializeMacro,
+    strum_macros::Display,
+)]
 pub enum InterestingTransactionKind {
+    Upgrade = 150,
+    AdminChange = 149,
+    OwnershipTransfer = 148,
     Send = 100,
     Transfer = 50,
     Transfer1155 = 49,
@@ -18,6 +41,18 @@ pub enum InterestingTransactionKind {
     Other = 0,
 }
 
+impl InterestingTransactionKind {
+    /// Proxy upgrades and admin or owner changes of a watched contract
+    pub fn is_contract_control(&self) -> bool {
+        matches!(
+            self,
+            InterestingTransactionKind::Upgrade
+                | InterestingTransactionKind::AdminChange
+                | InterestingTransactionKind::OwnershipTransfer
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct InterestingTransaction {
     pub hash: H256,
@@ -26,7 +61,94 @@ pub struct InterestingTransaction {
     pub kind: InterestingTransactionKind,
     pub amount: Option<U256>,
     pub token: Option<Address>,
+    /// Transferred NFT of an ERC721 or ERC1155 transfer
+    pub token_id: Option<U256>,
     pub involved_account: Address,
+    /// Event of a loaded ABI, for transactions with no known operation
+    pub event: Option<DecodedEvent>,
+    /// Custom event notified with its own message
+    pub custom_event: Option<String>,
+    /// Function called by an unknown operation, from the bundled signatures
+    pub function: Option<String>,
+    pub block_number: Option<U64>,
+    /// Unix timestamp of the block
+    pub timestamp: Option<u64>,
+    /// Gas paid by the transaction, in wei
+    pub fee: Option<U256>,
+}
+
+/// Notified transaction as sent to integrations, such as account webhooks and the live stream
+#[derive(Clone, Debug, SerializeMacro, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEvent {
+    pub chain: String,
+    pub account: String,
+    pub hash: String,
+    pub kind: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub token: Option<String>,
+    pub amount: Option<String>,
+    pub block_number: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub fee: Option<String>,
+    pub message: String,
+    pub url: Option<String>,
+    /// Tenant of the account, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl TransactionEvent {
+    pub fn new(chain: &Chain, tx: &InterestingTransaction, notification: &Notification) -> Self {
+        Self {
+            chain: chain.name.clone(),
+            account: tx.involved_account.full_string(),
+            hash: tx.hash.full_string(),
+            kind: tx.kind.to_string(),
+            from: tx.from.map(|from| from.full_string()),
+            to: tx.to.map(|to| to.full_string()),
+            token: tx.token.map(|token| token.full_string()),
+            amount: tx.amount.map(|amount| amount.to_string()),
+            block_number: tx.block_number.map(|block_number| block_number.as_u64()),
+            timestamp: tx.timestamp,
+            fee: tx.fee.map(|fee| fee.to_string()),
+            message: notification.message.clone(),
+            url: notification.url.clone(),
+            tenant: tenants::name_of(&tx.involved_account).map(str::to_string),
+        }
+    }
+
+    /// Prefix of the topic the event is published to, followed by the tenant's name for the
+    /// accounts of a tenant so that each tenant can only be subscribed to its own
+    pub fn topic_prefix(&self, prefix: &str, separator: char) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("{}{}{}", prefix, separator, tenant),
+            None => prefix.to_string(),
+        }
+    }
+
+    /// Dot separated `<prefix>.<chain>` or `<prefix>.<tenant>.<chain>` name of the NATS subject
+    /// and Kafka topic of the event. Spaces, dots and other characters neither allow in a
+    /// name segment are replaced by underscores, so a name can't add levels to the subject
+    pub fn dotted_topic(&self, prefix: &str) -> String {
+        let segment = |name: &str| {
+            name.to_lowercase()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        };
+        match &self.tenant {
+            Some(tenant) => format!("{}.{}.{}", prefix, segment(tenant), segment(&self.chain)),
+            None => format!("{}.{}", prefix, segment(&self.chain)),
+        }
+    }
 }
 
 pub trait BuildNotification {
@@ -43,112 +165,307 @@ impl BuildNotification for InterestingTransaction {
         chain: &Chain,
         addressbook: &HashMap<String, String>,
     ) -> Notification {
-        debug!("Interesting tx: {}", self.hash.full_string());
+        debug!(
+            "Interesting tx: {} ({})",
+            self.hash.full_string(),
+            self.category(chain)
+                .map_or("Uncategorized".to_string(), |category| category.to_string())
+        );
+
+        let url = chain.tx_url(self.hash);
+
+        let template_variables = || self.template_variables(chain, addressbook, url.clone());
+        let message = match &self.custom_event {
+            Some(custom_event) => custom_events::render(custom_event, template_variables),
+            None => template::render(&self.kind, template_variables),
+        }
+        .unwrap_or_else(|| {
+            let message = match self.kind {
+                InterestingTransactionKind::Send => {
+                    if let Some(amount) = self.amount {
+                        let scaled_amount = scale_amount(amount, chain.native_decimals);
+                        format!(
+                            "Sending {} {} from {} to {} on {}",
+                            scaled_amount,
+                            chain.native_symbol,
+                            self.from.unwrap().to_label(addressbook),
+                            self.to.unwrap().to_label(addressbook),
+                            chain.name
+                        )
+                    } else {
+                        format!(
+                            "Sending {} from {} to {} on {}",
+                            chain.native_symbol,
+                            self.from.unwrap().to_label(addressbook),
+                            self.to.unwrap().to_label(addressbook),
+                            chain.name
+                        )
+                    }
+                }
 
-        let message = match self.kind {
-            InterestingTransactionKind::Send => {
-                if self.amount.is_some() {
-                    let scaled_amount = scale_amount(self.amount.unwrap(), 18);
+                InterestingTransactionKind::Transfer => {
+                    let token: Token = Token::from_chain_address(chain, self.token.unwrap());
+
+                    // ERC721 transfers
+                    let transferred = match self.token_id {
+                        Some(token_id) => nft_metadata::describe(chain, self)
+                            .unwrap_or(format!("{} #{}", token.symbol, token_id)),
+                        None => format!(
+                            "{} {}",
+                            scale_amount(self.amount.unwrap(), token.decimals),
+                            token.symbol
+                        ),
+                    };
                     format!(
-                        "Sending {} native from {} to {} on {}",
-                        scaled_amount,
+                        "Transfering {} from {} to {} on {}",
+                        transferred,
                         self.from.unwrap().to_label(addressbook),
                         self.to.unwrap().to_label(addressbook),
                         chain.name
                     )
-                } else {
+                }
+
+                InterestingTransactionKind::Transfer1155 => {
+                    let token: Token = Token::from_chain_address(chain, self.token.unwrap());
+
                     format!(
-                        "Sending native from {} to {} on {}",
+                        "Transfering ERC1155 {} from {} to {} on {}",
+                        nft_metadata::describe(chain, self).unwrap_or(token.symbol),
                         self.from.unwrap().to_label(addressbook),
                         self.to.unwrap().to_label(addressbook),
                         chain.name
                     )
                 }
-            }
 
-            InterestingTransactionKind::Transfer => {
-                let token: Token = Token::from_chain_address(chain, self.token.unwrap());
+                InterestingTransactionKind::Approval => {
+                    let token: Token = Token::from_chain_address(chain, self.token.unwrap());
 
-                let scaled_amount = scale_amount(self.amount.unwrap(), token.decimals);
-                format!(
-                    "Transfering {} {} from {} to {} on {}",
-                    scaled_amount,
-                    token.symbol,
-                    self.from.unwrap().to_label(addressbook),
+                    let scaled_amount = match self.amount.unwrap() == U256::MAX {
+                        true => "Infinite".to_string(),
+                        false => scale_amount(self.amount.unwrap(), token.decimals),
+                    };
+                    format!(
+                        "Approving {} to spend {} {} from {} on {}",
+                        self.to.unwrap().to_label(addressbook),
+                        scaled_amount,
+                        token.symbol,
+                        self.from.unwrap().to_label(addressbook),
+                        chain.name
+                    )
+                }
+
+                InterestingTransactionKind::Upgrade => format!(
+                    "Proxy {} upgraded to implementation {} on {}",
+                    self.involved_account.to_label(addressbook),
                     self.to.unwrap().to_label(addressbook),
                     chain.name
-                )
-            }
-
-            InterestingTransactionKind::Transfer1155 => {
-                let token: Token = Token::from_chain_address(chain, self.token.unwrap());
+                ),
 
-                format!(
-                    "Transfering ERC1155 {} from {} to {} on {}",
-                    token.symbol,
+                InterestingTransactionKind::AdminChange => format!(
+                    "Proxy admin of {} changed from {} to {} on {}",
+                    self.involved_account.to_label(addressbook),
                     self.from.unwrap().to_label(addressbook),
                     self.to.unwrap().to_label(addressbook),
                     chain.name
-                )
-            }
+                ),
 
-            InterestingTransactionKind::Approval => {
-                let token: Token = Token::from_chain_address(chain, self.token.unwrap());
-
-                let scaled_amount = match self.amount.unwrap() == U256::MAX {
-                    true => "Infinite".to_string(),
-                    false => scale_amount(self.amount.unwrap(), token.decimals),
-                };
-                format!(
-                    "Approving {} to spend {} {} from {} on {}",
-                    self.to.unwrap().to_label(addressbook),
-                    scaled_amount,
-                    token.symbol,
+                InterestingTransactionKind::OwnershipTransfer => format!(
+                    "Ownership of {} transferred from {} to {} on {}",
+                    self.involved_account.to_label(addressbook),
                     self.from.unwrap().to_label(addressbook),
+                    self.to.unwrap().to_label(addressbook),
                     chain.name
-                )
+                ),
+
+                InterestingTransactionKind::Other => match &self.event {
+                    Some(event) => format!(
+                        "{} involving {} on {}",
+                        event.describe(addressbook),
+                        self.involved_account.to_label(addressbook),
+                        chain.name
+                    ),
+                    None => match (&self.function, self.to) {
+                        (Some(function), Some(to)) => format!(
+                            "Called {} on {} involving {} on {}",
+                            function,
+                            to.to_label(addressbook),
+                            self.involved_account.to_label(addressbook),
+                            chain.name
+                        ),
+                        _ => format!(
+                            "Unknown operation involving {} on {}",
+                            self.involved_account.to_label(addressbook),
+                            chain.name
+                        ),
+                    },
+                },
+            };
+
+            match transaction_details::describe(chain, self) {
+                Some(details) => format!("{} ({})", message, details),
+                None => message,
             }
+        });
 
-            InterestingTransactionKind::Other => {
-                format!(
-                    "Unknown operation involving {} on {}",
-                    self.involved_account.to_label(addressbook),
-                    chain.name
-                )
+        let links = self.links(chain, url.clone());
+        Notification {
+            message,
+            url,
+            severity: self.severity(chain),
+            route: None,
+            image: nft_metadata::image(chain, self),
+            links,
+        }
+    }
+}
+
+/// Variables available to the notification templates
+#[derive(SerializeMacro)]
+struct TemplateVariables {
+    chain: String,
+    hash: String,
+    url: Option<String>,
+    kind: String,
+    category: Option<String>,
+    account: String,
+    from: Option<String>,
+    to: Option<String>,
+    symbol: Option<String>,
+    amount: Option<String>,
+    event: Option<String>,
+    function: Option<String>,
+    nft: Option<String>,
+    block: Option<u64>,
+    timestamp: Option<String>,
+    fee: Option<String>,
+}
+
+impl InterestingTransaction {
+    /// Transaction of the kind involving the account, with every other field unset
+    pub fn new(hash: H256, kind: InterestingTransactionKind, involved_account: Address) -> Self {
+        InterestingTransaction {
+            hash,
+            from: None,
+            to: None,
+            kind,
+            amount: None,
+            token: None,
+            token_id: None,
+            involved_account,
+            event: None,
+            custom_event: None,
+            function: None,
+            block_number: None,
+            timestamp: None,
+            fee: None,
+        }
+    }
+
+    /// Explorer links of the transaction and of its sender and recipient
+    fn links(&self, chain: &Chain, url: Option<String>) -> Vec<Link> {
+        let mut links: Vec<Link> = url
+            .map(|url| Link {
+                label: "View tx".to_string(),
+                url,
+            })
+            .into_iter()
+            .collect();
+        // Mints and burns have no counterparty worth linking
+        let parties = [("From", self.from), ("To", self.to)];
+        for (label, address) in parties {
+            if let Some(url) = address
+                .filter(|address| !address.is_zero())
+                .and_then(|address| chain.address_url(address))
+            {
+                links.push(Link {
+                    label: label.to_string(),
+                    url,
+                });
             }
-        };
+        }
+        links
+    }
 
-        let url = chain
-            .explorer
-            .clone()
-            .map(|explorer| format!("{}/tx/{}", explorer, self.hash.full_string()));
+    fn template_variables(
+        &self,
+        chain: &Chain,
+        addressbook: &HashMap<String, String>,
+        url: Option<String>,
+    ) -> TemplateVariables {
+        let token = self
+            .token
+            .map(|token| Token::from_chain_address(chain, token));
+        let decimals = token
+            .as_ref()
+            .map_or(chain.native_decimals, |token| token.decimals);
+        let amount = self.amount.map(|amount| {
+            if self.kind == InterestingTransactionKind::Approval && amount == U256::MAX {
+                "Infinite".to_string()
+            } else {
+                scale_amount(amount, decimals)
+            }
+        });
 
-        Notification { message, url }
+        TemplateVariables {
+            chain: chain.name.clone(),
+            hash: self.hash.full_string(),
+            url,
+            kind: self.kind.to_string(),
+            category: self.category(chain).map(|category| category.to_string()),
+            account: self.involved_account.to_label(addressbook),
+            from: self.from.map(|from| from.to_label(addressbook)),
+            to: self.to.map(|to| to.to_label(addressbook)),
+            symbol: match token {
+                Some(token) => Some(token.symbol),
+                None if self.kind == InterestingTransactionKind::Send => {
+                    Some(chain.native_symbol.clone())
+                }
+                None => None,
+            },
+            amount,
+            event: self.event.as_ref().map(|event| event.describe(addressbook)),
+            function: self.function.clone(),
+            nft: nft_metadata::describe(chain, self),
+            block: self.block_number.map(|block_number| block_number.as_u64()),
+            timestamp: self
+                .timestamp
+                .map(|timestamp| UtcDateTime::from_timestamp(timestamp).to_string()),
+            fee: self.fee.map(|fee| scale_amount(fee, chain.native_decimals)),
+        }
     }
 }
 
 pub trait SpamFilter {
-    fn is_spam(&self, spam_filter_level: &SpamFilterLevel) -> bool;
+    /// Why the transaction is spam at the filter level, `None` when it isn't
+    fn spam_reason(&self, spam_filter_level: &SpamFilterLevel) -> Option<&'static str>;
+
+    fn is_spam(&self, spam_filter_level: &SpamFilterLevel) -> bool {
+        self.spam_reason(spam_filter_level).is_some()
+    }
 }
 
 impl SpamFilter for InterestingTransaction {
-    fn is_spam(&self, spam_filter_level: &SpamFilterLevel) -> bool {
-        match spam_filter_level {
-            SpamFilterLevel::None => false,
-            SpamFilterLevel::KnownAssets => match self.kind {
-                InterestingTransactionKind::Send => false,
-                InterestingTransactionKind::Other => false,
-                _ => {
-                    !(self.token.unwrap().is_known_token())
-                        || self.involved_account != self.from.unwrap()
-                }
-            },
+    fn spam_reason(&self, spam_filter_level: &SpamFilterLevel) -> Option<&'static str> {
+        let never_spam = matches!(spam_filter_level, SpamFilterLevel::None)
+            || matches!(
+                self.kind,
+                InterestingTransactionKind::Send | InterestingTransactionKind::Other
+            )
+            || self.kind.is_contract_control()
+            || scam_list::flagged_counterparty(self).is_some();
+        if never_spam {
+            return None;
+        }
 
-            SpamFilterLevel::SelfSubmittedTxs => match self.kind {
-                InterestingTransactionKind::Send => false,
-                InterestingTransactionKind::Other => false,
-                _ => self.involved_account != self.from.unwrap(),
-            },
+        let self_submitted = self.involved_account == self.from.unwrap();
+        match spam_filter_level {
+            SpamFilterLevel::KnownAssets if !self.token.unwrap().is_known_token() => {
+                Some("Unknown token")
+            }
+            SpamFilterLevel::KnownAssets | SpamFilterLevel::SelfSubmittedTxs if !self_submitted => {
+                Some("Not sent by the watched account")
+            }
+            _ => None,
         }
     }
 }
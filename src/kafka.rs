@@ -0,0 +1,83 @@
+use eyre::Result;
+use lazy_static::lazy_static;
+use log::error;
+use reqwest::Url;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+use crate::{http_client, interesting_transaction::TransactionEvent};
+
+lazy_static! {
+    /// Kafka REST Proxy, with the credentials of its basic auth if any
+    static ref KAFKA_REST_URL: Option<Url> = env::var("KAFKA_REST_URL")
+        .ok()
+        .map(|url| Url::parse(&url).expect("Invalid KAFKA_REST_URL"));
+    static ref KAFKA_TOPIC_PREFIX: String =
+        env::var("KAFKA_TOPIC_PREFIX").unwrap_or("account-monitor".to_string());
+}
+
+/// Bounds how long an unresponsive REST Proxy holds the processing of the block
+const PRODUCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn is_enabled() -> bool {
+    KAFKA_REST_URL.is_some()
+}
+
+/// Produces the records to the topic through the v2 API of the Confluent REST Proxy
+async fn produce(topic: &str, transaction_events: &[&TransactionEvent]) -> Result<()> {
+    let mut url = KAFKA_REST_URL.clone().unwrap();
+    let username = url.username().to_string();
+    let password = url.password().map(|password| password.to_string());
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.path_segments_mut()
+        .map_err(|_| eyre::eyre!("Invalid KAFKA_REST_URL"))?
+        .pop_if_empty()
+        .extend(["topics", topic]);
+
+    let mut request = http_client::client()
+        .post(url)
+        .timeout(PRODUCE_TIMEOUT)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/vnd.kafka.json.v2+json",
+        )
+        .json(&json!({
+            "records": transaction_events
+                .iter()
+                .map(|transaction_event| json!({
+                    "key": transaction_event.account,
+                    "value": transaction_event,
+                }))
+                .collect::<Vec<_>>()
+        }));
+    if !username.is_empty() {
+        request = request.basic_auth(username, password);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Produces each transaction as JSON to the `<prefix>.<chain>` topic, or
+/// `<prefix>.<tenant>.<chain>` for the accounts of a tenant, keyed by its account so the
+/// transactions of an account stay in order. Only the notified transactions are produced,
+/// the ones dropped by the spam filter or the directions of the account are not
+pub async fn publish_events(transaction_events: &[TransactionEvent]) {
+    if !is_enabled() {
+        return;
+    }
+    let mut topics: BTreeMap<String, Vec<&TransactionEvent>> = BTreeMap::new();
+    for transaction_event in transaction_events {
+        topics
+            .entry(transaction_event.dotted_topic(&KAFKA_TOPIC_PREFIX))
+            .or_default()
+            .push(transaction_event);
+    }
+    for (topic, transaction_events) in topics {
+        if let Err(err) = produce(&topic, &transaction_events).await {
+            error!("Error while producing transactions to Kafka: {}", err);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use lazy_static::lazy_static;
+use log::{error, info};
+use rusqlite::named_params;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state_db::CONNECTION;
+
+#[derive(Clone, Debug)]
+pub struct LabelChange {
+    pub label: String,
+    /// Unix timestamp from which the label is used
+    pub since: u64,
+}
+
+lazy_static! {
+    /// Labels each watched account had, oldest first
+    static ref LABEL_HISTORY: Mutex<HashMap<String, Vec<LabelChange>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Loads the labels recorded by previous runs from the state database
+pub fn load() {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare("SELECT address, label, since FROM label_history ORDER BY since")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u64>(2)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Error while reading the label history: {}", err);
+            return;
+        }
+    };
+
+    let mut label_history = LABEL_HISTORY.lock().unwrap();
+    for (address, label, since) in rows {
+        label_history
+            .entry(address)
+            .or_default()
+            .push(LabelChange { label, since });
+    }
+    info!(
+        "Loaded the label history of {} accounts",
+        label_history.len()
+    );
+}
+
+fn store(address: &str, change: &LabelChange) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR IGNORE INTO label_history (address, label, since)
+        VALUES (:address, :label, :since)",
+        named_params! {":address": address, ":label": change.label, ":since": change.since},
+    ) {
+        error!("Error while storing the label of {}: {}", address, err);
+    }
+}
+
+/// Records the account's label if it changed
+pub fn record_label(address: &str, label: &str) {
+    let address = address.to_lowercase();
+    let mut label_history = LABEL_HISTORY.lock().unwrap();
+    let changes = label_history.entry(address.clone()).or_default();
+
+    if changes.last().is_none_or(|change| change.label != label) {
+        let change = LabelChange {
+            label: label.to_string(),
+            since: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        };
+        store(&address, &change);
+        changes.push(change);
+    }
+}
+
+pub fn history(address: &str) -> Vec<LabelChange> {
+    LABEL_HISTORY
+        .lock()
+        .unwrap()
+        .get(&address.to_lowercase())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Label the account had at the unix timestamp, or its first one for earlier timestamps since
+/// accounts are usually watched after their first transactions
+pub fn label_at(address: &str, timestamp: u64) -> Option<String> {
+    let history = history(address);
+
+    history
+        .iter()
+        .rev()
+        .find(|change| change.since <= timestamp)
+        .or(history.first())
+        .map(|change| change.label.clone())
+}
@@ -1,9 +1,165 @@
+//! Monitor of the transactions of watched accounts on EVM chains, notifying them through the
+//! configured sinks.
+//!
+//! [`run`] runs the whole monitor as the `account-monitor` binary does. To embed it in another
+//! service, a [`Monitor`] holds the watched accounts and spawns a [`ChainWorker`] per chain,
+//! while [`parse_logs`], [`process_block`] and the notification pipeline
+//! ([`filter_interesting_transactions`], [`notified_transactions`] and [`build_notifications`])
+//! find and describe the transactions of watched accounts on their own.
+//!
+//! Like the binary, the library is configured with the environment variables documented in the
+//! README. They are read on first use and invalid values panic, so they should be set before
+//! anything else is called.
+//!
+//! ```no_run
+//! use account_monitor::{Chain, EnvInitializable, Monitor, WatchedAccount};
+//!
+//! # async fn embed() {
+//! let monitor = Monitor::new();
+//! monitor.watch_account(WatchedAccount {
+//!     address: "0x3333333333333333333333333333333333333333".to_string(),
+//!     label: "Treasury".to_string(),
+//!     webhook: None,
+//!     direction: Default::default(),
+//! });
+//! for chain in Chain::init_from_env_vec() {
+//!     monitor.spawn_chain(chain);
+//! }
+//! # }
+//! ```
+
 use ethers::core::{
     types::{Address, H256, U256},
     utils::format_units,
 };
-use rusqlite::{named_params, Connection};
+use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+
+mod abi_registry;
+mod account_direction;
+mod account_webhook;
+mod address_poisoning;
+mod allowance_audit;
+mod asset_db;
+mod batch;
+mod bridges;
+mod category;
+mod chain;
+mod chain_control;
+mod chain_presets;
+mod chain_worker;
+mod custom_events;
+mod decoding;
+mod delegation;
+mod effective_config;
+mod ens_names;
+mod escalation;
+mod expectation;
+mod explain;
+mod explorer_api;
+mod explorer_url;
+mod fixtures;
+mod fleet;
+mod four_byte;
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod history;
+mod http_client;
+mod interesting_transaction;
+mod kafka;
+mod label_history;
+mod live_stream;
+mod liveness;
+mod logging;
+mod message_budget;
+mod metrics;
+mod metrics_middleware;
+mod metrics_push;
+mod monitor;
+mod mqtt;
+mod nats;
+mod nft_metadata;
+mod notification;
+mod openapi;
+mod persistent_metrics;
+mod pipeline;
+mod price_alert;
+mod protocol_decoders;
+mod provider;
+mod recovery;
+mod redis_backend;
+mod reorg;
+mod retry;
+mod retryables;
+mod rotki;
+mod routing;
+mod rpc_error;
+mod rules;
+mod safe_proposals;
+mod scam_list;
+mod schedule;
+mod selector_watch;
+mod server;
+mod spam_report;
+mod spam_score;
+mod state_db;
+mod stuck_transactions;
+mod template;
+mod tenants;
+mod throttled_http;
+mod token;
+mod transaction_details;
+mod wallet_drain;
+mod watched_topics;
+mod webhook;
+mod withdrawals;
+
+pub use abi_registry::DecodedEvent;
+pub use account_direction::Direction;
+pub use chain::{
+    Chain, ChainDefinition, ChainMode, Confirmations, EnvInitializable, SpamFilterLevel,
+};
+pub use chain_worker::{ChainWorker, Reprocessed};
+pub use decoding::{parse_logs, process_block, register_decoder, LogDecoder};
+pub use escalation::Severity;
+pub use fixtures::replay as replay_fixtures;
+pub use interesting_transaction::{InterestingTransaction, InterestingTransactionKind};
+pub use monitor::{AddChainError, Monitor, WatchedAccount};
+pub use notification::{Link, Notification, Sendable};
+pub use pipeline::{build_notifications, filter_interesting_transactions, notified_transactions};
+pub use routing::Route;
+pub use server::run;
+pub use webhook::WebhookPayload;
+
+lazy_static! {
+    /// Labels of known protocol addresses, by lowercase address
+    static ref PROTOCOL_LABELS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+    /// Separator of the thousands of the amounts, none by default
+    static ref AMOUNT_GROUPING_SEPARATOR: String =
+        env::var("AMOUNT_GROUPING_SEPARATOR").unwrap_or_default();
+    /// Decimals the amounts are rounded to, all of them by default
+    static ref AMOUNT_MAX_DECIMALS: Option<u32> = env::var("AMOUNT_MAX_DECIMALS")
+        .ok()
+        .map(|max_decimals| max_decimals
+            .parse::<u32>()
+            .expect("Invalid AMOUNT_MAX_DECIMALS"));
+}
+
+/// Version of the running build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit of the running build, `unknown` when built outside of a checkout without `GIT_COMMIT`
+pub const COMMIT: &str = env!("GIT_COMMIT");
+
+/// Labels the address when it is missing from the addressbook
+pub(crate) fn register_protocol_label(address: &Address, label: String) {
+    PROTOCOL_LABELS
+        .write()
+        .unwrap()
+        .insert(address.full_string(), label);
+}
 
 pub trait FullString {
     fn full_string(&self) -> String;
@@ -21,7 +177,7 @@ impl FullString for H256 {
     }
 }
 
-pub trait ToLabel {
+pub(crate) trait ToLabel {
     fn to_label(&self, addressbook: &HashMap<String, String>) -> String;
 }
 
@@ -35,52 +191,136 @@ impl ToLabel for Address {
                 .to_string()
         } else if full_address == "0x0000000000000000000000000000000000000000" {
             "NULL".to_owned()
-        } else if full_address == "0x4822521e6135cd2599199c83ea35179229a172ee" {
-            "Gnosis Pay Spender".to_owned()
+        } else if let Some(label) = PROTOCOL_LABELS.read().unwrap().get(full_address) {
+            label.clone()
         } else {
             full_address.to_string()
         }
     }
 }
 
-pub trait IsKnownToken {
+pub(crate) trait IsKnownToken {
     fn is_known_token(&self) -> bool;
 }
 
 impl IsKnownToken for Address {
     fn is_known_token(&self) -> bool {
-        let connection = Connection::open("rotki_db.db").unwrap();
-        let query = "SELECT COUNT(1)
-                FROM evm_tokens
-                WHERE
-                  lower(address) = lower(:address)";
-        let mut statement = connection.prepare(query).unwrap();
-
-        let res: Result<bool, rusqlite::Error> = statement
-            .query_row(named_params! {":address": self.full_string()}, |row| {
-                Ok(1 == row.get::<usize, i32>(0).unwrap())
-            });
-
-        res.unwrap()
+        asset_db::asset_db().is_known_token(*self)
     }
 }
 
-pub fn scale_amount(amount: U256, decimals: u32) -> String {
+/// Amount in token units, rounded to `AMOUNT_MAX_DECIMALS` and with its thousands separated by
+/// `AMOUNT_GROUPING_SEPARATOR`. Dust rounded to zero is shown as below the smallest decimal,
+/// e.g. `<0.0001`
+pub(crate) fn scale_amount(amount: U256, decimals: u32) -> String {
+    let (amount, decimals) = match *AMOUNT_MAX_DECIMALS {
+        Some(max_decimals) if max_decimals < decimals => {
+            let unit = U256::exp10((decimals - max_decimals) as usize);
+            let (quotient, remainder) = amount.div_mod(unit);
+            let rounded_amount = if remainder >= unit / 2 {
+                quotient + 1
+            } else {
+                quotient
+            };
+
+            if rounded_amount.is_zero() && !amount.is_zero() {
+                return match max_decimals {
+                    0 => "<1".to_owned(),
+                    _ => format!("<0.{}1", "0".repeat(max_decimals as usize - 1)),
+                };
+            }
+            (rounded_amount, max_decimals)
+        }
+        _ => (amount, decimals),
+    };
+
     let scaled_amount = format_units(amount, decimals).unwrap();
+    let (integer, fraction) = scaled_amount
+        .split_once('.')
+        .unwrap_or((&scaled_amount, ""));
+    let fraction = fraction.trim_end_matches('0');
+
+    let integer = group_thousands(integer, &AMOUNT_GROUPING_SEPARATOR);
+    match fraction.is_empty() {
+        true => integer,
+        false => format!("{}.{}", integer, fraction),
+    }
+}
+
+fn group_thousands(integer: &str, separator: &str) -> String {
+    if separator.is_empty() {
+        return integer.to_owned();
+    }
 
-    let separator_index = scaled_amount.find('.').unwrap();
-    let least_significant_index = scaled_amount.len()
-        - scaled_amount
-            .chars()
-            .rev()
-            .position(|c| !(c == '0' || c == '.'))
-            .unwrap_or(scaled_amount.len());
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
 
-    let cutoff = if separator_index > least_significant_index {
-        separator_index
+/// Rough duration, in the largest unit it holds at least twice
+pub(crate) fn format_duration(seconds: f64) -> String {
+    if seconds >= 2.0 * 86400.0 {
+        format!("{:.0} days", seconds / 86400.0)
+    } else if seconds >= 2.0 * 3600.0 {
+        format!("{:.0} hours", seconds / 3600.0)
     } else {
-        least_significant_index
-    };
+        format!("{:.0} minutes", seconds / 60.0)
+    }
+}
+
+/// Calendar date and time of a unix timestamp, in UTC
+pub(crate) struct UtcDateTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
 
-    scaled_amount[..cutoff].to_owned()
+impl UtcDateTime {
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / 86400) as i64;
+        let seconds_of_day = timestamp % 86400;
+
+        // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcDateTime {
+            year,
+            month,
+            day,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day % 3600 / 60,
+            second: seconds_of_day % 60,
+        }
+    }
+}
+
+impl std::fmt::Display for UtcDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
 }
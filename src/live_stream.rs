@@ -0,0 +1,72 @@
+use futures::stream::{self, Stream};
+use lazy_static::lazy_static;
+use log::warn;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::convert::Infallible;
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::IntoParams;
+use warp::sse::Event;
+
+use crate::interesting_transaction::TransactionEvent;
+
+/// Events buffered for each subscriber, slower ones skip the oldest
+const LIVE_STREAM_CAPACITY: usize = 256;
+
+lazy_static! {
+    static ref TRANSACTION_EVENTS: broadcast::Sender<TransactionEvent> =
+        broadcast::channel(LIVE_STREAM_CAPACITY).0;
+}
+
+/// Query of the live stream
+#[derive(Debug, Default, DeserializeMacro, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamFilter {
+    /// Only the transactions of this tenant's accounts
+    pub tenant: Option<String>,
+}
+
+pub fn publish(transaction_events: &[TransactionEvent]) {
+    for transaction_event in transaction_events {
+        // Only fails when nobody is subscribed
+        let _ = TRANSACTION_EVENTS.send(transaction_event.clone());
+    }
+}
+
+/// Each notified transaction from now on
+pub fn subscribe_events() -> broadcast::Receiver<TransactionEvent> {
+    TRANSACTION_EVENTS.subscribe()
+}
+
+/// Whether a subscriber of the tenant receives the event, subscribers without a tenant receive
+/// every event
+pub fn is_visible(transaction_event: &TransactionEvent, tenant: Option<&str>) -> bool {
+    tenant.is_none_or(|tenant| transaction_event.tenant.as_deref() == Some(tenant))
+}
+
+/// Server-Sent Events with each notified transaction from now on, only of the tenant's accounts
+/// if set
+pub fn subscribe(tenant: Option<String>) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(subscribe_events(), move |mut receiver| {
+        let tenant = tenant.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(transaction_event) if !is_visible(&transaction_event, tenant.as_deref()) => {
+                    }
+                    Ok(transaction_event) => {
+                        let event = Event::default()
+                            .event("transaction")
+                            .id(transaction_event.hash.clone())
+                            .json_data(&transaction_event)
+                            .unwrap();
+                        return Some((Ok(event), receiver));
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Live stream subscriber lagging, skipped {} events", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,118 @@
+use ethers::{core::types::BlockNumber, middleware::Middleware};
+use log::{debug, error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    chain::Chain,
+    chain_control,
+    metrics::{CURRENT_BLOCK, LATEST_BLOCK_AGE},
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+};
+
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Blocks timestamped further in the future are considered skewed
+const MAX_FUTURE_SKEW: i64 = 60;
+
+#[derive(Debug, PartialEq)]
+enum Liveness {
+    Healthy,
+    /// The RPC serves blocks from the future, either it or the local clock is wrong
+    Skewed,
+    /// No new blocks from the RPC, either the chain halted or the RPC stopped syncing
+    Halted,
+    /// The chain moves on but the monitor doesn't
+    MonitorStuck,
+}
+
+/// Periodically compares the latest block against the wall clock and the monitor's progress,
+/// notifying whenever the chain's status changes
+pub async fn watch_chain_liveness(chain: Chain) {
+    let (chain, provider) = connect_and_verify(chain).await;
+
+    let mut liveness = Liveness::Healthy;
+    let mut monitored_block = CURRENT_BLOCK.with_label_values(&[&chain.name]).get();
+    let mut last_progress = Instant::now();
+
+    loop {
+        sleep(LIVENESS_CHECK_INTERVAL).await;
+
+        let latest_block = match provider.get_block(BlockNumber::Latest).await {
+            Ok(Some(latest_block)) => latest_block,
+            Ok(None) => continue,
+            Err(err) => {
+                log_rpc_error(&chain, "latest block", &err);
+                continue;
+            }
+        };
+        let latest_block_number = latest_block.number.unwrap_or_default().as_u64() as i64;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let block_age = now - latest_block.timestamp.as_u64() as i64;
+        LATEST_BLOCK_AGE
+            .with_label_values(&[&chain.name])
+            .set(block_age);
+
+        let current_block = CURRENT_BLOCK.with_label_values(&[&chain.name]).get();
+        // A paused monitor isn't stuck, its grace period starts over once resumed
+        if current_block != monitored_block || chain_control::is_paused(&chain.name) {
+            monitored_block = current_block;
+            last_progress = Instant::now();
+        }
+
+        let current_liveness = if block_age < -MAX_FUTURE_SKEW {
+            Liveness::Skewed
+        } else if block_age > chain.max_block_age.as_secs() as i64 {
+            Liveness::Halted
+        } else if last_progress.elapsed() > chain.max_block_age
+            && latest_block_number > monitored_block
+        {
+            Liveness::MonitorStuck
+        } else {
+            Liveness::Healthy
+        };
+        debug!(
+            "{} latest block {} is {} seconds old, monitored block {} ({:?})",
+            chain.name, latest_block_number, block_age, monitored_block, current_liveness
+        );
+
+        if current_liveness == liveness {
+            continue;
+        }
+
+        let message = match current_liveness {
+            Liveness::Healthy => format!("{} is healthy again", chain.name),
+            Liveness::Skewed => format!(
+                "{} RPC serves blocks {} seconds in the future, check the RPC and the system clock",
+                chain.name, -block_age
+            ),
+            Liveness::Halted => format!(
+                "No new {} blocks for {} minutes, the chain halted or its RPC is out of sync",
+                chain.name,
+                block_age / 60
+            ),
+            Liveness::MonitorStuck => format!(
+                "Account Monitor is stuck on {} block {} while the chain is at block {}",
+                chain.name, monitored_block, latest_block_number
+            ),
+        };
+        info!("{}", message);
+        liveness = current_liveness;
+
+        let notification = Notification {
+            message,
+            url: None,
+            severity: None,
+            route: None,
+            image: None,
+            links: vec![],
+        };
+        if notification.send().await.is_err() {
+            error!("Error while sending liveness notification");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, RwLock};
+use tracing::{level_filters::LevelFilter, Subscriber};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, registry::LookupSpan, reload,
+    util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+/// Replaces the filter of the logs with the given `RUST_LOG` directives
+type Reload = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+static RELOAD: OnceLock<Reload> = OnceLock::new();
+
+/// env_logger, with a filter which can be replaced at runtime
+struct ReloadableLogger {
+    logger: env_logger::Logger,
+    filter: RwLock<env_filter::Filter>,
+}
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.filter.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.filter.read().unwrap().matches(record) {
+            self.logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+enum LogTarget {
+    Stderr,
+    Stdout,
+    File(File),
+}
+
+fn log_target() -> LogTarget {
+    match env::var("LOG_TARGET")
+        .unwrap_or("stderr".to_string())
+        .as_str()
+    {
+        "stderr" => LogTarget::Stderr,
+        "stdout" => LogTarget::Stdout,
+        path => LogTarget::File(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("Could not open LOG_TARGET file."),
+        ),
+    }
+}
+
+/// Exports the tracing spans through OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set. The
+/// exporter is configured with the standard `OTEL_*` variables
+fn otlp_layer<S>() -> Option<OpenTelemetryLayer<S, opentelemetry_sdk::trace::SdkTracer>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+
+    // The blocking HTTP client can't be created within the tokio runtime
+    let exporter = std::thread::spawn(|| {
+        opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+    })
+    .join()
+    .unwrap()
+    .expect("Invalid OTLP exporter configuration");
+
+    let mut resource = Resource::builder();
+    if env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name("account-monitor");
+    }
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+    let tracer = tracer_provider.tracer("account-monitor");
+    TRACER_PROVIDER.set(tracer_provider).unwrap();
+
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Logs as plain text with env_logger, or as JSON lines (including the chain and block being
+/// processed) when `LOG_FORMAT` is `json`. Both are filtered by `RUST_LOG`, until replaced with
+/// `set_filter`
+pub fn init() {
+    let log_format = env::var("LOG_FORMAT")
+        .unwrap_or("text".to_string())
+        .to_lowercase();
+
+    match log_format.as_str() {
+        "text" => {
+            let target = match log_target() {
+                LogTarget::Stderr => env_logger::Target::Stderr,
+                LogTarget::Stdout => env_logger::Target::Stdout,
+                LogTarget::File(file) => env_logger::Target::Pipe(Box::new(file)),
+            };
+            // Records are filtered by the reloadable filter, the logger only formats them
+            let logger: &'static ReloadableLogger = Box::leak(Box::new(ReloadableLogger {
+                logger: env_logger::Builder::new()
+                    .filter_level(log::LevelFilter::Trace)
+                    .target(target)
+                    .build(),
+                filter: RwLock::new(env_filter::Builder::from_env("RUST_LOG").build()),
+            }));
+            log::set_logger(logger).expect("Could not set the logger");
+            log::set_max_level(logger.filter.read().unwrap().filter());
+
+            // Spans are only exported, the records of the log macros stay with env_logger. They
+            // are filtered by the same directives, reloaded along with the logger's filter
+            let span_filter = otlp_layer().map(|otlp_layer| {
+                let (filter, handle) = reload::Layer::new(EnvFilter::from_default_env());
+                tracing::subscriber::set_global_default(
+                    Registry::default().with(filter).with(otlp_layer),
+                )
+                .expect("Could not set the tracing subscriber");
+                handle
+            });
+            set_reload(Box::new(move |directives| {
+                let filter = env_filter::Builder::new()
+                    .try_parse(directives)
+                    .map_err(|err| err.to_string())?
+                    .build();
+                if let Some(span_filter) = span_filter.as_ref() {
+                    let span_filter_directives =
+                        EnvFilter::try_new(directives).map_err(|err| err.to_string())?;
+                    span_filter
+                        .reload(span_filter_directives)
+                        .map_err(|err| err.to_string())?;
+                }
+                log::set_max_level(filter.filter());
+                *logger.filter.write().unwrap() = filter;
+                Ok(())
+            }));
+        }
+        "json" => {
+            let writer = match log_target() {
+                LogTarget::Stderr => BoxMakeWriter::new(std::io::stderr),
+                LogTarget::Stdout => BoxMakeWriter::new(std::io::stdout),
+                LogTarget::File(file) => BoxMakeWriter::new(Mutex::new(file)),
+            };
+            // Records from the log macros are forwarded to tracing, within the chain spans
+            let builder = tracing_subscriber::fmt()
+                .json()
+                .with_env_filter(EnvFilter::from_default_env())
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(writer)
+                .with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.finish().with(otlp_layer()).init();
+            set_reload(Box::new(move |directives| {
+                let filter = EnvFilter::try_new(directives).map_err(|err| err.to_string())?;
+                handle.reload(filter).map_err(|err| err.to_string())?;
+                // The log macros are skipped above the max level of the log crate
+                log::set_max_level(
+                    log::LevelFilter::from_str(&LevelFilter::current().to_string())
+                        .unwrap_or(log::LevelFilter::Trace),
+                );
+                Ok(())
+            }));
+        }
+        _ => panic!("Invalid LOG_FORMAT"),
+    }
+}
+
+fn set_reload(reload: Reload) {
+    if RELOAD.set(reload).is_err() {
+        panic!("Logging initialized twice");
+    }
+}
+
+/// Filters the logs with the given `RUST_LOG` directives from now on, e.g.
+/// `info,account_monitor::chain_worker=debug`
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    match RELOAD.get() {
+        Some(reload) => reload(directives),
+        None => Err("Logging is not initialized".to_string()),
+    }
+}
+
+/// Exports the spans which are still buffered
+pub fn shutdown() {
+    if let Some(tracer_provider) = TRACER_PROVIDER.get() {
+        if let Err(err) = tracer_provider.shutdown() {
+            eprintln!("Error while exporting the remaining spans: {}", err);
+        }
+    }
+}
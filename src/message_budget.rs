@@ -0,0 +1,99 @@
+use lazy_static::lazy_static;
+use std::env;
+
+const ELLIPSIS: &str = "…";
+/// Hex characters kept at each end of a shortened address or hash
+const KEPT_HEX: usize = 4;
+/// Shortest hex string which is shortened, an address
+const MIN_SHORTENED_HEX: usize = 40;
+
+lazy_static! {
+    /// Messages longer than ntfy's default `message-size-limit` are turned into attachments
+    pub static ref NTFY_MESSAGE_LIMIT: usize = env::var("NTFY_MESSAGE_LIMIT")
+        .map(|limit| limit.parse::<usize>().expect("Invalid NTFY_MESSAGE_LIMIT"))
+        .unwrap_or(4096);
+    /// Telegram, the most restrictive of the common Apprise services, rejects longer messages
+    pub static ref APPRISE_MESSAGE_LIMIT: usize = env::var("APPRISE_MESSAGE_LIMIT")
+        .map(|limit| limit.parse::<usize>().expect("Invalid APPRISE_MESSAGE_LIMIT"))
+        .unwrap_or(4096);
+}
+
+/// Trims the middle of the addresses and hashes in the word, unless it is a link
+fn shorten_hex(word: &str) -> String {
+    if word.starts_with("http") {
+        return word.to_string();
+    }
+
+    let mut shortened = String::new();
+    let mut rest = word;
+    while let Some(start) = rest.find("0x") {
+        let hex_start = start + 2;
+        let hex_end = hex_start
+            + rest[hex_start..]
+                .bytes()
+                .take_while(u8::is_ascii_hexdigit)
+                .count();
+        if hex_end - hex_start >= MIN_SHORTENED_HEX {
+            shortened.push_str(&rest[..hex_start + KEPT_HEX]);
+            shortened.push_str(ELLIPSIS);
+            shortened.push_str(&rest[hex_end - KEPT_HEX..hex_end]);
+        } else {
+            shortened.push_str(&rest[..hex_end]);
+        }
+        rest = &rest[hex_end..];
+    }
+    shortened.push_str(rest);
+
+    shortened
+}
+
+/// Cuts the text at the last character fitting in the bytes, ellipsis included
+fn cut(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+
+    let mut end = max_bytes.saturating_sub(ELLIPSIS.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
+/// Fits the message in the bytes accepted by a sink. Addresses and hashes outside of links are
+/// shortened first, then the last lines of lists (such as digests) are replaced by their count,
+/// and only a single line is cut
+pub fn fit(message: &str, max_bytes: usize) -> String {
+    if message.len() <= max_bytes {
+        return message.to_string();
+    }
+
+    let shortened = message
+        .lines()
+        .map(|line| {
+            line.split(' ')
+                .map(shorten_hex)
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if shortened.len() <= max_bytes {
+        return shortened;
+    }
+
+    let lines: Vec<&str> = shortened.lines().collect();
+    for kept in (1..lines.len()).rev() {
+        let fitted = format!(
+            "{}\n{} {} more lines",
+            lines[..kept].join("\n"),
+            ELLIPSIS,
+            lines.len() - kept
+        );
+        if fitted.len() <= max_bytes {
+            return fitted;
+        }
+    }
+
+    cut(lines.first().copied().unwrap_or_default(), max_bytes)
+}
@@ -0,0 +1,152 @@
+use lazy_static::lazy_static;
+use log::warn;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntGauge, IntGaugeVec, Opts as PrometheusOpts, Registry,
+};
+use std::time::Duration;
+
+use crate::{
+    chain::Chain, fleet, metrics_middleware, persistent_metrics, price_alert, COMMIT, VERSION,
+};
+
+const OVER_BUDGET_WARNING_COUNT: u32 = 10;
+
+lazy_static! {
+    /// Registry of the metrics served by the API
+    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref CURRENT_BLOCK: IntGaugeVec = IntGaugeVec::new(
+        PrometheusOpts::new("current_block", "Current Block on each chain"),
+        &["chain"]
+    )
+    .expect("metric can be created");
+    pub static ref BUILD_INFO: IntGaugeVec = IntGaugeVec::new(
+        PrometheusOpts::new("build_info", "Version and commit of the running build, always 1"),
+        &["version", "commit"]
+    )
+    .expect("metric can be created");
+    pub static ref MONITORED_ACCOUNTS: IntGauge =
+        IntGauge::new("monitored_accounts", "Count of monitored accounts")
+            .expect("metric can be created");
+    pub static ref LOOP_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "loop_duration_seconds",
+            "Time spent on each polling loop iteration of each chain"
+        ),
+        &["chain"]
+    )
+    .expect("metric can be created");
+    pub static ref LOOP_BUDGET_USAGE: IntGaugeVec = IntGaugeVec::new(
+        PrometheusOpts::new(
+            "loop_budget_usage_percent",
+            "Last polling loop iteration duration as a percentage of the chain's blocktime"
+        ),
+        &["chain"]
+    )
+    .expect("metric can be created");
+    pub static ref LATEST_BLOCK_AGE: IntGaugeVec = IntGaugeVec::new(
+        PrometheusOpts::new(
+            "latest_block_age_seconds",
+            "Seconds since the timestamp of the latest block served by each chain's RPC"
+        ),
+        &["chain"]
+    )
+    .expect("metric can be created");
+    pub static ref PENDING_BLOCKS: IntGaugeVec = IntGaugeVec::new(
+        PrometheusOpts::new(
+            "pending_blocks",
+            "Blocks left to process on each chain after the last polling loop iteration"
+        ),
+        &["chain"]
+    )
+    .expect("metric can be created");
+}
+
+/// Registers the metrics of every module, before any is served
+pub fn register() {
+    BUILD_INFO.with_label_values(&[VERSION, COMMIT]).set(1);
+    REGISTRY
+        .register(Box::new(BUILD_INFO.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(CURRENT_BLOCK.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(MONITORED_ACCOUNTS.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(LOOP_DURATION.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(LOOP_BUDGET_USAGE.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(PENDING_BLOCKS.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(LATEST_BLOCK_AGE.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(metrics_middleware::RPC_REQUESTS.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(metrics_middleware::RPC_REQUEST_DURATION.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(fleet::RELAYER_BALANCE.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(fleet::RELAYER_TIME_TO_EMPTY.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(price_alert::TOKEN_PRICE.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(persistent_metrics::NOTIFICATIONS_SENT.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(persistent_metrics::TRANSACTIONS_PROCESSED.clone()))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(
+            persistent_metrics::INTERESTING_TRANSACTIONS.clone(),
+        ))
+        .expect("collector can be registered");
+    REGISTRY
+        .register(Box::new(persistent_metrics::BLOCKS_PROCESSED.clone()))
+        .expect("collector can be registered");
+}
+
+/// Records the duration of a polling loop iteration and the blocks left to process, warning when
+/// the chain keeps going over its blocktime budget
+pub fn record_loop_iteration(
+    chain: &Chain,
+    elapsed_time: Duration,
+    pending_blocks: u64,
+    consecutive_over_budget: &mut u32,
+) {
+    LOOP_DURATION
+        .with_label_values(&[chain.name.as_str()])
+        .observe(elapsed_time.as_secs_f64());
+    LOOP_BUDGET_USAGE
+        .with_label_values(&[chain.name.as_str()])
+        .set((elapsed_time.as_millis() * 100 / chain.blocktime.as_millis().max(1)) as i64);
+    PENDING_BLOCKS
+        .with_label_values(&[chain.name.as_str()])
+        .set(pending_blocks as i64);
+
+    if elapsed_time <= chain.blocktime {
+        *consecutive_over_budget = 0;
+        return;
+    }
+
+    *consecutive_over_budget += 1;
+    if consecutive_over_budget.is_multiple_of(OVER_BUDGET_WARNING_COUNT) {
+        warn!(
+            "{} exceeded its {} ms blocktime budget for {} consecutive iterations ({} blocks pending), consider tuning its blocktime or block range",
+            chain.name,
+            chain.blocktime.as_millis(),
+            consecutive_over_budget,
+            pending_blocks
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use ethers::core::types::{
+    Block, BlockId, BlockNumber, Filter, Log, Transaction, TransactionReceipt, TxHash, U256, U64,
+};
+use ethers::providers::{Middleware, ProviderError};
+use lazy_static::lazy_static;
+use log::warn;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts as PrometheusOpts};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{info_span, Instrument};
+
+const SLOW_REQUEST_DURATION: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    pub static ref RPC_REQUESTS: IntCounterVec = IntCounterVec::new(
+        PrometheusOpts::new(
+            "rpc_requests_total",
+            "RPC requests sent by each chain, per method"
+        ),
+        &["chain", "method", "outcome"]
+    )
+    .expect("metric can be created");
+    pub static ref RPC_REQUEST_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "rpc_request_duration_seconds",
+            "Duration of the RPC requests of each chain, per method"
+        ),
+        &["chain", "method"]
+    )
+    .expect("metric can be created");
+}
+
+/// Records the count and latency of the RPC requests of a chain, and logs the slow ones
+#[derive(Debug)]
+pub struct MetricsMiddleware<M> {
+    inner: M,
+    chain: String,
+}
+
+impl<M> MetricsMiddleware<M>
+where
+    M: Middleware<Error = ProviderError>,
+{
+    pub fn new(inner: M, chain: &str) -> Self {
+        Self {
+            inner,
+            chain: chain.to_string(),
+        }
+    }
+
+    pub fn record(&self, method: &str, elapsed: Duration, succeeded: bool) {
+        RPC_REQUESTS
+            .with_label_values(&[
+                self.chain.as_str(),
+                method,
+                if succeeded { "ok" } else { "error" },
+            ])
+            .inc();
+        RPC_REQUEST_DURATION
+            .with_label_values(&[self.chain.as_str(), method])
+            .observe(elapsed.as_secs_f64());
+
+        if elapsed > SLOW_REQUEST_DURATION {
+            warn!(
+                "Slow {} {} request, took {} ms",
+                self.chain,
+                method,
+                elapsed.as_millis()
+            );
+        }
+    }
+
+    async fn observe<T>(
+        &self,
+        method: &str,
+        request: impl Future<Output = Result<T, ProviderError>>,
+    ) -> Result<T, ProviderError> {
+        let now = Instant::now();
+        let result = request.instrument(info_span!("rpc_request", method)).await;
+        self.record(method, now.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Sends a raw JSON-RPC request, for methods without a [`Middleware`] counterpart
+    pub async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: Serialize + DeserializeOwned + Debug + Send,
+    {
+        self.observe(method, self.provider().request(method, params))
+            .await
+    }
+}
+
+fn block_method(block_id: BlockId) -> &'static str {
+    match block_id {
+        BlockId::Hash(_) => "eth_getBlockByHash",
+        BlockId::Number(_) => "eth_getBlockByNumber",
+    }
+}
+
+#[async_trait]
+impl<M> Middleware for MetricsMiddleware<M>
+where
+    M: Middleware<Error = ProviderError>,
+{
+    type Error = ProviderError;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        self.observe("eth_chainId", self.inner.get_chainid()).await
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        self.observe("eth_blockNumber", self.inner.get_block_number())
+            .await
+    }
+
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        let block_id = block_hash_or_number.into();
+        self.observe(block_method(block_id), self.inner.get_block(block_id))
+            .await
+    }
+
+    async fn get_block_with_txs<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<Transaction>>, Self::Error> {
+        let block_id = block_hash_or_number.into();
+        self.observe(
+            block_method(block_id),
+            self.inner.get_block_with_txs(block_id),
+        )
+        .await
+    }
+
+    async fn get_block_receipts<T: Into<BlockNumber> + Send + Sync>(
+        &self,
+        block: T,
+    ) -> Result<Vec<TransactionReceipt>, Self::Error> {
+        self.observe("eth_getBlockReceipts", self.inner.get_block_receipts(block))
+            .await
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        self.observe("eth_getLogs", self.inner.get_logs(filter))
+            .await
+    }
+}
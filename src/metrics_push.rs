@@ -0,0 +1,238 @@
+use eyre::Result;
+use lazy_static::lazy_static;
+use log::{error, info};
+use prometheus::{proto::MetricType, Encoder, TextEncoder};
+use prost::Message;
+use reqwest::RequestBuilder;
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use strum_macros::EnumString;
+use tokio::time::sleep;
+
+use crate::{http_client, metrics::REGISTRY};
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+pub enum PushProtocol {
+    /// Replaces the metrics of the job on a Pushgateway, in the text format
+    Pushgateway,
+    /// Prometheus remote-write 1.0, snappy compressed protobuf samples
+    RemoteWrite,
+}
+
+lazy_static! {
+    static ref METRICS_PUSH_URL: Option<String> = env::var("METRICS_PUSH_URL").ok();
+    static ref METRICS_PUSH_PROTOCOL: PushProtocol = PushProtocol::from_str(
+        &env::var("METRICS_PUSH_PROTOCOL").unwrap_or("Pushgateway".to_string())
+    )
+    .expect("Invalid METRICS_PUSH_PROTOCOL");
+    static ref METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(
+        env::var("METRICS_PUSH_INTERVAL")
+            .map(|interval| match interval.parse::<u64>() {
+                Ok(interval) if interval > 0 => interval,
+                _ => panic!("Invalid METRICS_PUSH_INTERVAL"),
+            })
+            .unwrap_or(60)
+    );
+    static ref METRICS_PUSH_JOB: String =
+        env::var("METRICS_PUSH_JOB").unwrap_or("account-monitor".to_string());
+    /// Username and password, as `username:password`
+    static ref METRICS_PUSH_BASIC_AUTH: Option<(String, String)> =
+        env::var("METRICS_PUSH_BASIC_AUTH").ok().map(|basic_auth| {
+            let (username, password) = basic_auth
+                .split_once(':')
+                .expect("Invalid METRICS_PUSH_BASIC_AUTH");
+            (username.to_string(), password.to_string())
+        });
+    static ref METRICS_PUSH_TOKEN: Option<String> = env::var("METRICS_PUSH_TOKEN").ok();
+}
+
+pub fn is_enabled() -> bool {
+    METRICS_PUSH_URL.is_some()
+}
+
+/// Remote-write 1.0 messages, from the Prometheus `remote.proto`
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Series of every metric of the registry, histograms split into their buckets, sum and count as
+/// Prometheus scrapes them
+fn time_series() -> Vec<TimeSeries> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let mut time_series = vec![];
+    for metric_family in REGISTRY.gather() {
+        let name = metric_family.get_name();
+        for metric in metric_family.get_metric() {
+            let mut push = |suffix: &str, extra_label: Option<(&str, String)>, value: f64| {
+                let mut labels: Vec<Label> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| Label {
+                        name: label.get_name().to_string(),
+                        value: label.get_value().to_string(),
+                    })
+                    .chain(extra_label.map(|(name, value)| Label {
+                        name: name.to_string(),
+                        value,
+                    }))
+                    .collect();
+                labels.push(Label {
+                    name: "__name__".to_string(),
+                    value: format!("{}{}", name, suffix),
+                });
+                labels.push(Label {
+                    name: "job".to_string(),
+                    value: METRICS_PUSH_JOB.clone(),
+                });
+                // Remote-write requires the labels sorted by name
+                labels.sort_by(|a, b| a.name.cmp(&b.name));
+                time_series.push(TimeSeries {
+                    labels,
+                    samples: vec![Sample { value, timestamp }],
+                });
+            };
+            match metric_family.get_field_type() {
+                MetricType::COUNTER => push("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => push("", None, metric.get_gauge().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        push(
+                            "_bucket",
+                            Some(("le", bucket.get_upper_bound().to_string())),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    push(
+                        "_bucket",
+                        Some(("le", "+Inf".to_string())),
+                        histogram.get_sample_count() as f64,
+                    );
+                    push("_sum", None, histogram.get_sample_sum());
+                    push("_count", None, histogram.get_sample_count() as f64);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    push("_sum", None, summary.get_sample_sum());
+                    push("_count", None, summary.get_sample_count() as f64);
+                }
+                MetricType::UNTYPED => push("", None, metric.get_untyped().get_value()),
+            }
+        }
+    }
+    time_series
+}
+
+/// Snappy block format made of literals only, which every decoder accepts. The samples are small
+/// enough for the missing compression not to matter
+fn snappy_literals(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + data.len() / 60 + 10);
+    // Uncompressed length, as a varint
+    let mut length = data.len() as u64;
+    while length >= 0x80 {
+        encoded.push((length as u8) | 0x80);
+        length >>= 7;
+    }
+    encoded.push(length as u8);
+
+    for chunk in data.chunks(u16::MAX as usize + 1) {
+        let length = chunk.len() - 1;
+        if length < 60 {
+            encoded.push((length as u8) << 2);
+        } else if length <= u8::MAX as usize {
+            encoded.push(60 << 2);
+            encoded.push(length as u8);
+        } else {
+            encoded.push(61 << 2);
+            encoded.extend_from_slice(&(length as u16).to_le_bytes());
+        }
+        encoded.extend_from_slice(chunk);
+    }
+    encoded
+}
+
+fn authenticated(request: RequestBuilder) -> RequestBuilder {
+    let request = match METRICS_PUSH_BASIC_AUTH.as_ref() {
+        Some((username, password)) => request.basic_auth(username, Some(password)),
+        None => request,
+    };
+    match METRICS_PUSH_TOKEN.as_ref() {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+async fn push() -> Result<()> {
+    let url = METRICS_PUSH_URL.as_ref().unwrap().trim_end_matches('/');
+    let request = match *METRICS_PUSH_PROTOCOL {
+        PushProtocol::Pushgateway => {
+            let encoder = TextEncoder::new();
+            let mut body = vec![];
+            encoder.encode(&REGISTRY.gather(), &mut body)?;
+            http_client::client()
+                .put(format!("{}/metrics/job/{}", url, METRICS_PUSH_JOB.as_str()))
+                .header(reqwest::header::CONTENT_TYPE, encoder.format_type())
+                .body(body)
+        }
+        PushProtocol::RemoteWrite => {
+            let body = WriteRequest {
+                timeseries: time_series(),
+            }
+            .encode_to_vec();
+            http_client::client()
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                .header(reqwest::header::CONTENT_ENCODING, "snappy")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                .body(snappy_literals(&body))
+        }
+    };
+    authenticated(request).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Pushes the metrics every `METRICS_PUSH_INTERVAL`, for instances Prometheus can't scrape
+pub async fn push_periodically() {
+    info!(
+        "Pushing metrics every {} seconds through {:?}",
+        METRICS_PUSH_INTERVAL.as_secs(),
+        *METRICS_PUSH_PROTOCOL
+    );
+    loop {
+        if let Err(err) = push().await {
+            error!("Error while pushing metrics: {}", err);
+        }
+        sleep(*METRICS_PUSH_INTERVAL).await;
+    }
+}
@@ -0,0 +1,230 @@
+use ethers::{core::types::Address, middleware::Middleware};
+use log::info;
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+use tracing::Instrument;
+use utoipa::ToSchema;
+
+use crate::{
+    account_direction, account_webhook, allowance_audit,
+    chain::{Chain, ChainDefinition, ChainMode},
+    chain_control,
+    chain_worker::{chain_span, ChainWorker},
+    delegation, ens_names, explorer_url, label_history, liveness,
+    metrics::MONITORED_ACCOUNTS,
+    provider::build_provider,
+    retryables, safe_proposals, stuck_transactions,
+    webhook::WebhookPayload,
+    withdrawals, FullString,
+};
+
+/// Account whose transactions are notified
+#[derive(Clone, DeserializeMacro, SerializeMacro, Debug, ToSchema)]
+pub struct WatchedAccount {
+    /// Address, or the explorer URL of an address or transaction
+    pub address: String,
+    pub label: String,
+    /// Receives only this account's transactions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    #[serde(default)]
+    pub direction: account_direction::Direction,
+}
+
+/// Why a chain added through the API is not monitored
+pub enum AddChainError {
+    /// Invalid definition, or unreachable or mismatching RPC
+    Invalid(String),
+    AlreadyMonitored(String),
+}
+
+/// Watched accounts shared by every chain worker, which are told when an account is added
+#[derive(Clone)]
+pub struct Monitor {
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    addressbook_updates: watch::Sender<()>,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self {
+            addressbook: Arc::new(Mutex::new(HashMap::new())),
+            addressbook_updates: watch::channel(()).0,
+        }
+    }
+
+    /// Labels of the watched accounts, by lowercase address
+    pub fn addressbook(&self) -> Arc<Mutex<HashMap<String, String>>> {
+        self.addressbook.clone()
+    }
+
+    /// Watches the account on every chain, returning the count of watched accounts
+    pub fn watch_account(&self, new_account: WatchedAccount) -> u32 {
+        let watched_accounts_count = {
+            let mut addressbook = self.addressbook.lock().unwrap();
+            label_history::record_label(&new_account.address, &new_account.label);
+            if let Some(webhook) = &new_account.webhook {
+                account_webhook::register(&new_account.address, webhook);
+            }
+            account_direction::register(&new_account.address, new_account.direction);
+            addressbook.insert(new_account.address.to_lowercase(), new_account.label);
+            addressbook.len() as u32
+        };
+        MONITORED_ACCOUNTS.set(watched_accounts_count as i64);
+
+        // Chain tasks rebuild their cached topics on the next parse
+        self.addressbook_updates.send_replace(());
+
+        watched_accounts_count
+    }
+
+    /// Validates an account added through the API before watching it, resolving the explorer
+    /// URLs of the chains to the address they point to
+    pub async fn add_account(
+        &self,
+        chains: &[Chain],
+        mut account: WatchedAccount,
+    ) -> Result<u32, String> {
+        if account.address.starts_with("http") {
+            account.address = explorer_url::resolve_account(chains, &account.address)
+                .await?
+                .full_string();
+        }
+        if Address::from_str(&account.address).is_err() {
+            return Err("Invalid account address".to_string());
+        }
+        if account
+            .webhook
+            .as_ref()
+            .is_some_and(|webhook| reqwest::Url::parse(webhook).is_err())
+        {
+            return Err("Invalid webhook URL".to_string());
+        }
+        let watched_accounts_count = self.watch_account(account);
+        info!("Watched Accounts: {}", watched_accounts_count);
+        Ok(watched_accounts_count)
+    }
+
+    /// Monitors a chain added through the API, after checking its RPC since a failure in the
+    /// chain worker would go unnoticed. Returns the name of the chain
+    pub async fn add_chain(
+        &self,
+        chain_definition: ChainDefinition,
+    ) -> Result<String, AddChainError> {
+        let chain = chain_definition
+            .into_chain()
+            .map_err(AddChainError::Invalid)?;
+        match build_provider(&chain).get_chainid().await {
+            Ok(chain_id) if chain.id.is_some_and(|id| id != chain_id) => {
+                return Err(AddChainError::Invalid(format!(
+                    "{} RPC is connected to chain {}",
+                    chain.name, chain_id
+                )));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                return Err(AddChainError::Invalid(format!(
+                    "Could not connect to {} RPC: {}",
+                    chain.name, err
+                )));
+            }
+        }
+        if !chain_control::register(&chain) {
+            return Err(AddChainError::AlreadyMonitored(format!(
+                "{} is already monitored",
+                chain.name
+            )));
+        }
+
+        info!("Adding {} through the API", chain.name);
+        let name = chain.name.clone();
+        self.spawn_chain(chain);
+        Ok(name)
+    }
+
+    /// Worker of the chain watching the monitor's accounts, to be run by the caller
+    pub fn chain_worker(&self, chain: Chain) -> ChainWorker {
+        ChainWorker::new(
+            chain,
+            self.addressbook.clone(),
+            self.addressbook_updates.subscribe(),
+        )
+    }
+
+    /// Spawns the worker of a `Blocks` or `Events` chain, along with its liveness, delegation,
+    /// Safe proposal, stuck transaction, allowance and ENS name checks
+    pub fn spawn_chain(&self, chain: Chain) {
+        if matches!(chain.mode, ChainMode::Push) {
+            panic!(
+                "{} is in Push mode, it has to be spawned with its webhook payloads",
+                chain.name
+            );
+        }
+        tokio::spawn(liveness::watch_chain_liveness(chain.clone()).instrument(chain_span(&chain)));
+        self.spawn_account_checks(&chain);
+        tokio::spawn(self.chain_worker(chain).run());
+    }
+
+    /// Spawns the worker of a `Push` chain, processing the payloads of its webhook
+    pub fn spawn_push_chain(&self, chain: Chain, webhook_payloads: mpsc::Receiver<WebhookPayload>) {
+        self.spawn_account_checks(&chain);
+        tokio::spawn(self.chain_worker(chain).run_push(webhook_payloads));
+    }
+
+    fn spawn_account_checks(&self, chain: &Chain) {
+        if delegation::is_enabled() {
+            tokio::spawn(
+                delegation::watch_delegations(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+        if safe_proposals::is_enabled(chain) {
+            tokio::spawn(
+                safe_proposals::watch_safe_proposals(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+        if stuck_transactions::is_enabled() {
+            tokio::spawn(
+                stuck_transactions::watch_stuck_transactions(
+                    chain.clone(),
+                    self.addressbook.clone(),
+                )
+                .instrument(chain_span(chain)),
+            );
+        }
+        if allowance_audit::is_enabled() {
+            tokio::spawn(
+                allowance_audit::audit_allowances(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+        if ens_names::is_enabled() {
+            tokio::spawn(
+                ens_names::watch_ens_names(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+        if retryables::is_enabled() {
+            tokio::spawn(
+                retryables::watch_retryables(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+        if withdrawals::is_enabled() {
+            tokio::spawn(
+                withdrawals::remind_claimable_withdrawals(chain.clone(), self.addressbook.clone())
+                    .instrument(chain_span(chain)),
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use rumqttc::{AsyncClient, ClientError, MqttOptions, QoS, Transport};
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{interesting_transaction::TransactionEvent, notification::Notification};
+
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+const MQTT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Messages waiting to be sent while the broker is unreachable
+const MQTT_QUEUE_SIZE: usize = 100;
+
+lazy_static! {
+    static ref MQTT_URL: Option<String> = env::var("MQTT_URL").ok();
+    static ref MQTT_TOPIC_PREFIX: String =
+        env::var("MQTT_TOPIC_PREFIX").unwrap_or("account-monitor".to_string());
+}
+
+static CLIENT: OnceLock<AsyncClient> = OnceLock::new();
+
+pub fn is_enabled() -> bool {
+    MQTT_URL.is_some()
+}
+
+/// Connects to the broker, reconnecting in the background whenever the connection drops
+pub fn connect() {
+    let url = reqwest::Url::parse(MQTT_URL.as_ref().unwrap()).expect("Invalid MQTT_URL");
+    let tls = match url.scheme() {
+        "mqtt" | "tcp" => false,
+        "mqtts" | "ssl" => true,
+        _ => panic!("Invalid MQTT_URL"),
+    };
+
+    let mut options = MqttOptions::new(
+        format!("account-monitor-{:08x}", rand::random::<u32>()),
+        url.host_str().expect("Invalid MQTT_URL"),
+        url.port().unwrap_or(if tls { 8883 } else { 1883 }),
+    );
+    options.set_keep_alive(MQTT_KEEP_ALIVE);
+    if !url.username().is_empty() {
+        options.set_credentials(url.username(), url.password().unwrap_or_default());
+    }
+    if tls {
+        options.set_transport(Transport::tls_with_default_config());
+    }
+
+    let (client, mut event_loop) = AsyncClient::new(options, MQTT_QUEUE_SIZE);
+    CLIENT.set(client).unwrap();
+
+    tokio::spawn(async move {
+        let mut connected = false;
+        loop {
+            match event_loop.poll().await {
+                Ok(_) => {
+                    if !connected {
+                        info!("Connected to MQTT broker");
+                        connected = true;
+                    }
+                }
+                Err(err) => {
+                    error!("MQTT connection error: {}", err);
+                    connected = false;
+                    sleep(MQTT_RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+}
+
+/// Queues the message without waiting, dropping it when the queue of an unreachable broker is
+/// full rather than holding the processing of the blocks back
+fn publish(topic: String, payload: Vec<u8>) -> Result<(), ClientError> {
+    let Some(client) = CLIENT.get() else {
+        return Ok(());
+    };
+    client
+        .try_publish(&topic, QoS::AtLeastOnce, false, payload)
+        .inspect_err(|err| {
+            warn!(
+                "Dropped the MQTT message to {}, the queue is full: {}",
+                topic, err
+            )
+        })
+}
+
+/// Publishes the notification as JSON to `<prefix>/notifications`
+pub fn publish_notification(notification: &Notification) -> Result<(), ClientError> {
+    publish(
+        format!("{}/notifications", *MQTT_TOPIC_PREFIX),
+        serde_json::to_vec(notification).unwrap(),
+    )
+}
+
+/// Publishes each transaction as JSON to `<prefix>/<chain>/<account>`, for automations to react
+/// to a single account. The accounts of a tenant are under `<prefix>/<tenant>`
+pub fn publish_events(transaction_events: &[TransactionEvent]) {
+    for transaction_event in transaction_events {
+        let topic = format!(
+            "{}/{}/{}",
+            transaction_event.topic_prefix(&MQTT_TOPIC_PREFIX, '/'),
+            transaction_event.chain.to_lowercase(),
+            transaction_event.account
+        );
+        // Dropped messages are already logged
+        let _ = publish(topic, serde_json::to_vec(transaction_event).unwrap());
+    }
+}
@@ -0,0 +1,52 @@
+use async_nats::{Client, ConnectOptions, Event};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use std::env;
+use std::sync::OnceLock;
+
+use crate::interesting_transaction::TransactionEvent;
+
+lazy_static! {
+    static ref NATS_URL: Option<String> = env::var("NATS_URL").ok();
+    static ref NATS_SUBJECT_PREFIX: String =
+        env::var("NATS_SUBJECT_PREFIX").unwrap_or("account-monitor".to_string());
+}
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+pub fn is_enabled() -> bool {
+    NATS_URL.is_some()
+}
+
+/// Connects to the server, retrying in the background while it is unreachable
+pub async fn connect() {
+    let client = ConnectOptions::new()
+        .name("account-monitor")
+        .retry_on_initial_connect()
+        .event_callback(|event| async move {
+            match event {
+                Event::Connected => info!("Connected to NATS"),
+                Event::Disconnected => warn!("Disconnected from NATS"),
+                event => info!("NATS event: {}", event),
+            }
+        })
+        .connect(NATS_URL.as_ref().unwrap().as_str())
+        .await
+        .expect("Invalid NATS_URL");
+    CLIENT.set(client).unwrap();
+}
+
+/// Publishes each transaction as JSON to the `<prefix>.<chain>` subject, or
+/// `<prefix>.<tenant>.<chain>` for the accounts of a tenant
+pub async fn publish_events(transaction_events: &[TransactionEvent]) {
+    let Some(client) = CLIENT.get() else {
+        return;
+    };
+    for transaction_event in transaction_events {
+        let subject = transaction_event.dotted_topic(&NATS_SUBJECT_PREFIX);
+        let payload = serde_json::to_vec(transaction_event).unwrap();
+        if let Err(err) = client.publish(subject, payload.into()).await {
+            error!("Error while publishing transaction to NATS: {}", err);
+        }
+    }
+}
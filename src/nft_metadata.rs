@@ -0,0 +1,264 @@
+use base64::Engine;
+use ethers::{
+    core::{
+        abi::{AbiDecode, AbiEncode},
+        types::{Address, Bytes, U256},
+        utils::hex,
+    },
+    providers::Provider,
+};
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use log::{debug, error};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::FullString;
+use crate::{
+    batch,
+    chain::Chain,
+    http_client,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    metrics_middleware::MetricsMiddleware,
+    redis_backend,
+    throttled_http::ThrottledHttp,
+};
+
+const NAME_SELECTOR: &str = "0x06fdde03";
+/// ERC721 `tokenURI(uint256)`
+const TOKEN_URI_SELECTOR: &str = "0xc87b56dd";
+/// ERC1155 `uri(uint256)`
+const URI_SELECTOR: &str = "0x0e89341c";
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref NFT_METADATA: bool = env::var("NFT_METADATA")
+        .unwrap_or("false".into())
+        .to_lowercase()
+        == "true";
+    /// Gateway the `ipfs://` URIs of the metadata and images are served from
+    static ref IPFS_GATEWAY: String =
+        env::var("IPFS_GATEWAY").unwrap_or("https://ipfs.io/ipfs/".to_string());
+    /// Metadata of the transferred NFTs (if any), by chain, contract and token id
+    static ref METADATA: Mutex<HashMap<(U256, Address, U256), Option<NftMetadata>>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn is_enabled() -> bool {
+    *NFT_METADATA
+}
+
+#[derive(Clone, DeserializeMacro, SerializeMacro)]
+pub struct NftMetadata {
+    pub collection: Option<String>,
+    pub name: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Metadata JSON of ERC721 and ERC1155 tokens
+#[derive(DeserializeMacro)]
+struct MetadataJson {
+    name: Option<String>,
+    image: Option<String>,
+    image_url: Option<String>,
+}
+
+/// URL of an `ipfs://` URI through the gateway, other URIs are kept
+fn gateway_url(uri: &str) -> String {
+    match uri.strip_prefix("ipfs://") {
+        Some(path) => format!("{}{}", *IPFS_GATEWAY, path.trim_start_matches("ipfs/")),
+        None => uri.to_string(),
+    }
+}
+
+/// Reads the metadata JSON, either embedded in a `data:` URI or served from its URL
+async fn fetch_metadata_json(uri: &str) -> eyre::Result<MetadataJson> {
+    if let Some(data) = uri.strip_prefix("data:application/json;base64,") {
+        return Ok(serde_json::from_slice(
+            &base64::engine::general_purpose::STANDARD.decode(data)?,
+        )?);
+    }
+    if let Some((_, data)) = uri
+        .strip_prefix("data:application/json")
+        .and_then(|data| data.split_once(','))
+    {
+        return Ok(serde_json::from_str(data)?);
+    }
+
+    Ok(http_client::client()
+        .get(gateway_url(uri))
+        .timeout(METADATA_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn fetch_metadata(collection: Option<String>, uri: Option<String>) -> Option<NftMetadata> {
+    let metadata_json = match uri {
+        Some(uri) => fetch_metadata_json(&uri)
+            .await
+            .inspect_err(|err| debug!("Error while fetching NFT metadata {}: {}", uri, err))
+            .ok(),
+        None => None,
+    };
+    if collection.is_none() && metadata_json.is_none() {
+        return None;
+    }
+
+    let (name, image) = metadata_json.map_or((None, None), |metadata_json| {
+        (
+            metadata_json.name,
+            metadata_json.image.or(metadata_json.image_url),
+        )
+    });
+    Some(NftMetadata {
+        collection,
+        name,
+        // Embedded images can't be attached
+        image: image
+            .filter(|image| !image.starts_with("data:"))
+            .map(|image| gateway_url(&image)),
+    })
+}
+
+fn metadata_cache_key(chain_id: U256, token: Address, token_id: U256) -> String {
+    format!("nft:{}:{}:{}", chain_id, token.full_string(), token_id)
+}
+
+/// Reads the collection name and token URI of the transferred NFTs from their contracts, with
+/// all the calls in a single JSON-RPC batch, then fetches their metadata
+#[tracing::instrument(skip_all)]
+pub async fn prefetch(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: &[InterestingTransaction],
+) {
+    if !is_enabled() {
+        return;
+    }
+
+    let chain_id = chain.id.unwrap();
+    let mut missing_nfts: Vec<(Address, U256, InterestingTransactionKind)> = {
+        let metadata = METADATA.lock().unwrap();
+        interesting_transactions
+            .iter()
+            .filter_map(|tx| Some((tx.token?, tx.token_id?, tx.kind)))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|(token, token_id, _)| !metadata.contains_key(&(chain_id, *token, *token_id)))
+            .collect()
+    };
+
+    if redis_backend::is_enabled() {
+        let mut uncached_nfts = vec![];
+        for (token, token_id, kind) in missing_nfts {
+            let cached_metadata =
+                redis_backend::get_cached(&metadata_cache_key(chain_id, token, token_id))
+                    .await
+                    .and_then(|cached| serde_json::from_str::<Option<NftMetadata>>(&cached).ok());
+            match cached_metadata {
+                Some(cached_metadata) => {
+                    METADATA
+                        .lock()
+                        .unwrap()
+                        .insert((chain_id, token, token_id), cached_metadata);
+                }
+                None => uncached_nfts.push((token, token_id, kind)),
+            }
+        }
+        missing_nfts = uncached_nfts;
+    }
+
+    if missing_nfts.is_empty() {
+        return;
+    }
+
+    let params: Vec<serde_json::Value> = missing_nfts
+        .iter()
+        .flat_map(|(token, token_id, kind)| {
+            let uri_selector = match kind {
+                InterestingTransactionKind::Transfer1155 => URI_SELECTOR,
+                _ => TOKEN_URI_SELECTOR,
+            };
+            let uri_call = format!("{}{}", uri_selector, hex::encode(token_id.encode()));
+            [
+                json!([{ "to": token, "data": NAME_SELECTOR }, "latest"]),
+                json!([{ "to": token, "data": uri_call }, "latest"]),
+            ]
+        })
+        .collect();
+
+    let results = match batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await {
+        Ok(results) => results,
+        Err(err) => {
+            error!("Error while getting {} NFT metadata: {}", chain.name, err);
+            return;
+        }
+    };
+
+    let fetched_metadata = join_all(missing_nfts.iter().zip(results.chunks(2)).map(
+        |((_, token_id, _), calls)| {
+            let collection = calls[0]
+                .as_ref()
+                .ok()
+                .and_then(|name| String::decode(name).ok())
+                .filter(|name| !name.is_empty());
+            // ERC1155 URIs are templates of the hex token id
+            let uri = calls[1]
+                .as_ref()
+                .ok()
+                .and_then(|uri| String::decode(uri).ok())
+                .filter(|uri| !uri.is_empty())
+                .map(|uri| uri.replace("{id}", &format!("{:064x}", token_id)));
+            fetch_metadata(collection, uri)
+        },
+    ))
+    .await;
+
+    for ((token, token_id, _), metadata) in missing_nfts.into_iter().zip(fetched_metadata) {
+        if redis_backend::is_enabled() {
+            redis_backend::set_cached(
+                &metadata_cache_key(chain_id, token, token_id),
+                serde_json::to_string(&metadata).unwrap(),
+            )
+            .await;
+        }
+        METADATA
+            .lock()
+            .unwrap()
+            .insert((chain_id, token, token_id), metadata);
+    }
+}
+
+fn get(chain: &Chain, tx: &InterestingTransaction) -> Option<NftMetadata> {
+    METADATA
+        .lock()
+        .unwrap()
+        .get(&(chain.id?, tx.token?, tx.token_id?))
+        .cloned()
+        .flatten()
+}
+
+/// Item and collection of the transferred NFT, e.g. `Zorb #12 (Zorbs)`
+pub fn describe(chain: &Chain, tx: &InterestingTransaction) -> Option<String> {
+    let metadata = get(chain, tx)?;
+    match (metadata.name, metadata.collection) {
+        (Some(name), Some(collection)) if !name.contains(&collection) => {
+            Some(format!("{} ({})", name, collection))
+        }
+        (Some(name), _) => Some(name),
+        (None, Some(collection)) => Some(format!("{} #{}", collection, tx.token_id?)),
+        (None, None) => None,
+    }
+}
+
+/// Image of the transferred NFT
+pub fn image(chain: &Chain, tx: &InterestingTransaction) -> Option<String> {
+    get(chain, tx)?.image
+}
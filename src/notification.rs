@@ -1,45 +1,483 @@
-use eyre::Result;
-use log::info;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::json;
+use std::collections::HashMap;
 use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use utoipa::ToSchema;
 
+use crate::{
+    escalation::{self, Severity},
+    http_client, message_budget, mqtt, persistent_metrics, redis_backend,
+    routing::{self, Route, Sink},
+};
+
+#[derive(DeserializeMacro, SerializeMacro, ToSchema)]
 pub struct Notification {
     pub url: Option<String>,
     pub message: String,
+    pub severity: Option<Severity>,
+    /// Route of the routing rule matching the transaction, sent to every sink if not set
+    #[serde(skip)]
+    pub route: Option<&'static Route>,
+    /// Image attached to the ntfy notification, such as the picture of a transferred NFT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Labelled links, such as the explorer pages of the transaction and its parties. Shown as
+    /// the ntfy actions instead of `url` when set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+}
+
+#[derive(Clone, DeserializeMacro, SerializeMacro, ToSchema)]
+pub struct Link {
+    pub label: String,
+    pub url: String,
+}
+
+struct RecentMessage {
+    first_sent: Instant,
+    url: Option<String>,
+    route: Option<&'static Route>,
+    repeated: u32,
+}
+
+lazy_static! {
+    pub static ref DEDUP_WINDOW: Option<Duration> = env::var("NOTIFICATION_DEDUP_WINDOW")
+        .ok()
+        .map(|window| Duration::from_secs(
+            window
+                .parse::<u64>()
+                .expect("Invalid NOTIFICATION_DEDUP_WINDOW")
+        ))
+        .filter(|window| !window.is_zero());
+    /// Messages sent during the dedup window, by the dedup key of their route and message
+    static ref RECENT_MESSAGES: Mutex<HashMap<(String, String), RecentMessage>> =
+        Mutex::new(HashMap::new());
+    static ref APPRISE_URL: Option<String> = env::var("APPRISE_URL").ok();
+    /// Prints the notifications to stdout as JSON lines instead of sending them to any sink,
+    /// for local development without ntfy
+    pub static ref DRY_RUN: bool = match env::var("NOTIFICATION_SINK") {
+        Ok(sink) if sink.to_lowercase() == "stdout" => true,
+        Ok(_) => panic!("Invalid NOTIFICATION_SINK"),
+        Err(_) => false,
+    };
+    /// ntfy server the notifications are pushed to, `None` when disabled
+    pub static ref NTFY: Option<NtfyConfig> = match *DRY_RUN
+        || env::var("NTFY_DISABLE")
+            .unwrap_or("false".into())
+            .to_lowercase()
+            == "true"
+    {
+        true => None,
+        false => Some(NtfyConfig::from_env()),
+    };
+    /// Address the ntfy pushes routed or escalated to `Email` are forwarded to
+    pub static ref NTFY_EMAIL: Option<String> =
+        env::var("NTFY_EMAIL").ok().filter(|email| !email.is_empty());
+}
+
+pub struct NtfyConfig {
+    pub url: String,
+    pub topic: String,
+    pub token: String,
+}
+
+impl NtfyConfig {
+    fn from_env() -> Self {
+        let var = |name: &str| {
+            env::var(name).ok().filter(|value| !value.is_empty()).unwrap_or_else(|| {
+                panic!(
+                    "Missing {}, set NTFY_DISABLE=true or NOTIFICATION_SINK=stdout to run without ntfy",
+                    name
+                )
+            })
+        };
+        let url = var("NTFY_URL");
+        if reqwest::Url::parse(&url).is_err() {
+            panic!("Invalid NTFY_URL, it should be the server URL, e.g. https://ntfy.sh");
+        }
+        NtfyConfig {
+            url: url.trim_end_matches('/').to_string(),
+            topic: var("NTFY_TOPIC"),
+            token: var("NTFY_TOKEN"),
+        }
+    }
+}
+
+/// Checks the ntfy configuration on startup instead of on the first notification
+pub fn load() {
+    if NTFY.is_none() {
+        info!("ntfy disabled");
+    }
+}
+
+/// Fails on startup when `what` sends notifications to `Email` through ntfy without `NTFY_EMAIL`
+pub fn require_email(what: &str) {
+    if NTFY.is_some() && NTFY_EMAIL.is_none() {
+        panic!("Missing NTFY_EMAIL, {} send to Email", what);
+    }
+}
+
+/// Sinks the notifications are delivered to, before routing
+pub fn configured_sinks() -> Vec<&'static str> {
+    if *DRY_RUN {
+        return vec!["stdout"];
+    }
+    [
+        ("ntfy", NTFY.is_some()),
+        ("email", NTFY.is_some() && NTFY_EMAIL.is_some()),
+        ("apprise", APPRISE_URL.is_some()),
+        ("webhook", http_client::NOTIFICATION_WEBHOOK_URL.is_some()),
+        ("redis", redis_backend::is_enabled()),
+        ("mqtt", mqtt::is_enabled()),
+    ]
+    .into_iter()
+    .filter(|(_, configured)| *configured)
+    .map(|(sink, _)| sink)
+    .collect()
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Messages delivered in the test running on the thread, which are never sent
+    static TEST_MESSAGES: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
 }
 
+/// Messages delivered so far in the test running on the current thread
+#[cfg(test)]
+pub(crate) fn delivered_for_test() -> Vec<String> {
+    TEST_MESSAGES.with_borrow(|messages| messages.clone())
+}
+
+/// ntfy rejects notifications with more actions
+const NTFY_MAX_ACTIONS: usize = 3;
+
 pub trait Sendable {
-    async fn send(&self) -> Result<()>;
+    fn send(&self) -> impl std::future::Future<Output = Result<()>> + Send;
 }
 
 impl Sendable for Notification {
+    #[tracing::instrument(name = "send_notification", skip_all)]
     async fn send(&self) -> Result<()> {
-        let ntfy_disable = env::var("NTFY_DISABLE")
-            .unwrap_or("false".into())
-            .to_lowercase();
+        if let Some(window) = *DEDUP_WINDOW {
+            let route_key = dedup_key(self.route);
+            if is_repeated(&route_key, &self.message, window).await {
+                debug!("Suppressed repeated notification: {}", self.message);
+                return Ok(());
+            }
 
-        if ntfy_disable == "true" {
-            info!("{}", self.message);
-        } else {
-            let ntfy_url = env::var("NTFY_URL").expect("Missing NTFY_URL");
-            let ntfy_topic = env::var("NTFY_TOPIC").expect("Missing NTFY_TOPIC");
-            let ntfy_token = env::var("NTFY_TOKEN").expect("Missing NTFY_TOKEN");
-
-            let client = reqwest::Client::new();
-            client
-                .post(format!("{}/{}", ntfy_url, ntfy_topic))
-                .body(self.message.clone())
-                .header("Authorization", format!("Bearer {}", ntfy_token))
+            // Sent by this instance, which will also send the repetitions summary
+            let expired_message = RECENT_MESSAGES.lock().unwrap().insert(
+                (route_key.clone(), self.message.clone()),
+                RecentMessage {
+                    first_sent: Instant::now(),
+                    url: self.url.clone(),
+                    route: self.route,
+                    repeated: 0,
+                },
+            );
+
+            if let Some(expired_message) = expired_message {
+                if let Some(repeated_notification) =
+                    repeated_notification(&route_key, &self.message, expired_message).await
+                {
+                    repeated_notification.deliver(None).await?;
+                }
+            }
+        }
+
+        if let Some(route) = self.route.filter(|route| route.digest) {
+            routing::add_to_digest(route, self);
+            return Ok(());
+        }
+
+        let acknowledge_id = escalation::track(self);
+        self.deliver(acknowledge_id.as_deref()).await
+    }
+}
+
+impl Notification {
+    /// Keeps the notification instead of delivering it in tests, returning whether it was kept
+    fn kept_for_test(&self) -> bool {
+        #[cfg(test)]
+        TEST_MESSAGES.with_borrow_mut(|messages| messages.push(self.message.clone()));
+        cfg!(test)
+    }
+
+    fn sends_to(&self, sink: Sink) -> bool {
+        match self.route {
+            Some(route) => route.sends_to(sink),
+            // Emails are only sent when routed to, besides escalations
+            None => sink != Sink::Email,
+        }
+    }
+
+    /// Sends the notification to the sinks of its route, skipping the dedup and digests. Every
+    /// sink is tried, the error lists the ones which failed
+    #[tracing::instrument(skip_all)]
+    pub async fn deliver(&self, acknowledge_id: Option<&str>) -> Result<()> {
+        if self.kept_for_test() {
+            return Ok(());
+        }
+        if *DRY_RUN {
+            println!("{}", serde_json::to_string(self)?);
+            return Ok(());
+        }
+
+        // A failing sink doesn't keep the notification from the others
+        let mut errors = vec![];
+
+        if self.sends_to(Sink::Ntfy) {
+            if let Err(err) = self.deliver_ntfy(acknowledge_id).await {
+                error!("Error while sending notification to ntfy: {}", err);
+                errors.push(format!("ntfy: {}", err));
+            }
+        }
+
+        if self.sends_to(Sink::Email) {
+            if let Err(err) = escalation::send_email(
+                self.severity.unwrap_or(Severity::Normal),
+                &self.message,
+                self.url.clone(),
+            )
+            .await
+            {
+                error!("Error while sending notification email: {}", err);
+                errors.push(format!("email: {}", err));
+            }
+        }
+
+        if APPRISE_URL.is_some() && self.sends_to(Sink::Apprise) {
+            if let Err(err) = self.deliver_apprise().await {
+                error!("Error while sending notification through Apprise: {}", err);
+                errors.push(format!("Apprise: {}", err));
+            }
+        }
+
+        if let Some(webhook_url) = http_client::NOTIFICATION_WEBHOOK_URL
+            .as_ref()
+            .filter(|_| self.sends_to(Sink::Webhook))
+        {
+            if let Err(err) = http_client::post_webhook(webhook_url, self).await {
+                error!("Error while posting notification to webhook: {}", err);
+                errors.push(format!("webhook: {}", err));
+            }
+        }
+
+        if redis_backend::is_enabled() && self.sends_to(Sink::Redis) {
+            if let Err(err) = redis_backend::publish(self).await {
+                error!("Error while publishing notification to Redis: {}", err);
+                errors.push(format!("Redis: {}", err));
+            }
+        }
+
+        if mqtt::is_enabled() && self.sends_to(Sink::Mqtt) {
+            if let Err(err) = mqtt::publish_notification(self) {
+                error!("Error while publishing notification to MQTT: {}", err);
+                errors.push(format!("MQTT: {}", err));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(eyre!("Failed sinks: {}", errors.join("; ")));
+        }
+        // Only the notifications delivered to every sink are counted
+        persistent_metrics::NOTIFICATIONS_SENT
+            .with_label_values(&[&self.severity.unwrap_or(Severity::Normal).to_string()])
+            .inc();
+        Ok(())
+    }
+
+    /// Pushes the notification to the ntfy topic of its route, or `NTFY_TOPIC`
+    #[tracing::instrument(skip_all)]
+    async fn deliver_ntfy(&self, acknowledge_id: Option<&str>) -> Result<()> {
+        let message = message_budget::fit(&self.message, *message_budget::NTFY_MESSAGE_LIMIT);
+        if let Some(ntfy) = NTFY.as_ref() {
+            let ntfy_topic = self
+                .route
+                .and_then(|route| route.topic.as_ref())
+                .unwrap_or(&ntfy.topic);
+
+            let acknowledge_action = escalation::ACKNOWLEDGE_URL
+                .as_ref()
+                .zip(acknowledge_id)
+                .map(|(acknowledge_url, acknowledge_id)| {
+                    format!(
+                        "http, Acknowledge, {}/acknowledge/{}, method=POST, clear=true",
+                        acknowledge_url, acknowledge_id
+                    )
+                });
+            let links = match self.links.is_empty() {
+                true => self
+                    .url
+                    .iter()
+                    .map(|url| Link {
+                        label: "Explorer".to_string(),
+                        url: url.clone(),
+                    })
+                    .collect(),
+                false => self.links.clone(),
+            };
+            // The acknowledge action is kept over the last links
+            let mut actions: Vec<String> = links
+                .iter()
+                .take(NTFY_MAX_ACTIONS - usize::from(acknowledge_action.is_some()))
+                .map(|link| format!("view, {}, {}, clear=true", link.label, link.url))
+                .collect();
+            actions.extend(acknowledge_action);
+
+            let mut request = http_client::client()
+                .post(format!("{}/{}", ntfy.url, ntfy_topic))
+                .body(message)
+                .header("Authorization", format!("Bearer {}", ntfy.token))
+                .header("Actions", actions.join("; "))
                 .header(
-                    "Actions",
-                    if self.url.is_some() {
-                        format!("view, Explorer, {}, clear=true", self.url.as_ref().unwrap())
-                    } else {
-                        "".to_string()
-                    },
-                )
-                .send()
-                .await?;
+                    "Priority",
+                    self.severity
+                        .map_or(Severity::Normal.priority(), |severity| severity.priority()),
+                );
+            if let Some(image) = &self.image {
+                request = request.header("Attach", image);
+            }
+            request.send().await?.error_for_status()?;
+        } else {
+            info!("{}", message);
         }
+
         Ok(())
     }
+
+    /// Sends the notification to an Apprise API notify endpoint, which fans it out to the
+    /// services configured there
+    #[tracing::instrument(skip_all)]
+    async fn deliver_apprise(&self) -> Result<()> {
+        let notification_type = match self.severity {
+            Some(Severity::High) => "warning",
+            Some(Severity::Critical) => "failure",
+            _ => "info",
+        };
+        // The link is kept whole, only the message is shortened
+        let body = match &self.url {
+            Some(url) => format!(
+                "{}\n{}",
+                message_budget::fit(
+                    &self.message,
+                    message_budget::APPRISE_MESSAGE_LIMIT.saturating_sub(url.len() + 1)
+                ),
+                url
+            ),
+            None => message_budget::fit(&self.message, *message_budget::APPRISE_MESSAGE_LIMIT),
+        };
+
+        http_client::client()
+            .post(APPRISE_URL.as_ref().unwrap())
+            .json(&json!({
+                "title": "Account Monitor",
+                "body": body,
+                "type": notification_type,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Dedup key of the route, messages are only repetitions of the ones sent through the same route
+fn dedup_key(route: Option<&Route>) -> String {
+    route.map(Route::dedup_key).unwrap_or_default()
+}
+
+/// Whether the message was already sent through the route during the dedup window, counting the
+/// repetition. When Redis is configured the window is shared by all the instances using it
+async fn is_repeated(route_key: &str, message: &str, window: Duration) -> bool {
+    if redis_backend::is_enabled() {
+        return match redis_backend::claim_message(route_key, message, window).await {
+            Ok(claimed) => !claimed,
+            Err(err) => {
+                error!(
+                    "Error while checking repeated notification in Redis: {}",
+                    err
+                );
+                false
+            }
+        };
+    }
+
+    match RECENT_MESSAGES
+        .lock()
+        .unwrap()
+        .get_mut(&(route_key.to_string(), message.to_string()))
+    {
+        Some(recent_message) if recent_message.first_sent.elapsed() < window => {
+            recent_message.repeated += 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+async fn repeated_notification(
+    route_key: &str,
+    message: &str,
+    recent_message: RecentMessage,
+) -> Option<Notification> {
+    let repeated = if redis_backend::is_enabled() {
+        redis_backend::take_repeated(route_key, message).await
+    } else {
+        recent_message.repeated
+    };
+
+    if repeated == 0 {
+        return None;
+    }
+
+    Some(Notification {
+        message: format!("Repeated {} times: {}", repeated, message),
+        url: recent_message.url,
+        severity: None,
+        route: recent_message.route,
+        image: None,
+        links: vec![],
+    })
+}
+
+/// Sends a single follow-up for each message which was suppressed during its dedup window
+pub async fn flush_repeated_notifications(window: Duration) {
+    loop {
+        sleep(window).await;
+
+        let expired_messages: Vec<((String, String), RecentMessage)> = {
+            let mut recent_messages = RECENT_MESSAGES.lock().unwrap();
+            let expired_messages: Vec<(String, String)> = recent_messages
+                .iter()
+                .filter(|(_, recent_message)| recent_message.first_sent.elapsed() >= window)
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            expired_messages
+                .into_iter()
+                .map(|key| {
+                    let recent_message = recent_messages.remove(&key).unwrap();
+                    (key, recent_message)
+                })
+                .collect()
+        };
+
+        for ((route_key, message), recent_message) in expired_messages {
+            if let Some(notification) =
+                repeated_notification(&route_key, &message, recent_message).await
+            {
+                if notification.deliver(None).await.is_err() {
+                    error!("Error while sending repeated notification summary");
+                }
+            }
+        }
+    }
 }
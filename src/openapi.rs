@@ -0,0 +1,284 @@
+//! OpenAPI description of the REST API. The routes are warp filters built in `main`, each is
+//! described here by an empty function, while the bodies are the schemas of the types the routes
+//! read and reply with
+// The functions only carry the description of their route
+#![allow(dead_code)]
+
+use utoipa::OpenApi;
+
+use crate::{
+    chain::ChainDefinition, chain_control::ChainState, chain_worker::Reprocessed,
+    effective_config::EffectiveConfig, expectation::Expectation, explain::Explanation,
+    history::HistoryEntry, history::HistoryFilter, interesting_transaction::TransactionEvent,
+    live_stream::StreamFilter, monitor::WatchedAccount, VERSION,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Account Monitor"),
+    paths(
+        watch_account,
+        expect_transaction,
+        acknowledge,
+        chains,
+        add_chain,
+        pause_chain,
+        resume_chain,
+        reprocess_block,
+        explain_transaction,
+        test_notification,
+        set_log_level,
+        config,
+        history,
+        stream,
+        calendar,
+        push_webhook,
+        graphql,
+        metrics,
+        openapi_document,
+    ),
+    components(schemas(TransactionEvent))
+)]
+struct ApiDoc;
+
+/// Watch an account
+#[utoipa::path(
+    post,
+    path = "/accounts",
+    request_body = WatchedAccount,
+    responses(
+        (status = 202, description = "Count of watched accounts", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid address, explorer URL or webhook URL", body = String, content_type = "text/plain"),
+    )
+)]
+fn watch_account() {}
+
+/// Expect a transaction, alerting if it doesn't land in time
+#[utoipa::path(
+    post,
+    path = "/expectations",
+    request_body = Expectation,
+    responses(
+        (status = 202, description = "Identifier of the expectation", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid expectation", body = String, content_type = "text/plain"),
+    )
+)]
+fn expect_transaction() {}
+
+/// Acknowledge a notification, stopping its escalation
+#[utoipa::path(
+    post,
+    path = "/acknowledge/{id}",
+    params(("id" = String, Path, description = "Identifier of the notification")),
+    responses(
+        (status = 200, description = "Acknowledged", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 404, description = "Unknown or already acknowledged notification", body = String, content_type = "text/plain"),
+    )
+)]
+fn acknowledge() {}
+
+/// State of the monitored chains
+#[utoipa::path(
+    get,
+    path = "/chains",
+    responses((status = 200, description = "Monitored chains", body = Vec<ChainState>))
+)]
+fn chains() {}
+
+/// Monitor a chain
+#[utoipa::path(
+    post,
+    path = "/chains",
+    request_body = ChainDefinition,
+    responses(
+        (status = 202, description = "Chain monitored", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 409, description = "Chain already monitored", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid chain, or unreachable or mismatching RPC", body = String, content_type = "text/plain"),
+    )
+)]
+fn add_chain() {}
+
+/// Pause a polling chain
+#[utoipa::path(
+    post,
+    path = "/chains/{chain}/pause",
+    params(("chain" = String, Path, description = "Name of the chain, case insensitive")),
+    responses(
+        (status = 200, description = "Chain paused", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 404, description = "No polling chain with this name", body = String, content_type = "text/plain"),
+    )
+)]
+fn pause_chain() {}
+
+/// Resume a paused chain
+#[utoipa::path(
+    post,
+    path = "/chains/{chain}/resume",
+    params(("chain" = String, Path, description = "Name of the chain, case insensitive")),
+    responses(
+        (status = 200, description = "Chain resumed", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 404, description = "No polling chain with this name", body = String, content_type = "text/plain"),
+    )
+)]
+fn resume_chain() {}
+
+/// Notifications a block would generate, without sending them
+#[utoipa::path(
+    post,
+    path = "/chains/{chain}/reprocess/{block}",
+    params(
+        ("chain" = String, Path, description = "Name of the chain, case insensitive"),
+        ("block" = u64, Path, description = "Block number"),
+    ),
+    responses(
+        (status = 200, description = "Notifications of the block", body = Reprocessed),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 404, description = "No chain with this name", body = String),
+        (status = 502, description = "RPC error", body = String),
+    )
+)]
+fn reprocess_block() {}
+
+/// How a transaction is decoded, filtered and notified
+#[utoipa::path(
+    get,
+    path = "/explain/{chain}/{hash}",
+    params(
+        ("chain" = String, Path, description = "Name of the chain, case insensitive"),
+        ("hash" = String, Path, description = "Transaction hash"),
+    ),
+    responses(
+        (status = 200, description = "Explanation of the transaction", body = Explanation),
+        (status = 404, description = "No chain with this name", body = String),
+        (status = 422, description = "Invalid transaction hash", body = String),
+        (status = 502, description = "RPC error or unknown transaction", body = String),
+    )
+)]
+fn explain_transaction() {}
+
+/// Send a made up transfer notification to every sink
+#[utoipa::path(
+    post,
+    path = "/test-notification",
+    responses(
+        (status = 200, description = "Message of the notification", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 502, description = "Delivery error", body = String, content_type = "text/plain"),
+    )
+)]
+fn test_notification() {}
+
+/// Replace the log filter
+#[utoipa::path(
+    put,
+    path = "/log-level",
+    request_body(
+        content = String,
+        content_type = "text/plain",
+        example = "info,account_monitor=debug"
+    ),
+    responses(
+        (status = 200, description = "Log level set", body = String, content_type = "text/plain"),
+        (status = 403, description = "Read-only mode", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid directives", body = String, content_type = "text/plain"),
+    )
+)]
+fn set_log_level() {}
+
+/// Effective configuration, with the secrets masked
+#[utoipa::path(
+    get,
+    path = "/config",
+    responses((status = 200, description = "Configuration", body = EffectiveConfig))
+)]
+fn config() {}
+
+/// Notified transactions, newest first
+#[utoipa::path(
+    get,
+    path = "/history",
+    params(HistoryFilter),
+    responses(
+        (status = 200, description = "Transactions", body = Vec<HistoryEntry>),
+        (status = 404, description = "History is not enabled", body = String),
+        (status = 500, description = "Database error", body = String),
+    )
+)]
+fn history() {}
+
+/// Server-sent events of the notified transactions
+#[utoipa::path(
+    get,
+    path = "/stream",
+    params(StreamFilter),
+    responses((
+        status = 200,
+        description = "A `TransactionEvent` as JSON in the data of each event",
+        body = String,
+        content_type = "text/event-stream"
+    ))
+)]
+fn stream() {}
+
+/// Calendar of the known upcoming events
+#[utoipa::path(
+    get,
+    path = "/calendar.ics",
+    responses((status = 200, description = "iCalendar feed", body = String, content_type = "text/calendar"))
+)]
+fn calendar() {}
+
+/// Blocks pushed by the provider of a chain in `Push` mode
+#[utoipa::path(
+    post,
+    path = "/webhooks/{chain}",
+    params(("chain" = String, Path, description = "Name of the chain, case insensitive")),
+    request_body = Object,
+    responses(
+        (status = 202, description = "Accepted", body = String, content_type = "text/plain"),
+        (status = 401, description = "Invalid signature", body = String, content_type = "text/plain"),
+        (status = 404, description = "Unknown chain or not in Push mode", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid payload", body = String, content_type = "text/plain"),
+    )
+)]
+fn push_webhook() {}
+
+/// GraphQL queries of the accounts, chains and history
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    responses((status = 200, description = "GraphQL response", body = Object))
+)]
+fn graphql() {}
+
+/// Prometheus metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"))
+)]
+fn metrics() {}
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "OpenAPI document", body = Object))
+)]
+fn openapi_document() {}
+
+/// OpenAPI 3.1 description of the REST API, to generate its clients
+pub fn document() -> utoipa::openapi::OpenApi {
+    let mut document = ApiDoc::openapi();
+    document.info.version = VERSION.to_string();
+    // Filled from the empty fields of the manifest
+    document.info.description = None;
+    document.info.license = None;
+    document
+}
@@ -0,0 +1,146 @@
+use lazy_static::lazy_static;
+use log::{error, info};
+use prometheus::{core::Collector, IntCounterVec, Opts as PrometheusOpts};
+use rusqlite::named_params;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{metrics_middleware::RPC_REQUESTS, state_db::CONNECTION};
+
+const METRICS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    pub static ref NOTIFICATIONS_SENT: IntCounterVec = IntCounterVec::new(
+        PrometheusOpts::new(
+            "notifications_sent_total",
+            "Notifications sent, per severity"
+        ),
+        &["severity"]
+    )
+    .expect("metric can be created");
+    pub static ref TRANSACTIONS_PROCESSED: IntCounterVec = IntCounterVec::new(
+        PrometheusOpts::new(
+            "transactions_processed_total",
+            "Interesting transactions found on each chain, per kind, spam included"
+        ),
+        &["chain", "kind"]
+    )
+    .expect("metric can be created");
+    pub static ref INTERESTING_TRANSACTIONS: IntCounterVec = IntCounterVec::new(
+        PrometheusOpts::new(
+            "interesting_transactions_total",
+            "Interesting transactions passing the spam filter on each chain, per kind"
+        ),
+        &["chain", "kind"]
+    )
+    .expect("metric can be created");
+    pub static ref BLOCKS_PROCESSED: IntCounterVec = IntCounterVec::new(
+        PrometheusOpts::new(
+            "blocks_processed_total",
+            "Blocks processed on each polling chain"
+        ),
+        &["chain"]
+    )
+    .expect("metric can be created");
+}
+
+/// Monotonic counters kept across restarts
+fn persisted_counters() -> [(&'static str, &'static IntCounterVec); 5] {
+    [
+        ("rpc_requests_total", &RPC_REQUESTS),
+        ("notifications_sent_total", &NOTIFICATIONS_SENT),
+        ("transactions_processed_total", &TRANSACTIONS_PROCESSED),
+        ("interesting_transactions_total", &INTERESTING_TRANSACTIONS),
+        ("blocks_processed_total", &BLOCKS_PROCESSED),
+    ]
+}
+
+/// Adds the values saved by a previous run to the counters, before they are first incremented
+pub fn restore() {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+    let connection = connection.lock().unwrap();
+
+    for (name, counter) in persisted_counters() {
+        let rows = connection
+            .prepare("SELECT labels, value FROM metric_counters WHERE name = :name")
+            .and_then(|mut statement| {
+                statement
+                    .query_map(named_params! {":name": name}, |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<(String, u64)>>>()
+            });
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(err) => {
+                error!("Error while restoring {}: {}", name, err);
+                continue;
+            }
+        };
+
+        for (labels, value) in rows {
+            let Ok(labels) = serde_json::from_str::<HashMap<String, String>>(&labels) else {
+                continue;
+            };
+            let labels: HashMap<&str, &str> = labels
+                .iter()
+                .map(|(label, value)| (label.as_str(), value.as_str()))
+                .collect();
+            match counter.get_metric_with(&labels) {
+                Ok(counter) => counter.inc_by(value),
+                Err(err) => error!("Invalid saved {} labels: {}", name, err),
+            }
+        }
+        info!("Restored {}", name);
+    }
+}
+
+/// Stores the current value of each counter
+pub fn save() {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+    let mut connection = connection.lock().unwrap();
+
+    let result = connection.transaction().and_then(|transaction| {
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO metric_counters (name, labels, value) VALUES (:name, :labels, :value)
+                ON CONFLICT (name, labels) DO UPDATE SET value = :value",
+            )?;
+            for (name, counter) in persisted_counters() {
+                for metric in counter
+                    .collect()
+                    .iter()
+                    .flat_map(|metric_family| metric_family.get_metric())
+                {
+                    let labels: BTreeMap<&str, &str> = metric
+                        .get_label()
+                        .iter()
+                        .map(|label| (label.get_name(), label.get_value()))
+                        .collect();
+                    statement.execute(named_params! {
+                        ":name": name,
+                        ":labels": serde_json::to_string(&labels).unwrap(),
+                        ":value": metric.get_counter().get_value() as u64,
+                    })?;
+                }
+            }
+        }
+        transaction.commit()
+    });
+
+    if let Err(err) = result {
+        error!("Error while saving metric counters: {}", err);
+    }
+}
+
+pub async fn save_periodically() {
+    loop {
+        sleep(METRICS_SAVE_INTERVAL).await;
+        save();
+    }
+}
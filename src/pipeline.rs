@@ -0,0 +1,307 @@
+use ethers::{core::types::H256, providers::Provider};
+use log::{error, info};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    account_direction, account_webhook, address_poisoning, asset_db, bridges,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    expectation, history,
+    interesting_transaction::{
+        BuildNotification, InterestingTransaction, SpamFilter, TransactionEvent,
+    },
+    kafka, live_stream,
+    metrics_middleware::MetricsMiddleware,
+    mqtt, nats, nft_metadata,
+    notification::{Notification, Sendable},
+    persistent_metrics,
+    recovery::Recovery,
+    rotki,
+    routing::Routable,
+    scam_list, selector_watch, spam_report, spam_score, tenants,
+    throttled_http::ThrottledHttp,
+    token, transaction_details, wallet_drain, withdrawals, FullString, ToLabel,
+};
+
+/// Notifies the transactions passing the chain's spam filter, after pushing them to rotki. The
+/// ones missed while stopped are added to the recovery summary instead
+#[tracing::instrument(skip_all, fields(transactions = interesting_transactions.len()))]
+pub async fn notify_interesting_transactions(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    interesting_transactions: Vec<InterestingTransaction>,
+    mut recovery: Option<&mut Recovery>,
+) {
+    for tx in interesting_transactions.iter() {
+        persistent_metrics::TRANSACTIONS_PROCESSED
+            .with_label_values(&[&chain.name, &tx.kind.to_string()])
+            .inc();
+    }
+    history::record(chain, &interesting_transactions);
+    expectation::check(chain, &interesting_transactions).await;
+    let drains = wallet_drain::detect(provider, chain, &interesting_transactions).await;
+    let selector_calls = selector_watch::detect(provider, chain, &interesting_transactions).await;
+    // Tokens are only known by the OnChain asset DB once their metadata is read
+    if !asset_db::is_curated() {
+        token::prefetch_tokens(
+            provider,
+            chain,
+            interesting_transactions
+                .iter()
+                .filter_map(|tx| tx.token)
+                .collect(),
+        )
+        .await;
+    }
+    let interesting_transactions = filter_interesting_transactions(interesting_transactions, chain);
+    let interesting_transactions = address_poisoning::filter(
+        chain,
+        interesting_transactions,
+        &addressbook.lock().unwrap(),
+    );
+    token::prefetch_tokens(
+        provider,
+        chain,
+        interesting_transactions
+            .iter()
+            .filter_map(|tx| tx.token)
+            .chain(drains.values().flat_map(|drain| drain.tokens()))
+            .collect(),
+    )
+    .await;
+    // Scored once the metadata of their tokens is read
+    let mut interesting_transactions =
+        spam_score::filter(provider, chain, interesting_transactions).await;
+    for tx in interesting_transactions.iter() {
+        persistent_metrics::INTERESTING_TRANSACTIONS
+            .with_label_values(&[&chain.name, &tx.kind.to_string()])
+            .inc();
+    }
+    transaction_details::fetch_missing(provider, chain, &mut interesting_transactions).await;
+    let bridge_calls = bridges::fetch_details(provider, chain, &mut interesting_transactions).await;
+    rotki::push_transactions(chain, &interesting_transactions);
+    nft_metadata::prefetch(provider, chain, &interesting_transactions).await;
+
+    let mut notifications =
+        build_notifications(&interesting_transactions, chain, addressbook.clone());
+    // Alerts of watched function calls, and a single notification summarizing all the outflows
+    // of each drain
+    for (tx, notification) in interesting_transactions
+        .iter()
+        .zip(notifications.iter_mut())
+    {
+        if let Some(selector_call) = selector_calls.get(&tx.hash) {
+            notification.message = selector_call.message(chain, &addressbook.lock().unwrap());
+            notification.severity = Some(Severity::High);
+        }
+        // The notified account of the transaction may not be the drained one, but both are
+        // notified to the same tenant
+        let tx_drains: Vec<String> = drains
+            .iter()
+            .filter(|((hash, account), _)| {
+                *hash == tx.hash
+                    && tenants::name_of(account) == tenants::name_of(&tx.involved_account)
+            })
+            .map(|(_, drain)| drain.message(chain, &addressbook.lock().unwrap()))
+            .collect();
+        if !tx_drains.is_empty() {
+            notification.message = tx_drains.join("\n");
+            notification.severity = Some(Severity::Critical);
+        }
+    }
+    let transaction_events: Vec<TransactionEvent> = interesting_transactions
+        .iter()
+        .zip(&notifications)
+        .map(|(tx, notification)| TransactionEvent::new(chain, tx, notification))
+        .collect();
+    live_stream::publish(&transaction_events);
+    mqtt::publish_events(&transaction_events);
+    nats::publish_events(&transaction_events).await;
+    kafka::publish_events(&transaction_events).await;
+    account_webhook::post_events(&transaction_events).await;
+
+    for (tx, notification) in interesting_transactions.iter().zip(notifications) {
+        if let Some(recovery) = recovery
+            .as_deref_mut()
+            .filter(|recovery| recovery.includes(tx))
+        {
+            recovery.add(chain, tx, &notification);
+            continue;
+        }
+        let Some(notification) = bridges::correlate(
+            chain,
+            tx,
+            notification,
+            &bridge_calls,
+            &addressbook.lock().unwrap(),
+        ) else {
+            continue;
+        };
+
+        if notification.send().await.is_err() {
+            error!("Error while sending notification");
+        } else {
+            chain_control::record_notification(chain);
+        }
+    }
+}
+
+/// Transactions passing the chain's spam filter and account directions, keeping the most
+/// relevant kind of each transaction
+pub fn filter_interesting_transactions(
+    interesting_transactions: Vec<InterestingTransaction>,
+    chain: &Chain,
+) -> Vec<InterestingTransaction> {
+    let interesting_transactions: Vec<InterestingTransaction> = interesting_transactions
+        .into_iter()
+        .filter(account_direction::is_watched)
+        .filter_map(|tx| {
+            if tx.is_spam(&chain.spam_filter_level) {
+                info!("Spam tx {} on {}", tx.hash.full_string(), chain.name);
+                spam_report::record(chain, &tx);
+                None
+            } else {
+                Some(tx)
+            }
+        })
+        .collect();
+    // Withdrawals are recorded before only the most relevant kind of each transaction is kept
+    withdrawals::record(chain, &interesting_transactions);
+
+    most_relevant(interesting_transactions)
+}
+
+/// Transactions passing the chain's spam filter and account directions, keeping the most
+/// relevant kind of each transaction, without recording the filtered ones or the withdrawals.
+/// Used to preview what would be notified
+pub fn notified_transactions(
+    interesting_transactions: Vec<InterestingTransaction>,
+    chain: &Chain,
+) -> Vec<InterestingTransaction> {
+    most_relevant(
+        interesting_transactions
+            .into_iter()
+            .filter(account_direction::is_watched)
+            .filter(|tx| !tx.is_spam(&chain.spam_filter_level))
+            .collect(),
+    )
+}
+
+/// The most relevant kind of each transaction, which is the one notified, once for each tenant
+/// involved
+pub fn most_relevant(
+    interesting_transactions: Vec<InterestingTransaction>,
+) -> Vec<InterestingTransaction> {
+    interesting_transactions
+        .into_iter()
+        .fold(
+            // Only one notification per transaction and tenant, each tenant is notified of the
+            // transactions of its own accounts
+            HashMap::<(H256, Option<&str>), InterestingTransaction>::new(),
+            |mut acc, tx| {
+                let key = (tx.hash, tenants::name_of(&tx.involved_account));
+                match acc.get(&key) {
+                    Some(current_tx) => {
+                        // Custom events are preferred over the unknown logs of the transaction
+                        if tx.kind > current_tx.kind
+                            || (tx.kind == current_tx.kind
+                                && tx.custom_event.is_some()
+                                && current_tx.custom_event.is_none())
+                        {
+                            acc.insert(key, tx);
+                        }
+                    }
+                    None => {
+                        acc.insert(key, tx);
+                    }
+                };
+                acc
+            },
+        )
+        .into_values()
+        .collect()
+}
+
+/// Notifications of the transactions, labelled with the addressbook
+#[tracing::instrument(skip_all, fields(transactions = interesting_transactions.len()))]
+pub fn build_notifications(
+    interesting_transactions: &[InterestingTransaction],
+    chain: &Chain,
+    addressbook_mutex: Arc<Mutex<HashMap<String, String>>>,
+) -> Vec<Notification> {
+    let addressbook = addressbook_mutex.lock().unwrap();
+
+    interesting_transactions
+        .iter()
+        .map(|tx| {
+            let mut notification = tx.build_notification(chain, &addressbook);
+            notification.route = tx.route(chain);
+            if let Some(severity) = notification.route.and_then(|route| route.severity) {
+                notification.severity = Some(severity);
+            }
+            if let Some(warning) = address_poisoning::warning(tx, chain, &addressbook) {
+                notification.message = warning;
+                notification.severity = Some(Severity::High);
+            }
+            if let Some(flagged_address) = scam_list::flagged_counterparty(tx) {
+                notification.message = format!(
+                    "⚠ Possible phishing, {} is a flagged scam address: {}",
+                    flagged_address.to_label(&addressbook),
+                    notification.message
+                );
+                notification.severity = Some(Severity::Critical);
+            }
+            notification
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chain::test_chain, interesting_transaction::InterestingTransactionKind};
+    use ethers::core::types::{Address, U256};
+    use serde_json::json;
+
+    const TREASURY: &str = "0x3333333333333333333333333333333333333333";
+    const ALICE: &str = "0x5555555555555555555555555555555555555555";
+
+    fn transfer(involved_account: &str) -> InterestingTransaction {
+        InterestingTransaction {
+            from: Some(TREASURY.parse().unwrap()),
+            to: Some(ALICE.parse().unwrap()),
+            amount: Some(U256::exp10(18)),
+            ..InterestingTransaction::new(
+                H256::zero(),
+                InterestingTransactionKind::Send,
+                involved_account.parse().unwrap(),
+            )
+        }
+    }
+
+    #[test]
+    fn notifies_each_tenant_of_its_accounts() {
+        tenants::set_for_test("tests/fixtures/tenants.yaml");
+
+        let notified = most_relevant(vec![transfer(TREASURY), transfer(ALICE)]);
+        let mut accounts: Vec<Address> = notified.iter().map(|tx| tx.involved_account).collect();
+        accounts.sort();
+        assert_eq!(
+            accounts,
+            vec![TREASURY.parse().unwrap(), ALICE.parse().unwrap()]
+        );
+
+        let chain = test_chain(json!({}));
+        for tx in notified {
+            let topic = tx.route(&chain).and_then(|route| route.topic.as_deref());
+            let expected = match tx.involved_account == TREASURY.parse().unwrap() {
+                true => "treasury-alerts",
+                false => "alice",
+            };
+            assert_eq!(topic, Some(expected));
+        }
+    }
+}
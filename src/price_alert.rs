@@ -0,0 +1,317 @@
+use ethers::{
+    core::{
+        abi::AbiDecode,
+        types::{Address, Bytes, U256},
+        utils::format_units,
+    },
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use prometheus::{GaugeVec, Opts as PrometheusOpts};
+use serde_derive::Deserialize as DeserializeMacro;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::FullString;
+use crate::{
+    batch,
+    chain::Chain,
+    escalation::Severity,
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    tenants,
+    throttled_http::ThrottledHttp,
+    token::{self, FromChainAddress, Token},
+};
+
+const PRICE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_WINDOW: u64 = 3600;
+const LATEST_ROUND_DATA_SELECTOR: &str = "0xfeaf968c";
+const DECIMALS_SELECTOR: &str = "0x313ce567";
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+
+lazy_static! {
+    pub static ref TOKEN_PRICE: GaugeVec = GaugeVec::new(
+        PrometheusOpts::new(
+            "token_price",
+            "Price of each token with a price alert, as reported by its feed"
+        ),
+        &["chain", "token"]
+    )
+    .expect("metric can be created");
+}
+
+/// Token whose price is watched while the watched accounts hold enough of it
+#[derive(Debug, Clone, DeserializeMacro)]
+pub struct PriceAlert {
+    pub chain: String,
+    pub token: Address,
+    /// Chainlink aggregator (or any feed implementing `latestRoundData`) of the token price
+    pub feed: Address,
+    /// Only alerts while the watched accounts hold at least this amount, in token units
+    #[serde(default)]
+    pub min_balance: f64,
+    /// Alerts when the price drops more than this percentage below its highest price in the window
+    pub max_drop: f64,
+    /// Seconds over which the drop is measured
+    #[serde(default = "default_window")]
+    pub window: u64,
+}
+
+fn default_window() -> u64 {
+    DEFAULT_WINDOW
+}
+
+struct PriceHistory {
+    alert: PriceAlert,
+    /// Prices in the window, oldest first
+    samples: VecDeque<(Instant, f64)>,
+    /// Tenants alerted of the current drop, `None` for the accounts without one. Only they are
+    /// notified once the price is stable again
+    alerted: Vec<Option<&'static str>>,
+}
+
+impl PriceHistory {
+    fn new(alert: PriceAlert) -> Self {
+        Self {
+            alert,
+            samples: VecDeque::new(),
+            alerted: vec![],
+        }
+    }
+
+    fn push(&mut self, price: f64) {
+        self.samples.push_back((Instant::now(), price));
+        while self.samples.front().is_some_and(|(sampled_at, _)| {
+            sampled_at.elapsed() > Duration::from_secs(self.alert.window)
+        }) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Percentage below the highest price in the window
+    fn drop(&self) -> f64 {
+        let highest = self
+            .samples
+            .iter()
+            .map(|(_, price)| *price)
+            .fold(0.0, f64::max);
+        let current = self.samples.back().map_or(0.0, |(_, price)| *price);
+
+        if highest > 0.0 {
+            (highest - current) / highest * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+fn to_units(amount: U256, decimals: u32) -> f64 {
+    format_units(amount, decimals)
+        .ok()
+        .and_then(|amount| amount.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Current price of each feed, with all the calls in a single JSON-RPC batch
+async fn get_prices(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    feeds: &[Address],
+) -> Vec<Option<f64>> {
+    let params: Vec<serde_json::Value> = feeds
+        .iter()
+        .flat_map(|feed| {
+            [LATEST_ROUND_DATA_SELECTOR, DECIMALS_SELECTOR]
+                .map(|selector| json!([{ "to": feed, "data": selector }, "latest"]))
+        })
+        .collect();
+
+    let results = match batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await {
+        Ok(results) => results,
+        Err(err) => {
+            error!("Error while getting {} prices: {}", chain.name, err);
+            return vec![None; feeds.len()];
+        }
+    };
+
+    results
+        .chunks(2)
+        .map(|feed_results| {
+            // The answer is the second value of the round data
+            let answer = feed_results[0]
+                .as_ref()
+                .ok()
+                .filter(|round_data| round_data.len() >= 64)
+                .map(|round_data| U256::from_big_endian(&round_data[32..64]))?;
+            let decimals = feed_results[1]
+                .as_ref()
+                .ok()
+                .and_then(|decimals| U256::decode(decimals).ok())
+                .filter(|decimals| *decimals <= U256::from(u8::MAX))?;
+            Some(to_units(answer, decimals.as_u32()))
+        })
+        .collect()
+}
+
+/// Amount of the token held by the watched accounts of each tenant, `None` for the accounts
+/// without one
+async fn get_held_amounts(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    token: Address,
+    accounts: &[Address],
+) -> Option<HashMap<Option<&'static str>, f64>> {
+    let params: Vec<serde_json::Value> = accounts
+        .iter()
+        .map(|account| {
+            json!([{
+                "to": token,
+                "data": format!("{}{:0>64}", BALANCE_OF_SELECTOR, &account.full_string()[2..])
+            }, "latest"])
+        })
+        .collect();
+
+    let balances = match batch::batch_request::<_, U256>(provider, "eth_call", &params).await {
+        Ok(balances) => balances,
+        Err(err) => {
+            error!("Error while getting {} token balances: {}", chain.name, err);
+            return None;
+        }
+    };
+
+    let decimals = Token::from_chain_address(chain, token).decimals;
+    let mut held_amounts = HashMap::new();
+    for (account, balance) in accounts.iter().zip(balances) {
+        *held_amounts.entry(tenants::name_of(account)).or_default() += to_units(balance, decimals);
+    }
+    Some(held_amounts)
+}
+
+/// Polls the price feeds of the chain's price alerts, alerting on sharp drops of the tokens held
+/// by the watched accounts
+pub async fn watch_prices(
+    chain: Chain,
+    alerts: Vec<PriceAlert>,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    let feeds: Vec<Address> = alerts.iter().map(|alert| alert.feed).collect();
+    token::prefetch_tokens(
+        &provider,
+        &chain,
+        alerts.iter().map(|alert| alert.token).collect(),
+    )
+    .await;
+    let mut price_histories: Vec<PriceHistory> =
+        alerts.into_iter().map(PriceHistory::new).collect();
+
+    info!(
+        "Watching the prices of {} tokens on {}",
+        price_histories.len(),
+        chain.name
+    );
+
+    loop {
+        let prices = get_prices(&provider, &chain, &feeds).await;
+
+        for (price_history, price) in price_histories.iter_mut().zip(prices) {
+            let token = Token::from_chain_address(&chain, price_history.alert.token);
+            let Some(price) = price else {
+                error!(
+                    "Could not read the {} price of {} from its feed",
+                    chain.name, token.symbol
+                );
+                continue;
+            };
+            price_history.push(price);
+            TOKEN_PRICE
+                .with_label_values(&[&chain.name, &price_history.alert.token.full_string()])
+                .set(price);
+
+            let drop = price_history.drop();
+            debug!(
+                "{} price of {} is {}, {:.2}% below the window high",
+                chain.name, token.symbol, price, drop
+            );
+
+            let dropped = drop > price_history.alert.max_drop;
+            let alerting = !price_history.alerted.is_empty();
+            if dropped == alerting {
+                continue;
+            }
+
+            let mut alerts = vec![];
+            if dropped {
+                let accounts: Vec<Address> = addressbook
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .filter_map(|account| Address::from_str(account).ok())
+                    .collect();
+                let Some(held_amounts) =
+                    get_held_amounts(&provider, &chain, price_history.alert.token, &accounts).await
+                else {
+                    continue;
+                };
+                for (tenant, held_amount) in held_amounts {
+                    if held_amount < price_history.alert.min_balance {
+                        debug!(
+                            "{} price of {} dropped, but only {} is held",
+                            chain.name, token.symbol, held_amount
+                        );
+                        continue;
+                    }
+                    alerts.push((
+                        tenant,
+                        format!(
+                            "{} price dropped {:.2}% to {} in the last {} minutes on {}, watched accounts hold {} {}",
+                            token.symbol,
+                            drop,
+                            price,
+                            price_history.alert.window / 60,
+                            chain.name,
+                            held_amount,
+                            token.symbol
+                        ),
+                    ));
+                }
+            } else {
+                for tenant in std::mem::take(&mut price_history.alerted) {
+                    alerts.push((
+                        tenant,
+                        format!(
+                            "{} price is stable again at {} on {}",
+                            token.symbol, price, chain.name
+                        ),
+                    ));
+                }
+            }
+
+            for (tenant, message) in alerts {
+                info!("{}", message);
+                let notification = Notification {
+                    message,
+                    url: None,
+                    severity: dropped.then_some(Severity::High),
+                    route: tenant.and_then(tenants::route_of_tenant),
+                    image: None,
+                    links: vec![],
+                };
+                if notification.send().await.is_err() {
+                    error!("Error while sending price alert");
+                } else if dropped {
+                    price_history.alerted.push(tenant);
+                }
+            }
+        }
+
+        sleep(PRICE_POLL_INTERVAL).await;
+    }
+}
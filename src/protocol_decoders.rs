@@ -0,0 +1,132 @@
+use ethers::core::types::{Address, Log, H256, U256};
+use log::info;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::HashMap;
+use std::env;
+
+use crate::{
+    decoding::LogDecoder,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+};
+
+/// Protocols known without any configuration, the ones of `PROTOCOL_DECODERS_PATH` are added
+const BUILT_IN_PROTOCOLS: &str = r#"
+- name: Gnosis Pay
+  labels:
+    "0x4822521e6135cd2599199c83ea35179229a172ee": Gnosis Pay Spender
+  events:
+    - name: SafeSend
+      topic: "0x3d0ce9bfc3ed7d6862dbb28b2dea94561fe714a1b4d019aa8af39730d1ad7c3d"
+      kind: Send
+      from: Topic1
+      to: Emitter
+      amount: Data
+"#;
+
+/// Part of a log a transaction field is read from. Addresses in the data are read from its first
+/// word, as are amounts
+#[derive(Clone, Copy, Debug, DeserializeMacro)]
+pub enum LogField {
+    Emitter,
+    Topic1,
+    Topic2,
+    Topic3,
+    Data,
+}
+
+impl LogField {
+    fn word(&self, log: &Log) -> Option<H256> {
+        match self {
+            LogField::Emitter => Some(H256::from(log.address)),
+            LogField::Topic1 => log.topics.get(1).copied(),
+            LogField::Topic2 => log.topics.get(2).copied(),
+            LogField::Topic3 => log.topics.get(3).copied(),
+            LogField::Data => log.data.get(..32).map(H256::from_slice),
+        }
+    }
+
+    fn address(&self, log: &Log) -> Option<Address> {
+        self.word(log).map(Address::from)
+    }
+
+    fn amount(&self, log: &Log) -> Option<U256> {
+        self.word(log)
+            .map(|word| U256::from_big_endian(word.as_bytes()))
+    }
+}
+
+/// Event of a protocol notified as a known kind of transaction
+#[derive(Debug, DeserializeMacro)]
+pub struct EventDecoder {
+    pub name: String,
+    pub topic: H256,
+    pub kind: InterestingTransactionKind,
+    pub from: Option<LogField>,
+    pub to: Option<LogField>,
+    pub amount: Option<LogField>,
+    pub token: Option<LogField>,
+}
+
+#[derive(Debug, DeserializeMacro)]
+pub struct Protocol {
+    pub name: String,
+    /// Labels of the protocol's addresses, for the ones missing from the addressbook
+    #[serde(default)]
+    pub labels: HashMap<Address, String>,
+    #[serde(default)]
+    pub events: Vec<EventDecoder>,
+}
+
+/// Event decoders of the built-in and user protocols, whose labels are registered
+pub fn load() -> Vec<EventDecoder> {
+    let mut protocols: Vec<Protocol> =
+        serde_yaml::from_str(BUILT_IN_PROTOCOLS).expect("Invalid built-in protocols");
+    if let Ok(protocol_decoders_path) = env::var("PROTOCOL_DECODERS_PATH") {
+        let file = std::fs::File::open(protocol_decoders_path)
+            .expect("Could not open protocol decoders file.");
+        let user_protocols: Vec<Protocol> =
+            serde_yaml::from_reader(file).expect("Could not read protocol decoders.");
+        protocols.extend(user_protocols);
+    }
+
+    let mut event_decoders = vec![];
+    for protocol in protocols {
+        for (address, label) in protocol.labels {
+            crate::register_protocol_label(&address, label);
+        }
+        event_decoders.extend(protocol.events);
+        info!("Loaded {} protocol", protocol.name);
+    }
+    event_decoders
+}
+
+impl LogDecoder for EventDecoder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn topic(&self) -> H256 {
+        self.topic
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        Some(InterestingTransaction {
+            hash: log.transaction_hash.unwrap(),
+            from: self.from.and_then(|from| from.address(log)),
+            to: self.to.and_then(|to| to.address(log)),
+            kind: self.kind,
+            amount: self
+                .amount
+                .map(|amount| amount.amount(log).unwrap_or_default()),
+            token: self.token.and_then(|token| token.address(log)),
+            token_id: None,
+            involved_account,
+            event: None,
+            custom_event: None,
+            function: None,
+            block_number: log.block_number,
+            timestamp: None,
+            fee: None,
+        })
+    }
+}
@@ -0,0 +1,89 @@
+use ethers::{
+    middleware::Middleware,
+    providers::{Authorization, Provider},
+};
+use lazy_static::lazy_static;
+use log::debug;
+use rand::Rng;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{
+    chain::Chain, chain_control, http_client, metrics_middleware::MetricsMiddleware,
+    throttled_http::ThrottledHttp,
+};
+
+lazy_static! {
+    /// Maximum random delay before a chain task connects, to spread the startup requests
+    static ref STARTUP_JITTER: Option<Duration> = env::var("STARTUP_JITTER")
+        .ok()
+        .map(|jitter| Duration::from_secs(jitter.parse::<u64>().expect("Invalid STARTUP_JITTER")))
+        .filter(|jitter| !jitter.is_zero());
+}
+
+/// Provider for the chain's RPC, with its headers, authentication and rate limit
+pub fn build_provider(chain: &Chain) -> MetricsMiddleware<Provider<ThrottledHttp>> {
+    let url = reqwest::Url::parse(chain.rpc.as_str()).expect("Invalid RPC");
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in chain.rpc_headers.iter() {
+        headers.insert(
+            reqwest::header::HeaderName::from_str(name).expect("Invalid RPC header name"),
+            reqwest::header::HeaderValue::from_str(value).expect("Invalid RPC header value"),
+        );
+    }
+    if let Some((username, password)) = &chain.rpc_basic_auth {
+        let mut authorization = reqwest::header::HeaderValue::from_str(
+            &Authorization::basic(username, password).to_string(),
+        )
+        .unwrap();
+        authorization.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, authorization);
+    }
+
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::new(5, 0))
+        .user_agent(http_client::USER_AGENT.as_str())
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    MetricsMiddleware::new(
+        Provider::new(ThrottledHttp::new(url, http_client, chain.max_rps)),
+        &chain.name,
+    )
+}
+
+/// Provider for the chain's RPC, after checking it serves the configured chain. Chains without
+/// a configured id take the RPC's
+pub async fn connect_and_verify(
+    mut chain: Chain,
+) -> (Chain, MetricsMiddleware<Provider<ThrottledHttp>>) {
+    if let Some(startup_jitter) = *STARTUP_JITTER {
+        let delay = rand::thread_rng().gen_range(Duration::ZERO..=startup_jitter);
+        debug!(
+            "Delaying {} startup for {} ms",
+            chain.name,
+            delay.as_millis()
+        );
+        sleep(delay).await;
+    }
+
+    let provider = build_provider(&chain);
+    let chainid = provider.get_chainid().await.unwrap();
+
+    if let Some(configured_chainid) = chain.id {
+        if chainid != configured_chainid {
+            panic!(
+                "Configured for {} ({}) but connected to {}",
+                chain.name, configured_chainid, chainid
+            );
+        }
+    } else {
+        chain.id = Some(chainid);
+    }
+    chain_control::record_connected(&chain);
+
+    (chain, provider)
+}
@@ -0,0 +1,284 @@
+use ethers::core::{
+    types::{Address, U64},
+    utils::format_units,
+};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use rusqlite::{named_params, OptionalExtension};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use crate::ToLabel;
+use crate::{
+    chain::Chain,
+    escalation::Severity,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    notification::{Notification, Sendable},
+    state_db::CONNECTION,
+    tenants,
+    token::{FromChainAddress, Token},
+};
+
+/// Notable transactions listed in a recovery summary, the rest are only counted
+const MAX_NOTABLE_TRANSACTIONS: usize = 10;
+
+lazy_static! {
+    static ref RECOVERY_MAX_BLOCKS: u64 = env::var("RECOVERY_MAX_BLOCKS")
+        .map(|max_blocks| max_blocks
+            .parse::<u64>()
+            .expect("Invalid RECOVERY_MAX_BLOCKS"))
+        .unwrap_or(10000);
+    /// Transfers of at least these amounts, by symbol of the native currency or token, are
+    /// notable whatever their severity
+    static ref RECOVERY_NOTABLE_AMOUNTS: HashMap<String, f64> = env::var("RECOVERY_NOTABLE_AMOUNTS")
+        .map(|notable_amounts| {
+            notable_amounts
+                .split(',')
+                .map(|notable_amount| {
+                    let (symbol, amount) = notable_amount
+                        .split_once('=')
+                        .expect("Invalid RECOVERY_NOTABLE_AMOUNTS");
+                    (
+                        symbol.trim().to_uppercase(),
+                        amount
+                            .trim()
+                            .parse::<f64>()
+                            .expect("Invalid RECOVERY_NOTABLE_AMOUNTS"),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+/// Whether the transaction sends or transfers at least the notable amount of its currency or
+/// token. NFTs are never notable for their amount
+fn is_large_transfer(chain: &Chain, tx: &InterestingTransaction) -> bool {
+    if !matches!(
+        tx.kind,
+        InterestingTransactionKind::Send | InterestingTransactionKind::Transfer
+    ) || tx.token_id.is_some()
+    {
+        return false;
+    }
+    let Some(amount) = tx.amount else {
+        return false;
+    };
+    let (symbol, decimals) = match tx.token {
+        Some(token) => {
+            let token = Token::from_chain_address(chain, token);
+            (token.symbol, token.decimals)
+        }
+        None => (chain.native_symbol.clone(), chain.native_decimals),
+    };
+
+    RECOVERY_NOTABLE_AMOUNTS
+        .get(&symbol.to_uppercase())
+        .is_some_and(|notable_amount| {
+            format_units(amount, decimals)
+                .ok()
+                .and_then(|amount| amount.parse::<f64>().ok())
+                .is_some_and(|amount| amount >= *notable_amount)
+        })
+}
+
+/// Last block processed by a previous run
+fn last_processed_block(chain: &Chain) -> Option<U64> {
+    let connection = CONNECTION.as_ref()?.lock().unwrap();
+    connection
+        .query_row(
+            "SELECT block FROM chain_progress WHERE chain = :chain",
+            named_params! {":chain": chain.name},
+            |row| row.get::<_, u64>(0),
+        )
+        .optional()
+        .unwrap_or_else(|err| {
+            error!("Error while reading {} progress: {}", chain.name, err);
+            None
+        })
+        .map(U64::from)
+}
+
+/// Stores the last processed block, for the next run to resume from
+pub fn save_progress(chain: &Chain, block_number: U64) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT INTO chain_progress (chain, block) VALUES (:chain, :block)
+        ON CONFLICT (chain) DO UPDATE SET block = :block",
+        named_params! {":chain": chain.name, ":block": block_number.as_u64()},
+    ) {
+        error!("Error while saving {} progress: {}", chain.name, err);
+    }
+}
+
+/// Activity of the accounts of a tenant found while recovering
+#[derive(Default)]
+struct TenantActivity {
+    accounts: Vec<(Address, usize)>,
+    notable: Vec<String>,
+}
+
+/// Activity found while catching up with the blocks missed while stopped, notified as a single
+/// summary instead of one notification per transaction
+pub struct Recovery {
+    from: U64,
+    /// First block which is not part of the recovery, the chain head when starting
+    until: U64,
+    unchecked_blocks: u64,
+    accounts: HashMap<Address, usize>,
+    /// Notable transactions, with their involved account
+    notable: Vec<(Address, String)>,
+}
+
+/// Block to start from, and the recovery of the missed blocks when resuming from the progress of
+/// a previous run
+pub fn resume(chain: &Chain, head: U64) -> (U64, Option<Recovery>) {
+    let Some(last_processed_block) = last_processed_block(chain) else {
+        return (head, None);
+    };
+    // Local devnets start over from genesis when restarted
+    if last_processed_block > head {
+        warn!(
+            "{} is at block {}, behind the last processed block {}, assuming it was reset",
+            chain.name, head, last_processed_block
+        );
+        return (head, None);
+    }
+    if last_processed_block + 1 >= head {
+        return (last_processed_block + 1, None);
+    }
+
+    let oldest_block = head.saturating_sub(U64::from(*RECOVERY_MAX_BLOCKS));
+    let from = (last_processed_block + 1).max(oldest_block);
+    info!(
+        "Resuming {} from block {}, recovering the activity until block {}",
+        chain.name, from, head
+    );
+
+    (
+        from,
+        Some(Recovery {
+            from,
+            until: head,
+            unchecked_blocks: (from - last_processed_block - 1).as_u64(),
+            accounts: HashMap::new(),
+            notable: vec![],
+        }),
+    )
+}
+
+impl Recovery {
+    pub fn includes(&self, tx: &InterestingTransaction) -> bool {
+        tx.block_number
+            .is_some_and(|block_number| block_number < self.until)
+    }
+
+    /// Counts the transaction, listing it when its severity is `High` or `Critical`, or it
+    /// transfers at least the notable amount of its currency or token
+    pub fn add(&mut self, chain: &Chain, tx: &InterestingTransaction, notification: &Notification) {
+        *self.accounts.entry(tx.involved_account).or_default() += 1;
+
+        if notification
+            .severity
+            .is_some_and(|severity| matches!(severity, Severity::High | Severity::Critical))
+            || is_large_transfer(chain, tx)
+        {
+            self.notable.push((
+                tx.involved_account,
+                match &notification.url {
+                    Some(url) => format!("{} {}", notification.message, url),
+                    None => notification.message.clone(),
+                },
+            ));
+        }
+    }
+
+    pub fn is_done(&self, next_block_number: U64) -> bool {
+        next_block_number >= self.until
+    }
+
+    /// Sends the transaction counts of each account and the notable transactions, as a summary
+    /// of the accounts without a tenant and one for each tenant with activity
+    pub async fn send_summary(
+        self,
+        chain: &Chain,
+        addressbook: Arc<Mutex<HashMap<String, String>>>,
+    ) {
+        let addressbook = addressbook.lock().unwrap().clone();
+
+        let mut tenant_activity: HashMap<Option<&str>, TenantActivity> =
+            HashMap::from([(None, TenantActivity::default())]);
+        for (account, count) in self.accounts {
+            tenant_activity
+                .entry(tenants::name_of(&account))
+                .or_default()
+                .accounts
+                .push((account, count));
+        }
+        for (account, notable) in self.notable {
+            tenant_activity
+                .entry(tenants::name_of(&account))
+                .or_default()
+                .notable
+                .push(notable);
+        }
+
+        for (tenant, mut activity) in tenant_activity {
+            let mut lines = vec![format!(
+                "Recovered {} blocks {} to {} missed while stopped",
+                chain.name,
+                self.from,
+                self.until - 1
+            )];
+            if self.unchecked_blocks > 0 {
+                lines.push(format!(
+                    "{} older blocks were not checked",
+                    self.unchecked_blocks
+                ));
+            }
+
+            if activity.accounts.is_empty() {
+                lines.push("No activity found".to_string());
+            }
+            activity
+                .accounts
+                .sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            for (account, count) in activity.accounts {
+                lines.push(format!(
+                    "{}: {} transaction{}",
+                    account.to_label(&addressbook),
+                    count,
+                    if count == 1 { "" } else { "s" }
+                ));
+            }
+
+            let notable_count = activity.notable.len();
+            if notable_count > 0 {
+                lines.push("Notable:".to_string());
+                lines.extend(activity.notable.into_iter().take(MAX_NOTABLE_TRANSACTIONS));
+                if notable_count > MAX_NOTABLE_TRANSACTIONS {
+                    lines.push(format!(
+                        "And {} more",
+                        notable_count - MAX_NOTABLE_TRANSACTIONS
+                    ));
+                }
+            }
+
+            let notification = Notification {
+                message: lines.join("\n"),
+                url: None,
+                severity: (notable_count > 0).then_some(Severity::High),
+                route: tenant.and_then(tenants::route_of_tenant),
+                image: None,
+                links: vec![],
+            };
+            if notification.send().await.is_err() {
+                error!("Error while sending recovery summary");
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use ethers::core::utils::{hex, keccak256};
+use lazy_static::lazy_static;
+use log::error;
+use redis::{
+    aio::ConnectionManager, streams::StreamMaxlen, AsyncCommands, RedisResult, SetExpiry,
+    SetOptions,
+};
+use std::env;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::notification::Notification;
+
+lazy_static! {
+    static ref REDIS_URL: Option<String> = env::var("REDIS_URL").ok();
+    /// Not published to when set to an empty string
+    static ref REDIS_CHANNEL: Option<String> = match env::var("REDIS_CHANNEL") {
+        Ok(channel) if channel.is_empty() => None,
+        Ok(channel) => Some(channel),
+        Err(_) => Some("account-monitor".to_string()),
+    };
+    static ref REDIS_STREAM: Option<String> = env::var("REDIS_STREAM").ok();
+    /// Approximate count of notifications the stream is trimmed to
+    static ref REDIS_STREAM_MAXLEN: usize = env::var("REDIS_STREAM_MAXLEN")
+        .map(|maxlen| maxlen.parse().expect("Invalid REDIS_STREAM_MAXLEN"))
+        .unwrap_or(10000);
+}
+
+static CONNECTION: OnceCell<ConnectionManager> = OnceCell::const_new();
+
+pub fn is_enabled() -> bool {
+    REDIS_URL.is_some()
+}
+
+async fn connection() -> RedisResult<ConnectionManager> {
+    let connection = CONNECTION
+        .get_or_try_init(|| async {
+            let client = redis::Client::open(REDIS_URL.as_ref().unwrap().as_str())?;
+            ConnectionManager::new(client).await
+        })
+        .await?;
+
+    // Cloning the manager shares the same multiplexed connection
+    Ok(connection.clone())
+}
+
+fn message_key(prefix: &str, route: &str, message: &str) -> String {
+    format!(
+        "account-monitor:{}:{}",
+        prefix,
+        hex::encode(keccak256(format!("{}\n{}", route, message).as_bytes()))
+    )
+}
+
+/// Publishes the notification as JSON to the configured channel, and adds it to the configured
+/// stream in its `notification` field for the consumers which can't miss any
+#[tracing::instrument(skip_all)]
+pub async fn publish(notification: &Notification) -> eyre::Result<()> {
+    let payload = serde_json::to_string(notification).unwrap();
+    let mut connection = connection().await?;
+    // The stream is still added to when publishing to the channel fails, as they may have
+    // different consumers
+    let mut errors = Vec::new();
+    if let Some(channel) = REDIS_CHANNEL.as_ref() {
+        if let Err(err) = connection.publish::<_, _, ()>(channel, &payload).await {
+            errors.push(format!("PUBLISH: {}", err));
+        }
+    }
+    if let Some(stream) = REDIS_STREAM.as_ref() {
+        if let Err(err) = connection
+            .xadd_maxlen::<_, _, _, _, ()>(
+                stream,
+                StreamMaxlen::Approx(*REDIS_STREAM_MAXLEN),
+                "*",
+                &[("notification", &payload)],
+            )
+            .await
+        {
+            errors.push(format!("XADD: {}", err));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre::eyre!(errors.join(", ")))
+    }
+}
+
+/// Claims the message sent through the route, identified by its dedup key, for the dedup window,
+/// shared by every instance using the same Redis. Returns false (and counts the repetition) if it
+/// was already claimed
+pub async fn claim_message(route: &str, message: &str, window: Duration) -> RedisResult<bool> {
+    let mut connection = connection().await?;
+    let claimed: Option<String> = connection
+        .set_options(
+            message_key("dedup", route, message),
+            1,
+            SetOptions::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(window.as_secs())),
+        )
+        .await?;
+
+    if claimed.is_none() {
+        let repeated_key = message_key("repeated", route, message);
+        connection.incr::<_, _, ()>(&repeated_key, 1).await?;
+        connection
+            .expire::<_, ()>(&repeated_key, 2 * window.as_secs() as i64)
+            .await?;
+    }
+
+    Ok(claimed.is_some())
+}
+
+/// Times the message was repeated through the route by any instance since it was claimed,
+/// resetting the count
+pub async fn take_repeated(route: &str, message: &str) -> u32 {
+    let repeated: RedisResult<Option<u32>> = match connection().await {
+        Ok(mut connection) => {
+            connection
+                .get_del(message_key("repeated", route, message))
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    repeated
+        .unwrap_or_else(|err| {
+            error!(
+                "Error while getting repeated notifications from Redis: {}",
+                err
+            );
+            None
+        })
+        .unwrap_or(0)
+}
+
+pub async fn get_cached(key: &str) -> Option<String> {
+    let cached: RedisResult<Option<String>> = match connection().await {
+        Ok(mut connection) => {
+            connection
+                .get(format!("account-monitor:cache:{}", key))
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    cached.unwrap_or_else(|err| {
+        error!("Error while reading from the Redis cache: {}", err);
+        None
+    })
+}
+
+pub async fn set_cached(key: &str, value: String) {
+    let cached: RedisResult<()> = match connection().await {
+        Ok(mut connection) => {
+            connection
+                .set(format!("account-monitor:cache:{}", key), value)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = cached {
+        error!("Error while writing to the Redis cache: {}", err);
+    }
+}
@@ -0,0 +1,88 @@
+use ethers::core::types::{H256, U64};
+use std::collections::{HashSet, VecDeque};
+
+struct RecentBlock {
+    number: U64,
+    hash: H256,
+    interesting_transactions: HashSet<H256>,
+}
+
+/// Hashes of the last processed blocks of a chain, used to detect reorgs and to avoid notifying
+/// again about transactions that were already notified before the reorg.
+pub struct RecentBlocks {
+    depth: usize,
+    blocks: VecDeque<RecentBlock>,
+    replayed_transactions: HashSet<H256>,
+    replay_until: Option<U64>,
+}
+
+impl RecentBlocks {
+    pub fn new(depth: usize) -> RecentBlocks {
+        RecentBlocks {
+            depth,
+            blocks: VecDeque::with_capacity(depth),
+            replayed_transactions: HashSet::new(),
+            replay_until: None,
+        }
+    }
+
+    /// Whether `parent_hash` matches the stored hash of the block before `number`. Unknown
+    /// parents (startup, or after a gap) are accepted.
+    pub fn is_parent(&self, number: U64, parent_hash: H256) -> bool {
+        match self.blocks.back() {
+            Some(last) if last.number + 1 == number => last.hash == parent_hash,
+            _ => true,
+        }
+    }
+
+    /// Stored blocks, newest first, to be checked against the canonical chain when looking for
+    /// the fork point
+    pub fn stored(&self) -> Vec<(U64, H256)> {
+        self.blocks
+            .iter()
+            .rev()
+            .map(|block| (block.number, block.hash))
+            .collect()
+    }
+
+    pub fn push(&mut self, number: U64, hash: H256, interesting_transactions: HashSet<H256>) {
+        if self.blocks.len() == self.depth {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(RecentBlock {
+            number,
+            hash,
+            interesting_transactions,
+        });
+
+        if self.replay_until.is_some_and(|until| number >= until) {
+            self.replay_until = None;
+            self.replayed_transactions.clear();
+        }
+    }
+
+    /// Drops every block after `fork_point`, remembering their transactions so they are not
+    /// notified again while the replaced blocks are re-processed
+    pub fn rollback(&mut self, fork_point: U64) {
+        let previous_tip = self.blocks.back().map(|block| block.number);
+
+        while self
+            .blocks
+            .back()
+            .is_some_and(|block| block.number > fork_point)
+        {
+            let block = self.blocks.pop_back().unwrap();
+            self.replayed_transactions
+                .extend(block.interesting_transactions);
+        }
+
+        self.replay_until = match (self.replay_until, previous_tip) {
+            (Some(until), Some(tip)) => Some(until.max(tip)),
+            (until, tip) => until.or(tip),
+        };
+    }
+
+    pub fn was_notified(&self, transaction_hash: &H256) -> bool {
+        self.replayed_transactions.contains(transaction_hash)
+    }
+}
@@ -0,0 +1,112 @@
+use ethers::providers::ProviderError;
+use log::{error, info};
+use rand::Rng;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::chain::Chain;
+use crate::notification::{Notification, Sendable};
+use crate::rpc_error::RpcErrorKind;
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+const RETRY_DELAY_JITTER: f64 = 0.2;
+/// Consecutive failures after which the RPC is declared down
+const RPC_DOWN_FAILURE_COUNT: u32 = 10;
+
+/// Consecutive RPC failures of a chain, waiting exponentially longer between retries and
+/// notifying when the RPC is declared down and when it recovers
+#[derive(Default)]
+pub struct RpcBackoff {
+    failures: u32,
+    down_since: Option<Instant>,
+}
+
+impl RpcBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs the failed request and waits according to the error kind before it is retried
+    pub async fn failed(&mut self, chain: &Chain, requested: &str, err: &ProviderError) {
+        let kind = log_rpc_error(chain, requested, err);
+        self.failures += 1;
+
+        if self.failures == RPC_DOWN_FAILURE_COUNT {
+            self.down_since = Some(Instant::now());
+            notify(format!(
+                "{} RPC is down after {} consecutive failures, last one: {}",
+                chain.name, self.failures, kind
+            ))
+            .await;
+        }
+
+        if let Some(delay) = retry_delay(kind, self.failures, chain.blocktime) {
+            error!(
+                "{} retry count {}, waiting {} seconds before next retry",
+                chain.name,
+                self.failures,
+                delay.as_secs()
+            );
+            sleep(delay).await;
+        }
+    }
+
+    pub async fn succeeded(&mut self, chain: &Chain) {
+        if let Some(down_since) = self.down_since.take() {
+            notify(format!(
+                "{} RPC is back up after {} minutes",
+                chain.name,
+                down_since.elapsed().as_secs() / 60
+            ))
+            .await;
+        }
+        self.failures = 0;
+    }
+}
+
+pub fn log_rpc_error(chain: &Chain, requested: &str, err: &ProviderError) -> RpcErrorKind {
+    let kind = RpcErrorKind::classify(err);
+    error!(
+        "Error while getting {} {} from RPC, {}{}: {}, retrying",
+        chain.name,
+        requested,
+        kind,
+        kind.hint()
+            .map(|hint| format!(" ({})", hint))
+            .unwrap_or_default(),
+        err
+    );
+    kind
+}
+
+/// Delay before retrying after `failures` consecutive failures, doubling with each delayed retry
+/// up to a cap. The jitter keeps chains sharing an RPC from retrying in lockstep
+fn retry_delay(kind: RpcErrorKind, failures: u32, blocktime: Duration) -> Option<Duration> {
+    let (immediate_retries, initial_delay) = kind.retry_policy(blocktime);
+    let delayed_retries = failures.saturating_sub(immediate_retries);
+    if delayed_retries == 0 {
+        return None;
+    }
+
+    let delay = initial_delay
+        .saturating_mul(2u32.saturating_pow(delayed_retries - 1))
+        .min(MAX_RETRY_DELAY);
+    let jitter = rand::thread_rng().gen_range(1.0 - RETRY_DELAY_JITTER..=1.0 + RETRY_DELAY_JITTER);
+
+    Some(delay.mul_f64(jitter).min(MAX_RETRY_DELAY))
+}
+
+async fn notify(message: String) {
+    info!("{}", message);
+    let notification = Notification {
+        message,
+        url: None,
+        severity: None,
+        route: None,
+        image: None,
+        links: vec![],
+    };
+    if notification.send().await.is_err() {
+        error!("Error while sending RPC status notification");
+    }
+}
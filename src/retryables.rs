@@ -0,0 +1,611 @@
+use ethers::{
+    core::{
+        abi::{AbiDecode, AbiEncode},
+        types::{Address, Bytes, Filter as LogFilter, TransactionReceipt, H256, U256, U64},
+        utils::hex,
+    },
+    middleware::Middleware,
+    providers::{Provider, ProviderError},
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use rusqlite::{named_params, OptionalExtension};
+use serde_derive::Deserialize as DeserializeMacro;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    batch,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    four_byte,
+    metrics_middleware::MetricsMiddleware,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+    state_db::CONNECTION,
+    tenants,
+    throttled_http::ThrottledHttp,
+};
+use crate::{scale_amount, FullString, ToLabel, UtcDateTime};
+
+/// Arbitrum One and Nova
+const ARBITRUM_CHAIN_IDS: [u64; 2] = [42161, 42170];
+const GET_TIMEOUT_SELECTOR: &str = "0x9f1025c6";
+/// Blocks read per check at most, older redeems are skipped after a long pause
+const MAX_BLOCK_RANGE: u64 = 10_000;
+/// Time before the expiration of a failed ticket its reminder is sent
+const EXPIRATION_REMINDER: Duration = Duration::from_secs(86400);
+
+lazy_static! {
+    /// Seconds between checks of the retryable tickets on Arbitrum, `0` disables them
+    pub static ref RETRYABLE_CHECK_INTERVAL: Duration = Duration::from_secs(
+        env::var("RETRYABLE_CHECK_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid RETRYABLE_CHECK_INTERVAL"))
+            .unwrap_or(60)
+    );
+    /// Precompile holding the retryable tickets
+    static ref ARB_RETRYABLE_TX: Address =
+        Address::from_str("0x000000000000000000000000000000000000006E").unwrap();
+    static ref REDEEM_SCHEDULED_TOPIC: H256 =
+        H256::from_str("0x5ccd009502509cf28762c67858994d85b163bb6e451f5e9df7c5e18c9c2e123e")
+            .unwrap();
+    static ref TICKET_CREATED_TOPIC: H256 =
+        H256::from_str("0x7c793cced5743dc5f531bbe2bfb5a9fa3f40adef29231e6ab165c08a29e3dd89")
+            .unwrap();
+}
+
+pub fn is_enabled() -> bool {
+    !RETRYABLE_CHECK_INTERVAL.is_zero()
+}
+
+/// Ticket created by an L1 message, as returned by `eth_getTransactionByHash` of its id
+#[derive(DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct RetryableTicket {
+    retry_to: Option<Address>,
+    retry_value: Option<U256>,
+    retry_data: Option<Bytes>,
+    beneficiary: Option<Address>,
+    refund_to: Option<Address>,
+}
+
+impl RetryableTicket {
+    /// Watched account receiving the call, value or refunds of the ticket, including the
+    /// recipients of token deposits which are parameters of the call
+    fn involved_account(&self, accounts: &[Address]) -> Option<Address> {
+        accounts.iter().copied().find(|account| {
+            [self.retry_to, self.beneficiary, self.refund_to].contains(&Some(*account))
+                || self.retry_data.as_ref().is_some_and(|data| {
+                    data.windows(32)
+                        .any(|word| word == H256::from(*account).as_bytes())
+                })
+        })
+    }
+
+    fn describe(&self, chain: &Chain, addressbook: &HashMap<String, String>) -> String {
+        let to = self
+            .retry_to
+            .map_or("an unknown contract".to_string(), |to| {
+                to.to_label(addressbook)
+            });
+        match (
+            self.retry_data.as_ref().filter(|data| !data.is_empty()),
+            self.retry_value.filter(|value| !value.is_zero()),
+        ) {
+            (Some(data), _) => match four_byte::lookup(data) {
+                Some(function) => format!("{} on {}", function, to),
+                None => format!("call to {}", to),
+            },
+            (None, Some(value)) => format!(
+                "send {} {} to {}",
+                scale_amount(value, chain.native_decimals),
+                chain.native_symbol,
+                to
+            ),
+            (None, None) => format!("call to {}", to),
+        }
+    }
+}
+
+/// Failed ticket of a watched account, waiting to be redeemed
+struct FailedTicket {
+    account: Address,
+    /// Unix timestamp after which the ticket can be discarded
+    timeout: u64,
+    reminded: bool,
+}
+
+/// Failed tickets of the chain pending from a previous run
+fn load_failed_tickets(chain: &Chain) -> HashMap<H256, FailedTicket> {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return HashMap::new();
+    };
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare(
+            "SELECT ticket_id, account, timeout, reminded FROM failed_retryable_tickets
+            WHERE chain = :chain",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(named_params! {":chain": chain.name}, |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, bool>(3)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!(
+                "Error while reading the failed retryable tickets of {}: {}",
+                chain.name, err
+            );
+            return HashMap::new();
+        }
+    };
+
+    rows.into_iter()
+        .filter_map(|(ticket_id, account, timeout, reminded)| {
+            let (Ok(ticket_id), Ok(account)) =
+                (H256::from_str(&ticket_id), Address::from_str(&account))
+            else {
+                error!(
+                    "Invalid failed retryable ticket {} on {}",
+                    ticket_id, chain.name
+                );
+                return None;
+            };
+            Some((
+                ticket_id,
+                FailedTicket {
+                    account,
+                    timeout,
+                    reminded,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn store_failed_ticket(chain: &Chain, ticket_id: H256, ticket: &FailedTicket) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR REPLACE INTO failed_retryable_tickets (chain, ticket_id, account, timeout,
+        reminded) VALUES (:chain, :ticket_id, :account, :timeout, :reminded)",
+        named_params! {
+            ":chain": chain.name,
+            ":ticket_id": ticket_id.full_string(),
+            ":account": ticket.account.full_string(),
+            ":timeout": ticket.timeout,
+            ":reminded": ticket.reminded,
+        },
+    ) {
+        error!(
+            "Error while storing failed retryable ticket {}: {}",
+            ticket_id.full_string(),
+            err
+        );
+    }
+}
+
+fn remove_failed_ticket(chain: &Chain, ticket_id: H256) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "DELETE FROM failed_retryable_tickets WHERE chain = :chain AND ticket_id = :ticket_id",
+        named_params! {":chain": chain.name, ":ticket_id": ticket_id.full_string()},
+    ) {
+        error!(
+            "Error while removing failed retryable ticket {}: {}",
+            ticket_id.full_string(),
+            err
+        );
+    }
+}
+
+/// Last block checked by a previous run
+fn last_checked_block(chain: &Chain) -> Option<U64> {
+    let connection = CONNECTION.as_ref()?.lock().unwrap();
+    connection
+        .query_row(
+            "SELECT block FROM retryable_progress WHERE chain = :chain",
+            named_params! {":chain": chain.name},
+            |row| row.get::<_, u64>(0),
+        )
+        .optional()
+        .unwrap_or_else(|err| {
+            error!(
+                "Error while reading {} retryable progress: {}",
+                chain.name, err
+            );
+            None
+        })
+        .map(U64::from)
+}
+
+fn save_progress(chain: &Chain, block_number: U64) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT INTO retryable_progress (chain, block) VALUES (:chain, :block)
+        ON CONFLICT (chain) DO UPDATE SET block = :block",
+        named_params! {":chain": chain.name, ":block": block_number.as_u64()},
+    ) {
+        error!(
+            "Error while saving {} retryable progress: {}",
+            chain.name, err
+        );
+    }
+}
+
+fn notification(
+    chain: &Chain,
+    ticket_id: H256,
+    account: Address,
+    message: String,
+    severity: Severity,
+) -> Notification {
+    Notification {
+        message,
+        url: chain.tx_url(ticket_id),
+        severity: Some(severity),
+        route: tenants::route_of(&account),
+        image: None,
+        links: vec![],
+    }
+}
+
+/// Expiration of each ticket, `None` for the tickets which no longer exist
+async fn timeouts(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    ticket_ids: &[H256],
+) -> Result<Vec<Option<u64>>, ProviderError> {
+    let params: Vec<serde_json::Value> = ticket_ids
+        .iter()
+        .map(|ticket_id| {
+            json!([{
+                "to": *ARB_RETRYABLE_TX,
+                "data": format!("{}{}", GET_TIMEOUT_SELECTOR, hex::encode(ticket_id.encode())),
+            }, "latest"])
+        })
+        .collect();
+    Ok(
+        batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params)
+            .await?
+            .into_iter()
+            .map(|timeout| {
+                timeout
+                    .ok()
+                    .and_then(|timeout| U256::decode(timeout).ok())
+                    .map(|timeout| timeout.low_u64())
+            })
+            .collect(),
+    )
+}
+
+/// Periodically reads the tickets created and the redeems scheduled on Arbitrum, alerting when
+/// the redeem of a retryable ticket sending a call, value or tokens to a watched account fails,
+/// or when no redeem was scheduled for it, since the ticket is discarded with its funds unless
+/// redeemed before its deadline. Failed tickets are followed to remind of their deadline a day
+/// before, and to notify them once redeemed or expired. The failed tickets and the last block
+/// checked are kept across restarts in the state DB
+pub async fn watch_retryables(chain: Chain, addressbook: Arc<Mutex<HashMap<String, String>>>) {
+    let (chain, provider) = connect_and_verify(chain).await;
+    if !chain
+        .id
+        .is_some_and(|id| ARBITRUM_CHAIN_IDS.contains(&id.as_u64()))
+    {
+        return;
+    }
+    info!("Watching retryable tickets on {}", chain.name);
+
+    let mut failed_tickets: HashMap<H256, FailedTicket> = load_failed_tickets(&chain);
+    let mut last_block: Option<U64> = last_checked_block(&chain);
+
+    loop {
+        sleep(*RETRYABLE_CHECK_INTERVAL).await;
+        if chain_control::is_paused(&chain.name) {
+            continue;
+        }
+
+        let latest_block = match provider.get_block_number().await {
+            Ok(latest_block) => latest_block,
+            Err(err) => {
+                log_rpc_error(&chain, "block number", &err);
+                continue;
+            }
+        };
+        let Some(from_block) = last_block.map(|last_block| {
+            (last_block + 1).max(latest_block.saturating_sub(MAX_BLOCK_RANGE.into()))
+        }) else {
+            last_block = Some(latest_block);
+            save_progress(&chain, latest_block);
+            continue;
+        };
+        if from_block > latest_block {
+            continue;
+        }
+
+        let filter = LogFilter::new()
+            .from_block(from_block)
+            .to_block(latest_block)
+            .address(*ARB_RETRYABLE_TX)
+            .topic0(vec![*REDEEM_SCHEDULED_TOPIC, *TICKET_CREATED_TOPIC]);
+        let logs = match provider.get_logs(&filter).await {
+            Ok(logs) => logs,
+            Err(err) => {
+                log_rpc_error(&chain, "retryable redeems", &err);
+                continue;
+            }
+        };
+        let redeems: Vec<(H256, H256)> = logs
+            .iter()
+            .filter(|log| log.topics.len() > 2 && log.topics[0] == *REDEEM_SCHEDULED_TOPIC)
+            .map(|log| (log.topics[1], log.topics[2]))
+            .collect();
+        // Tickets submitted without enough gas for their auto-redeem must be redeemed manually
+        let unscheduled: HashSet<H256> = logs
+            .iter()
+            .filter(|log| log.topics.len() > 1 && log.topics[0] == *TICKET_CREATED_TOPIC)
+            .map(|log| log.topics[1])
+            .filter(|ticket_id| {
+                !redeems
+                    .iter()
+                    .any(|(redeemed_ticket_id, _)| redeemed_ticket_id == ticket_id)
+            })
+            .collect();
+        let params: Vec<serde_json::Value> = redeems
+            .iter()
+            .map(|(_, retry_tx_hash)| json!([retry_tx_hash]))
+            .collect();
+        let receipts = match batch::batch_request::<_, Option<TransactionReceipt>>(
+            &provider,
+            "eth_getTransactionReceipt",
+            &params,
+        )
+        .await
+        {
+            Ok(receipts) => receipts,
+            Err(err) => {
+                log_rpc_error(&chain, "retryable redeem receipts", &err);
+                continue;
+            }
+        };
+
+        let addressbook = addressbook.lock().unwrap().clone();
+        let mut notifications = vec![];
+        let mut failed_redeems: HashSet<H256> = HashSet::new();
+        for ((ticket_id, _), receipt) in redeems.iter().zip(receipts) {
+            let succeeded = receipt.is_some_and(|receipt| receipt.status == Some(U64::one()));
+            if !succeeded {
+                failed_redeems.insert(*ticket_id);
+                continue;
+            }
+            // A redeem of a ticket failing earlier in the same range succeeded
+            failed_redeems.remove(ticket_id);
+            if let Some(ticket) = failed_tickets.remove(ticket_id) {
+                remove_failed_ticket(&chain, *ticket_id);
+                info!(
+                    "Retryable ticket {} of {} redeemed on {}",
+                    ticket_id.full_string(),
+                    ticket.account.full_string(),
+                    chain.name
+                );
+                notifications.push(notification(
+                    &chain,
+                    *ticket_id,
+                    ticket.account,
+                    format!(
+                        "Failed retryable ticket of {} was redeemed on {}",
+                        ticket.account.to_label(&addressbook),
+                        chain.name
+                    ),
+                    Severity::Normal,
+                ));
+            }
+        }
+        failed_redeems.extend(unscheduled.iter().copied());
+        failed_redeems.retain(|ticket_id| !failed_tickets.contains_key(ticket_id));
+
+        // Only the tickets failing to be redeemed are read, to find the watched ones
+        let new_ticket_ids: Vec<H256> = failed_redeems.into_iter().collect();
+        let params: Vec<serde_json::Value> = new_ticket_ids
+            .iter()
+            .map(|ticket_id| json!([ticket_id]))
+            .collect();
+        let tickets = match batch::batch_request::<_, Option<RetryableTicket>>(
+            &provider,
+            "eth_getTransactionByHash",
+            &params,
+        )
+        .await
+        {
+            Ok(tickets) => tickets,
+            Err(err) => {
+                log_rpc_error(&chain, "retryable tickets", &err);
+                continue;
+            }
+        };
+        let accounts: Vec<Address> = addressbook
+            .keys()
+            .filter_map(|address| Address::from_str(address).ok())
+            .collect();
+        let new_tickets: Vec<(H256, Address, RetryableTicket)> = new_ticket_ids
+            .into_iter()
+            .zip(tickets)
+            .filter_map(|(ticket_id, ticket)| {
+                let ticket = ticket?;
+                Some((ticket_id, ticket.involved_account(&accounts)?, ticket))
+            })
+            .collect();
+
+        let ticket_ids: Vec<H256> = new_tickets
+            .iter()
+            .map(|(ticket_id, _, _)| *ticket_id)
+            .chain(failed_tickets.keys().copied())
+            .collect();
+        let ticket_timeouts = match timeouts(&provider, &ticket_ids).await {
+            Ok(ticket_timeouts) => ticket_timeouts,
+            Err(err) => {
+                log_rpc_error(&chain, "retryable ticket timeouts", &err);
+                continue;
+            }
+        };
+        let ticket_timeouts: HashMap<H256, Option<u64>> =
+            ticket_ids.into_iter().zip(ticket_timeouts).collect();
+        last_block = Some(latest_block);
+        save_progress(&chain, latest_block);
+
+        for (ticket_id, account, ticket) in new_tickets {
+            // Redeemed or discarded in the meantime
+            let Some(timeout) = ticket_timeouts[&ticket_id] else {
+                continue;
+            };
+            let failure = match unscheduled.contains(&ticket_id) {
+                true => "wasn't redeemed automatically",
+                false => "failed",
+            };
+            warn!(
+                "Retryable ticket {} of {} {} on {}",
+                ticket_id.full_string(),
+                account.full_string(),
+                failure,
+                chain.name
+            );
+            notifications.push(notification(
+                &chain,
+                ticket_id,
+                account,
+                format!(
+                    "⚠ Retryable ticket of {} {} on {}: {}. Redeem it before {} or its funds are lost",
+                    account.to_label(&addressbook),
+                    failure,
+                    chain.name,
+                    ticket.describe(&chain, &addressbook),
+                    UtcDateTime::from_timestamp(timeout)
+                ),
+                Severity::High,
+            ));
+            let ticket = FailedTicket {
+                account,
+                timeout,
+                reminded: false,
+            };
+            store_failed_ticket(&chain, ticket_id, &ticket);
+            failed_tickets.insert(ticket_id, ticket);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut expired = vec![];
+        for (ticket_id, ticket) in failed_tickets.iter_mut() {
+            match ticket_timeouts.get(ticket_id).copied().flatten() {
+                Some(timeout) => {
+                    // The lifetime of tickets can be extended
+                    if timeout != ticket.timeout {
+                        ticket.timeout = timeout;
+                        ticket.reminded = false;
+                        store_failed_ticket(&chain, *ticket_id, ticket);
+                    }
+                    if ticket.reminded || timeout > now + EXPIRATION_REMINDER.as_secs() {
+                        continue;
+                    }
+                    ticket.reminded = true;
+                    store_failed_ticket(&chain, *ticket_id, ticket);
+                    notifications.push(notification(
+                        &chain,
+                        *ticket_id,
+                        ticket.account,
+                        format!(
+                            "⚠ Failed retryable ticket of {} on {} expires on {}, redeem it or its funds are lost",
+                            ticket.account.to_label(&addressbook),
+                            chain.name,
+                            UtcDateTime::from_timestamp(timeout)
+                        ),
+                        Severity::High,
+                    ));
+                }
+                None => expired.push(*ticket_id),
+            }
+        }
+        for ticket_id in expired {
+            let ticket = failed_tickets.remove(&ticket_id).unwrap();
+            remove_failed_ticket(&chain, ticket_id);
+            // Tickets gone before their timeout were redeemed in a skipped range, or canceled
+            // refunding their beneficiary
+            if now < ticket.timeout {
+                info!(
+                    "Retryable ticket {} of {} no longer pending on {}",
+                    ticket_id.full_string(),
+                    ticket.account.full_string(),
+                    chain.name
+                );
+                notifications.push(notification(
+                    &chain,
+                    ticket_id,
+                    ticket.account,
+                    format!(
+                        "Failed retryable ticket of {} on {} was redeemed or canceled",
+                        ticket.account.to_label(&addressbook),
+                        chain.name
+                    ),
+                    Severity::Normal,
+                ));
+                continue;
+            }
+            warn!(
+                "Retryable ticket {} of {} expired on {}",
+                ticket_id.full_string(),
+                ticket.account.full_string(),
+                chain.name
+            );
+            notifications.push(notification(
+                &chain,
+                ticket_id,
+                ticket.account,
+                format!(
+                    "⚠ Failed retryable ticket of {} on {} expired without being redeemed, its funds are lost",
+                    ticket.account.to_label(&addressbook),
+                    chain.name
+                ),
+                Severity::Critical,
+            ));
+        }
+        debug!(
+            "Checked {} retryable redeems on {}, {} failed tickets pending",
+            redeems.len(),
+            chain.name,
+            failed_tickets.len()
+        );
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending retryable ticket notification");
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use ethers::core::{types::Address, utils::to_checksum};
+use log::{debug, error};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use crate::FullString;
+use crate::{chain::Chain, http_client, interesting_transaction::InterestingTransaction};
+
+/// rotki decodes the pushed transactions before answering, which can take a while
+const PUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Name used by rotki for each supported EVM chain
+fn rotki_chain_name(chain: &Chain) -> Option<&'static str> {
+    match chain.id?.as_u64() {
+        1 => Some("ethereum"),
+        10 => Some("optimism"),
+        56 => Some("binance_sc"),
+        100 => Some("gnosis"),
+        137 => Some("polygon_pos"),
+        8453 => Some("base"),
+        42161 => Some("arbitrum_one"),
+        534352 => Some("scroll"),
+        _ => None,
+    }
+}
+
+/// Asks a running rotki instance to pull and decode the transactions, so they show up in its
+/// history without waiting for the next manual refresh. The request is sent in the background, so
+/// the notifications don't wait for rotki
+#[tracing::instrument(skip_all)]
+pub fn push_transactions(chain: &Chain, interesting_transactions: &[InterestingTransaction]) {
+    let Ok(rotki_url) = env::var("ROTKI_URL") else {
+        return;
+    };
+
+    if interesting_transactions.is_empty() {
+        return;
+    }
+
+    let Some(evm_chain) = rotki_chain_name(chain) else {
+        debug!(
+            "{} is not supported by rotki, not pushing transactions",
+            chain.name
+        );
+        return;
+    };
+
+    let tx_hashes: Vec<String> = interesting_transactions
+        .iter()
+        .map(|tx| tx.hash.full_string())
+        .collect();
+
+    let chain_name = chain.name.clone();
+    let request = http_client::client()
+        .put(format!(
+            "{}/api/1/blockchains/evm/transactions/decode",
+            rotki_url
+        ))
+        .timeout(PUSH_TIMEOUT)
+        .json(&json!({
+            "async_query": false,
+            "data": [{ "evm_chain": evm_chain, "tx_hashes": tx_hashes }],
+        }));
+    tokio::spawn(async move {
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        if let Err(err) = response {
+            error!(
+                "Error while pushing {} transactions to rotki: {}",
+                chain_name, err
+            );
+        }
+    });
+}
+
+pub fn is_enabled() -> bool {
+    env::var("ROTKI_URL").is_ok()
+}
+
+/// USD prices of the ERC20 tokens known by the running rotki instance
+pub async fn usd_prices(chain: &Chain, tokens: &[Address]) -> HashMap<Address, f64> {
+    let (Ok(rotki_url), Some(chain_id)) = (env::var("ROTKI_URL"), chain.id) else {
+        return HashMap::new();
+    };
+    if tokens.is_empty() {
+        return HashMap::new();
+    }
+
+    // rotki identifies EVM tokens by CAIP-19 asset id
+    let assets: HashMap<String, Address> = tokens
+        .iter()
+        .map(|token| {
+            (
+                format!("eip155:{}/erc20:{}", chain_id, to_checksum(token, None)),
+                *token,
+            )
+        })
+        .collect();
+
+    let response = async {
+        http_client::client()
+            .post(format!("{}/api/1/assets/prices/latest", rotki_url))
+            .json(&json!({
+                "assets": assets.keys().collect::<Vec<_>>(),
+                "target_asset": "USD",
+                "async_query": false,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await
+    }
+    .await;
+
+    match response {
+        Ok(response) => assets
+            .into_iter()
+            .filter_map(|(asset, token)| {
+                // Prices come along with the oracle they were read from
+                let price = &response["result"]["assets"][&asset];
+                let price = price.get(0).unwrap_or(price);
+                let price = match price {
+                    serde_json::Value::String(price) => price.parse::<f64>().ok()?,
+                    price => price.as_f64()?,
+                };
+                (price > 0.0).then_some((token, price))
+            })
+            .collect(),
+        Err(err) => {
+            error!(
+                "Error while getting {} prices from rotki: {}",
+                chain.name, err
+            );
+            HashMap::new()
+        }
+    }
+}
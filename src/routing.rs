@@ -0,0 +1,212 @@
+use ethers::core::{types::U256, utils::parse_units};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::Deserialize as DeserializeMacro;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{
+    chain::Chain,
+    escalation::Severity,
+    interesting_transaction::InterestingTransaction,
+    notification::{self, Notification},
+    rules::TransactionConditions,
+    tenants,
+    token::{FromChainAddress, Token},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, DeserializeMacro)]
+pub enum Sink {
+    Ntfy,
+    /// ntfy push also forwarded to `NTFY_EMAIL`
+    Email,
+    Apprise,
+    Webhook,
+    Redis,
+    Mqtt,
+}
+
+/// Where and how the notifications of a routing rule are sent
+#[derive(Debug, DeserializeMacro)]
+pub struct Route {
+    /// Sinks to send to, every configured sink except `Email` if not set
+    sinks: Option<Vec<Sink>>,
+    /// ntfy topic replacing `NTFY_TOPIC`
+    pub topic: Option<String>,
+    pub severity: Option<Severity>,
+    /// Collects the notifications into a periodic digest instead of sending them one by one
+    #[serde(default)]
+    pub digest: bool,
+}
+
+impl Route {
+    /// Whether the sink is explicitly part of the route
+    pub fn routes_to(&self, sink: Sink) -> bool {
+        self.sinks
+            .as_ref()
+            .is_some_and(|sinks| sinks.contains(&sink))
+    }
+
+    pub fn sends_to(&self, sink: Sink) -> bool {
+        match &self.sinks {
+            Some(sinks) => sinks.contains(&sink),
+            None => sink != Sink::Email,
+        }
+    }
+
+    /// Identifies where and how the route sends, the same message sent through another route
+    /// (e.g. to another tenant) isn't a repetition
+    pub fn dedup_key(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Route of the transactions matching every configured condition. Amounts are in native or token
+/// units, transactions without an amount never match rules with amount limits
+#[derive(Debug, DeserializeMacro)]
+struct RoutingRule {
+    #[serde(flatten)]
+    conditions: TransactionConditions,
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+    #[serde(flatten)]
+    route: Route,
+}
+
+impl RoutingRule {
+    fn matches(&self, tx: &InterestingTransaction, chain: &Chain) -> bool {
+        if !self.conditions.matches(tx, chain) {
+            return false;
+        }
+        if self.min_amount.is_none() && self.max_amount.is_none() {
+            return true;
+        }
+
+        let Some(amount) = tx.amount else {
+            return false;
+        };
+        let decimals = tx.token.map_or(chain.native_decimals, |token| {
+            Token::from_chain_address(chain, token).decimals
+        });
+
+        self.min_amount.as_deref().is_none_or(|min_amount| {
+            parse_units(min_amount, decimals)
+                .is_ok_and(|min_amount| amount >= U256::from(min_amount))
+        }) && self.max_amount.as_deref().is_none_or(|max_amount| {
+            parse_units(max_amount, decimals)
+                .is_ok_and(|max_amount| amount <= U256::from(max_amount))
+        })
+    }
+}
+
+struct Digest {
+    route: &'static Route,
+    messages: Vec<String>,
+}
+
+lazy_static! {
+    static ref ROUTING_RULES: Vec<RoutingRule> = match env::var("ROUTING_RULES_PATH") {
+        Ok(routing_rules_path) => {
+            let file = std::fs::File::open(routing_rules_path)
+                .expect("Could not open routing rules file.");
+            serde_yaml::from_reader(file).expect("Could not read routing rules.")
+        }
+        Err(_) => vec![],
+    };
+    pub static ref ROUTING_DIGEST_INTERVAL: Duration = Duration::from_secs(
+        env::var("ROUTING_DIGEST_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid ROUTING_DIGEST_INTERVAL"))
+            .unwrap_or(86400)
+    );
+    static ref DIGESTS: Mutex<Vec<Digest>> = Mutex::new(vec![]);
+}
+
+/// Loads the rules, failing on startup when their amounts are invalid or they send to `Email`
+/// without `NTFY_EMAIL`
+pub fn load() {
+    if ROUTING_RULES
+        .iter()
+        .any(|rule| rule.route.routes_to(Sink::Email))
+    {
+        notification::require_email("routing rules");
+    }
+    for rule in ROUTING_RULES.iter() {
+        for amount in [&rule.min_amount, &rule.max_amount].into_iter().flatten() {
+            if parse_units(amount, 18).is_err() {
+                panic!("Invalid amount {} in routing rules", amount);
+            }
+        }
+    }
+}
+
+pub fn has_digests() -> bool {
+    ROUTING_RULES.iter().any(|rule| rule.route.digest) || tenants::has_digests()
+}
+
+pub trait Routable {
+    fn route(&self, chain: &Chain) -> Option<&'static Route>;
+}
+
+impl Routable for InterestingTransaction {
+    /// Route of the tenant of the involved account, or of the first matching rule. Tenants only
+    /// receive their own notifications, so the global rules never apply to their accounts
+    fn route(&self, chain: &Chain) -> Option<&'static Route> {
+        tenants::route_of(&self.involved_account).or_else(|| {
+            ROUTING_RULES
+                .iter()
+                .find(|rule| rule.matches(self, chain))
+                .map(|rule| &rule.route)
+        })
+    }
+}
+
+/// Adds the notification to the digest of its route
+pub fn add_to_digest(route: &'static Route, notification: &Notification) {
+    let message = match &notification.url {
+        Some(url) => format!("{} {}", notification.message, url),
+        None => notification.message.clone(),
+    };
+
+    let mut digests = DIGESTS.lock().unwrap();
+    match digests
+        .iter_mut()
+        .find(|digest| std::ptr::eq(digest.route, route))
+    {
+        Some(digest) => digest.messages.push(message),
+        None => digests.push(Digest {
+            route,
+            messages: vec![message],
+        }),
+    }
+}
+
+/// Sends the notifications collected by each digest route as a single notification
+pub async fn send_digests(interval: Duration) {
+    loop {
+        sleep(interval).await;
+
+        let digests: Vec<Digest> = std::mem::take(&mut *DIGESTS.lock().unwrap());
+        for digest in digests {
+            info!("Sending digest of {} notifications", digest.messages.len());
+            let notification = Notification {
+                message: format!(
+                    "Digest of {} notifications:\n{}",
+                    digest.messages.len(),
+                    digest.messages.join("\n")
+                ),
+                url: None,
+                severity: digest.route.severity,
+                route: Some(digest.route),
+                image: None,
+                links: vec![],
+            };
+            if notification.deliver(None).await.is_err() {
+                error!("Error while sending notifications digest");
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use ethers::providers::{HttpClientError, ProviderError};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+const IMMEDIATE_RETRY_COUNT: u32 = 4;
+const UNREACHABLE_RETRY_DELAY: Duration = Duration::from_secs(15);
+const MISCONFIGURED_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const METHOD_NOT_FOUND_CODE: i64 = -32601;
+const INVALID_PARAMS_CODE: i64 = -32602;
+const LIMIT_EXCEEDED_CODE: i64 = -32005;
+
+/// What went wrong while talking to the RPC, which decides how the request is retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpcErrorKind {
+    Dns,
+    Connection,
+    Tls,
+    Timeout,
+    RateLimited,
+    HttpClient(u16),
+    HttpServer(u16),
+    JsonRpc(i64),
+    InvalidResponse,
+    Other,
+}
+
+impl fmt::Display for RpcErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcErrorKind::Dns => write!(f, "DNS lookup failed"),
+            RpcErrorKind::Connection => write!(f, "connection failed"),
+            RpcErrorKind::Tls => write!(f, "TLS handshake failed"),
+            RpcErrorKind::Timeout => write!(f, "request timed out"),
+            RpcErrorKind::RateLimited => write!(f, "rate limited"),
+            RpcErrorKind::HttpClient(status) => write!(f, "HTTP {} client error", status),
+            RpcErrorKind::HttpServer(status) => write!(f, "HTTP {} server error", status),
+            RpcErrorKind::JsonRpc(code) => write!(f, "JSON-RPC error {}", code),
+            RpcErrorKind::InvalidResponse => write!(f, "invalid response"),
+            RpcErrorKind::Other => write!(f, "unexpected error"),
+        }
+    }
+}
+
+impl RpcErrorKind {
+    pub fn classify(err: &ProviderError) -> RpcErrorKind {
+        match err {
+            ProviderError::HTTPError(err) => classify_reqwest_error(err),
+            ProviderError::JsonRpcClientError(rpc_error) => {
+                let rpc_error: &(dyn Error + 'static) = rpc_error.as_ref();
+                match rpc_error.downcast_ref::<HttpClientError>() {
+                    Some(HttpClientError::ReqwestError(err)) => classify_reqwest_error(err),
+                    Some(HttpClientError::JsonRpcError(err)) => {
+                        classify_json_rpc_error(err.code, &err.message)
+                    }
+                    Some(HttpClientError::SerdeJson { text, .. }) => classify_response_text(text),
+                    None => RpcErrorKind::Other,
+                }
+            }
+            ProviderError::SerdeJson(_) => RpcErrorKind::InvalidResponse,
+            _ => RpcErrorKind::Other,
+        }
+    }
+
+    /// Hint for the operator when the error is unlikely to go away without their intervention
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            RpcErrorKind::Dns => Some("check the RPC hostname"),
+            RpcErrorKind::Tls => Some("check the RPC certificate"),
+            RpcErrorKind::HttpClient(401 | 403) => Some("check the RPC API key"),
+            RpcErrorKind::HttpClient(_) => Some("check the RPC URL"),
+            RpcErrorKind::JsonRpc(METHOD_NOT_FOUND_CODE) => {
+                Some("the RPC does not support the method, check the chain mode")
+            }
+            _ => None,
+        }
+    }
+
+    /// Retries attempted right away, and the delay before the first of the following ones.
+    /// Transient errors are retried immediately a few times, while unreachable or misconfigured
+    /// RPCs are given time to recover before hammering them again
+    pub fn retry_policy(&self, blocktime: Duration) -> (u32, Duration) {
+        match self {
+            RpcErrorKind::Dns | RpcErrorKind::Connection => {
+                (0, blocktime.max(UNREACHABLE_RETRY_DELAY))
+            }
+            RpcErrorKind::Tls
+            | RpcErrorKind::HttpClient(_)
+            | RpcErrorKind::JsonRpc(METHOD_NOT_FOUND_CODE | INVALID_PARAMS_CODE) => {
+                (0, blocktime.max(MISCONFIGURED_RETRY_DELAY))
+            }
+            // The transport already holds the requests back for as long as the RPC asked to
+            RpcErrorKind::RateLimited
+            | RpcErrorKind::Timeout
+            | RpcErrorKind::HttpServer(_)
+            | RpcErrorKind::JsonRpc(_)
+            | RpcErrorKind::InvalidResponse
+            | RpcErrorKind::Other => (IMMEDIATE_RETRY_COUNT, blocktime),
+        }
+    }
+}
+
+fn classify_reqwest_error(err: &reqwest::Error) -> RpcErrorKind {
+    if let Some(status) = err.status() {
+        return match status.as_u16() {
+            429 => RpcErrorKind::RateLimited,
+            status if status >= 500 => RpcErrorKind::HttpServer(status),
+            status => RpcErrorKind::HttpClient(status),
+        };
+    }
+    if err.is_timeout() {
+        return RpcErrorKind::Timeout;
+    }
+    if err.is_decode() {
+        return RpcErrorKind::InvalidResponse;
+    }
+
+    // reqwest doesn't expose the cause of connection errors, only their messages
+    let mut causes = String::new();
+    let mut source: Option<&(dyn Error + 'static)> = Some(err);
+    while let Some(cause) = source {
+        causes.push_str(&cause.to_string().to_lowercase());
+        causes.push('\n');
+        source = cause.source();
+    }
+
+    if causes.contains("dns error") || causes.contains("failed to lookup address") {
+        RpcErrorKind::Dns
+    } else if ["certificate", "tls", "ssl", "handshake"]
+        .iter()
+        .any(|hint| causes.contains(hint))
+    {
+        RpcErrorKind::Tls
+    } else if err.is_connect() || err.is_request() {
+        RpcErrorKind::Connection
+    } else {
+        RpcErrorKind::Other
+    }
+}
+
+pub fn classify_json_rpc_error(code: i64, message: &str) -> RpcErrorKind {
+    let message = message.to_lowercase();
+    // -32005 is also used for too many results, see is_log_limit_error
+    if code == 429
+        || (code == LIMIT_EXCEEDED_CODE
+            && (message.contains("rate") || message.contains("requests")))
+    {
+        RpcErrorKind::RateLimited
+    } else {
+        RpcErrorKind::JsonRpc(code)
+    }
+}
+
+/// Whether the RPC doesn't support the method at all, rather than failing this time. Not every
+/// node uses the dedicated code for it
+pub fn is_method_not_found(err: &ProviderError) -> bool {
+    let ProviderError::JsonRpcClientError(rpc_error) = err else {
+        return false;
+    };
+
+    rpc_error.as_error_response().is_some_and(|response| {
+        let message = response.message.to_lowercase();
+        response.code == METHOD_NOT_FOUND_CODE
+            || (message.contains("method")
+                && [
+                    "not found",
+                    "not supported",
+                    "does not exist",
+                    "not available",
+                ]
+                .iter()
+                .any(|hint| message.contains(hint)))
+    })
+}
+
+/// The provider doesn't check the HTTP status, so error pages from the RPC or a proxy in front of
+/// it end up as responses which can't be parsed
+fn classify_response_text(text: &str) -> RpcErrorKind {
+    let text = text.to_lowercase();
+    if text.contains("too many requests") || text.contains("rate limit") {
+        RpcErrorKind::RateLimited
+    } else {
+        RpcErrorKind::InvalidResponse
+    }
+}
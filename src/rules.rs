@@ -0,0 +1,56 @@
+use ethers::core::types::Address;
+use serde_derive::Deserialize as DeserializeMacro;
+
+use crate::{
+    chain::Chain,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+};
+
+#[derive(Debug, PartialEq, DeserializeMacro)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// Conditions shared by the rules configured for transactions, a rule applies to the transactions
+/// matching every configured condition
+#[derive(Debug, DeserializeMacro)]
+pub struct TransactionConditions {
+    chain: Option<String>,
+    kind: Option<InterestingTransactionKind>,
+    direction: Option<Direction>,
+    account: Option<Address>,
+    counterparty: Option<Address>,
+    token: Option<Address>,
+}
+
+impl TransactionConditions {
+    pub fn matches(&self, tx: &InterestingTransaction, chain: &Chain) -> bool {
+        let direction = if tx.to == Some(tx.involved_account) {
+            Some(Direction::Incoming)
+        } else if tx.from == Some(tx.involved_account) {
+            Some(Direction::Outgoing)
+        } else {
+            None
+        };
+        let counterparty = match direction {
+            Some(Direction::Incoming) => tx.from,
+            Some(Direction::Outgoing) => tx.to,
+            None => None,
+        };
+
+        self.chain.as_ref().is_none_or(|name| *name == chain.name)
+            && self.kind.as_ref().is_none_or(|kind| *kind == tx.kind)
+            && self
+                .direction
+                .as_ref()
+                .is_none_or(|rule_direction| Some(rule_direction) == direction.as_ref())
+            && self
+                .account
+                .is_none_or(|account| account == tx.involved_account)
+            && self
+                .counterparty
+                .is_none_or(|address| Some(address) == counterparty)
+            && self.token.is_none_or(|token| Some(token) == tx.token)
+    }
+}
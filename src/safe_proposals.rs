@@ -0,0 +1,350 @@
+use ethers::core::{
+    types::{Address, Bytes, H256, U256},
+    utils::to_checksum,
+};
+use futures::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::{
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    four_byte, http_client,
+    notification::{Notification, Sendable},
+    tenants,
+};
+use crate::{scale_amount, FullString, ToLabel};
+
+/// Pending proposals read per Safe, the most recent nonces first
+const PROPOSALS_LIMIT: &str = "20";
+/// Safes whose proposals are fetched at the same time
+const CONCURRENT_REQUESTS: usize = 4;
+
+lazy_static! {
+    /// Seconds between checks of the pending proposals of the watched Safes
+    pub static ref SAFE_PROPOSALS_CHECK_INTERVAL: Duration = Duration::from_secs(
+        env::var("SAFE_PROPOSALS_CHECK_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid SAFE_PROPOSALS_CHECK_INTERVAL"))
+            .unwrap_or(60)
+    );
+    static ref SAFE_API_KEY: Option<String> = env::var("SAFE_API_KEY").ok();
+    /// Requests per second to the Safe Transaction Services, shared by every chain as they are
+    /// usually behind the same gateway and API key
+    static ref SAFE_API_MAX_RPS: u32 = env::var("SAFE_API_MAX_RPS")
+        .map(|max_rps| max_rps
+            .parse::<u32>()
+            .ok()
+            .filter(|max_rps| *max_rps > 0)
+            .expect("Invalid SAFE_API_MAX_RPS"))
+        .unwrap_or(5);
+    static ref NEXT_REQUEST: tokio::sync::Mutex<Instant> =
+        tokio::sync::Mutex::new(Instant::now());
+}
+
+pub fn is_enabled(chain: &Chain) -> bool {
+    chain.safe_transaction_service.is_some()
+}
+
+#[derive(DeserializeMacro)]
+struct MultisigTransactions {
+    results: Vec<MultisigTransaction>,
+}
+
+/// Transaction proposed to a Safe, as returned by the Safe Transaction Service
+#[derive(DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct MultisigTransaction {
+    safe_tx_hash: H256,
+    to: Address,
+    value: String,
+    data: Option<Bytes>,
+    data_decoded: Option<DataDecoded>,
+    nonce: u64,
+    proposer: Option<Address>,
+    confirmations_required: usize,
+    confirmations: Option<Vec<Confirmation>>,
+}
+
+#[derive(DeserializeMacro)]
+struct DataDecoded {
+    method: String,
+    parameters: Option<Vec<Parameter>>,
+}
+
+#[derive(DeserializeMacro)]
+struct Parameter {
+    value: serde_json::Value,
+}
+
+#[derive(DeserializeMacro)]
+struct Confirmation {
+    owner: Address,
+}
+
+impl MultisigTransaction {
+    fn signers(&self) -> Vec<Address> {
+        self.confirmations
+            .iter()
+            .flatten()
+            .map(|confirmation| confirmation.owner)
+            .collect()
+    }
+
+    /// Call of the proposal, decoded by the service or else looked up in the bundled signatures
+    fn call(&self, chain: &Chain, addressbook: &HashMap<String, String>) -> String {
+        let to = self.to.to_label(addressbook);
+        if let Some(data_decoded) = &self.data_decoded {
+            let parameters: Vec<String> = data_decoded
+                .parameters
+                .iter()
+                .flatten()
+                .map(|parameter| match &parameter.value {
+                    serde_json::Value::String(value) => Address::from_str(value)
+                        .map_or(value.clone(), |address| address.to_label(addressbook)),
+                    value => value.to_string(),
+                })
+                .collect();
+            return format!(
+                "{}({}) on {}",
+                data_decoded.method,
+                parameters.join(", "),
+                to
+            );
+        }
+
+        match self.data.as_ref().filter(|data| !data.is_empty()) {
+            Some(data) => match four_byte::lookup(data) {
+                Some(function) => format!("{} on {}", function, to),
+                None => format!("call to {}", to),
+            },
+            None => format!(
+                "send {} {} to {}",
+                scale_amount(
+                    U256::from_dec_str(&self.value).unwrap_or_default(),
+                    chain.native_decimals
+                ),
+                chain.native_symbol,
+                to
+            ),
+        }
+    }
+}
+
+/// Pending proposals of the account, `None` when it is not a Safe
+async fn fetch_proposals(
+    safe_transaction_service: &str,
+    account: Address,
+) -> eyre::Result<Option<Vec<MultisigTransaction>>> {
+    let mut request = http_client::client()
+        .get(format!(
+            "{}/api/v1/safes/{}/multisig-transactions/",
+            safe_transaction_service.trim_end_matches('/'),
+            to_checksum(&account, None)
+        ))
+        .query(&[
+            ("executed", "false"),
+            ("ordering", "-nonce"),
+            ("limit", PROPOSALS_LIMIT),
+        ]);
+    if let Some(api_key) = SAFE_API_KEY.as_ref() {
+        request = request.bearer_auth(api_key);
+    }
+
+    // Slots are handed out in order, waiting for them without holding the other requests back
+    let mut next_request = NEXT_REQUEST.lock().await;
+    let slot = (*next_request).max(Instant::now());
+    *next_request = slot + Duration::from_secs(1) / *SAFE_API_MAX_RPS;
+    drop(next_request);
+    sleep_until(slot).await;
+
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let transactions: MultisigTransactions = response.error_for_status()?.json().await?;
+    Ok(Some(transactions.results))
+}
+
+fn proposal_notification(
+    chain: &Chain,
+    safe: Address,
+    proposal: &MultisigTransaction,
+    new_signers: &[Address],
+    addressbook: &HashMap<String, String>,
+) -> Notification {
+    let signatures = format!(
+        "{}/{} signatures",
+        proposal.signers().len(),
+        proposal.confirmations_required
+    );
+    let (message, severity) = match new_signers {
+        [] => (
+            format!(
+                "📝 New proposal #{} on {} by {} on {}: {} ({})",
+                proposal.nonce,
+                safe.to_label(addressbook),
+                proposal
+                    .proposer
+                    .map_or("an unknown proposer".to_string(), |proposer| proposer
+                        .to_label(addressbook)),
+                chain.name,
+                proposal.call(chain, addressbook),
+                signatures
+            ),
+            Severity::High,
+        ),
+        new_signers => (
+            format!(
+                "✍ {} signed proposal #{} on {} on {}: {} ({})",
+                new_signers
+                    .iter()
+                    .map(|signer| signer.to_label(addressbook))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                proposal.nonce,
+                safe.to_label(addressbook),
+                chain.name,
+                proposal.call(chain, addressbook),
+                signatures
+            ),
+            Severity::Normal,
+        ),
+    };
+
+    Notification {
+        message,
+        url: chain.address_url(safe),
+        severity: Some(severity),
+        route: tenants::route_of(&safe),
+        image: None,
+        links: vec![],
+    }
+}
+
+/// Periodically reads the pending proposals of the watched Safes from the chain's Safe
+/// Transaction Service, notifying new proposals and signatures before they are executed. The
+/// proposals of a Safe when it is first seen are only logged
+pub async fn watch_safe_proposals(chain: Chain, addressbook: Arc<Mutex<HashMap<String, String>>>) {
+    let safe_transaction_service = chain.safe_transaction_service.clone().unwrap();
+    // Signers of the pending proposals of each watched Safe
+    let mut safes: HashMap<Address, HashMap<H256, HashSet<Address>>> = HashMap::new();
+    let mut not_safes: HashSet<Address> = HashSet::new();
+
+    loop {
+        sleep(*SAFE_PROPOSALS_CHECK_INTERVAL).await;
+        if chain_control::is_paused(&chain.name) {
+            continue;
+        }
+
+        let accounts: Vec<Address> = addressbook
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|address| Address::from_str(address).ok())
+            .filter(|address| !not_safes.contains(address))
+            .collect();
+
+        let fetched: Vec<_> = stream::iter(accounts)
+            .map(|account| {
+                let safe_transaction_service = &safe_transaction_service;
+                async move {
+                    (
+                        account,
+                        fetch_proposals(safe_transaction_service, account).await,
+                    )
+                }
+            })
+            .buffered(CONCURRENT_REQUESTS)
+            .collect()
+            .await;
+
+        let mut notifications = vec![];
+        for (account, proposals) in fetched {
+            let proposals = match proposals {
+                Ok(Some(proposals)) => proposals,
+                Ok(None) => {
+                    not_safes.insert(account);
+                    continue;
+                }
+                Err(err) => {
+                    error!(
+                        "Error while getting {} Safe proposals of {}: {}",
+                        chain.name,
+                        account.full_string(),
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            let pending: HashMap<H256, HashSet<Address>> = proposals
+                .iter()
+                .map(|proposal| {
+                    (
+                        proposal.safe_tx_hash,
+                        proposal.signers().into_iter().collect(),
+                    )
+                })
+                .collect();
+            let Some(known) = safes.insert(account, pending) else {
+                info!(
+                    "{} is a Safe with {} pending proposals on {}",
+                    account.full_string(),
+                    proposals.len(),
+                    chain.name
+                );
+                continue;
+            };
+
+            for proposal in proposals {
+                let new_signers: Vec<Address> = match known.get(&proposal.safe_tx_hash) {
+                    None => vec![],
+                    Some(signers) => proposal
+                        .signers()
+                        .into_iter()
+                        .filter(|signer| !signers.contains(signer))
+                        .collect(),
+                };
+                if known.contains_key(&proposal.safe_tx_hash) && new_signers.is_empty() {
+                    continue;
+                }
+
+                info!(
+                    "Safe proposal {} of {} changed on {}",
+                    proposal.safe_tx_hash.full_string(),
+                    account.full_string(),
+                    chain.name
+                );
+                notifications.push(proposal_notification(
+                    &chain,
+                    account,
+                    &proposal,
+                    &new_signers,
+                    &addressbook.lock().unwrap(),
+                ));
+            }
+        }
+        debug!(
+            "Checked the proposals of {} Safes on {}",
+            safes.len(),
+            chain.name
+        );
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending Safe proposal notification");
+            }
+        }
+    }
+}
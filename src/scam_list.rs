@@ -0,0 +1,91 @@
+use ethers::core::types::Address;
+use eyre::Result;
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::collections::HashSet;
+use std::env;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{http_client, interesting_transaction::InterestingTransaction};
+
+lazy_static! {
+    static ref SCAM_ADDRESSES_URL: Option<String> = env::var("SCAM_ADDRESSES_URL").ok();
+    static ref SCAM_ADDRESSES_PATH: Option<String> = env::var("SCAM_ADDRESSES_PATH").ok();
+    pub static ref SCAM_ADDRESSES_REFRESH_INTERVAL: Duration = Duration::from_secs(
+        env::var("SCAM_ADDRESSES_REFRESH_INTERVAL")
+            .map(|interval| interval
+                .parse::<u64>()
+                .expect("Invalid SCAM_ADDRESSES_REFRESH_INTERVAL"))
+            .unwrap_or(3600)
+    );
+    static ref FLAGGED_ADDRESSES: RwLock<HashSet<Address>> = RwLock::new(HashSet::new());
+}
+
+pub fn is_enabled() -> bool {
+    SCAM_ADDRESSES_URL.is_some() || SCAM_ADDRESSES_PATH.is_some()
+}
+
+/// Addresses of a JSON array (as exported by ScamSniffer), or of a list with one address per
+/// line and `#` comments
+fn parse_addresses(list: &str) -> HashSet<Address> {
+    let entries: Vec<String> = serde_json::from_str(list).unwrap_or_else(|_| {
+        list.lines()
+            .map(|line| line.split('#').next().unwrap().trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    });
+
+    entries
+        .iter()
+        .filter_map(|entry| Address::from_str(entry.trim()).ok())
+        .collect()
+}
+
+async fn load_flagged_addresses() -> Result<HashSet<Address>> {
+    let mut flagged_addresses = HashSet::new();
+
+    if let Some(url) = SCAM_ADDRESSES_URL.as_ref() {
+        let list = http_client::client()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        flagged_addresses.extend(parse_addresses(&list));
+    }
+    if let Some(path) = SCAM_ADDRESSES_PATH.as_ref() {
+        flagged_addresses.extend(parse_addresses(&std::fs::read_to_string(path)?));
+    }
+
+    Ok(flagged_addresses)
+}
+
+/// Reloads the flagged addresses from the feed and the local list, keeping the previous ones
+/// when they can't be read
+pub async fn refresh_flagged_addresses(interval: Duration) {
+    loop {
+        match load_flagged_addresses().await {
+            Ok(flagged_addresses) => {
+                info!("Loaded {} flagged scam addresses", flagged_addresses.len());
+                *FLAGGED_ADDRESSES.write().unwrap() = flagged_addresses;
+            }
+            Err(err) => error!("Error while loading flagged scam addresses: {}", err),
+        }
+
+        sleep(interval).await;
+    }
+}
+
+/// Flagged address the watched account interacted with in the transaction, if any
+pub fn flagged_counterparty(tx: &InterestingTransaction) -> Option<Address> {
+    let flagged_addresses = FLAGGED_ADDRESSES.read().unwrap();
+    [tx.from, tx.to, tx.token]
+        .into_iter()
+        .flatten()
+        .filter(|address| *address != tx.involved_account)
+        .find(|address| flagged_addresses.contains(address))
+}
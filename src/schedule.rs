@@ -0,0 +1,96 @@
+use lazy_static::lazy_static;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::UtcDateTime;
+
+/// Upcoming on-chain deadline or unlock, exported in the calendar feed
+#[derive(Clone, Debug, DeserializeMacro)]
+pub struct ScheduledEvent {
+    pub uid: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+lazy_static! {
+    static ref SCHEDULED_EVENTS: Mutex<Vec<ScheduledEvent>> = Mutex::new(vec![]);
+}
+
+/// Adds an event, replacing any previous event with the same uid
+pub fn schedule_event(event: ScheduledEvent) {
+    let mut scheduled_events = SCHEDULED_EVENTS.lock().unwrap();
+    scheduled_events.retain(|scheduled_event| scheduled_event.uid != event.uid);
+    scheduled_events.push(event);
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Formats a unix timestamp as an iCalendar UTC date-time (`YYYYMMDDTHHMMSSZ`)
+fn format_ical_timestamp(timestamp: u64) -> String {
+    let date_time = UtcDateTime::from_timestamp(timestamp);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        date_time.year,
+        date_time.month,
+        date_time.day,
+        date_time.hour,
+        date_time.minute,
+        date_time.second
+    )
+}
+
+fn escape_ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Renders the upcoming events as an iCalendar feed
+pub fn calendar() -> String {
+    let now = now();
+    let mut upcoming_events: Vec<ScheduledEvent> = SCHEDULED_EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|event| event.at >= now)
+        .cloned()
+        .collect();
+    upcoming_events.sort_by_key(|event| event.at);
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Account Monitor//EN".to_string(),
+    ];
+
+    for event in upcoming_events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:{}@account-monitor",
+            escape_ical_text(&event.uid)
+        ));
+        lines.push(format!("DTSTAMP:{}", format_ical_timestamp(now)));
+        lines.push(format!("DTSTART:{}", format_ical_timestamp(event.at)));
+        lines.push(format!("SUMMARY:{}", escape_ical_text(&event.title)));
+        if let Some(description) = event.description {
+            lines.push(format!("DESCRIPTION:{}", escape_ical_text(&description)));
+        }
+        if let Some(url) = event.url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.join("\r\n") + "\r\n"
+}
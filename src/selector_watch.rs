@@ -0,0 +1,129 @@
+use ethers::{
+    core::types::{Address, H256},
+    providers::Provider,
+    utils::id,
+};
+use lazy_static::lazy_static;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+use crate::{
+    chain::Chain,
+    interesting_transaction::{InterestingTransaction, SpamFilter},
+    metrics_middleware::MetricsMiddleware,
+    throttled_http::ThrottledHttp,
+    transaction_details,
+};
+use crate::{FullString, ToLabel};
+
+lazy_static! {
+    /// Functions alerted when called by a watched account, by selector. Given in
+    /// `WATCHED_SELECTORS_PATH` as signatures, e.g. `upgradeTo(address)`, or 4-byte selectors
+    static ref WATCHED_SELECTORS: HashMap<[u8; 4], String> =
+        match env::var("WATCHED_SELECTORS_PATH") {
+            Ok(watched_selectors_path) => {
+                let file = std::fs::File::open(watched_selectors_path)
+                    .expect("Could not open watched selectors file.");
+                let functions: Vec<String> =
+                    serde_yaml::from_reader(file).expect("Could not read watched selectors.");
+                functions
+                    .into_iter()
+                    .map(|function| (selector(&function), function))
+                    .collect()
+            }
+            Err(_) => HashMap::new(),
+        };
+}
+
+fn selector(function: &str) -> [u8; 4] {
+    if function.contains('(') {
+        return id(function);
+    }
+
+    let bytes = ethers::core::utils::hex::decode(function.trim_start_matches("0x"))
+        .unwrap_or_else(|_| panic!("Invalid watched selector {}", function));
+    bytes
+        .try_into()
+        .unwrap_or_else(|_| panic!("Invalid watched selector {}", function))
+}
+
+pub fn is_enabled() -> bool {
+    !WATCHED_SELECTORS.is_empty()
+}
+
+/// Loads the watched selectors, failing on startup when they are invalid
+pub fn load() {
+    if is_enabled() {
+        info!("Watching {} function selectors", WATCHED_SELECTORS.len());
+    }
+}
+
+/// Call of a watched function by a watched account
+pub struct SelectorCall {
+    account: Address,
+    function: String,
+    contract: Option<Address>,
+}
+
+impl SelectorCall {
+    /// Alert replacing the message of the transaction
+    pub fn message(&self, chain: &Chain, addressbook: &HashMap<String, String>) -> String {
+        format!(
+            "⚠ {} called {} on {} on {}",
+            self.account.to_label(addressbook),
+            self.function,
+            self.contract
+                .map_or("a new contract".to_string(), |contract| contract
+                    .to_label(addressbook)),
+            chain.name
+        )
+    }
+}
+
+/// Transactions of watched accounts calling a watched function, whatever the logs they emitted
+#[tracing::instrument(skip_all)]
+pub async fn detect(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: &[InterestingTransaction],
+) -> HashMap<H256, SelectorCall> {
+    if !is_enabled() {
+        return HashMap::new();
+    }
+
+    let hashes: HashSet<H256> = interesting_transactions
+        .iter()
+        .filter(|tx| !tx.is_spam(&chain.spam_filter_level))
+        .map(|tx| tx.hash)
+        .collect();
+    let transactions = transaction_details::fetch_transactions(provider, chain, hashes).await;
+
+    let watched_accounts: HashSet<(H256, Address)> = interesting_transactions
+        .iter()
+        .map(|tx| (tx.hash, tx.involved_account))
+        .collect();
+    transactions
+        .into_values()
+        .filter(|transaction| watched_accounts.contains(&(transaction.hash, transaction.from)))
+        .filter_map(|transaction| {
+            let selector: [u8; 4] = transaction.input.get(..4)?.try_into().ok()?;
+            let function = WATCHED_SELECTORS.get(&selector)?;
+            info!(
+                "{} called {} in {} on {}",
+                transaction.from.full_string(),
+                function,
+                transaction.hash.full_string(),
+                chain.name
+            );
+            Some((
+                transaction.hash,
+                SelectorCall {
+                    account: transaction.from,
+                    function: function.clone(),
+                    contract: transaction.to,
+                },
+            ))
+        })
+        .collect()
+}
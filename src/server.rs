@@ -0,0 +1,820 @@
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::{
+    abi_registry, allowance_audit, asset_db, bridges,
+    chain::{self, Chain, ChainMode, EnvInitializable},
+    chain_control, chain_worker, decoding, effective_config, escalation, expectation, explain,
+    fixtures, fleet, graphql, history,
+    interesting_transaction::{
+        BuildNotification, InterestingTransaction, InterestingTransactionKind,
+    },
+    label_history, live_stream, logging, metrics,
+    metrics::REGISTRY,
+    metrics_push,
+    monitor::{AddChainError, Monitor, WatchedAccount},
+    mqtt, nats,
+    notification::{self, Notification, Sendable},
+    openapi, persistent_metrics, price_alert, rotki, routing, scam_list, schedule,
+    schedule::ScheduledEvent,
+    selector_watch, spam_report, state_db, template, tenants, token, webhook,
+    webhook::WebhookPayload,
+    withdrawals, FullString, COMMIT, VERSION,
+};
+use ethers::core::types::{Address, H256, U256};
+use eyre::{Result, WrapErr};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde_yaml::{self};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+};
+use tracing::Instrument;
+use warp::Filter;
+
+const WEBHOOK_QUEUE_SIZE: usize = 100;
+
+lazy_static! {
+    /// Rejects every API request changing the state, configuration only comes from files
+    static ref READ_ONLY: bool = env::var("READ_ONLY")
+        .unwrap_or("false".into())
+        .to_lowercase()
+        == "true";
+}
+
+/// Runs the monitor as configured by the environment variables: loads the state, serves the API,
+/// spawns the chain workers and background tasks, and waits for a shutdown signal
+pub async fn run() -> Result<()> {
+    dotenv::dotenv().ok();
+    logging::init();
+
+    notification::load();
+    metrics::register();
+    persistent_metrics::restore();
+    template::load();
+    decoding::load();
+    selector_watch::load();
+    asset_db::load();
+    token::load();
+    routing::load();
+    escalation::load();
+    tenants::load();
+    bridges::load();
+    withdrawals::load();
+    label_history::load();
+    spam_report::load();
+    #[cfg(not(feature = "grpc"))]
+    if env::var("GRPC_PORT").is_ok() {
+        panic!("GRPC_PORT is set, but the grpc feature was not built");
+    }
+
+    if state_db::is_enabled() {
+        tokio::spawn(persistent_metrics::save_periodically());
+    }
+
+    if *READ_ONLY {
+        info!("Read-only mode, the API rejects all mutations");
+    }
+
+    if history::is_enabled() {
+        info!("Storing interesting transactions history");
+    }
+
+    if allowance_audit::ALLOWANCE_REPORT_INTERVAL.is_some()
+        || allowance_audit::ALLOWANCE_EXPOSURE_THRESHOLD.is_some()
+    {
+        if !history::is_enabled() {
+            warn!("Allowances are only audited with HISTORY_DB_PATH set");
+        } else if allowance_audit::ALLOWANCE_EXPOSURE_THRESHOLD.is_some() && !rotki::is_enabled() {
+            warn!("Allowance exposures are only priced with ROTKI_URL set");
+        }
+    }
+
+    if let Some(dedup_window) = *notification::DEDUP_WINDOW {
+        tokio::spawn(notification::flush_repeated_notifications(dedup_window));
+    }
+
+    if escalation::is_enabled() {
+        tokio::spawn(escalation::escalate_unacknowledged());
+    }
+
+    if routing::has_digests() {
+        tokio::spawn(routing::send_digests(*routing::ROUTING_DIGEST_INTERVAL));
+    }
+
+    if bridges::is_enabled() {
+        tokio::spawn(bridges::send_held_departures());
+    }
+
+    if scam_list::is_enabled() {
+        tokio::spawn(scam_list::refresh_flagged_addresses(
+            *scam_list::SCAM_ADDRESSES_REFRESH_INTERVAL,
+        ));
+    }
+
+    tokio::spawn(expectation::alert_overdue_expectations());
+
+    if let Ok(abi_dir) = env::var("ABI_DIR") {
+        tokio::spawn(abi_registry::watch_abi_dir(abi_dir.into()));
+    }
+    abi_registry::load_fetched();
+
+    if mqtt::is_enabled() {
+        mqtt::connect();
+    }
+
+    if nats::is_enabled() {
+        nats::connect().await;
+    }
+
+    if metrics_push::is_enabled() {
+        tokio::spawn(metrics_push::push_periodically());
+    }
+
+    if let Some(spam_report_interval) = *spam_report::SPAM_REPORT_INTERVAL {
+        tokio::spawn(spam_report::send_spam_reports(spam_report_interval));
+    }
+
+    let monitor = Monitor::new();
+    let chains = Chain::init_from_env_vec();
+    for chain in chains.iter() {
+        if !chain_control::register(chain) {
+            panic!("Duplicate chain {}", chain.name);
+        }
+    }
+
+    // Payloads received by the webhook endpoint of each chain in Push mode
+    let mut webhook_senders = HashMap::new();
+    let mut webhook_receivers = HashMap::new();
+    for chain in chains
+        .iter()
+        .filter(|chain| matches!(chain.mode, ChainMode::Push))
+    {
+        let (sender, receiver) = mpsc::channel::<WebhookPayload>(WEBHOOK_QUEUE_SIZE);
+        webhook_senders.insert(
+            chain.name.to_lowercase(),
+            (chain.webhook_secret.clone().unwrap(), sender),
+        );
+        webhook_receivers.insert(chain.name.clone(), receiver);
+    }
+
+    let account_monitor = monitor.clone();
+    let explorer_chains = Arc::new(chains.clone());
+    let add_monitor_account = warp::post()
+        .and(warp::path("accounts"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and_then(move |account: WatchedAccount| {
+            let monitor = account_monitor.clone();
+            let explorer_chains = explorer_chains.clone();
+            async move {
+                // Accounts can also be added from an explorer address or transaction URL
+                Ok::<_, Infallible>(match monitor.add_account(&explorer_chains, account).await {
+                    Ok(watched_accounts_count) => warp::reply::with_status(
+                        format!("Watching {} accounts\n", watched_accounts_count),
+                        warp::http::StatusCode::ACCEPTED,
+                    ),
+                    Err(err) => warp::reply::with_status(
+                        format!("{}\n", err),
+                        warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                    ),
+                })
+            }
+        });
+
+    // Answers the mutations before their routes in read-only mode
+    let read_only_route = warp::post()
+        .or(warp::put())
+        .unify()
+        .and(
+            warp::path("accounts")
+                .or(warp::path("expectations"))
+                .unify()
+                .or(warp::path("acknowledge"))
+                .unify()
+                .or(warp::path("chains"))
+                .unify()
+                .or(warp::path("test-notification"))
+                .unify()
+                .or(warp::path("log-level"))
+                .unify(),
+        )
+        .and_then(|| async {
+            if *READ_ONLY {
+                Ok(warp::reply::with_status(
+                    "Read-only mode\n".to_string(),
+                    warp::http::StatusCode::FORBIDDEN,
+                ))
+            } else {
+                Err(warp::reject::not_found())
+            }
+        });
+
+    let metrics_route = warp::get().and(warp::path("metrics")).map(|| {
+        use prometheus::Encoder;
+        let encoder = prometheus::TextEncoder::new();
+
+        let mut buffer = Vec::new();
+        if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
+            error!("could not encode custom metrics: {}", e);
+        };
+        let res = match String::from_utf8(buffer.clone()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("custom metrics could not be from_utf8'd: {}", e);
+                String::default()
+            }
+        };
+        buffer.clear();
+
+        res
+    });
+
+    let expectation_chains = chains.clone();
+    let expectation_route = warp::post()
+        .and(warp::path("expectations"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .map(move |expectation: expectation::Expectation| {
+            if let Err(err) = expectation.validate(&expectation_chains) {
+                return warp::reply::with_status(err, warp::http::StatusCode::UNPROCESSABLE_ENTITY);
+            }
+
+            let id = expectation::register(expectation);
+            warp::reply::with_status(format!("{}\n", id), warp::http::StatusCode::ACCEPTED)
+        });
+
+    let acknowledge_route =
+        warp::post()
+            .and(warp::path!("acknowledge" / String))
+            .map(|acknowledge_id: String| {
+                if escalation::acknowledge(&acknowledge_id) {
+                    warp::reply::with_status(
+                        "Acknowledged\n".to_string(),
+                        warp::http::StatusCode::OK,
+                    )
+                } else {
+                    warp::reply::with_status(
+                        "Unknown or already acknowledged notification\n".to_string(),
+                        warp::http::StatusCode::NOT_FOUND,
+                    )
+                }
+            });
+
+    let chain_monitor = monitor.clone();
+    let add_chain_route = warp::post()
+        .and(warp::path("chains"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and_then(move |chain_definition: chain::ChainDefinition| {
+            let monitor = chain_monitor.clone();
+            async move {
+                Ok::<_, Infallible>(match monitor.add_chain(chain_definition).await {
+                    Ok(name) => warp::reply::with_status(
+                        format!("Monitoring {}\n", name),
+                        warp::http::StatusCode::ACCEPTED,
+                    ),
+                    Err(AddChainError::Invalid(err)) => warp::reply::with_status(
+                        format!("{}\n", err),
+                        warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                    ),
+                    Err(AddChainError::AlreadyMonitored(err)) => warp::reply::with_status(
+                        format!("{}\n", err),
+                        warp::http::StatusCode::CONFLICT,
+                    ),
+                })
+            }
+        });
+
+    let test_notification_monitor = monitor.clone();
+    let test_notification_chain = chains[0].clone();
+    let test_notification_route = warp::post()
+        .and(warp::path("test-notification"))
+        .and(warp::path::end())
+        .and_then(move || {
+            let addressbook = test_notification_monitor.addressbook();
+            let chain = test_notification_chain.clone();
+            async move { Ok::<_, Infallible>(send_test_notification(&chain, addressbook).await) }
+        });
+
+    let log_level_route = warp::put()
+        .and(warp::path("log-level"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::bytes())
+        .map(|body: warp::hyper::body::Bytes| {
+            let directives = String::from_utf8_lossy(&body).trim().to_string();
+            match logging::set_filter(&directives) {
+                Ok(()) => {
+                    info!("Log level set to {} through the API", directives);
+                    warp::reply::with_status(
+                        format!("Log level set to {}\n", directives),
+                        warp::http::StatusCode::OK,
+                    )
+                }
+                Err(err) => warp::reply::with_status(
+                    format!("Invalid log level {}: {}\n", directives, err),
+                    warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                ),
+            }
+        });
+
+    let chains_route = warp::get()
+        .and(warp::path("chains"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&chain_control::states()));
+
+    let config_monitor = monitor.clone();
+    let config_route = warp::get()
+        .and(warp::path("config"))
+        .and(warp::path::end())
+        .map(move || {
+            let addressbook = config_monitor.addressbook();
+            let addressbook = addressbook.lock().unwrap();
+            warp::reply::json(&effective_config::current(&addressbook))
+        });
+
+    let openapi_route = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&openapi::document()));
+
+    let pause_route = warp::post()
+        .and(warp::path!("chains" / String / "pause"))
+        .map(|chain_name: String| set_chain_paused(&chain_name, true));
+
+    let resume_route = warp::post()
+        .and(warp::path!("chains" / String / "resume"))
+        .map(|chain_name: String| set_chain_paused(&chain_name, false));
+
+    let reprocess_monitor = monitor.clone();
+    let reprocess_route = warp::post()
+        .and(warp::path!("chains" / String / "reprocess" / u64))
+        .and_then(move |chain_name: String, block_number: u64| {
+            let monitor = reprocess_monitor.clone();
+            async move {
+                let Some(chain) = chain_control::get(&chain_name) else {
+                    return Ok::<_, Infallible>(warp::reply::with_status(
+                        warp::reply::json(&format!("No chain named {}", chain_name)),
+                        warp::http::StatusCode::NOT_FOUND,
+                    ));
+                };
+
+                info!(
+                    "Reprocessing {} block {} through the API",
+                    chain.name, block_number
+                );
+                Ok(
+                    match monitor.chain_worker(chain).reprocess(block_number).await {
+                        Ok(reprocessed) => warp::reply::with_status(
+                            warp::reply::json(&reprocessed),
+                            warp::http::StatusCode::OK,
+                        ),
+                        Err(err) => warp::reply::with_status(
+                            warp::reply::json(&format!(
+                                "Could not reprocess block {}: {}",
+                                block_number, err
+                            )),
+                            warp::http::StatusCode::BAD_GATEWAY,
+                        ),
+                    },
+                )
+            }
+        });
+
+    let explain_monitor = monitor.clone();
+    let explain_route = warp::get()
+        .and(warp::path!("explain" / String / String))
+        .and_then(move |chain_name: String, tx_hash: String| {
+            let addressbook = explain_monitor.addressbook();
+            async move {
+                let Some(chain) = chain_control::get(&chain_name) else {
+                    return Ok::<_, Infallible>(warp::reply::with_status(
+                        warp::reply::json(&format!("No chain named {}", chain_name)),
+                        warp::http::StatusCode::NOT_FOUND,
+                    ));
+                };
+                let Ok(hash) = H256::from_str(&tx_hash) else {
+                    return Ok(warp::reply::with_status(
+                        warp::reply::json(&format!("Invalid transaction hash {}", tx_hash)),
+                        warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                    ));
+                };
+
+                Ok(match explain::explain(&chain, hash, addressbook).await {
+                    Ok(explanation) => warp::reply::with_status(
+                        warp::reply::json(&explanation),
+                        warp::http::StatusCode::OK,
+                    ),
+                    Err(err) => warp::reply::with_status(
+                        warp::reply::json(&err),
+                        warp::http::StatusCode::BAD_GATEWAY,
+                    ),
+                })
+            }
+        });
+
+    let webhook_route = warp::post()
+        .and(warp::path!("webhooks" / String))
+        .and(warp::header::headers_cloned())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::bytes())
+        .and_then(
+            move |chain_name: String,
+                  headers: warp::http::HeaderMap,
+                  body: warp::hyper::body::Bytes| {
+                let webhook_sender = webhook_senders.get(&chain_name.to_lowercase()).cloned();
+                async move {
+                    let Some((webhook_secret, sender)) = webhook_sender else {
+                        return Ok::<_, Infallible>(warp::reply::with_status(
+                            "Unknown chain or not in Push mode\n".to_string(),
+                            warp::http::StatusCode::NOT_FOUND,
+                        ));
+                    };
+                    if !webhook::verify_signature(&webhook_secret, &headers, &body) {
+                        warn!("Rejected {} webhook with an invalid signature", chain_name);
+                        return Ok(warp::reply::with_status(
+                            "Invalid signature\n".to_string(),
+                            warp::http::StatusCode::UNAUTHORIZED,
+                        ));
+                    }
+
+                    match serde_json::from_slice::<WebhookPayload>(&body) {
+                        Ok(webhook_payload) => {
+                            if sender.send(webhook_payload).await.is_err() {
+                                error!("Error while queueing {} webhook payload", chain_name);
+                            }
+                            Ok(warp::reply::with_status(
+                                "Accepted\n".to_string(),
+                                warp::http::StatusCode::ACCEPTED,
+                            ))
+                        }
+                        Err(err) => {
+                            warn!("Invalid {} webhook payload: {}", chain_name, err);
+                            Ok(warp::reply::with_status(
+                                "Invalid payload\n".to_string(),
+                                warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+                            ))
+                        }
+                    }
+                }
+            },
+        );
+
+    let live_stream_route = warp::get()
+        .and(warp::path("stream"))
+        .and(warp::query::<live_stream::StreamFilter>())
+        .map(|filter: live_stream::StreamFilter| {
+            warp::sse::reply(warp::sse::keep_alive().stream(live_stream::subscribe(filter.tenant)))
+        });
+
+    let calendar_route = warp::get().and(warp::path("calendar.ics")).map(|| {
+        warp::reply::with_header(
+            schedule::calendar(),
+            "Content-Type",
+            "text/calendar; charset=utf-8",
+        )
+    });
+
+    let history_route = warp::get()
+        .and(warp::path("history"))
+        .and(warp::query::<history::HistoryFilter>())
+        .map(|filter: history::HistoryFilter| {
+            if !history::is_enabled() {
+                return warp::reply::with_status(
+                    warp::reply::json(&"History is not enabled, set HISTORY_DB_PATH"),
+                    warp::http::StatusCode::NOT_FOUND,
+                );
+            }
+
+            match history::query(&filter) {
+                Ok(entries) => warp::reply::with_status(
+                    warp::reply::json(&entries),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(err) => {
+                    error!("Error while querying history: {}", err);
+                    warp::reply::with_status(
+                        warp::reply::json(&"Error while querying history"),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                }
+            }
+        });
+
+    let graphql_route = warp::path("graphql")
+        .and(async_graphql_warp::graphql(graphql::build_schema(
+            monitor.addressbook(),
+            chains.clone(),
+        )))
+        .and_then(
+            |(schema, request): (graphql::AccountMonitorSchema, async_graphql::Request)| async move {
+                Ok::<_, Infallible>(async_graphql_warp::GraphQLResponse::from(
+                    schema.execute(request).await,
+                ))
+            },
+        );
+
+    tokio::spawn(async move {
+        warp::serve(
+            metrics_route
+                .or(read_only_route)
+                .or(add_monitor_account)
+                .or(expectation_route)
+                .or(acknowledge_route)
+                .or(test_notification_route)
+                .or(log_level_route)
+                .or(chains_route)
+                .or(config_route)
+                .or(openapi_route)
+                .or(add_chain_route)
+                .or(pause_route)
+                .or(resume_route)
+                .or(reprocess_route)
+                .or(explain_route)
+                .or(webhook_route)
+                .or(calendar_route)
+                .or(history_route)
+                .or(live_stream_route)
+                .or(graphql_route),
+        )
+        .run(([0, 0, 0, 0], 3030))
+        .await;
+    });
+
+    #[cfg(feature = "grpc")]
+    if grpc::is_enabled() {
+        tokio::spawn(grpc::serve(grpc::AccountMonitorService::new(
+            monitor.clone(),
+            chains.clone(),
+            *READ_ONLY,
+        )));
+    }
+
+    let mut watched_accounts_count: u32 = 0;
+    if let Ok(static_accounts_path) = env::var("STATIC_ACCOUNTS_PATH") {
+        let file =
+            std::fs::File::open(static_accounts_path).expect("Could not open accounts file.");
+        let accounts_to_add: Vec<WatchedAccount> =
+            serde_yaml::from_reader(file).expect("Could not read accounts.");
+        watched_accounts_count = accounts_to_add
+            .into_iter()
+            .map(|acc| monitor.watch_account(acc))
+            .max()
+            .unwrap();
+    }
+    for account in tenants::watched_accounts() {
+        watched_accounts_count = monitor.watch_account(account);
+    }
+
+    if let Ok(replay_fixtures_path) = env::var("REPLAY_FIXTURES_PATH") {
+        warn!("Replaying the fixtures of {}", replay_fixtures_path);
+        let differences = fixtures::replay(
+            &chains,
+            monitor.addressbook(),
+            replay_fixtures_path.as_ref(),
+        );
+        for difference in differences.iter() {
+            error!("Unexpected notifications of {}", difference);
+        }
+        logging::shutdown();
+        if !differences.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Ok(calendar_events_path) = env::var("CALENDAR_EVENTS_PATH") {
+        let file = std::fs::File::open(calendar_events_path)
+            .expect("Could not open calendar events file.");
+        let events_to_schedule: Vec<ScheduledEvent> =
+            serde_yaml::from_reader(file).expect("Could not read calendar events.");
+        events_to_schedule
+            .into_iter()
+            .for_each(schedule::schedule_event);
+    }
+
+    // Doubles as a test of the ntfy configuration, before any alert depends on it
+    Notification {
+        message: format!(
+            "Account Monitor Started, version {} ({}), {} accounts configured on {}",
+            VERSION,
+            COMMIT,
+            watched_accounts_count,
+            chains
+                .iter()
+                .map(|chain| chain.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ),
+        url: None,
+        severity: None,
+        route: None,
+        image: None,
+        links: vec![],
+    }
+    .send()
+    .await
+    .wrap_err(
+        "Could not send the startup notification, check NTFY_URL, NTFY_TOPIC and NTFY_TOKEN",
+    )?;
+
+    if let Ok(debug_block_var) = env::var("DEBUG_BLOCK") {
+        let debug_blocks = parse_debug_blocks(&debug_block_var);
+        warn!(
+            "Running in debug mode, getting blocks {} to {}",
+            debug_blocks.start(),
+            debug_blocks.end()
+        );
+
+        let results = futures::future::join_all(
+            chains
+                .into_iter()
+                .map(|chain| monitor.chain_worker(chain).debug(debug_blocks.clone())),
+        )
+        .await;
+        let mut notified = 0;
+        let mut failed = false;
+        for result in results {
+            match result {
+                Ok(chain_notified) => notified += chain_notified,
+                Err(err) => {
+                    error!("Error while debugging blocks: {}", err);
+                    failed = true;
+                }
+            }
+        }
+        logging::shutdown();
+        if failed {
+            std::process::exit(2);
+        }
+        if notified == 0 {
+            warn!("No transaction by monitored accounts found, have the accounts been setup?");
+            std::process::exit(1);
+        }
+        info!("{} notifications sent, exiting", notified);
+        std::process::exit(0);
+    } else {
+        if let Ok(relayer_fleet_path) = env::var("RELAYER_FLEET_PATH") {
+            let file = std::fs::File::open(relayer_fleet_path)
+                .expect("Could not open relayer fleet file.");
+            let relayers: Vec<fleet::Relayer> =
+                serde_yaml::from_reader(file).expect("Could not read relayer fleet.");
+
+            if let Some(relayer) = relayers.iter().find(|relayer| {
+                !chains
+                    .iter()
+                    .any(|chain| relayer.chain.eq_ignore_ascii_case(&chain.name))
+            }) {
+                panic!("Relayer {} is on an unknown chain", relayer.label);
+            }
+
+            for chain in chains.iter() {
+                let chain_relayers: Vec<fleet::Relayer> = relayers
+                    .iter()
+                    .filter(|relayer| relayer.chain.eq_ignore_ascii_case(&chain.name))
+                    .cloned()
+                    .collect();
+                if !chain_relayers.is_empty() {
+                    tokio::spawn(
+                        fleet::watch_relayers(chain.clone(), chain_relayers)
+                            .instrument(chain_worker::chain_span(chain)),
+                    );
+                }
+            }
+        }
+
+        if let Ok(price_alerts_path) = env::var("PRICE_ALERTS_PATH") {
+            let file =
+                std::fs::File::open(price_alerts_path).expect("Could not open price alerts file.");
+            let price_alerts: Vec<price_alert::PriceAlert> =
+                serde_yaml::from_reader(file).expect("Could not read price alerts.");
+
+            if let Some(price_alert) = price_alerts.iter().find(|price_alert| {
+                !chains
+                    .iter()
+                    .any(|chain| price_alert.chain.eq_ignore_ascii_case(&chain.name))
+            }) {
+                panic!(
+                    "Price alert of {} is on an unknown chain",
+                    price_alert.token.full_string()
+                );
+            }
+
+            for chain in chains.iter() {
+                let chain_price_alerts: Vec<price_alert::PriceAlert> = price_alerts
+                    .iter()
+                    .filter(|price_alert| price_alert.chain.eq_ignore_ascii_case(&chain.name))
+                    .cloned()
+                    .collect();
+                if !chain_price_alerts.is_empty() {
+                    tokio::spawn(
+                        price_alert::watch_prices(
+                            chain.clone(),
+                            chain_price_alerts,
+                            monitor.addressbook(),
+                        )
+                        .instrument(chain_worker::chain_span(chain)),
+                    );
+                }
+            }
+        }
+
+        for chain in chains.into_iter() {
+            if matches!(chain.mode, ChainMode::Push) {
+                let webhook_payloads = webhook_receivers.remove(&chain.name).unwrap();
+                monitor.spawn_push_chain(chain, webhook_payloads);
+            } else {
+                monitor.spawn_chain(chain);
+            }
+        }
+    }
+
+    let mut sigint = signal(SignalKind::interrupt()).unwrap();
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = sigint.recv() => info!("SIGINT"),
+        _ = sigterm.recv() => info!("SIGTERM")
+    }
+    persistent_metrics::save();
+    logging::shutdown();
+
+    Ok(())
+}
+
+/// Blocks of `DEBUG_BLOCK`, a single block or an inclusive `start..end` range
+fn parse_debug_blocks(debug_block_var: &str) -> RangeInclusive<u64> {
+    let parse = |block: &str| block.trim().parse::<u64>().expect("Invalid DEBUG_BLOCK");
+    let debug_blocks = match debug_block_var.split_once("..") {
+        Some((start, end)) => parse(start)..=parse(end.trim_start_matches('=')),
+        None => parse(debug_block_var)..=parse(debug_block_var),
+    };
+    if debug_blocks.is_empty() {
+        panic!("Invalid DEBUG_BLOCK");
+    }
+    debug_blocks
+}
+
+fn set_chain_paused(chain_name: &str, paused: bool) -> warp::reply::WithStatus<String> {
+    if chain_control::set_paused(chain_name, paused) {
+        info!(
+            "{} {} through the API",
+            if paused { "Pausing" } else { "Resuming" },
+            chain_name
+        );
+        warp::reply::with_status(
+            format!(
+                "{} {}\n",
+                chain_name,
+                if paused { "paused" } else { "resumed" }
+            ),
+            warp::http::StatusCode::OK,
+        )
+    } else {
+        warp::reply::with_status(
+            format!("No polling chain named {}\n", chain_name),
+            warp::http::StatusCode::NOT_FOUND,
+        )
+    }
+}
+
+/// Renders a made up transfer of a watched account like any notified transaction, and delivers
+/// it to every sink, to check the whole delivery after changing their configuration
+async fn send_test_notification(
+    chain: &Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+) -> warp::reply::WithStatus<String> {
+    let addressbook = addressbook.lock().unwrap().clone();
+    let account = addressbook
+        .keys()
+        .min()
+        .and_then(|account| Address::from_str(account).ok())
+        .unwrap_or_default();
+    let tx = InterestingTransaction {
+        from: Some(account),
+        to: Some(Address::from_low_u64_be(0xdead)),
+        amount: Some(U256::exp10(chain.native_decimals as usize)),
+        ..InterestingTransaction::new(H256::zero(), InterestingTransactionKind::Send, account)
+    };
+    let mut notification = tx.build_notification(chain, &addressbook);
+    notification.message = format!("Test notification: {}", notification.message);
+
+    info!("Sending a test notification through the API");
+    match notification.deliver(None).await {
+        Ok(()) => warp::reply::with_status(
+            format!("{}\n", notification.message),
+            warp::http::StatusCode::OK,
+        ),
+        Err(err) => warp::reply::with_status(
+            format!("Could not send the test notification: {}\n", err),
+            warp::http::StatusCode::BAD_GATEWAY,
+        ),
+    }
+}
@@ -0,0 +1,230 @@
+use ethers::core::types::Address;
+use lazy_static::lazy_static;
+use log::{error, info};
+use rusqlite::named_params;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::FullString;
+use crate::{
+    chain::Chain,
+    escalation::Severity,
+    interesting_transaction::InterestingTransaction,
+    notification::{Notification, Sendable},
+    state_db::CONNECTION,
+    tenants,
+    token::{FromChainAddress, Token},
+};
+
+/// Transactions listed for each token, the rest are only counted
+const MAX_EXAMPLES: usize = 3;
+
+type SuppressedTokenKey = (Option<&'static str>, String, Option<Address>);
+
+struct SuppressedToken {
+    symbol: String,
+    count: u32,
+    examples: Vec<String>,
+}
+
+lazy_static! {
+    pub static ref SPAM_REPORT_INTERVAL: Option<Duration> = env::var("SPAM_REPORT_INTERVAL")
+        .ok()
+        .map(|interval| Duration::from_secs(
+            interval.parse::<u64>().expect("Invalid SPAM_REPORT_INTERVAL")
+        ))
+        .filter(|interval| !interval.is_zero());
+    /// Spam filtered since the last report, per tenant of the recipient, chain and token
+    static ref SUPPRESSED_TOKENS: Mutex<HashMap<SuppressedTokenKey, SuppressedToken>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Loads the spam filtered since the last report of a previous run from the state database.
+/// The spam of tenants which were removed since is dropped
+pub fn load() {
+    if SPAM_REPORT_INTERVAL.is_none() {
+        return;
+    }
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare("SELECT tenant, chain, token, symbol, count, examples FROM suppressed_tokens")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, u32>(4)?,
+                        row.get::<_, String>(5)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Error while reading the suppressed spam: {}", err);
+            return;
+        }
+    };
+
+    let mut suppressed_tokens = SUPPRESSED_TOKENS.lock().unwrap();
+    for (tenant, chain, token, symbol, count, examples) in rows {
+        let tenant = match tenant.as_str() {
+            "" => None,
+            name => match tenants::find_name(name) {
+                Some(tenant) => Some(tenant),
+                None => continue,
+            },
+        };
+        let token = match token.as_str() {
+            "" => None,
+            token => match Address::from_str(token) {
+                Ok(token) => Some(token),
+                Err(_) => {
+                    error!("Invalid stored suppressed token {}", token);
+                    continue;
+                }
+            },
+        };
+        suppressed_tokens.insert(
+            (tenant, chain, token),
+            SuppressedToken {
+                symbol,
+                count,
+                examples: serde_json::from_str(&examples).unwrap_or_default(),
+            },
+        );
+    }
+    info!(
+        "Loaded {} tokens filtered as spam since the last report",
+        suppressed_tokens.len()
+    );
+}
+
+/// Stores the suppressed token, the tenant and token being empty when there is none
+fn store(key: &SuppressedTokenKey, suppressed_token: &SuppressedToken) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    let (tenant, chain, token) = key;
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR REPLACE INTO suppressed_tokens (tenant, chain, token, symbol, count, examples)
+        VALUES (:tenant, :chain, :token, :symbol, :count, :examples)",
+        named_params! {
+            ":tenant": tenant.unwrap_or_default(),
+            ":chain": chain,
+            ":token": token.map(|token| token.full_string()).unwrap_or_default(),
+            ":symbol": suppressed_token.symbol,
+            ":count": suppressed_token.count,
+            ":examples": serde_json::to_string(&suppressed_token.examples).unwrap(),
+        },
+    ) {
+        error!("Error while storing the suppressed spam: {}", err);
+    }
+}
+
+/// Forgets the reported spam
+fn clear() {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection
+        .lock()
+        .unwrap()
+        .execute("DELETE FROM suppressed_tokens", [])
+    {
+        error!("Error while removing the reported spam: {}", err);
+    }
+}
+
+/// Adds the transaction filtered as spam to the next report
+pub fn record(chain: &Chain, tx: &InterestingTransaction) {
+    if SPAM_REPORT_INTERVAL.is_none() {
+        return;
+    }
+
+    let key = (
+        tenants::name_of(&tx.involved_account),
+        chain.name.clone(),
+        tx.token,
+    );
+    let mut suppressed_tokens = SUPPRESSED_TOKENS.lock().unwrap();
+    let suppressed_token =
+        suppressed_tokens
+            .entry(key.clone())
+            .or_insert_with(|| SuppressedToken {
+                symbol: tx.token.map_or(chain.native_symbol.clone(), |token| {
+                    Token::from_chain_address(chain, token).symbol
+                }),
+                count: 0,
+                examples: vec![],
+            });
+
+    suppressed_token.count += 1;
+    if suppressed_token.examples.len() < MAX_EXAMPLES {
+        suppressed_token.examples.push(
+            chain
+                .tx_url(tx.hash)
+                .unwrap_or_else(|| tx.hash.full_string()),
+        );
+    }
+    store(&key, suppressed_token);
+}
+
+/// Periodically sends what was filtered as spam, so false positives can be spotted without
+/// disabling the filter
+pub async fn send_spam_reports(interval: Duration) {
+    loop {
+        sleep(interval).await;
+
+        // Cleared under the lock, so the spam recorded meanwhile is stored for the next report
+        let mut suppressed_tokens: Vec<(SuppressedTokenKey, SuppressedToken)> = {
+            let mut suppressed_tokens = SUPPRESSED_TOKENS.lock().unwrap();
+            clear();
+            suppressed_tokens.drain().collect()
+        };
+        suppressed_tokens.sort_by_key(|(_, suppressed_token)| Reverse(suppressed_token.count));
+
+        // Each tenant is only reported the spam sent to its own accounts
+        let mut reports: HashMap<Option<&'static str>, Vec<String>> = HashMap::new();
+        for ((tenant, chain_name, token), suppressed_token) in suppressed_tokens {
+            reports.entry(tenant).or_default().push(format!(
+                "{} {}{}: {} transactions, e.g. {}",
+                chain_name,
+                suppressed_token.symbol,
+                token.map_or(String::new(), |token| format!(" ({})", token.full_string())),
+                suppressed_token.count,
+                suppressed_token.examples.join(" ")
+            ));
+        }
+
+        for (tenant, lines) in reports {
+            let notification = Notification {
+                message: format!("Spam filtered since the last report:\n{}", lines.join("\n")),
+                url: None,
+                severity: Some(Severity::Low),
+                route: tenant.and_then(tenants::route_of_tenant),
+                image: None,
+                links: vec![],
+            };
+            if notification.send().await.is_err() {
+                error!("Error while sending spam report");
+            }
+        }
+    }
+}
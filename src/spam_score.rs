@@ -0,0 +1,338 @@
+use ethers::{
+    core::{
+        abi::AbiDecode,
+        types::{Address, Filter as LogFilter, U256, U64},
+    },
+    middleware::Middleware,
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::info;
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    asset_db,
+    chain::{Chain, SpamFilterLevel},
+    decoding::TRANSFER_TOPIC,
+    explorer_api,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    metrics_middleware::MetricsMiddleware,
+    retry::log_rpc_error,
+    scam_list, spam_report,
+    throttled_http::ThrottledHttp,
+    token, FullString, IsKnownToken,
+};
+
+/// Parts of symbols linking to or asking to visit a site, as honeypot airdrops do to lure the
+/// recipients into approving their tokens
+const HONEYPOT_PATTERNS: [&str; 12] = [
+    "http", "www", ".com", ".io", ".org", ".net", ".xyz", ".app", "claim", "reward", "visit",
+    "airdrop",
+];
+
+lazy_static! {
+    /// Score from which the token transfers of `Heuristic` chains are spam
+    static ref SPAM_SCORE_THRESHOLD: u32 = env::var("SPAM_SCORE_THRESHOLD")
+        .map(|threshold| threshold.parse().expect("Invalid SPAM_SCORE_THRESHOLD"))
+        .unwrap_or(3);
+    /// Transfers of a token with the same amount in a block from which they are a mass mint
+    static ref SPAM_MASS_TRANSFER_COUNT: usize = env::var("SPAM_MASS_TRANSFER_COUNT")
+        .map(|count| count.parse().expect("Invalid SPAM_MASS_TRANSFER_COUNT"))
+        .unwrap_or(20);
+    /// Age under which token contracts are new
+    static ref SPAM_MIN_TOKEN_AGE: Duration = Duration::from_secs(
+        env::var("SPAM_MIN_TOKEN_AGE")
+            .map(|age| age.parse().expect("Invalid SPAM_MIN_TOKEN_AGE"))
+            .unwrap_or(7 * 86400)
+    );
+}
+
+/// Signs of an airdropped spam token, each adding its weight to the score of the transfer
+#[derive(Clone, Copy, Debug, PartialEq, strum_macros::Display)]
+enum Signal {
+    /// Symbol of a known token on another contract
+    Impersonation,
+    /// Symbol linking to a site, or with lookalike non-ASCII characters
+    HoneypotSymbol,
+    /// Neither known nor reporting a symbol and decimals
+    UnverifiableMetadata,
+    /// Many identical transfers of the token in the same block
+    MassTransfer,
+    /// Contract created shortly before the transfer
+    NewContract,
+}
+
+impl Signal {
+    fn weight(&self) -> u32 {
+        match self {
+            Signal::HoneypotSymbol => 3,
+            // Legitimate tokens of chains missing from the asset DB share the symbols of the
+            // known ones, which is not enough alone
+            Signal::Impersonation | Signal::UnverifiableMetadata | Signal::MassTransfer => 2,
+            Signal::NewContract => 1,
+        }
+    }
+}
+
+fn is_honeypot_symbol(symbol: &str) -> bool {
+    let symbol = symbol.to_lowercase();
+    !symbol.is_ascii()
+        || HONEYPOT_PATTERNS
+            .iter()
+            .any(|pattern| symbol.contains(pattern))
+}
+
+/// Incoming token transfers, the ones airdropped spam arrives as. Curated or overridden tokens,
+/// and flagged ones (which are notified as phishing), are never scored
+fn is_scored(chain: &Chain, tx: &InterestingTransaction) -> bool {
+    let Some(token) = tx.token else {
+        return false;
+    };
+    matches!(
+        tx.kind,
+        InterestingTransactionKind::Transfer | InterestingTransactionKind::Transfer1155
+    ) && tx.from != Some(tx.involved_account)
+        && !(asset_db::is_curated() && token.is_known_token())
+        && !token::is_overridden(chain, token)
+        && scam_list::flagged_counterparty(tx).is_none()
+}
+
+/// Signals of the token itself, from its symbol and whether it reports one. Tokens whose
+/// metadata couldn't be read (e.g. on RPC errors) are not unverifiable
+fn token_signals(chain: &Chain, token: Address) -> Vec<Signal> {
+    let asset_db = asset_db::asset_db();
+    match token::onchain_token(chain, token).or_else(|| asset_db.token(chain, token)) {
+        Some(metadata) => [
+            (
+                asset_db.is_known_symbol(&metadata.symbol),
+                Signal::Impersonation,
+            ),
+            (is_honeypot_symbol(&metadata.symbol), Signal::HoneypotSymbol),
+        ]
+        .into_iter()
+        .filter(|(matched, _)| *matched)
+        .map(|(_, signal)| signal)
+        .collect(),
+        None if token::is_onchain_read(chain, token) => vec![Signal::UnverifiableMetadata],
+        None => vec![],
+    }
+}
+
+/// Transfers of the token in the block with the same amount, counting all the transfers of
+/// NFTs which have none
+async fn identical_transfers(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    token: Address,
+    block_number: U64,
+    amount: U256,
+) -> Option<usize> {
+    let filter = LogFilter::new()
+        .from_block(block_number)
+        .to_block(block_number)
+        .address(token)
+        .topic0(*TRANSFER_TOPIC);
+    match provider.get_logs(&filter).await {
+        Ok(logs) => Some(
+            logs.iter()
+                .filter(|log| U256::decode(&log.data).unwrap_or_default() == amount)
+                .count(),
+        ),
+        Err(err) => {
+            log_rpc_error(chain, "token transfers", &err);
+            None
+        }
+    }
+}
+
+/// Unix timestamp of the creation of the contract, from the chain's explorer API
+async fn creation_timestamp(chain: &Chain, token: Address) -> Option<u64> {
+    let creations = explorer_api::query(
+        chain,
+        &[
+            ("module", "contract"),
+            ("action", "getcontractcreation"),
+            ("contractaddresses", &token.full_string()),
+        ],
+    )
+    .await
+    .ok()??;
+
+    creations.get(0)?.get("timestamp")?.as_str()?.parse().ok()
+}
+
+fn score(signals: &[Signal]) -> u32 {
+    signals.iter().map(Signal::weight).sum()
+}
+
+/// Signals of the transfer. The mass transfer and new contract ones need requests, which are
+/// only made while the transfer isn't spam yet
+async fn signals(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    tx: &InterestingTransaction,
+    cached_token_signals: &mut HashMap<Address, Vec<Signal>>,
+) -> Vec<Signal> {
+    let token = tx.token.unwrap();
+    let mut signals = cached_token_signals
+        .entry(token)
+        .or_insert_with(|| token_signals(chain, token))
+        .clone();
+
+    if score(&signals) < *SPAM_SCORE_THRESHOLD && tx.kind == InterestingTransactionKind::Transfer {
+        if let Some(block_number) = tx.block_number {
+            let transfers = identical_transfers(
+                provider,
+                chain,
+                token,
+                block_number,
+                tx.amount.unwrap_or_default(),
+            )
+            .await;
+            if transfers.is_some_and(|transfers| transfers >= *SPAM_MASS_TRANSFER_COUNT) {
+                signals.push(Signal::MassTransfer);
+            }
+        }
+    }
+
+    if score(&signals) < *SPAM_SCORE_THRESHOLD && explorer_api::is_enabled(chain) {
+        if let Some(created_at) = creation_timestamp(chain, token).await {
+            let transferred_at = tx.timestamp.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            });
+            if transferred_at.saturating_sub(created_at) < SPAM_MIN_TOKEN_AGE.as_secs() {
+                signals.push(Signal::NewContract);
+            }
+        }
+    }
+
+    signals
+}
+
+/// Drops the token transfers of `Heuristic` chains scoring at least `SPAM_SCORE_THRESHOLD`,
+/// once the metadata of their tokens is fetched
+#[tracing::instrument(skip_all)]
+pub async fn filter(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: Vec<InterestingTransaction>,
+) -> Vec<InterestingTransaction> {
+    if !matches!(chain.spam_filter_level, SpamFilterLevel::Heuristic) {
+        return interesting_transactions;
+    }
+
+    let mut cached_token_signals = HashMap::new();
+    let mut filtered_transactions = vec![];
+    for tx in interesting_transactions {
+        if !is_scored(chain, &tx) {
+            filtered_transactions.push(tx);
+            continue;
+        }
+
+        let signals = signals(provider, chain, &tx, &mut cached_token_signals).await;
+        let score = score(&signals);
+        if score >= *SPAM_SCORE_THRESHOLD {
+            info!(
+                "Spam tx {} on {}, scored {} ({})",
+                tx.hash.full_string(),
+                chain.name,
+                score,
+                signals
+                    .iter()
+                    .map(Signal::to_string)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+            spam_report::record(chain, &tx);
+        } else {
+            filtered_transactions.push(tx);
+        }
+    }
+    filtered_transactions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::test_chain;
+    use ethers::core::types::H256;
+    use serde_json::json;
+
+    const ACCOUNT: &str = "0x3333333333333333333333333333333333333333";
+    const SENDER: &str = "0x4444444444444444444444444444444444444444";
+    const TOKEN: &str = "0x2222222222222222222222222222222222222222";
+
+    fn chain() -> Chain {
+        asset_db::set_on_chain_for_test();
+        test_chain(json!({"spam_filter_level": "Heuristic"}))
+    }
+
+    fn transfer(kind: InterestingTransactionKind, from: &str) -> InterestingTransaction {
+        InterestingTransaction {
+            from: Some(from.parse().unwrap()),
+            to: Some(ACCOUNT.parse().unwrap()),
+            amount: Some(U256::exp10(18)),
+            token: Some(TOKEN.parse().unwrap()),
+            block_number: Some(U64::from(100)),
+            ..InterestingTransaction::new(H256::zero(), kind, ACCOUNT.parse().unwrap())
+        }
+    }
+
+    #[test]
+    fn detects_honeypot_symbols() {
+        assert!(is_honeypot_symbol("Visit usdc-rewards.com"));
+        assert!(is_honeypot_symbol("CLAIM at t.me/airdrops"));
+        assert!(is_honeypot_symbol("www.eth-gift"));
+        // Cyrillic С
+        assert!(is_honeypot_symbol("USDС"));
+        assert!(!is_honeypot_symbol("USDC"));
+        assert!(!is_honeypot_symbol("wstETH"));
+    }
+
+    #[test]
+    fn scores_the_signals() {
+        assert_eq!(score(&[]), 0);
+        assert_eq!(score(&[Signal::NewContract]), 1);
+        assert_eq!(
+            score(&[
+                Signal::Impersonation,
+                Signal::MassTransfer,
+                Signal::NewContract
+            ]),
+            5
+        );
+        // A known symbol alone is not spam by default
+        assert!(score(&[Signal::Impersonation]) < 3);
+        assert!(score(&[Signal::HoneypotSymbol]) >= 3);
+    }
+
+    #[test]
+    fn scores_incoming_token_transfers_only() {
+        let chain = chain();
+        assert!(is_scored(
+            &chain,
+            &transfer(InterestingTransactionKind::Transfer, SENDER)
+        ));
+        assert!(is_scored(
+            &chain,
+            &transfer(InterestingTransactionKind::Transfer1155, SENDER)
+        ));
+        assert!(!is_scored(
+            &chain,
+            &transfer(InterestingTransactionKind::Transfer, ACCOUNT)
+        ));
+        assert!(!is_scored(
+            &chain,
+            &transfer(InterestingTransactionKind::Approval, SENDER)
+        ));
+
+        let mut native_transfer = transfer(InterestingTransactionKind::Transfer, SENDER);
+        native_transfer.token = None;
+        assert!(!is_scored(&chain, &native_transfer));
+    }
+}
@@ -0,0 +1,98 @@
+use lazy_static::lazy_static;
+use rusqlite::Connection;
+use std::env;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// State kept across restarts, such as the chains' progress, the metric counters, the
+    /// fetched ABIs, the pending withdrawals, the bridge departures, the failed retryable tickets,
+    /// the delegations, the label history and the spam filtered since the last report
+    pub static ref CONNECTION: Option<Mutex<Connection>> =
+        env::var("STATE_DB_PATH").ok().map(|state_db_path| {
+            let connection = Connection::open(state_db_path).expect("Could not open STATE_DB_PATH");
+            connection
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS chain_progress (
+                        chain TEXT PRIMARY KEY,
+                        block INTEGER NOT NULL
+                    );
+                    CREATE TABLE IF NOT EXISTS metric_counters (
+                        name TEXT NOT NULL,
+                        labels TEXT NOT NULL,
+                        value INTEGER NOT NULL,
+                        PRIMARY KEY (name, labels)
+                    );
+                    CREATE TABLE IF NOT EXISTS contract_abis (
+                        chain TEXT NOT NULL,
+                        address TEXT NOT NULL,
+                        abi TEXT,
+                        fetched_at INTEGER NOT NULL,
+                        PRIMARY KEY (chain, address)
+                    );
+                    CREATE TABLE IF NOT EXISTS withdrawals (
+                        chain TEXT NOT NULL,
+                        hash TEXT NOT NULL,
+                        account TEXT NOT NULL,
+                        token TEXT,
+                        amount TEXT NOT NULL,
+                        claimable_at INTEGER NOT NULL,
+                        PRIMARY KEY (chain, hash, account)
+                    );
+                    CREATE TABLE IF NOT EXISTS bridge_departures (
+                        chain TEXT NOT NULL,
+                        hash TEXT NOT NULL,
+                        account TEXT NOT NULL,
+                        bridge TEXT NOT NULL,
+                        symbol TEXT,
+                        amount REAL NOT NULL,
+                        transferred TEXT NOT NULL,
+                        sent_at INTEGER NOT NULL,
+                        held_notification TEXT,
+                        PRIMARY KEY (chain, hash, account)
+                    );
+                    CREATE TABLE IF NOT EXISTS retryable_progress (
+                        chain TEXT PRIMARY KEY,
+                        block INTEGER NOT NULL
+                    );
+                    CREATE TABLE IF NOT EXISTS failed_retryable_tickets (
+                        chain TEXT NOT NULL,
+                        ticket_id TEXT NOT NULL,
+                        account TEXT NOT NULL,
+                        timeout INTEGER NOT NULL,
+                        reminded INTEGER NOT NULL,
+                        PRIMARY KEY (chain, ticket_id)
+                    );
+                    CREATE TABLE IF NOT EXISTS delegation_progress (
+                        chain TEXT PRIMARY KEY,
+                        block INTEGER NOT NULL
+                    );
+                    CREATE TABLE IF NOT EXISTS delegations (
+                        chain TEXT NOT NULL,
+                        account TEXT NOT NULL,
+                        delegate TEXT,
+                        PRIMARY KEY (chain, account)
+                    );
+                    CREATE TABLE IF NOT EXISTS label_history (
+                        address TEXT NOT NULL,
+                        label TEXT NOT NULL,
+                        since INTEGER NOT NULL,
+                        PRIMARY KEY (address, since, label)
+                    );
+                    CREATE TABLE IF NOT EXISTS suppressed_tokens (
+                        tenant TEXT NOT NULL,
+                        chain TEXT NOT NULL,
+                        token TEXT NOT NULL,
+                        symbol TEXT NOT NULL,
+                        count INTEGER NOT NULL,
+                        examples TEXT NOT NULL,
+                        PRIMARY KEY (tenant, chain, token)
+                    );",
+                )
+                .expect("Could not create the state tables");
+            Mutex::new(connection)
+        });
+}
+
+pub fn is_enabled() -> bool {
+    CONNECTION.is_some()
+}
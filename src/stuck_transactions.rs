@@ -0,0 +1,255 @@
+use ethers::core::types::{Address, U256};
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use serde_derive::Deserialize as DeserializeMacro;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::{
+    batch,
+    chain::Chain,
+    chain_control,
+    escalation::Severity,
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    retry::log_rpc_error,
+    rpc_error, tenants,
+};
+use crate::{FullString, ToLabel};
+
+/// Longest time between checks of the nonces, shorter thresholds are checked more often
+const MAX_NONCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Seconds a transaction of a watched account can stay pending before being alerted as stuck
+    pub static ref STUCK_TRANSACTION_THRESHOLD: Option<Duration> =
+        env::var("STUCK_TRANSACTION_THRESHOLD").ok().map(|threshold| {
+            Duration::from_secs(
+                threshold
+                    .parse::<u64>()
+                    .expect("Invalid STUCK_TRANSACTION_THRESHOLD"),
+            )
+        });
+}
+
+pub fn is_enabled() -> bool {
+    STUCK_TRANSACTION_THRESHOLD.is_some_and(|threshold| !threshold.is_zero())
+}
+
+/// Transactions of an account sent to the mempool and not yet mined
+struct PendingNonce {
+    /// Nonce of the oldest pending transaction, the next one to be mined
+    nonce: U256,
+    since: Instant,
+    alerted: bool,
+}
+
+/// Transactions of an account in the node's mempool, by nonce
+#[derive(DeserializeMacro)]
+struct TxpoolContent {
+    queued: HashMap<String, serde_json::Value>,
+}
+
+fn notification(
+    chain: &Chain,
+    account: Address,
+    message: String,
+    severity: Severity,
+) -> Notification {
+    Notification {
+        message,
+        url: chain.address_url(account),
+        severity: Some(severity),
+        route: tenants::route_of(&account),
+        image: None,
+        links: vec![],
+    }
+}
+
+/// Periodically compares the pending and latest nonces of the watched accounts, alerting when a
+/// transaction stays pending longer than `STUCK_TRANSACTION_THRESHOLD`, and again once it is
+/// mined. When the RPC exposes `txpool_contentFrom`, transactions queued behind a missing nonce
+/// are alerted too
+pub async fn watch_stuck_transactions(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+) {
+    let threshold = STUCK_TRANSACTION_THRESHOLD.unwrap();
+    let (chain, provider) = connect_and_verify(chain).await;
+    let mut pending_nonces: HashMap<Address, PendingNonce> = HashMap::new();
+    // Queued nonce already alerted for each account with a nonce gap
+    let mut gaps: HashMap<Address, U256> = HashMap::new();
+    let mut txpool_supported = true;
+
+    loop {
+        sleep(threshold.min(MAX_NONCE_CHECK_INTERVAL)).await;
+        if chain_control::is_paused(&chain.name) {
+            continue;
+        }
+
+        let accounts: Vec<Address> = addressbook
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|address| Address::from_str(address).ok())
+            .collect();
+        let params: Vec<serde_json::Value> = ["latest", "pending"]
+            .iter()
+            .flat_map(|block| accounts.iter().map(move |account| json!([account, block])))
+            .collect();
+        let nonces =
+            match batch::batch_request::<_, U256>(&provider, "eth_getTransactionCount", &params)
+                .await
+            {
+                Ok(nonces) => nonces,
+                Err(err) => {
+                    log_rpc_error(&chain, "account nonces", &err);
+                    continue;
+                }
+            };
+        let (latest_nonces, pending_nonces_now) = nonces.split_at(accounts.len());
+
+        let mut notifications = vec![];
+        for ((account, latest), pending) in
+            accounts.iter().zip(latest_nonces).zip(pending_nonces_now)
+        {
+            if pending <= latest {
+                if let Some(pending_nonce) = pending_nonces.remove(account) {
+                    if pending_nonce.alerted {
+                        info!(
+                            "Stuck transaction of {} mined on {}",
+                            account.full_string(),
+                            chain.name
+                        );
+                        notifications.push(notification(
+                            &chain,
+                            *account,
+                            format!(
+                                "Stuck transaction with nonce {} of {} was mined on {}",
+                                pending_nonce.nonce,
+                                account.to_label(&addressbook.lock().unwrap()),
+                                chain.name
+                            ),
+                            Severity::Normal,
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            let pending_nonce = pending_nonces
+                .entry(*account)
+                .or_insert_with(|| PendingNonce {
+                    nonce: *latest,
+                    since: Instant::now(),
+                    alerted: false,
+                });
+            // The oldest pending transaction was mined, the next one starts waiting
+            if pending_nonce.nonce != *latest {
+                *pending_nonce = PendingNonce {
+                    nonce: *latest,
+                    since: Instant::now(),
+                    alerted: false,
+                };
+            }
+
+            if !pending_nonce.alerted && pending_nonce.since.elapsed() >= threshold {
+                pending_nonce.alerted = true;
+                warn!(
+                    "Transaction with nonce {} of {} stuck on {}",
+                    latest,
+                    account.full_string(),
+                    chain.name
+                );
+                notifications.push(notification(
+                    &chain,
+                    *account,
+                    format!(
+                        "⚠ Transaction with nonce {} of {} pending for over {} minutes on {} ({} pending), it may be under-priced",
+                        latest,
+                        account.to_label(&addressbook.lock().unwrap()),
+                        pending_nonce.since.elapsed().as_secs() / 60,
+                        chain.name,
+                        pending - latest
+                    ),
+                    Severity::High,
+                ));
+            }
+        }
+
+        if txpool_supported {
+            let params: Vec<serde_json::Value> =
+                accounts.iter().map(|account| json!([account])).collect();
+            match batch::batch_request::<_, TxpoolContent>(&provider, "txpool_contentFrom", &params)
+                .await
+            {
+                Ok(contents) => {
+                    for ((account, pending), content) in
+                        accounts.iter().zip(pending_nonces_now).zip(contents)
+                    {
+                        let queued = content
+                            .queued
+                            .keys()
+                            .filter_map(|nonce| U256::from_dec_str(nonce).ok())
+                            .filter(|nonce| nonce > pending)
+                            .min();
+                        let Some(queued) = queued else {
+                            gaps.remove(account);
+                            continue;
+                        };
+                        if gaps.insert(*account, queued) == Some(queued) {
+                            continue;
+                        }
+
+                        warn!(
+                            "Nonce gap of {} on {}, {} is queued behind {}",
+                            account.full_string(),
+                            chain.name,
+                            queued,
+                            pending
+                        );
+                        notifications.push(notification(
+                            &chain,
+                            *account,
+                            format!(
+                                "⚠ Transaction with nonce {} of {} is waiting for the missing nonce {} on {}",
+                                queued,
+                                account.to_label(&addressbook.lock().unwrap()),
+                                pending,
+                                chain.name
+                            ),
+                            Severity::High,
+                        ));
+                    }
+                }
+                Err(err) if rpc_error::is_method_not_found(&err) => {
+                    info!(
+                        "{} RPC doesn't support txpool_contentFrom, nonce gaps are not detected: {}",
+                        chain.name, err
+                    );
+                    txpool_supported = false;
+                }
+                // Checked again on the next round
+                Err(err) => {
+                    log_rpc_error(&chain, "txpool content", &err);
+                }
+            }
+        }
+        debug!(
+            "Checked the nonces of {} accounts on {}",
+            accounts.len(),
+            chain.name
+        );
+
+        for notification in notifications {
+            if notification.send().await.is_err() {
+                error!("Error while sending stuck transaction notification");
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use handlebars::Handlebars;
+use lazy_static::lazy_static;
+use log::error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+
+use crate::interesting_transaction::InterestingTransactionKind;
+
+lazy_static! {
+    /// User templates of the notification messages, named after the kind they replace
+    static ref TEMPLATES: Handlebars<'static> = {
+        let mut templates = Handlebars::new();
+        templates.register_escape_fn(handlebars::no_escape);
+        templates.set_strict_mode(true);
+
+        if let Ok(templates_path) = env::var("NOTIFICATION_TEMPLATES_PATH") {
+            let file = std::fs::File::open(templates_path)
+                .expect("Could not open notification templates file.");
+            let kind_templates: HashMap<InterestingTransactionKind, String> =
+                serde_yaml::from_reader(file).expect("Could not read notification templates.");
+            for (kind, template) in kind_templates {
+                templates
+                    .register_template_string(&kind.to_string(), template)
+                    .unwrap_or_else(|err| panic!("Invalid {} notification template: {}", kind, err));
+            }
+        }
+
+        templates
+    };
+}
+
+/// Loads the templates, failing on startup when they are invalid
+pub fn load() {
+    lazy_static::initialize(&TEMPLATES);
+}
+
+/// Whether any kind has a user template
+pub fn is_enabled() -> bool {
+    !TEMPLATES.get_templates().is_empty()
+}
+
+fn has_template(kind: &InterestingTransactionKind) -> bool {
+    TEMPLATES.has_template(&kind.to_string())
+}
+
+/// Message from the user template of the kind, if any. Variables are only built when needed
+pub fn render<T: Serialize>(
+    kind: &InterestingTransactionKind,
+    variables: impl FnOnce() -> T,
+) -> Option<String> {
+    if !has_template(kind) {
+        return None;
+    }
+
+    TEMPLATES
+        .render(&kind.to_string(), &variables())
+        .inspect_err(|err| {
+            error!(
+                "Error while rendering {} notification template: {}",
+                kind, err
+            )
+        })
+        .ok()
+}
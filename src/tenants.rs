@@ -0,0 +1,184 @@
+use ethers::core::types::Address;
+use lazy_static::lazy_static;
+use log::info;
+use serde_derive::Deserialize as DeserializeMacro;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+
+use crate::{
+    monitor::WatchedAccount,
+    notification,
+    routing::{Route, Sink},
+};
+
+/// Accounts of a user or team, whose notifications are only sent to their own route
+#[derive(DeserializeMacro)]
+struct Tenant {
+    name: String,
+    #[serde(flatten)]
+    route: Route,
+    accounts: Vec<WatchedAccount>,
+}
+
+/// Configured tenants, with the tenant of each account
+struct Tenants {
+    tenants: Vec<Tenant>,
+    /// Tenant of each account, as its index in `tenants`
+    account_tenants: HashMap<Address, usize>,
+}
+
+impl Tenants {
+    fn read(tenants_path: &str) -> Self {
+        let file = std::fs::File::open(tenants_path).expect("Could not open tenants file.");
+        let tenants: Vec<Tenant> = serde_yaml::from_reader(file).expect("Could not read tenants.");
+
+        let mut account_tenants = HashMap::new();
+        for (index, tenant) in tenants.iter().enumerate() {
+            for account in tenant.accounts.iter() {
+                let address = Address::from_str(&account.address).unwrap_or_else(|_| {
+                    panic!(
+                        "Invalid address {} of tenant {}",
+                        account.address, tenant.name
+                    )
+                });
+                if let Some(other) = account_tenants.insert(address, index) {
+                    panic!(
+                        "{} is in tenants {} and {}, accounts can only belong to one",
+                        account.address, tenants[other].name, tenant.name
+                    );
+                }
+            }
+        }
+
+        Self {
+            tenants,
+            account_tenants,
+        }
+    }
+
+    fn tenant(&self, account: &Address) -> Option<&Tenant> {
+        self.account_tenants
+            .get(account)
+            .map(|index| &self.tenants[*index])
+    }
+}
+
+lazy_static! {
+    static ref TENANTS: Tenants = match env::var("TENANTS_PATH") {
+        Ok(tenants_path) => Tenants::read(&tenants_path),
+        Err(_) => Tenants {
+            tenants: vec![],
+            account_tenants: HashMap::new(),
+        },
+    };
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Tenants of the test running on the thread, instead of the configured ones
+    static TEST_TENANTS: std::cell::Cell<Option<&'static Tenants>> =
+        const { std::cell::Cell::new(None) };
+}
+
+fn tenants() -> &'static Tenants {
+    #[cfg(test)]
+    if let Some(tenants) = TEST_TENANTS.get() {
+        return tenants;
+    }
+    &TENANTS
+}
+
+/// Uses the tenants of the file in the test running on the current thread
+#[cfg(test)]
+pub(crate) fn set_for_test(tenants_path: &str) {
+    TEST_TENANTS.set(Some(Box::leak(Box::new(Tenants::read(tenants_path)))));
+}
+
+pub fn is_enabled() -> bool {
+    !tenants().tenants.is_empty()
+}
+
+/// Loads the tenants, failing on startup when an account is invalid or shared between tenants,
+/// or a name can't be part of the topics their events are published to
+pub fn load() {
+    for tenant in tenants().tenants.iter() {
+        if tenant.name.is_empty()
+            || !tenant
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            panic!(
+                "Invalid tenant name {}, only letters, digits, - and _ are allowed",
+                tenant.name
+            );
+        }
+    }
+    if tenants()
+        .tenants
+        .iter()
+        .any(|tenant| tenant.route.routes_to(Sink::Email))
+    {
+        notification::require_email("tenants");
+    }
+    if is_enabled() {
+        info!(
+            "{} tenants with {} accounts",
+            tenants().tenants.len(),
+            tenants().account_tenants.len()
+        );
+    }
+}
+
+/// Accounts of every tenant, to be watched along with the static accounts
+pub fn watched_accounts() -> Vec<WatchedAccount> {
+    tenants()
+        .tenants
+        .iter()
+        .flat_map(|tenant| tenant.accounts.iter().cloned())
+        .collect()
+}
+
+/// Route of the tenant owning the account, if any
+pub fn route_of(account: &Address) -> Option<&'static Route> {
+    tenants().tenant(account).map(|tenant| &tenant.route)
+}
+
+/// Route of the tenant with the name
+pub fn route_of_tenant(name: &str) -> Option<&'static Route> {
+    tenants()
+        .tenants
+        .iter()
+        .find(|tenant| tenant.name == name)
+        .map(|tenant| &tenant.route)
+}
+
+/// Name of the configured tenant with the name, `None` when there is no such tenant
+pub fn find_name(name: &str) -> Option<&'static str> {
+    tenants()
+        .tenants
+        .iter()
+        .find(|tenant| tenant.name == name)
+        .map(|tenant| tenant.name.as_str())
+}
+
+/// Accounts of the tenant with the name
+pub fn accounts_of(name: &str) -> Vec<Address> {
+    let tenants = tenants();
+    tenants
+        .account_tenants
+        .iter()
+        .filter(|(_, index)| tenants.tenants[**index].name == name)
+        .map(|(account, _)| *account)
+        .collect()
+}
+
+/// Name of the tenant owning the account, if any
+pub fn name_of(account: &Address) -> Option<&'static str> {
+    tenants().tenant(account).map(|tenant| tenant.name.as_str())
+}
+
+pub fn has_digests() -> bool {
+    tenants().tenants.iter().any(|tenant| tenant.route.digest)
+}
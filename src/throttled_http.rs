@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use ethers::providers::{HttpClientError, JsonRpcClient, JsonRpcError};
+use log::warn;
+use reqwest::{header::RETRY_AFTER, StatusCode, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::{value::RawValue, Value};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, Instant},
+};
+
+use crate::rpc_error::{classify_json_rpc_error, RpcErrorKind};
+
+/// Pause used when the RPC rate limits a request without saying for how long
+const DEFAULT_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(5);
+const MAX_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(300);
+
+#[derive(SerializeMacro)]
+struct Request<'a, T: Serialize> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: T,
+}
+
+#[derive(DeserializeMacro)]
+struct Response<'a> {
+    #[serde(borrow)]
+    result: Option<&'a RawValue>,
+    error: Option<JsonRpcError>,
+}
+
+/// HTTP transport which spaces the requests to stay under the chain's requests per second
+/// ceiling, and holds all of them back for as long as the RPC asks to when it rate limits one
+#[derive(Debug)]
+pub struct ThrottledHttp {
+    id: AtomicU64,
+    client: reqwest::Client,
+    url: Url,
+    min_interval: Duration,
+    next_request: Mutex<Instant>,
+}
+
+impl ThrottledHttp {
+    pub fn new(url: Url, client: reqwest::Client, max_rps: Option<u32>) -> Self {
+        ThrottledHttp {
+            id: AtomicU64::new(1),
+            client,
+            url,
+            min_interval: max_rps
+                .map(|max_rps| Duration::from_secs(1) / max_rps)
+                .unwrap_or_default(),
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Waits for the next request slot, with `calls` being the JSON-RPC calls in the request
+    async fn throttle(&self, calls: u32) {
+        let mut next_request = self.next_request.lock().await;
+        sleep_until(*next_request).await;
+        *next_request = Instant::now() + self.min_interval * calls;
+    }
+
+    async fn pause(&self, pause: Duration) {
+        let pause = pause.min(MAX_RATE_LIMIT_PAUSE);
+        warn!(
+            "{} rate limited the requests, pausing them for {} seconds",
+            self.url.host_str().unwrap_or("RPC"),
+            pause.as_secs()
+        );
+
+        let mut next_request = self.next_request.lock().await;
+        *next_request = (*next_request).max(Instant::now() + pause);
+    }
+
+    /// Posts the body once allowed to, returning the response body regardless of the HTTP
+    /// status, like the ethers HTTP transport
+    pub async fn post<B: Serialize>(
+        &self,
+        body: &B,
+        calls: u32,
+    ) -> Result<Vec<u8>, HttpClientError> {
+        self.throttle(calls).await;
+
+        let response = self.client.post(self.url.clone()).json(body).send().await?;
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|retry_after| retry_after.to_str().ok())
+            .and_then(|retry_after| retry_after.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let status = response.status();
+        let body = response.bytes().await?.to_vec();
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            self.pause(retry_after.unwrap_or(DEFAULT_RATE_LIMIT_PAUSE))
+                .await;
+        }
+
+        Ok(body)
+    }
+
+    /// Pauses the requests when the JSON-RPC error is a rate limit, following the backoff hint
+    /// included by some providers (e.g. Infura's `backoff_seconds`)
+    async fn check_rate_limit(&self, error: &JsonRpcError) {
+        if classify_json_rpc_error(error.code, &error.message) == RpcErrorKind::RateLimited {
+            let backoff = error
+                .data
+                .as_ref()
+                .and_then(find_backoff_seconds)
+                .map(Duration::from_secs_f64);
+            self.pause(backoff.unwrap_or(DEFAULT_RATE_LIMIT_PAUSE))
+                .await;
+        }
+    }
+}
+
+fn find_backoff_seconds(data: &Value) -> Option<f64> {
+    match data {
+        Value::Object(fields) => fields.iter().find_map(|(key, value)| match value {
+            Value::Number(seconds) if key == "backoff_seconds" || key == "retry_after" => {
+                seconds.as_f64()
+            }
+            value => find_backoff_seconds(value),
+        }),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for ThrottledHttp {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, HttpClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let request = Request {
+            jsonrpc: "2.0",
+            id: self.id.fetch_add(1, Ordering::SeqCst),
+            method,
+            params,
+        };
+        let body = self.post(&request, 1).await?;
+
+        let response: Response =
+            serde_json::from_slice(&body).map_err(|err| HttpClientError::SerdeJson {
+                err,
+                text: String::from_utf8_lossy(&body).to_string(),
+            })?;
+
+        if let Some(error) = response.error {
+            self.check_rate_limit(&error).await;
+            return Err(error.into());
+        }
+
+        let result = response.result.map(RawValue::get).unwrap_or("null");
+        serde_json::from_str(result).map_err(|err| HttpClientError::SerdeJson {
+            err,
+            text: result.to_string(),
+        })
+    }
+}
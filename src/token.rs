@@ -1,47 +1,237 @@
-use ethers::core::types::Address;
-use rusqlite::{named_params, Connection};
+use ethers::{
+    core::{
+        abi::AbiDecode,
+        types::{Address, Bytes, U256},
+    },
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use serde_derive::{Deserialize as DeserializeMacro, Serialize as SerializeMacro};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::Mutex;
 
-use crate::chain::Chain;
-use account_monitor::FullString;
+use crate::FullString;
+use crate::{
+    asset_db, batch, chain::Chain, metrics_middleware::MetricsMiddleware, redis_backend,
+    throttled_http::ThrottledHttp,
+};
 
+const SYMBOL_SELECTOR: &str = "0x95d89b41";
+const DECIMALS_SELECTOR: &str = "0x313ce567";
+
+#[derive(Clone, DeserializeMacro, SerializeMacro)]
 pub struct Token {
     pub symbol: String,
     pub decimals: u32, // ERC20 supports only u8, but format units expects u32
 }
 
+/// Symbol and decimals of a token set by the user, for tokens misreporting them or renamed
+#[derive(DeserializeMacro)]
+struct TokenOverride {
+    chain: String,
+    address: Address,
+    symbol: String,
+    decimals: u32,
+}
+
+lazy_static! {
+    /// Tokens of `TOKEN_OVERRIDES_PATH`, by lowercase chain name and address
+    static ref TOKEN_OVERRIDES: HashMap<(String, Address), Token> =
+        match env::var("TOKEN_OVERRIDES_PATH") {
+            Ok(token_overrides_path) => {
+                let file = std::fs::File::open(token_overrides_path)
+                    .expect("Could not open token overrides file.");
+                let token_overrides: Vec<TokenOverride> =
+                    serde_yaml::from_reader(file).expect("Could not read token overrides.");
+                token_overrides
+                    .into_iter()
+                    .map(|token_override| {
+                        (
+                            (token_override.chain.to_lowercase(), token_override.address),
+                            Token {
+                                symbol: token_override.symbol,
+                                decimals: token_override.decimals,
+                            },
+                        )
+                    })
+                    .collect()
+            }
+            Err(_) => HashMap::new(),
+        };
+    /// Tokens missing from the asset DB, with the metadata read from their contracts (if any)
+    static ref ONCHAIN_TOKENS: Mutex<HashMap<(U256, Address), Option<Token>>> =
+        Mutex::new(HashMap::new());
+}
+
 pub trait FromChainAddress {
     fn from_chain_address(chain: &Chain, address: Address) -> Token;
 }
 
+/// Loads the token overrides, failing on startup when they are invalid
+pub fn load() {
+    if !TOKEN_OVERRIDES.is_empty() {
+        info!("Loaded {} token overrides", TOKEN_OVERRIDES.len());
+    }
+}
+
+fn from_overrides(chain: &Chain, address: Address) -> Option<Token> {
+    TOKEN_OVERRIDES
+        .get(&(chain.name.to_lowercase(), address))
+        .cloned()
+}
+
+/// Whether the user set the symbol and decimals of the token
+pub(crate) fn is_overridden(chain: &Chain, address: Address) -> bool {
+    TOKEN_OVERRIDES.contains_key(&(chain.name.to_lowercase(), address))
+}
+
 impl FromChainAddress for Token {
     fn from_chain_address(chain: &Chain, address: Address) -> Token {
-        let connection = Connection::open("rotki_db.db").unwrap();
-        let query = "SELECT
-                   decimals,
-                   symbol
-                FROM evm_tokens
-                JOIN common_asset_details ON evm_tokens.identifier = common_asset_details.identifier
-                WHERE
-                  lower(address) = lower(:address) AND
-                  chain = :chain";
-        let mut statement = connection.prepare(query).unwrap();
-
-        let res: Result<Token, rusqlite::Error> = statement.query_row(
-            named_params! {":address": address.full_string(),":chain": chain.id.unwrap().as_u64()},
-            |row| {
-                Ok(Token {
-                    decimals: row.get(0).unwrap(),
-                    symbol: row.get(1).unwrap(),
-                })
-            },
-        );
-
-        match res {
-            Ok(res_token) => res_token,
-            Err(_) => Token {
+        from_overrides(chain, address)
+            .or_else(|| asset_db::asset_db().token(chain, address))
+            .or_else(|| onchain_token(chain, address))
+            .unwrap_or(Token {
                 decimals: 18,
                 symbol: "UNK".to_owned(),
-            },
+            })
+    }
+}
+
+/// Metadata read from the contract of the token, if it was read and reported one
+pub(crate) fn onchain_token(chain: &Chain, address: Address) -> Option<Token> {
+    ONCHAIN_TOKENS
+        .lock()
+        .unwrap()
+        .get(&(chain.id.unwrap(), address))
+        .cloned()
+        .flatten()
+}
+
+/// Whether the metadata of the token was read from its contract, even if it reported none
+pub(crate) fn is_onchain_read(chain: &Chain, address: Address) -> bool {
+    ONCHAIN_TOKENS
+        .lock()
+        .unwrap()
+        .contains_key(&(chain.id.unwrap(), address))
+}
+
+/// Whether the contract of the token reported a symbol and decimals on any chain
+pub(crate) fn has_onchain_metadata(address: Address) -> bool {
+    ONCHAIN_TOKENS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|((_, token), metadata)| *token == address && metadata.is_some())
+}
+
+fn decode_symbol(symbol: &Bytes) -> Option<String> {
+    String::decode(symbol).ok().or_else(|| {
+        // Some old tokens (e.g. MKR) return the symbol as bytes32
+        (symbol.len() == 32)
+            .then(|| String::from_utf8(symbol.iter().cloned().filter(|b| *b != 0).collect()).ok())
+            .flatten()
+    })
+}
+
+/// Reads the symbol and decimals of the tokens neither overridden nor in the asset DB from their contracts,
+/// with all the calls in a single JSON-RPC batch
+#[tracing::instrument(skip_all)]
+pub async fn prefetch_tokens(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    tokens: Vec<Address>,
+) {
+    let chain_id = chain.id.unwrap();
+    let unread_tokens: Vec<Address> = {
+        let onchain_tokens = ONCHAIN_TOKENS.lock().unwrap();
+        tokens
+            .into_iter()
+            .collect::<HashSet<Address>>()
+            .into_iter()
+            .filter(|token| !onchain_tokens.contains_key(&(chain_id, *token)))
+            .collect()
+    };
+    // The OnChain asset DB reads the tokens read from their contracts, so without the lock
+    let mut missing_tokens: Vec<Address> = unread_tokens
+        .into_iter()
+        .filter(|token| from_overrides(chain, *token).is_none())
+        .filter(|token| asset_db::asset_db().token(chain, *token).is_none())
+        .collect();
+
+    if redis_backend::is_enabled() {
+        let mut uncached_tokens = vec![];
+        for token in missing_tokens {
+            let cached_token = redis_backend::get_cached(&token_cache_key(chain_id, token))
+                .await
+                .and_then(|cached| serde_json::from_str::<Option<Token>>(&cached).ok());
+            match cached_token {
+                Some(cached_token) => {
+                    ONCHAIN_TOKENS
+                        .lock()
+                        .unwrap()
+                        .insert((chain_id, token), cached_token);
+                }
+                None => uncached_tokens.push(token),
+            }
         }
+        missing_tokens = uncached_tokens;
     }
+
+    if missing_tokens.is_empty() {
+        return;
+    }
+
+    let params: Vec<serde_json::Value> = missing_tokens
+        .iter()
+        .flat_map(|token| {
+            [SYMBOL_SELECTOR, DECIMALS_SELECTOR]
+                .map(|selector| json!([{ "to": token, "data": selector }, "latest"]))
+        })
+        .collect();
+
+    let results = match batch::batch_request_each::<_, Bytes>(provider, "eth_call", &params).await {
+        Ok(results) => results,
+        Err(err) => {
+            error!("Error while getting {} token metadata: {}", chain.name, err);
+            return;
+        }
+    };
+
+    for (token, metadata) in missing_tokens.into_iter().zip(results.chunks(2)) {
+        let symbol = metadata[0].as_ref().ok().and_then(decode_symbol);
+        let decimals = metadata[1]
+            .as_ref()
+            .ok()
+            .and_then(|decimals| U256::decode(decimals).ok())
+            .filter(|decimals| *decimals <= U256::from(u8::MAX));
+
+        let onchain_token = match (symbol, decimals) {
+            (Some(symbol), Some(decimals)) => {
+                debug!("Got {} token {} from its contract", chain.name, symbol);
+                Some(Token {
+                    symbol,
+                    decimals: decimals.as_u32(),
+                })
+            }
+            _ => None,
+        };
+        if redis_backend::is_enabled() {
+            redis_backend::set_cached(
+                &token_cache_key(chain_id, token),
+                serde_json::to_string(&onchain_token).unwrap(),
+            )
+            .await;
+        }
+        ONCHAIN_TOKENS
+            .lock()
+            .unwrap()
+            .insert((chain_id, token), onchain_token);
+    }
+}
+
+fn token_cache_key(chain_id: U256, token: Address) -> String {
+    format!("token:{}:{}", chain_id, token.full_string())
 }
@@ -0,0 +1,227 @@
+use ethers::{
+    core::types::{Block, Transaction, TransactionReceipt, H256, U256, U64},
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::error;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use strum_macros::EnumString;
+
+use crate::{
+    batch,
+    chain::Chain,
+    four_byte,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    metrics_middleware::MetricsMiddleware,
+    template,
+    throttled_http::ThrottledHttp,
+};
+use crate::{scale_amount, UtcDateTime};
+
+#[derive(Debug, PartialEq, EnumString)]
+pub enum TransactionDetail {
+    Block,
+    Timestamp,
+    Fee,
+}
+
+lazy_static! {
+    /// Details of the transaction appended to the default notification messages
+    static ref NOTIFICATION_DETAILS: Vec<TransactionDetail> = env::var("NOTIFICATION_DETAILS")
+        .map(|details| {
+            details
+                .split(',')
+                .map(|detail| {
+                    TransactionDetail::from_str(detail.trim()).expect("Invalid NOTIFICATION_DETAILS")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+/// Whether the notification messages show the detail, through `NOTIFICATION_DETAILS` or the
+/// variables of the templates
+fn is_shown(detail: TransactionDetail) -> bool {
+    template::is_enabled() || NOTIFICATION_DETAILS.contains(&detail)
+}
+
+/// Gas paid by the transaction, in wei
+pub fn fee(receipt: &TransactionReceipt) -> Option<U256> {
+    Some(receipt.gas_used? * receipt.effective_gas_price?)
+}
+
+/// Gets the transactions by hash, leaving out the unavailable ones
+pub async fn fetch_transactions(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    hashes: HashSet<H256>,
+) -> HashMap<H256, Transaction> {
+    if hashes.is_empty() {
+        return HashMap::new();
+    }
+
+    let params: Vec<serde_json::Value> = hashes.iter().map(|hash| json!([hash])).collect();
+    match batch::batch_request::<_, Option<Transaction>>(
+        provider,
+        "eth_getTransactionByHash",
+        &params,
+    )
+    .await
+    {
+        Ok(transactions) => transactions
+            .into_iter()
+            .flatten()
+            .map(|transaction| (transaction.hash, transaction))
+            .collect(),
+        Err(err) => {
+            error!("Error while getting {} transactions: {}", chain.name, err);
+            HashMap::new()
+        }
+    }
+}
+
+/// Gets the block, timestamp and fee of the transactions found without them, such as the ones
+/// found through their logs, when the messages show them, and the function called by the
+/// unknown operations
+#[tracing::instrument(skip_all)]
+pub async fn fetch_missing(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: &mut [InterestingTransaction],
+) {
+    let missing_receipts: Vec<H256> = interesting_transactions
+        .iter()
+        .filter(|tx| {
+            (tx.block_number.is_none() && is_shown(TransactionDetail::Block))
+                || (tx.fee.is_none() && is_shown(TransactionDetail::Fee))
+        })
+        .map(|tx| tx.hash)
+        .collect::<HashSet<H256>>()
+        .into_iter()
+        .collect();
+
+    if !missing_receipts.is_empty() {
+        let params: Vec<serde_json::Value> =
+            missing_receipts.iter().map(|hash| json!([hash])).collect();
+        match batch::batch_request::<_, Option<TransactionReceipt>>(
+            provider,
+            "eth_getTransactionReceipt",
+            &params,
+        )
+        .await
+        {
+            Ok(receipts) => {
+                let receipts: HashMap<H256, TransactionReceipt> = receipts
+                    .into_iter()
+                    .flatten()
+                    .map(|receipt| (receipt.transaction_hash, receipt))
+                    .collect();
+                for tx in interesting_transactions.iter_mut() {
+                    if let Some(receipt) = receipts.get(&tx.hash) {
+                        tx.block_number = tx.block_number.or(receipt.block_number);
+                        tx.fee = tx.fee.or(fee(receipt));
+                    }
+                }
+            }
+            Err(err) => error!(
+                "Error while getting {} transaction receipts: {}",
+                chain.name, err
+            ),
+        }
+    }
+
+    let missing_blocks: Vec<U64> = interesting_transactions
+        .iter()
+        .filter(|tx| tx.timestamp.is_none() && is_shown(TransactionDetail::Timestamp))
+        .filter_map(|tx| tx.block_number)
+        .collect::<HashSet<U64>>()
+        .into_iter()
+        .collect();
+
+    if !missing_blocks.is_empty() {
+        let params: Vec<serde_json::Value> = missing_blocks
+            .iter()
+            .map(|block_number| json!([block_number, false]))
+            .collect();
+        match batch::batch_request::<_, Option<Block<H256>>>(
+            provider,
+            "eth_getBlockByNumber",
+            &params,
+        )
+        .await
+        {
+            Ok(blocks) => {
+                let timestamps: HashMap<U64, u64> = missing_blocks
+                    .into_iter()
+                    .zip(blocks)
+                    .filter_map(|(block_number, block)| {
+                        Some((block_number, block?.timestamp.as_u64()))
+                    })
+                    .collect();
+                for tx in interesting_transactions.iter_mut() {
+                    if let Some(block_number) = tx.block_number {
+                        tx.timestamp = tx.timestamp.or(timestamps.get(&block_number).copied());
+                    }
+                }
+            }
+            Err(err) => error!("Error while getting {} blocks: {}", chain.name, err),
+        }
+    }
+
+    // Operations decoded with an ABI are already described. The decoded from and to are kept,
+    // the transaction only fills the missing ones
+    let is_incomplete = |tx: &InterestingTransaction| {
+        tx.kind == InterestingTransactionKind::Other
+            && tx.event.is_none()
+            && (tx.function.is_none() || tx.from.is_none() || tx.to.is_none())
+    };
+    let unknown_operations: HashSet<H256> = interesting_transactions
+        .iter()
+        .filter(|tx| is_incomplete(tx))
+        .map(|tx| tx.hash)
+        .collect();
+    let transactions = fetch_transactions(provider, chain, unknown_operations).await;
+    for tx in interesting_transactions.iter_mut() {
+        if !is_incomplete(tx) {
+            continue;
+        }
+        if let Some(transaction) = transactions.get(&tx.hash) {
+            if tx.from.is_none() {
+                tx.from = Some(transaction.from);
+            }
+            if tx.to.is_none() {
+                tx.to = transaction.to;
+            }
+            if tx.function.is_none() {
+                tx.function = four_byte::lookup(&transaction.input).map(str::to_string);
+            }
+        }
+    }
+}
+
+/// The configured details of the transaction, e.g. `block 123, 2024-05-01 12:00:00 UTC`
+pub fn describe(chain: &Chain, tx: &InterestingTransaction) -> Option<String> {
+    let details: Vec<String> = NOTIFICATION_DETAILS
+        .iter()
+        .filter_map(|detail| match detail {
+            TransactionDetail::Block => tx
+                .block_number
+                .map(|block_number| format!("block {}", block_number)),
+            TransactionDetail::Timestamp => tx
+                .timestamp
+                .map(|timestamp| UtcDateTime::from_timestamp(timestamp).to_string()),
+            TransactionDetail::Fee => tx.fee.map(|fee| {
+                format!(
+                    "fee {} {}",
+                    scale_amount(fee, chain.native_decimals),
+                    chain.native_symbol
+                )
+            }),
+        })
+        .collect();
+
+    (!details.is_empty()).then(|| details.join(", "))
+}
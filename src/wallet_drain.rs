@@ -0,0 +1,302 @@
+use ethers::{
+    core::types::{Address, H256, U256, U64},
+    providers::Provider,
+};
+use lazy_static::lazy_static;
+use log::{error, warn};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+use crate::{
+    batch,
+    chain::Chain,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind, SpamFilter},
+    metrics_middleware::MetricsMiddleware,
+    throttled_http::ThrottledHttp,
+    token::{FromChainAddress, Token},
+    transaction_details,
+};
+use crate::{scale_amount, FullString, ToLabel};
+
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+
+lazy_static! {
+    /// Transactions sending out more distinct tokens than this are drains, `0` disables it
+    static ref DRAIN_MAX_TOKENS: usize = env::var("DRAIN_MAX_TOKENS")
+        .map(|max_tokens| max_tokens
+            .parse::<usize>()
+            .expect("Invalid DRAIN_MAX_TOKENS"))
+        .unwrap_or(3);
+    /// Transactions sending out more than this percentage of the balance of every token they
+    /// move are drains
+    static ref DRAIN_BALANCE_SHARE: Option<f64> = env::var("DRAIN_BALANCE_SHARE")
+        .ok()
+        .map(|balance_share| balance_share
+            .parse::<f64>()
+            .expect("Invalid DRAIN_BALANCE_SHARE"));
+}
+
+/// Transfer out of a watched account, of a token or the native one when `token` is `None`
+struct Outflow {
+    token: Option<Address>,
+    amount: Option<U256>,
+    to: Option<Address>,
+    kind_1155: bool,
+}
+
+/// Outflows of a watched account in a single transaction
+pub struct Drain {
+    account: Address,
+    block_number: Option<U64>,
+    outflows: Vec<Outflow>,
+}
+
+impl Drain {
+    fn token_amounts(&self) -> HashMap<Option<Address>, U256> {
+        let mut token_amounts: HashMap<Option<Address>, U256> = HashMap::new();
+        for outflow in self.outflows.iter() {
+            *token_amounts.entry(outflow.token).or_default() += outflow.amount.unwrap_or_default();
+        }
+        token_amounts
+    }
+
+    pub fn tokens(&self) -> Vec<Address> {
+        self.outflows
+            .iter()
+            .filter_map(|outflow| outflow.token)
+            .collect()
+    }
+
+    /// Summary of all the outflows, replacing the message of the transaction
+    pub fn message(&self, chain: &Chain, addressbook: &HashMap<String, String>) -> String {
+        let outflows: Vec<String> = self
+            .outflows
+            .iter()
+            .map(|outflow| {
+                let to = outflow
+                    .to
+                    .map_or("unknown".to_string(), |to| to.to_label(addressbook));
+                match (outflow.token, outflow.amount) {
+                    (None, Some(amount)) => {
+                        format!(
+                            "{} {} to {}",
+                            scale_amount(amount, chain.native_decimals),
+                            chain.native_symbol,
+                            to
+                        )
+                    }
+                    (Some(token), Some(amount)) if !outflow.kind_1155 => {
+                        let token = Token::from_chain_address(chain, token);
+                        format!(
+                            "{} {} to {}",
+                            scale_amount(amount, token.decimals),
+                            token.symbol,
+                            to
+                        )
+                    }
+                    (Some(token), _) => format!(
+                        "ERC1155 {} to {}",
+                        Token::from_chain_address(chain, token).symbol,
+                        to
+                    ),
+                    (None, None) => format!("{} to {}", chain.native_symbol, to),
+                }
+            })
+            .collect();
+
+        let tokens_count = self.token_amounts().len();
+        format!(
+            "⚠ Possible wallet drain of {} on {}, {} token{} sent out in one transaction:\n{}",
+            self.account.to_label(addressbook),
+            chain.name,
+            tokens_count,
+            if tokens_count == 1 { "" } else { "s" },
+            outflows.join("\n")
+        )
+    }
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+/// Percentage of the balance before the transaction sent out, for each token with an amount.
+/// The native share is the balance delta over the block, which also includes the native tokens
+/// moved by internal calls
+async fn balance_shares(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    drain: &Drain,
+) -> Option<Vec<f64>> {
+    let token_amounts = drain.token_amounts();
+    let has_native = token_amounts.contains_key(&None);
+    let tokens: Vec<(Address, U256)> = token_amounts
+        .into_iter()
+        .filter_map(|(token, amount)| Some((token?, amount)))
+        .filter(|(_, amount)| !amount.is_zero())
+        .collect();
+    // Balances right after the transaction's block
+    let block = drain
+        .block_number
+        .map_or(json!("latest"), |block_number| json!(block_number));
+
+    let token_params: Vec<serde_json::Value> = tokens
+        .iter()
+        .map(|(token, _)| {
+            json!([{
+                "to": token,
+                "data": format!("{}{:0>64}", BALANCE_OF_SELECTOR, &drain.account.full_string()[2..])
+            }, block])
+        })
+        .collect();
+    let balances = match batch::batch_request::<_, U256>(provider, "eth_call", &token_params).await
+    {
+        Ok(balances) => balances,
+        Err(err) => {
+            error!("Error while getting {} token balances: {}", chain.name, err);
+            return None;
+        }
+    };
+    let mut shares: Vec<f64> = tokens
+        .iter()
+        .zip(balances)
+        .map(|((_, amount), remaining)| {
+            let amount = to_f64(*amount);
+            amount / (amount + to_f64(remaining)) * 100.0
+        })
+        .collect();
+
+    if let (true, Some(block_number)) = (has_native, drain.block_number) {
+        let params = [
+            json!([drain.account, block_number - 1]),
+            json!([drain.account, block_number]),
+        ];
+        match batch::batch_request::<_, U256>(provider, "eth_getBalance", &params).await {
+            Ok(native_balances) => {
+                let (before, after) = (native_balances[0], native_balances[1]);
+                if !before.is_zero() {
+                    shares.push(to_f64(before.saturating_sub(after)) / to_f64(before) * 100.0);
+                }
+            }
+            Err(err) => {
+                error!("Error while getting {} balance: {}", chain.name, err);
+                return None;
+            }
+        }
+    }
+
+    Some(shares)
+}
+
+/// Native tokens sent by the transactions of the watched accounts, which are missing from the
+/// outflows of the transactions found through their token transfers, and have no amount when
+/// found through their receipts
+async fn add_native_outflows(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    candidates: &mut HashMap<(H256, Address), Drain>,
+) {
+    let hashes: HashSet<H256> = candidates.keys().map(|(hash, _)| *hash).collect();
+    let transactions = transaction_details::fetch_transactions(provider, chain, hashes).await;
+
+    for ((hash, account), drain) in candidates.iter_mut() {
+        let Some(transaction) = transactions
+            .get(hash)
+            .filter(|transaction| transaction.from == *account && !transaction.value.is_zero())
+        else {
+            continue;
+        };
+        match drain
+            .outflows
+            .iter_mut()
+            .find(|outflow| outflow.token.is_none())
+        {
+            Some(outflow) => {
+                outflow.amount.get_or_insert(transaction.value);
+            }
+            None => drain.outflows.push(Outflow {
+                token: None,
+                amount: Some(transaction.value),
+                to: transaction.to,
+                kind_1155: false,
+            }),
+        }
+    }
+}
+
+/// Transactions moving more than `DRAIN_MAX_TOKENS` distinct tokens, or more than
+/// `DRAIN_BALANCE_SHARE` of the balance of every token they move, out of a watched account. Each
+/// drained account of a transaction is a separate drain
+pub async fn detect(
+    provider: &MetricsMiddleware<Provider<ThrottledHttp>>,
+    chain: &Chain,
+    interesting_transactions: &[InterestingTransaction],
+) -> HashMap<(H256, Address), Drain> {
+    if *DRAIN_MAX_TOKENS == 0 && DRAIN_BALANCE_SHARE.is_none() {
+        return HashMap::new();
+    }
+
+    let mut candidates: HashMap<(H256, Address), Drain> = HashMap::new();
+    for tx in interesting_transactions.iter().filter(|tx| {
+        // Other transactions sent by the account only move its native tokens
+        matches!(
+            tx.kind,
+            InterestingTransactionKind::Send
+                | InterestingTransactionKind::Transfer
+                | InterestingTransactionKind::Transfer1155
+                | InterestingTransactionKind::Other
+        ) && tx.from == Some(tx.involved_account)
+            && !tx.is_spam(&chain.spam_filter_level)
+    }) {
+        let drain = candidates
+            .entry((tx.hash, tx.involved_account))
+            .or_insert(Drain {
+                account: tx.involved_account,
+                block_number: tx.block_number,
+                outflows: vec![],
+            });
+        if tx.kind != InterestingTransactionKind::Other {
+            drain.outflows.push(Outflow {
+                token: tx.token,
+                amount: tx.amount,
+                to: tx.to,
+                kind_1155: tx.kind == InterestingTransactionKind::Transfer1155,
+            });
+        }
+    }
+    if candidates.is_empty() {
+        return HashMap::new();
+    }
+    add_native_outflows(provider, chain, &mut candidates).await;
+    candidates.retain(|_, drain| !drain.outflows.is_empty());
+
+    let mut drains = HashMap::new();
+    for ((hash, account), drain) in candidates {
+        let tokens_count = drain.token_amounts().len();
+        let is_drain = if *DRAIN_MAX_TOKENS > 0 && tokens_count > *DRAIN_MAX_TOKENS {
+            true
+        } else if let Some(balance_share) = *DRAIN_BALANCE_SHARE {
+            balance_shares(provider, chain, &drain)
+                .await
+                .is_some_and(|shares| {
+                    !shares.is_empty() && shares.iter().all(|share| *share > balance_share)
+                })
+        } else {
+            false
+        };
+
+        if is_drain {
+            warn!(
+                "Possible wallet drain {} of {} token(s) of {} on {}",
+                hash.full_string(),
+                tokens_count,
+                account.full_string(),
+                chain.name
+            );
+            drains.insert((hash, account), drain);
+        }
+    }
+
+    drains
+}
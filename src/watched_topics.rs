@@ -0,0 +1,47 @@
+use ethers::core::types::{Address, H256};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Watched accounts encoded as log topics, cached by each chain task and only rebuilt when an
+/// update is pushed after the addressbook changes.
+pub struct WatchedTopics {
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+    updates: watch::Receiver<()>,
+    topics: HashSet<H256>,
+}
+
+impl WatchedTopics {
+    pub fn new(
+        addressbook: Arc<Mutex<HashMap<String, String>>>,
+        mut updates: watch::Receiver<()>,
+    ) -> WatchedTopics {
+        updates.borrow_and_update();
+        let topics = build_topics(&addressbook);
+
+        WatchedTopics {
+            addressbook,
+            updates,
+            topics,
+        }
+    }
+
+    pub fn current(&mut self) -> &HashSet<H256> {
+        if self.updates.has_changed().unwrap_or(false) {
+            self.updates.borrow_and_update();
+            self.topics = build_topics(&self.addressbook);
+        }
+
+        &self.topics
+    }
+}
+
+fn build_topics(addressbook: &Arc<Mutex<HashMap<String, String>>>) -> HashSet<H256> {
+    addressbook
+        .lock()
+        .unwrap()
+        .keys()
+        .map(|addr| H256::from(Address::from_str(addr).unwrap()))
+        .collect()
+}
@@ -0,0 +1,174 @@
+use ethers::core::{
+    types::{Address, TransactionReceipt, H256, U256, U64},
+    utils::hex,
+};
+use hmac::{Hmac, Mac};
+use serde_derive::Deserialize as DeserializeMacro;
+use sha2::Sha256;
+use std::collections::HashSet;
+use warp::http::HeaderMap;
+
+use crate::interesting_transaction::{InterestingTransaction, InterestingTransactionKind};
+
+#[derive(Debug, DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct AlchemyRawContract {
+    raw_value: Option<String>,
+    address: Option<Address>,
+}
+
+#[derive(Debug, DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct AlchemyErc1155Metadata {
+    token_id: U256,
+}
+
+#[derive(Debug, DeserializeMacro)]
+#[serde(rename_all = "camelCase")]
+struct AlchemyActivity {
+    from_address: Address,
+    to_address: Option<Address>,
+    hash: H256,
+    block_num: Option<U64>,
+    category: String,
+    raw_contract: AlchemyRawContract,
+    erc721_token_id: Option<U256>,
+    erc1155_metadata: Option<Vec<AlchemyErc1155Metadata>>,
+}
+
+#[derive(Debug, DeserializeMacro)]
+struct AlchemyEvent {
+    activity: Vec<AlchemyActivity>,
+}
+
+#[derive(Debug, DeserializeMacro)]
+pub struct AlchemyAddressActivity {
+    event: AlchemyEvent,
+}
+
+/// Payloads accepted by the webhook endpoint of chains in `Push` mode
+#[derive(Debug, DeserializeMacro)]
+#[serde(untagged)]
+pub enum WebhookPayload {
+    /// Alchemy Address Activity webhook
+    Alchemy(AlchemyAddressActivity),
+    /// QuickNode QuickAlert delivering the receipts of the matching transactions
+    QuickNode(Vec<TransactionReceipt>),
+}
+
+impl WebhookPayload {
+    /// Blocks the payload delivers the activity of
+    pub fn block_count(&self) -> usize {
+        let block_numbers: HashSet<U64> = match self {
+            WebhookPayload::Alchemy(address_activity) => address_activity
+                .event
+                .activity
+                .iter()
+                .filter_map(|activity| activity.block_num)
+                .collect(),
+            WebhookPayload::QuickNode(receipts) => receipts
+                .iter()
+                .filter_map(|receipt| receipt.block_number)
+                .collect(),
+        };
+        block_numbers.len()
+    }
+}
+
+/// Checks the payload was signed with the chain's webhook secret, as Alchemy (`X-Alchemy-Signature`)
+/// or QuickNode (`X-QN-Signature`, over the nonce and timestamp followed by the body) do
+pub fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+
+    let signature = if let Some(signature) = header("x-alchemy-signature") {
+        signature
+    } else if let (Some(signature), Some(nonce), Some(timestamp)) = (
+        header("x-qn-signature"),
+        header("x-qn-nonce"),
+        header("x-qn-timestamp"),
+    ) {
+        mac.update(nonce.as_bytes());
+        mac.update(timestamp.as_bytes());
+        signature
+    } else {
+        return false;
+    };
+    mac.update(body);
+
+    hex::decode(signature.trim_start_matches("0x"))
+        .is_ok_and(|signature| mac.verify_slice(&signature).is_ok())
+}
+
+impl AlchemyActivity {
+    fn into_interesting_transaction(
+        self,
+        watched_addresses_as_topics: &HashSet<H256>,
+    ) -> Option<InterestingTransaction> {
+        let is_watched =
+            |address: &Address| watched_addresses_as_topics.contains(&H256::from(*address));
+        let involved_account = if is_watched(&self.from_address) {
+            self.from_address
+        } else {
+            self.to_address.filter(is_watched)?
+        };
+
+        let kind = match self.category.as_str() {
+            "external" | "internal" => InterestingTransactionKind::Send,
+            "token" | "erc20" | "erc721" => InterestingTransactionKind::Transfer,
+            "erc1155" => InterestingTransactionKind::Transfer1155,
+            _ => InterestingTransactionKind::Other,
+        };
+        // Not set or just "0x" for some categories
+        let amount = self.raw_contract.raw_value.and_then(|raw_value| {
+            U256::from_str_radix(raw_value.trim_start_matches("0x"), 16).ok()
+        });
+        let token = match kind {
+            InterestingTransactionKind::Transfer | InterestingTransactionKind::Transfer1155 => {
+                Some(self.raw_contract.address?)
+            }
+            _ => None,
+        };
+
+        Some(InterestingTransaction {
+            hash: self.hash,
+            from: Some(self.from_address),
+            to: self.to_address,
+            amount: match kind {
+                InterestingTransactionKind::Transfer => Some(amount.unwrap_or_default()),
+                _ => amount,
+            },
+            kind,
+            token,
+            token_id: self.erc721_token_id.or_else(|| {
+                self.erc1155_metadata?
+                    .first()
+                    .map(|metadata| metadata.token_id)
+            }),
+            involved_account,
+            event: None,
+            custom_event: None,
+            function: None,
+            block_number: self.block_num,
+            timestamp: None,
+            fee: None,
+        })
+    }
+}
+
+impl AlchemyAddressActivity {
+    pub fn interesting_transactions(
+        self,
+        watched_addresses_as_topics: &HashSet<H256>,
+    ) -> Vec<InterestingTransaction> {
+        self.event
+            .activity
+            .into_iter()
+            .filter_map(|activity| {
+                activity.into_interesting_transaction(watched_addresses_as_topics)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,531 @@
+use ethers::core::{
+    abi::{AbiDecode, Token as AbiToken},
+    types::{Address, Bytes, Log, H256, U256},
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use rusqlite::named_params;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    abi_registry::DecodedEvent,
+    chain::Chain,
+    decoding::{self, LogDecoder},
+    escalation::Severity,
+    interesting_transaction::{InterestingTransaction, InterestingTransactionKind},
+    notification::{Notification, Sendable},
+    provider::connect_and_verify,
+    schedule::{self, ScheduledEvent},
+    state_db::CONNECTION,
+    tenants,
+    token::{self, FromChainAddress, Token},
+};
+use crate::{scale_amount, FullString, ToLabel, UtcDateTime};
+
+const CLAIM_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Seconds after its initiation a withdrawal from an optimistic rollup can be claimed on L1,
+    /// unless the chain sets its own. Withdrawals are only followed when set
+    pub static ref WITHDRAWAL_CHALLENGE_PERIOD: Option<Duration> =
+        env::var("WITHDRAWAL_CHALLENGE_PERIOD").ok().map(|period| {
+            Duration::from_secs(
+                period
+                    .parse::<u64>()
+                    .expect("Invalid WITHDRAWAL_CHALLENGE_PERIOD"),
+            )
+        });
+    /// OP Stack `L2StandardBridge` and `L2ToL1MessagePasser` predeploys
+    static ref OP_L2_STANDARD_BRIDGE: Address =
+        Address::from_str("0x4200000000000000000000000000000000000010").unwrap();
+    static ref OP_L2_TO_L1_MESSAGE_PASSER: Address =
+        Address::from_str("0x4200000000000000000000000000000000000016").unwrap();
+    /// Token of the native withdrawals of the OP Stack bridge
+    static ref OP_LEGACY_ERC20_ETH: Address =
+        Address::from_str("0xDeadDeAddeAddEAddeadDEaDDEAdDeaDDeAD0000").unwrap();
+    /// Arbitrum `ArbSys` precompile
+    static ref ARB_SYS: Address =
+        Address::from_str("0x0000000000000000000000000000000000000064").unwrap();
+    /// Withdrawals waiting for their challenge period to end
+    static ref WITHDRAWALS: Mutex<Vec<Withdrawal>> = Mutex::new(vec![]);
+}
+
+pub fn is_enabled() -> bool {
+    WITHDRAWAL_CHALLENGE_PERIOD.is_some()
+}
+
+/// Withdrawal initiated by a watched account, claimable on L1 once its challenge period ends
+struct Withdrawal {
+    chain: String,
+    hash: H256,
+    account: Address,
+    /// Withdrawn token on the L2, `None` for native withdrawals or when unknown
+    token: Option<Address>,
+    amount: U256,
+    /// Unix timestamp
+    claimable_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn withdrawal_transaction(
+    log: &Log,
+    event: DecodedEvent,
+    involved_account: Address,
+    recipient: Address,
+    token: Option<Address>,
+    amount: U256,
+) -> Option<InterestingTransaction> {
+    Some(InterestingTransaction {
+        hash: log.transaction_hash?,
+        from: Some(involved_account),
+        to: Some(recipient),
+        kind: InterestingTransactionKind::Other,
+        amount: Some(amount),
+        token,
+        token_id: None,
+        involved_account,
+        event: Some(event),
+        custom_event: None,
+        function: None,
+        block_number: log.block_number,
+        timestamp: None,
+        fee: None,
+    })
+}
+
+/// `WithdrawalInitiated` of the OP Stack L2 bridge, for native and ERC20 withdrawals
+struct OpWithdrawalInitiated;
+
+impl LogDecoder for OpWithdrawalInitiated {
+    fn name(&self) -> &str {
+        "WithdrawalInitiated"
+    }
+
+    fn topic(&self) -> H256 {
+        H256::from_str("0x73d170910aba9e6d50b102db522b1dbcd796216f5128b445aa2135272886497e")
+            .unwrap()
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let from = Address::from(*log.topics.get(3)?);
+        if log.address != *OP_L2_STANDARD_BRIDGE || from != involved_account {
+            return None;
+        }
+        let l1_token = Address::from(*log.topics.get(1)?);
+        let l2_token = Address::from(*log.topics.get(2)?);
+        let (to, amount, _) = <(Address, U256, Bytes)>::decode(&log.data).ok()?;
+        let event = DecodedEvent {
+            contract: log.address,
+            name: self.name().to_string(),
+            params: vec![
+                ("l1Token".to_string(), AbiToken::Address(l1_token)),
+                ("l2Token".to_string(), AbiToken::Address(l2_token)),
+                ("from".to_string(), AbiToken::Address(from)),
+                ("to".to_string(), AbiToken::Address(to)),
+                ("amount".to_string(), AbiToken::Uint(amount)),
+            ],
+        };
+        let token = Some(l2_token).filter(|l2_token| *l2_token != *OP_LEGACY_ERC20_ETH);
+        withdrawal_transaction(log, event, from, to, token, amount)
+    }
+}
+
+/// `MessagePassed` of the OP Stack message passer, for native withdrawals sent to it directly
+struct OpMessagePassed;
+
+impl LogDecoder for OpMessagePassed {
+    fn name(&self) -> &str {
+        "MessagePassed"
+    }
+
+    fn topic(&self) -> H256 {
+        H256::from_str("0x02a52367d10742d8032712c1bb8e0144ff1ec5ffda1ed7d70bb05a2744955054")
+            .unwrap()
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let sender = Address::from(*log.topics.get(2)?);
+        if log.address != *OP_L2_TO_L1_MESSAGE_PASSER || sender != involved_account {
+            return None;
+        }
+        let target = Address::from(*log.topics.get(3)?);
+        let (value, _, _, withdrawal_hash) = <(U256, U256, Bytes, H256)>::decode(&log.data).ok()?;
+        let event = DecodedEvent {
+            contract: log.address,
+            name: self.name().to_string(),
+            params: vec![
+                ("sender".to_string(), AbiToken::Address(sender)),
+                ("target".to_string(), AbiToken::Address(target)),
+                ("value".to_string(), AbiToken::Uint(value)),
+                (
+                    "withdrawalHash".to_string(),
+                    AbiToken::FixedBytes(withdrawal_hash.as_bytes().to_vec()),
+                ),
+            ],
+        };
+        withdrawal_transaction(log, event, sender, target, None, value)
+    }
+}
+
+/// `WithdrawalInitiated` of the Arbitrum token gateways, holding the token on L1
+struct ArbitrumWithdrawalInitiated;
+
+impl LogDecoder for ArbitrumWithdrawalInitiated {
+    fn name(&self) -> &str {
+        "WithdrawalInitiated"
+    }
+
+    fn topic(&self) -> H256 {
+        H256::from_str("0x3073a74ecb728d10be779fe19a74a1428e20468f5b4d167bf9c73d9067847d73")
+            .unwrap()
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let from = Address::from(*log.topics.get(1)?);
+        if from != involved_account {
+            return None;
+        }
+        let to = Address::from(*log.topics.get(2)?);
+        let (l1_token, _, amount) = <(Address, U256, U256)>::decode(&log.data).ok()?;
+        let event = DecodedEvent {
+            contract: log.address,
+            name: self.name().to_string(),
+            params: vec![
+                ("l1Token".to_string(), AbiToken::Address(l1_token)),
+                ("from".to_string(), AbiToken::Address(from)),
+                ("to".to_string(), AbiToken::Address(to)),
+                ("amount".to_string(), AbiToken::Uint(amount)),
+            ],
+        };
+        withdrawal_transaction(log, event, from, to, None, amount)
+    }
+}
+
+/// `L2ToL1Tx` of the Arbitrum `ArbSys` precompile, for native withdrawals
+struct ArbitrumL2ToL1Tx;
+
+impl LogDecoder for ArbitrumL2ToL1Tx {
+    fn name(&self) -> &str {
+        "L2ToL1Tx"
+    }
+
+    fn topic(&self) -> H256 {
+        H256::from_str("0x3e7aafa77dbf186b7fd488006beff893744caa3c4f6f299e8a709fa2087374fc")
+            .unwrap()
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        let destination = Address::from(*log.topics.get(1)?);
+        if log.address != *ARB_SYS || destination != involved_account {
+            return None;
+        }
+        let (caller, _, _, _, callvalue, _) =
+            <(Address, U256, U256, U256, U256, Bytes)>::decode(&log.data).ok()?;
+        let event = DecodedEvent {
+            contract: log.address,
+            name: self.name().to_string(),
+            params: vec![
+                ("caller".to_string(), AbiToken::Address(caller)),
+                ("destination".to_string(), AbiToken::Address(destination)),
+                ("callvalue".to_string(), AbiToken::Uint(callvalue)),
+            ],
+        };
+        withdrawal_transaction(log, event, destination, destination, None, callvalue)
+    }
+}
+
+/// Registers the decoders of the withdrawal events and loads the withdrawals not claimable yet,
+/// failing on startup when a decoder conflicts with a custom event
+pub fn load() {
+    if !is_enabled() {
+        return;
+    }
+    let decoders: [Box<dyn LogDecoder>; 4] = [
+        Box::new(OpWithdrawalInitiated),
+        Box::new(OpMessagePassed),
+        Box::new(ArbitrumWithdrawalInitiated),
+        Box::new(ArbitrumL2ToL1Tx),
+    ];
+    for decoder in decoders {
+        if let Err(err) = decoding::register_decoder(decoder) {
+            panic!("Could not register the withdrawal decoders: {}", err);
+        }
+    }
+
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+    let rows = connection
+        .lock()
+        .unwrap()
+        .prepare("SELECT chain, hash, account, token, amount, claimable_at FROM withdrawals")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, u64>(5)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Error while reading the pending withdrawals: {}", err);
+            return;
+        }
+    };
+
+    let mut withdrawals = WITHDRAWALS.lock().unwrap();
+    for (chain, hash, account, token, amount, claimable_at) in rows {
+        let (Ok(hash), Ok(account), Ok(amount)) = (
+            H256::from_str(&hash),
+            Address::from_str(&account),
+            U256::from_dec_str(&amount),
+        ) else {
+            error!("Invalid stored withdrawal {}", hash);
+            continue;
+        };
+        let withdrawal = Withdrawal {
+            chain,
+            hash,
+            account,
+            token: token.and_then(|token| Address::from_str(&token).ok()),
+            amount,
+            claimable_at,
+        };
+        schedule(&withdrawal, None);
+        withdrawals.push(withdrawal);
+    }
+    info!("Loaded {} pending withdrawals", withdrawals.len());
+}
+
+/// Records the withdrawals initiated by the watched accounts, to remind of them once claimable.
+/// Arbitrum gateways only name the L1 token, so the L2 one is read from the burn of the same
+/// transaction
+pub fn record(chain: &Chain, interesting_transactions: &[InterestingTransaction]) {
+    if !is_enabled() {
+        return;
+    }
+    let challenge_period = chain
+        .withdrawal_challenge_period
+        .or(*WITHDRAWAL_CHALLENGE_PERIOD)
+        .unwrap();
+
+    for tx in interesting_transactions {
+        // Events of the loaded ABIs sharing a name have no sender
+        let (Some(event), Some(from), Some(amount)) = (&tx.event, tx.from, tx.amount) else {
+            continue;
+        };
+        let withdrawal_event = match (event.name.as_str(), event.contract) {
+            ("WithdrawalInitiated", _) | ("L2ToL1Tx", _) => true,
+            ("MessagePassed", contract) => contract == *OP_L2_TO_L1_MESSAGE_PASSER,
+            _ => false,
+        };
+        if !withdrawal_event || from != tx.involved_account {
+            continue;
+        }
+
+        let is_arbitrum_gateway =
+            event.name == "WithdrawalInitiated" && event.contract != *OP_L2_STANDARD_BRIDGE;
+        let token = tx.token.or_else(|| {
+            interesting_transactions
+                .iter()
+                .find(|burn| {
+                    is_arbitrum_gateway
+                        && burn.hash == tx.hash
+                        && burn.kind == InterestingTransactionKind::Transfer
+                        && burn.from == Some(from)
+                        && burn.to.is_some_and(|to| to.is_zero())
+                })
+                .and_then(|burn| burn.token)
+        });
+        let withdrawal = Withdrawal {
+            chain: chain.name.clone(),
+            hash: tx.hash,
+            account: from,
+            token,
+            amount,
+            claimable_at: now() + challenge_period.as_secs(),
+        };
+
+        let mut withdrawals = WITHDRAWALS.lock().unwrap();
+        if withdrawals.iter().any(|recorded| {
+            recorded.chain == withdrawal.chain
+                && recorded.hash == withdrawal.hash
+                && recorded.account == withdrawal.account
+        }) {
+            continue;
+        }
+        info!(
+            "Withdrawal {} of {} on {} claimable on {}",
+            tx.hash.full_string(),
+            from.full_string(),
+            chain.name,
+            UtcDateTime::from_timestamp(withdrawal.claimable_at)
+        );
+        store(&withdrawal);
+        schedule(&withdrawal, chain.tx_url(withdrawal.hash));
+        withdrawals.push(withdrawal);
+    }
+}
+
+/// Adds the end of the challenge period to the calendar feed
+fn schedule(withdrawal: &Withdrawal, url: Option<String>) {
+    schedule::schedule_event(ScheduledEvent {
+        uid: format!(
+            "withdrawal-{}-{}-{}",
+            withdrawal.chain.to_lowercase(),
+            withdrawal.hash.full_string(),
+            withdrawal.account.full_string()
+        ),
+        title: format!("{} withdrawal claimable", withdrawal.chain),
+        description: Some(format!(
+            "Withdrawal {} of {} can be claimed on L1",
+            withdrawal.hash.full_string(),
+            withdrawal.account.full_string()
+        )),
+        url,
+        at: withdrawal.claimable_at,
+    });
+}
+
+fn store(withdrawal: &Withdrawal) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "INSERT OR IGNORE INTO withdrawals (chain, hash, account, token, amount, claimable_at)
+        VALUES (:chain, :hash, :account, :token, :amount, :claimable_at)",
+        named_params! {
+            ":chain": withdrawal.chain,
+            ":hash": withdrawal.hash.full_string(),
+            ":account": withdrawal.account.full_string(),
+            ":token": withdrawal.token.map(|token| token.full_string()),
+            ":amount": withdrawal.amount.to_string(),
+            ":claimable_at": withdrawal.claimable_at,
+        },
+    ) {
+        error!(
+            "Error while storing withdrawal {}: {}",
+            withdrawal.hash.full_string(),
+            err
+        );
+    }
+}
+
+fn remove(withdrawal: &Withdrawal) {
+    let Some(connection) = CONNECTION.as_ref() else {
+        return;
+    };
+
+    if let Err(err) = connection.lock().unwrap().execute(
+        "DELETE FROM withdrawals WHERE chain = :chain AND hash = :hash AND account = :account",
+        named_params! {
+            ":chain": withdrawal.chain,
+            ":hash": withdrawal.hash.full_string(),
+            ":account": withdrawal.account.full_string(),
+        },
+    ) {
+        error!(
+            "Error while removing withdrawal {}: {}",
+            withdrawal.hash.full_string(),
+            err
+        );
+    }
+}
+
+/// Reminds of the withdrawals of the chain once their challenge period has ended, to be claimed
+/// on L1
+pub async fn remind_claimable_withdrawals(
+    chain: Chain,
+    addressbook: Arc<Mutex<HashMap<String, String>>>,
+) {
+    let (chain, provider) = connect_and_verify(chain).await;
+
+    loop {
+        sleep(CLAIM_CHECK_INTERVAL).await;
+
+        let claimable: Vec<Withdrawal> = {
+            let mut withdrawals = WITHDRAWALS.lock().unwrap();
+            let (claimable, pending) = withdrawals.drain(..).partition(|withdrawal| {
+                withdrawal.chain == chain.name && withdrawal.claimable_at <= now()
+            });
+            *withdrawals = pending;
+            claimable
+        };
+        if claimable.is_empty() {
+            continue;
+        }
+        token::prefetch_tokens(
+            &provider,
+            &chain,
+            claimable
+                .iter()
+                .filter_map(|withdrawal| withdrawal.token)
+                .collect(),
+        )
+        .await;
+
+        for withdrawal in claimable {
+            let withdrawn = match withdrawal.token {
+                Some(token) => {
+                    let token = Token::from_chain_address(&chain, token);
+                    format!(
+                        "{} {}",
+                        scale_amount(withdrawal.amount, token.decimals),
+                        token.symbol
+                    )
+                }
+                None => format!(
+                    "{} {}",
+                    scale_amount(withdrawal.amount, chain.native_decimals),
+                    chain.native_symbol
+                ),
+            };
+            info!(
+                "Withdrawal {} of {} on {} is claimable",
+                withdrawal.hash.full_string(),
+                withdrawal.account.full_string(),
+                chain.name
+            );
+            let notification = Notification {
+                message: format!(
+                    "Withdrawal of {} by {} from {} can now be claimed on L1",
+                    withdrawn,
+                    withdrawal.account.to_label(&addressbook.lock().unwrap()),
+                    chain.name
+                ),
+                url: chain.tx_url(withdrawal.hash),
+                severity: Some(Severity::Normal),
+                route: tenants::route_of(&withdrawal.account),
+                image: None,
+                links: vec![],
+            };
+            // Reminded again on the next check until sent
+            if notification.send().await.is_err() {
+                error!("Error while sending withdrawal notification");
+                WITHDRAWALS.lock().unwrap().push(withdrawal);
+            } else {
+                remove(&withdrawal);
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use account_monitor::{replay_fixtures, ChainDefinition};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn replays_the_committed_fixtures() {
+    // Read once by their lazy statics, before anything is replayed. This is the only test of this
+    // binary, so no other test can read them first
+    std::env::set_var("ASSET_DB", "OnChain");
+    std::env::set_var(
+        "TOKEN_OVERRIDES_PATH",
+        "tests/fixtures/token_overrides.yaml",
+    );
+
+    let chain: ChainDefinition = serde_json::from_value(json!({
+        "name": "Gnosis",
+        "rpc": "http://127.0.0.1:8545",
+        "spam_filter_level": "None",
+    }))
+    .unwrap();
+    let addressbook = Arc::new(Mutex::new(HashMap::from([(
+        "0x3333333333333333333333333333333333333333".to_string(),
+        "Me".to_string(),
+    )])));
+
+    let differences = replay_fixtures(
+        &[chain.into_chain().unwrap()],
+        addressbook,
+        Path::new("tests/fixtures"),
+    );
+    assert!(differences.is_empty(), "{:#?}", differences);
+}
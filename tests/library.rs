@@ -0,0 +1,202 @@
+use account_monitor::{
+    build_notifications, notified_transactions, parse_logs, process_block, register_decoder,
+    ChainDefinition, InterestingTransaction, InterestingTransactionKind, LogDecoder, Monitor,
+    WatchedAccount,
+};
+use ethers::core::types::{Address, Bytes, Log, TransactionReceipt, H256, U256};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+const TREASURY: &str = "0x3333333333333333333333333333333333333333";
+const ALICE: &str = "0x5555555555555555555555555555555555555555";
+const TOKEN: &str = "0x2222222222222222222222222222222222222222";
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+fn address(address: &str) -> Address {
+    Address::from_str(address).unwrap()
+}
+
+fn watched_topics(accounts: &[&str]) -> HashSet<H256> {
+    accounts
+        .iter()
+        .map(|account| H256::from(address(account)))
+        .collect()
+}
+
+/// Gnosis on a local RPC with the fields of `definition` on top
+fn chain_definition(definition: Value) -> ChainDefinition {
+    let mut fields = json!({
+        "name": "Gnosis",
+        "rpc": "http://127.0.0.1:8545",
+        "blocktime": 5000,
+    });
+    fields
+        .as_object_mut()
+        .unwrap()
+        .extend(definition.as_object().unwrap().clone());
+    serde_json::from_value(fields).unwrap()
+}
+
+fn token_transfer(from: &str, to: &str, amount: u64) -> Log {
+    Log {
+        address: address(TOKEN),
+        topics: vec![
+            H256::from_str(TRANSFER_TOPIC).unwrap(),
+            H256::from(address(from)),
+            H256::from(address(to)),
+        ],
+        data: Bytes::from(H256::from_low_u64_be(amount).as_bytes().to_vec()),
+        transaction_hash: Some(H256::repeat_byte(0x55)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn parses_the_token_transfers_of_watched_accounts() {
+    let logs = vec![
+        token_transfer(ALICE, TREASURY, 1000),
+        token_transfer(ALICE, TOKEN, 2000),
+    ];
+
+    let interesting_transactions = parse_logs(&logs, &watched_topics(&[TREASURY]));
+    assert_eq!(interesting_transactions.len(), 1);
+    let tx = &interesting_transactions[0];
+    assert_eq!(tx.kind, InterestingTransactionKind::Transfer);
+    assert_eq!(tx.involved_account, address(TREASURY));
+    assert_eq!(tx.from, Some(address(ALICE)));
+    assert_eq!(tx.token, Some(address(TOKEN)));
+    assert_eq!(tx.amount, Some(U256::from(1000)));
+}
+
+#[test]
+fn processes_the_native_sends_of_watched_accounts_with_their_fee() {
+    let receipt = TransactionReceipt {
+        transaction_hash: H256::repeat_byte(0x66),
+        from: address(TREASURY),
+        to: Some(address(ALICE)),
+        gas_used: Some(U256::from(21000)),
+        effective_gas_price: Some(U256::from(2)),
+        ..Default::default()
+    };
+
+    let interesting_transactions = process_block(&[receipt], &watched_topics(&[TREASURY]));
+    assert_eq!(interesting_transactions.len(), 1);
+    let tx = &interesting_transactions[0];
+    assert_eq!(tx.kind, InterestingTransactionKind::Send);
+    assert_eq!(tx.involved_account, address(TREASURY));
+    assert_eq!(tx.fee, Some(U256::from(42000)));
+}
+
+struct PingDecoder;
+
+impl LogDecoder for PingDecoder {
+    fn name(&self) -> &str {
+        "Ping"
+    }
+
+    fn topic(&self) -> H256 {
+        H256::repeat_byte(0x77)
+    }
+
+    fn decode(&self, log: &Log, involved_account: Address) -> Option<InterestingTransaction> {
+        Some(InterestingTransaction {
+            to: Some(log.address),
+            custom_event: Some("Ping".to_string()),
+            ..InterestingTransaction::new(
+                log.transaction_hash?,
+                InterestingTransactionKind::Other,
+                involved_account,
+            )
+        })
+    }
+}
+
+#[test]
+fn decodes_the_events_of_registered_decoders() {
+    register_decoder(Box::new(PingDecoder)).unwrap();
+    assert!(register_decoder(Box::new(PingDecoder)).is_err());
+
+    let log = Log {
+        address: address(TOKEN),
+        topics: vec![H256::repeat_byte(0x77), H256::from(address(TREASURY))],
+        transaction_hash: Some(H256::repeat_byte(0x88)),
+        ..Default::default()
+    };
+    let interesting_transactions = parse_logs(&[log], &watched_topics(&[TREASURY]));
+    assert_eq!(interesting_transactions.len(), 1);
+    assert_eq!(
+        interesting_transactions[0].custom_event.as_deref(),
+        Some("Ping")
+    );
+}
+
+#[test]
+fn notifies_the_transactions_with_the_labels_of_the_watched_accounts() {
+    let monitor = Monitor::new();
+    let watched_account = |address: &str, label: &str| WatchedAccount {
+        address: address.to_string(),
+        label: label.to_string(),
+        webhook: None,
+        direction: Default::default(),
+    };
+    assert_eq!(monitor.watch_account(watched_account(TREASURY, "Vault")), 1);
+    // Watching an account again relabels it
+    assert_eq!(
+        monitor.watch_account(watched_account(TREASURY, "Treasury")),
+        1
+    );
+    assert_eq!(
+        monitor.watch_account(watched_account(
+            "0xABCDEFABCDEFABCDEFABCDEFABCDEFABCDEFABCD",
+            "Cold wallet"
+        )),
+        2
+    );
+    // The addressbook is keyed by lowercase address
+    assert!(monitor
+        .addressbook()
+        .lock()
+        .unwrap()
+        .contains_key("0xabcdefabcdefabcdefabcdefabcdefabcdefabcd"));
+
+    let chain = chain_definition(json!({
+        "spam_filter_level": "None",
+        "native_symbol": "xDAI",
+    }))
+    .into_chain()
+    .unwrap();
+    let send = InterestingTransaction {
+        from: Some(address(TREASURY)),
+        to: Some(address(ALICE)),
+        amount: Some(U256::exp10(18)),
+        ..InterestingTransaction::new(
+            H256::repeat_byte(0x99),
+            InterestingTransactionKind::Send,
+            address(TREASURY),
+        )
+    };
+
+    let notified = notified_transactions(vec![send], &chain);
+    let notifications = build_notifications(&notified, &chain, monitor.addressbook());
+    assert_eq!(notifications.len(), 1);
+    assert!(
+        notifications[0]
+            .message
+            .starts_with("Sending 1 xDAI from Treasury to "),
+        "{}",
+        notifications[0].message
+    );
+}
+
+#[test]
+fn rejects_invalid_chain_definitions() {
+    let invalid_name = chain_definition(json!({"name": "Gnosis Chain"}));
+    assert!(invalid_name.into_chain().is_err());
+
+    let missing_blocktime = chain_definition(json!({"name": "Devnet", "blocktime": null}));
+    assert!(missing_blocktime.into_chain().is_err());
+
+    let invalid_head_delay = chain_definition(json!({"head_delay": 12000}));
+    assert!(invalid_head_delay.into_chain().is_err());
+}